@@ -1,7 +1,5 @@
 //! Raft runtime configuration.
 
-use rand::{thread_rng, Rng};
-
 use crate::error::ConfigError;
 
 /// Default election timeout minimum, in milliseconds.
@@ -14,10 +12,24 @@ pub const DEFAULT_HEARTBEAT_INTERVAL: u64 = 50;
 pub const DEFAULT_LOGS_SINCE_LAST: u64 = 5000;
 /// Default maximum number of entries per replication payload.
 pub const DEFAULT_MAX_PAYLOAD_ENTRIES: u64 = 300;
+/// Default maximum number of in-flight AppendEntries RPCs per replication target.
+pub const DEFAULT_MAX_INFLIGHT_APPEND_ENTRIES: u64 = 1;
+/// Default maximum number of client writes which may be in flight at once.
+pub const DEFAULT_MAX_INFLIGHT_CLIENT_WRITES: u64 = 512;
+/// Default maximum number of API messages which may be queued for the Raft core at once.
+pub const DEFAULT_API_CHANNEL_CAPACITY: u64 = 1024;
+/// Default maximum size of the entries buffered per replication target, in bytes.
+pub const DEFAULT_MAX_REPLICATION_BUFFER_BYTES: u64 = 1024 * 1024 * 64;
 /// Default replication lag threshold.
 pub const DEFAULT_REPLICATION_LAG_THRESHOLD: u64 = 1000;
 /// Default snapshot chunksize.
 pub const DEFAULT_SNAPSHOT_CHUNKSIZE: u64 = 1024 * 1024 * 3;
+/// Default bound on clock drift between nodes, in milliseconds.
+pub const DEFAULT_CLOCK_DRIFT_BOUND: u64 = 15;
+/// Default idle period after which a leader quiesces its group, in milliseconds.
+pub const DEFAULT_QUIESCE_AFTER: u64 = 1000;
+/// Default idle period after which a client session expires, in milliseconds.
+pub const DEFAULT_CLIENT_SESSION_TIMEOUT: u64 = 1000 * 60 * 60;
 
 /// Log compaction and snapshot policy.
 ///
@@ -89,6 +101,50 @@ pub struct Config {
     /// up-to-speed. If this is too low, it will take longer for the nodes to be brought up to
     /// consistency with the rest of the cluster.
     pub max_payload_entries: u64,
+    /// The maximum number of AppendEntries RPCs which may be in flight to a single target at once.
+    ///
+    /// While a follower is replicating at line rate, the leader will send up to this many payloads
+    /// ahead without waiting for each response, which allows per-follower throughput to exceed
+    /// `max_payload_entries` per network round trip. If any pipelined payload is rejected, then
+    /// the follower falls back to lagging state. The `RaftNetwork` implementation should deliver
+    /// RPCs to a given target in order, as payloads which arrive out-of-order will be rejected.
+    ///
+    /// Defaults to 1, which disables pipelining.
+    pub max_inflight_append_entries: u64,
+    /// The maximum number of client writes which may be in flight on this node at once.
+    ///
+    /// A client write is in flight from when it is submitted via `Raft::client_write` or
+    /// `Raft::client_write_batch` until its response is returned. Writes submitted while this many
+    /// are already in flight are rejected at once with `ClientWriteError::Overloaded`, rather than
+    /// being queued without bound, so that applications may shed load. A batch is admitted or
+    /// rejected as a whole.
+    ///
+    /// Writes share the API channel with every other call, including RPCs from peers, so this must
+    /// be no greater than `api_channel_capacity`. Otherwise, a burst of admitted writes could fill
+    /// the channel & hold up heartbeats & votes instead of being rejected.
+    ///
+    /// Defaults to 512, or to half of `api_channel_capacity` if that is smaller, leaving room in the
+    /// channel for other calls.
+    pub max_inflight_client_writes: u64,
+    /// The maximum number of API messages which may be queued for the Raft core at once.
+    ///
+    /// Every call on a `Raft` handle, including client reads & writes, RPCs from peers, membership
+    /// changes and session registrations, is queued as a message for the Raft core. Once this many
+    /// messages are queued, further calls wait until the core has caught up, which bounds the
+    /// memory used by a burst of calls of any kind. Unlike `max_inflight_client_writes`, calls are
+    /// not rejected. A batch of client writes is queued as one message.
+    ///
+    /// Defaults to 1024.
+    pub api_channel_capacity: u64,
+    /// The maximum size in bytes of the entries buffered in memory for each replication target.
+    ///
+    /// While a follower is replicating at line rate, the leader buffers new entries for it in
+    /// memory. If the follower is too slow to keep up and its buffer grows beyond this size, the
+    /// buffer is dropped and the follower falls back to lagging state, in which entries are
+    /// fetched from the log instead. The size of an entry is the size of its bincode encoding.
+    ///
+    /// Defaults to 64MiB.
+    pub max_replication_buffer_bytes: u64,
     /// The distance behind in log replication a follower must fall before it is considered "lagging".
     ///
     /// This configuration parameter controls replication streams from the leader to followers in
@@ -102,6 +158,68 @@ pub struct Config {
     ///
     /// Defaults to 3Mib.
     pub snapshot_max_chunk_size: u64,
+    /// Enable the Pre-Vote phase of elections (§9.6 of the Raft thesis).
+    ///
+    /// When enabled, a node whose election timeout fires will first ask its peers if they would
+    /// grant it a vote, without incrementing its term. Only once a majority has indicated that it
+    /// could win the election will the node increment its term and become a real candidate. This
+    /// prevents nodes which have been partitioned from the cluster from inflating their term and
+    /// forcing a healthy leader to step down when they rejoin.
+    ///
+    /// Defaults to `false`.
+    pub pre_vote: bool,
+    /// Enable leader leases for client reads.
+    ///
+    /// When enabled, the leader will answer `Raft::client_read` calls locally, without a round of
+    /// heartbeats, as long as a majority of the cluster has acknowledged its heartbeats within the
+    /// lease duration. The lease duration is `election_timeout_min - clock_drift_bound`, as
+    /// followers will not vote for a new leader within `election_timeout_min` of hearing from
+    /// the current leader, nor within `election_timeout_min` of starting up.
+    ///
+    /// This depends upon bounded clock drift between nodes, and upon every node of the cluster
+    /// sharing the same `election_timeout_min`. Defaults to `false`.
+    pub leader_lease: bool,
+    /// The maximum expected clock drift between nodes over the course of a lease, in milliseconds.
+    ///
+    /// This is only used when `leader_lease` is enabled, in which case it must be less than
+    /// `election_timeout_min`. Defaults to 15 milliseconds.
+    pub clock_drift_bound: u64,
+    /// Enable CheckQuorum (§6.2 of the Raft thesis).
+    ///
+    /// When enabled, a leader which has not had its RPCs acknowledged by a majority of the cluster
+    /// within `election_timeout_max` will step down to follower. This prevents a leader which has
+    /// been partitioned into a minority from continuing to report itself as leader & accepting
+    /// client writes which can never be committed.
+    ///
+    /// Defaults to `false`.
+    pub check_quorum: bool,
+    /// The window within which heartbeats to the same node are coalesced into a single RPC, in milliseconds.
+    ///
+    /// This is only used by groups hosted on a `MultiRaft`. A heartbeat is delayed by at most this
+    /// long, so it must be less than `heartbeat_interval`. Defaults to a fifth of `heartbeat_interval`.
+    pub heartbeat_coalesce_window: u64,
+    /// Enable quiescence of idle groups.
+    ///
+    /// When enabled, a leader which has received no client requests for `quiesce_after`, and
+    /// whose followers are fully caught up, will instruct its followers to go quiet. Heartbeats
+    /// then stop, and followers suspend their election timers. Any client request or RPC wakes
+    /// the group up again. As a quiescent follower does not detect the failure of its leader on
+    /// its own, this is intended for applications hosting many mostly-idle groups, which wake a
+    /// group via `Raft::wake` or `MultiRaft::report_unreachable` once they suspect its leader has
+    /// failed.
+    ///
+    /// Defaults to `false`.
+    pub quiesce: bool,
+    /// The idle period after which a leader will quiesce its group, in milliseconds.
+    ///
+    /// This is only used when `quiesce` is enabled. Defaults to 1000 milliseconds.
+    pub quiesce_after: u64,
+    /// The idle period after which a client session expires, in milliseconds.
+    ///
+    /// The leader proposes the expiry of every session which has not submitted a write within this
+    /// period. Writes submitted as part of an expired session are rejected with
+    /// `ClientWriteError::UnknownSession`. Defaults to one hour.
+    pub client_session_timeout: u64,
 }
 
 impl Config {
@@ -116,15 +234,32 @@ impl Config {
             election_timeout_max: None,
             heartbeat_interval: None,
             max_payload_entries: None,
+            max_inflight_append_entries: None,
+            max_inflight_client_writes: None,
+            api_channel_capacity: None,
+            max_replication_buffer_bytes: None,
             replication_lag_threshold: None,
             snapshot_policy: None,
             snapshot_max_chunk_size: None,
+            pre_vote: None,
+            leader_lease: None,
+            clock_drift_bound: None,
+            check_quorum: None,
+            heartbeat_coalesce_window: None,
+            quiesce: None,
+            quiesce_after: None,
+            client_session_timeout: None,
         }
     }
 
     /// Generate a new random election timeout within the configured min & max.
     pub fn new_rand_election_timeout(&self) -> u64 {
-        thread_rng().gen_range(self.election_timeout_min, self.election_timeout_max)
+        crate::rng::gen_range(self.election_timeout_min, self.election_timeout_max)
+    }
+
+    /// The duration of a leader lease in milliseconds, measured from when a heartbeat was sent.
+    pub fn leader_lease_duration(&self) -> u64 {
+        self.election_timeout_min.saturating_sub(self.clock_drift_bound)
     }
 }
 
@@ -143,12 +278,36 @@ pub struct ConfigBuilder {
     pub heartbeat_interval: Option<u64>,
     /// The maximum number of entries per payload allowed to be transmitted during replication.
     pub max_payload_entries: Option<u64>,
+    /// The maximum number of AppendEntries RPCs which may be in flight to a single target at once.
+    pub max_inflight_append_entries: Option<u64>,
+    /// The maximum number of client writes which may be in flight at once.
+    pub max_inflight_client_writes: Option<u64>,
+    /// The maximum number of API messages which may be queued at once.
+    pub api_channel_capacity: Option<u64>,
+    /// The maximum size of the entries buffered per replication target, in bytes.
+    pub max_replication_buffer_bytes: Option<u64>,
     /// The distance behind in log replication a follower must fall before it is considered "lagging".
     pub replication_lag_threshold: Option<u64>,
     /// The snapshot policy.
     pub snapshot_policy: Option<SnapshotPolicy>,
     /// The maximum snapshot chunk size.
     pub snapshot_max_chunk_size: Option<u64>,
+    /// Enable the Pre-Vote phase of elections.
+    pub pre_vote: Option<bool>,
+    /// Enable leader leases for client reads.
+    pub leader_lease: Option<bool>,
+    /// The maximum expected clock drift between nodes, in milliseconds.
+    pub clock_drift_bound: Option<u64>,
+    /// Enable CheckQuorum.
+    pub check_quorum: Option<bool>,
+    /// The window within which heartbeats to the same node are coalesced, in milliseconds.
+    pub heartbeat_coalesce_window: Option<u64>,
+    /// Enable quiescence of idle groups.
+    pub quiesce: Option<bool>,
+    /// The idle period after which a leader will quiesce its group, in milliseconds.
+    pub quiesce_after: Option<u64>,
+    /// The idle period after which a client session expires, in milliseconds.
+    pub client_session_timeout: Option<u64>,
 }
 
 impl ConfigBuilder {
@@ -176,6 +335,30 @@ impl ConfigBuilder {
         self
     }
 
+    /// Set the desired value for `max_inflight_append_entries`.
+    pub fn max_inflight_append_entries(mut self, val: u64) -> Self {
+        self.max_inflight_append_entries = Some(val);
+        self
+    }
+
+    /// Set the desired value for `max_inflight_client_writes`.
+    pub fn max_inflight_client_writes(mut self, val: u64) -> Self {
+        self.max_inflight_client_writes = Some(val);
+        self
+    }
+
+    /// Set the desired value for `api_channel_capacity`.
+    pub fn api_channel_capacity(mut self, val: u64) -> Self {
+        self.api_channel_capacity = Some(val);
+        self
+    }
+
+    /// Set the desired value for `max_replication_buffer_bytes`.
+    pub fn max_replication_buffer_bytes(mut self, val: u64) -> Self {
+        self.max_replication_buffer_bytes = Some(val);
+        self
+    }
+
     /// Set the desired value for `replication_lag_threshold`.
     pub fn replication_lag_threshold(mut self, val: u64) -> Self {
         self.replication_lag_threshold = Some(val);
@@ -194,7 +377,59 @@ impl ConfigBuilder {
         self
     }
 
+    /// Set the desired value for `pre_vote`.
+    pub fn pre_vote(mut self, val: bool) -> Self {
+        self.pre_vote = Some(val);
+        self
+    }
+
+    /// Set the desired value for `leader_lease`.
+    pub fn leader_lease(mut self, val: bool) -> Self {
+        self.leader_lease = Some(val);
+        self
+    }
+
+    /// Set the desired value for `clock_drift_bound`.
+    pub fn clock_drift_bound(mut self, val: u64) -> Self {
+        self.clock_drift_bound = Some(val);
+        self
+    }
+
+    /// Set the desired value for `check_quorum`.
+    pub fn check_quorum(mut self, val: bool) -> Self {
+        self.check_quorum = Some(val);
+        self
+    }
+
+    /// Set the desired value for `heartbeat_coalesce_window`.
+    pub fn heartbeat_coalesce_window(mut self, val: u64) -> Self {
+        self.heartbeat_coalesce_window = Some(val);
+        self
+    }
+
+    /// Set the desired value for `quiesce`.
+    pub fn quiesce(mut self, val: bool) -> Self {
+        self.quiesce = Some(val);
+        self
+    }
+
+    /// Set the desired value for `quiesce_after`.
+    pub fn quiesce_after(mut self, val: u64) -> Self {
+        self.quiesce_after = Some(val);
+        self
+    }
+
+    /// Set the desired value for `client_session_timeout`.
+    pub fn client_session_timeout(mut self, val: u64) -> Self {
+        self.client_session_timeout = Some(val);
+        self
+    }
+
     /// Validate the state of this builder and produce a new `Config` instance if valid.
+    ///
+    /// Validation only covers this node's config. Leader leases are only safe if every node of
+    /// the cluster refuses votes for the same `election_timeout_min` after hearing from a leader,
+    /// so the nodes of a cluster using `leader_lease` must all be built with the same value.
     pub fn validate(self) -> Result<Config, ConfigError> {
         // Roll a random election time out based on the configured min & max or their respective defaults.
         let election_timeout_min = self.election_timeout_min.unwrap_or(DEFAULT_ELECTION_TIMEOUT_MIN);
@@ -208,18 +443,64 @@ impl ConfigBuilder {
         if max_payload_entries == 0 {
             return Err(ConfigError::MaxPayloadEntriesTooSmall);
         }
+        let max_inflight_append_entries = self.max_inflight_append_entries.unwrap_or(DEFAULT_MAX_INFLIGHT_APPEND_ENTRIES);
+        if max_inflight_append_entries == 0 {
+            return Err(ConfigError::MaxInflightAppendEntriesTooSmall);
+        }
+        let api_channel_capacity = self.api_channel_capacity.unwrap_or(DEFAULT_API_CHANNEL_CAPACITY);
+        if api_channel_capacity == 0 {
+            return Err(ConfigError::ApiChannelCapacityTooSmall);
+        }
+        let max_inflight_client_writes = self.max_inflight_client_writes
+            .unwrap_or_else(|| DEFAULT_MAX_INFLIGHT_CLIENT_WRITES.min(api_channel_capacity / 2).max(1));
+        if max_inflight_client_writes == 0 {
+            return Err(ConfigError::MaxInflightClientWritesTooSmall);
+        }
+        if max_inflight_client_writes > api_channel_capacity {
+            return Err(ConfigError::MaxInflightClientWritesTooLarge);
+        }
+        let max_replication_buffer_bytes = self.max_replication_buffer_bytes.unwrap_or(DEFAULT_MAX_REPLICATION_BUFFER_BYTES);
+        if max_replication_buffer_bytes == 0 {
+            return Err(ConfigError::MaxReplicationBufferBytesTooSmall);
+        }
         let replication_lag_threshold = self.replication_lag_threshold.unwrap_or(DEFAULT_REPLICATION_LAG_THRESHOLD);
         let snapshot_policy = self.snapshot_policy.unwrap_or_else(|| SnapshotPolicy::default());
         let snapshot_max_chunk_size = self.snapshot_max_chunk_size.unwrap_or(DEFAULT_SNAPSHOT_CHUNKSIZE);
+        let pre_vote = self.pre_vote.unwrap_or(false);
+        let leader_lease = self.leader_lease.unwrap_or(false);
+        let clock_drift_bound = self.clock_drift_bound.unwrap_or(DEFAULT_CLOCK_DRIFT_BOUND);
+        if leader_lease && clock_drift_bound >= election_timeout_min {
+            return Err(ConfigError::ClockDriftBoundTooLarge);
+        }
+        let check_quorum = self.check_quorum.unwrap_or(false);
+        let heartbeat_coalesce_window = self.heartbeat_coalesce_window.unwrap_or(heartbeat_interval / 5);
+        if heartbeat_coalesce_window >= heartbeat_interval {
+            return Err(ConfigError::HeartbeatCoalesceWindowTooLarge);
+        }
+        let quiesce = self.quiesce.unwrap_or(false);
+        let quiesce_after = self.quiesce_after.unwrap_or(DEFAULT_QUIESCE_AFTER);
+        let client_session_timeout = self.client_session_timeout.unwrap_or(DEFAULT_CLIENT_SESSION_TIMEOUT);
         Ok(Config{
             cluster_name: self.cluster_name,
             election_timeout_min,
             election_timeout_max,
             heartbeat_interval,
             max_payload_entries,
+            max_inflight_append_entries,
+            max_inflight_client_writes,
+            api_channel_capacity,
+            max_replication_buffer_bytes,
             replication_lag_threshold,
             snapshot_policy,
             snapshot_max_chunk_size,
+            pre_vote,
+            leader_lease,
+            clock_drift_bound,
+            check_quorum,
+            heartbeat_coalesce_window,
+            quiesce,
+            quiesce_after,
+            client_session_timeout,
         })
     }
 }
@@ -239,9 +520,21 @@ mod tests {
         assert!(cfg.election_timeout_max <= DEFAULT_ELECTION_TIMEOUT_MAX as u64);
         assert!(cfg.heartbeat_interval == DEFAULT_HEARTBEAT_INTERVAL as u64);
         assert!(cfg.max_payload_entries == DEFAULT_MAX_PAYLOAD_ENTRIES);
+        assert!(cfg.max_inflight_append_entries == DEFAULT_MAX_INFLIGHT_APPEND_ENTRIES);
+        assert!(cfg.max_inflight_client_writes == DEFAULT_MAX_INFLIGHT_CLIENT_WRITES);
+        assert!(cfg.api_channel_capacity == DEFAULT_API_CHANNEL_CAPACITY);
+        assert!(cfg.max_replication_buffer_bytes == DEFAULT_MAX_REPLICATION_BUFFER_BYTES);
         assert!(cfg.replication_lag_threshold == DEFAULT_REPLICATION_LAG_THRESHOLD);
         assert!(cfg.snapshot_max_chunk_size == DEFAULT_SNAPSHOT_CHUNKSIZE);
         assert!(cfg.snapshot_policy == SnapshotPolicy::LogsSinceLast(DEFAULT_LOGS_SINCE_LAST));
+        assert!(!cfg.pre_vote);
+        assert!(!cfg.leader_lease);
+        assert!(cfg.clock_drift_bound == DEFAULT_CLOCK_DRIFT_BOUND);
+        assert!(!cfg.check_quorum);
+        assert!(cfg.heartbeat_coalesce_window == DEFAULT_HEARTBEAT_INTERVAL / 5);
+        assert!(!cfg.quiesce);
+        assert!(cfg.quiesce_after == DEFAULT_QUIESCE_AFTER);
+        assert!(cfg.client_session_timeout == DEFAULT_CLIENT_SESSION_TIMEOUT);
     }
 
     #[test]
@@ -251,18 +544,43 @@ mod tests {
             .election_timeout_min(100)
             .heartbeat_interval(10)
             .max_payload_entries(100)
+            .max_inflight_append_entries(8)
+            .max_inflight_client_writes(50)
+            .api_channel_capacity(64)
+            .max_replication_buffer_bytes(4096)
             .replication_lag_threshold(100)
             .snapshot_max_chunk_size(200)
             .snapshot_policy(SnapshotPolicy::LogsSinceLast(10000))
+            .pre_vote(true)
+            .leader_lease(true)
+            .clock_drift_bound(20)
+            .check_quorum(true)
+            .heartbeat_coalesce_window(5)
+            .quiesce(true)
+            .quiesce_after(500)
+            .client_session_timeout(60000)
             .validate().unwrap();
 
         assert!(cfg.election_timeout_min >= 100);
         assert!(cfg.election_timeout_max <= 200);
         assert!(cfg.heartbeat_interval == 10);
         assert!(cfg.max_payload_entries == 100);
+        assert!(cfg.max_inflight_append_entries == 8);
+        assert!(cfg.max_inflight_client_writes == 50);
+        assert!(cfg.api_channel_capacity == 64);
+        assert!(cfg.max_replication_buffer_bytes == 4096);
         assert!(cfg.replication_lag_threshold == 100);
         assert!(cfg.snapshot_max_chunk_size == 200);
         assert!(cfg.snapshot_policy == SnapshotPolicy::LogsSinceLast(10000));
+        assert!(cfg.pre_vote);
+        assert!(cfg.leader_lease);
+        assert!(cfg.clock_drift_bound == 20);
+        assert!(cfg.check_quorum);
+        assert!(cfg.heartbeat_coalesce_window == 5);
+        assert!(cfg.quiesce);
+        assert!(cfg.quiesce_after == 500);
+        assert!(cfg.client_session_timeout == 60000);
+        assert!(cfg.leader_lease_duration() == 80);
     }
 
     #[test]
@@ -273,4 +591,72 @@ mod tests {
         let err = res.unwrap_err();
         assert_eq!(err, ConfigError::InvalidElectionTimeoutMinMax);
     }
+
+    #[test]
+    fn test_invalid_max_inflight_append_entries_config_produces_expected_error() {
+        let res = Config::build("cluster0".into()).max_inflight_append_entries(0).validate();
+        assert!(res.is_err());
+        let err = res.unwrap_err();
+        assert_eq!(err, ConfigError::MaxInflightAppendEntriesTooSmall);
+    }
+
+    #[test]
+    fn test_invalid_max_inflight_client_writes_config_produces_expected_error() {
+        let res = Config::build("cluster0".into()).max_inflight_client_writes(0).validate();
+        assert!(res.is_err());
+        let err = res.unwrap_err();
+        assert_eq!(err, ConfigError::MaxInflightClientWritesTooSmall);
+    }
+
+    #[test]
+    fn test_invalid_api_channel_capacity_config_produces_expected_error() {
+        let res = Config::build("cluster0".into()).api_channel_capacity(0).validate();
+        assert!(res.is_err());
+        let err = res.unwrap_err();
+        assert_eq!(err, ConfigError::ApiChannelCapacityTooSmall);
+    }
+
+    #[test]
+    fn test_max_inflight_client_writes_larger_than_api_channel_capacity_produces_expected_error() {
+        let res = Config::build("cluster0".into()).max_inflight_client_writes(65).api_channel_capacity(64).validate();
+        assert!(res.is_err());
+        let err = res.unwrap_err();
+        assert_eq!(err, ConfigError::MaxInflightClientWritesTooLarge);
+    }
+
+    #[test]
+    fn test_max_inflight_client_writes_defaults_within_api_channel_capacity() {
+        let cfg = Config::build("cluster0".into()).api_channel_capacity(64).validate().unwrap();
+        assert!(cfg.max_inflight_client_writes == 32);
+
+        let cfg = Config::build("cluster0".into()).api_channel_capacity(1).validate().unwrap();
+        assert!(cfg.max_inflight_client_writes == 1);
+    }
+
+    #[test]
+    fn test_invalid_max_replication_buffer_bytes_config_produces_expected_error() {
+        let res = Config::build("cluster0".into()).max_replication_buffer_bytes(0).validate();
+        assert!(res.is_err());
+        let err = res.unwrap_err();
+        assert_eq!(err, ConfigError::MaxReplicationBufferBytesTooSmall);
+    }
+
+    #[test]
+    fn test_invalid_clock_drift_bound_config_produces_expected_error() {
+        let res = Config::build("cluster0".into())
+            .election_timeout_min(100).election_timeout_max(200)
+            .leader_lease(true).clock_drift_bound(100).validate();
+        assert!(res.is_err());
+        let err = res.unwrap_err();
+        assert_eq!(err, ConfigError::ClockDriftBoundTooLarge);
+    }
+
+    #[test]
+    fn test_invalid_heartbeat_coalesce_window_config_produces_expected_error() {
+        let res = Config::build("cluster0".into())
+            .heartbeat_interval(50).heartbeat_coalesce_window(50).validate();
+        assert!(res.is_err());
+        let err = res.unwrap_err();
+        assert_eq!(err, ConfigError::HeartbeatCoalesceWindowTooLarge);
+    }
 }
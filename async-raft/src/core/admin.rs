@@ -2,11 +2,13 @@ use std::collections::HashSet;
 
 use futures::future::{FutureExt, TryFutureExt};
 use tokio::sync::oneshot;
+use tokio::time::{Duration, Instant};
+use tracing_futures::Instrument;
 
 use crate::{AppData, AppDataResponse, NodeId, RaftNetwork, RaftStorage};
-use crate::error::{InitializeError, ChangeConfigError, RaftError};
-use crate::raft::{ChangeMembershipTx, ClientWriteRequest, MembershipConfig};
-use crate::core::{ConsensusState, LeaderState, NonVoterReplicationState, NonVoterState, State, UpdateCurrentLeader};
+use crate::error::{InitializeError, ChangeConfigError, LeadershipTransferError, RaftError};
+use crate::raft::{ChangeMembershipTx, ClientWriteRequest, LeadershipTransferTx, MembershipConfig, TimeoutNowRequest};
+use crate::core::{ConsensusState, LeaderState, LeadershipTransferState, NonVoterReplicationState, NonVoterState, State, UpdateCurrentLeader};
 use crate::core::client::ClientRequestEntry;
 use crate::replication::RaftEvent;
 
@@ -26,7 +28,7 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
 
         // Build a new membership config from given init data & assign it as the new cluster
         // membership config in memory only.
-        self.core.membership = MembershipConfig{members, members_after_consensus: None};
+        self.core.membership = MembershipConfig{members, members_after_consensus: None, learners: HashSet::new(), witnesses: HashSet::new()};
 
         // Become a candidate and start campaigning for leadership. If this node is the only node
         // in the cluster, then become leader without holding an election. If members len == 1, we
@@ -45,30 +47,125 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
 }
 
 impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> LeaderState<'a, D, R, N, S> {
-    /// Add a new node to the cluster as a non-voter, bringing it up-to-speed, and then responding
+    /// Add a new node to the cluster as a learner, bringing it up-to-speed, and then responding
     /// on the given channel.
+    ///
+    /// The node is added to the learners of the cluster's membership config, which is committed
+    /// through the log so that every future leader will continue replicating to it. If the node is
+    /// to be a witness, then it is also added to the config's witnesses. A response is issued once
+    /// the node has been synced and the new config has been committed.
     #[tracing::instrument(level="trace", skip(self, tx))]
-    pub(super) fn add_member(&mut self, target: NodeId, tx: oneshot::Sender<Result<(), ChangeConfigError>>) {
+    pub(super) async fn add_member(&mut self, target: NodeId, is_witness: bool, tx: oneshot::Sender<Result<(), ChangeConfigError>>) {
+        // Only allow config updates when currently in a uniform consensus state.
+        if !matches!(self.consensus_state, ConsensusState::Uniform) {
+            let _ = tx.send(Err(ChangeConfigError::ConfigChangeInProgress));
+            return;
+        }
+
         // Ensure the node doesn't already exist in the current config, in the set of new nodes
         // alreading being synced, or in the nodes being removed.
         if self.core.membership.members.contains(&target)
         || self.core.membership.members_after_consensus.as_ref().map(|new| new.contains(&target)).unwrap_or(false)
+        || self.core.membership.is_learner(&target)
         || self.non_voters.contains_key(&target) {
             tracing::debug!("target node is already a cluster member or is being synced");
             let _ = tx.send(Err(ChangeConfigError::Noop));
             return;
         }
 
+        // Add the new node to the config. Witnesses must be recorded first, as the replication
+        // stream only replicates metadata to them.
+        self.core.membership.learners.insert(target);
+        if is_witness {
+            self.core.membership.witnesses.insert(target);
+        }
+
         // Spawn a replication stream for the new member. Track state as a non-voter so that it
         // can be updated to be added to the cluster config once it has been brought up-to-date.
         let state = self.spawn_replication_stream(target);
-        self.non_voters.insert(target, NonVoterReplicationState{state, is_ready_to_join: false, tx: Some(tx)});
+        let (tx_synced, rx_synced) = oneshot::channel();
+        self.non_voters.insert(target, NonVoterReplicationState{state, is_ready_to_join: false, tx: Some(tx_synced)});
+
+        // Propagate the config with the new learner as any other client request.
+        let payload = ClientWriteRequest::<D>::new_config(self.core.membership.clone());
+        let (tx_committed, rx_committed) = oneshot::channel::<Result<u64, RaftError>>();
+        let entry = match self.append_payload_to_log(payload.entry).await {
+            Ok(entry) => entry,
+            Err(err) => {
+                let _ = tx.send(Err(err.into()));
+                return;
+            }
+        };
+        let cr_entry = ClientRequestEntry::from_entry(entry, tx_committed);
+        self.replicate_client_request(cr_entry).await;
+        self.report_metrics();
+
+        // Respond once the node has been synced & the config which adds it has been committed.
+        tokio::spawn(async move {
+            let res = match futures::future::join(rx_synced, rx_committed).await {
+                (Ok(Ok(_)), Ok(Ok(_))) => Ok(()),
+                (Ok(Err(err)), _) => Err(err),
+                (_, Ok(Err(err))) => Err(ChangeConfigError::from(err)),
+                _ => Err(ChangeConfigError::RaftError(RaftError::ShuttingDown)),
+            };
+            let _ = tx.send(res);
+        });
+    }
+
+    /// Remove a learner from the cluster, responding on the given channel once the config which
+    /// removes it has been committed.
+    ///
+    /// The learner's replication stream is terminated at once, as a learner never counts towards
+    /// a quorum, so it needs no part in committing its own removal.
+    #[tracing::instrument(level="trace", skip(self, tx))]
+    pub(super) async fn remove_learner(&mut self, target: NodeId, tx: ChangeMembershipTx) {
+        // Only allow config updates when currently in a uniform consensus state.
+        if !matches!(self.consensus_state, ConsensusState::Uniform) {
+            let _ = tx.send(Err(ChangeConfigError::ConfigChangeInProgress));
+            return;
+        }
+        if !self.core.membership.is_learner(&target) {
+            tracing::debug!("target node is not a learner");
+            let _ = tx.send(Err(ChangeConfigError::Noop));
+            return;
+        }
+
+        // Remove the learner from the config & stop replicating to it.
+        self.core.membership.learners.remove(&target);
+        self.core.membership.witnesses.remove(&target);
+        if let Some(node) = self.non_voters.remove(&target) {
+            let _ = node.state.replstream.repltx.send(RaftEvent::Terminate);
+        }
+
+        // Propagate the config without the learner as any other client request.
+        let payload = ClientWriteRequest::<D>::new_config(self.core.membership.clone());
+        let (tx_committed, rx_committed) = oneshot::channel::<Result<u64, RaftError>>();
+        let entry = match self.append_payload_to_log(payload.entry).await {
+            Ok(entry) => entry,
+            Err(err) => {
+                let _ = tx.send(Err(err.into()));
+                return;
+            }
+        };
+        let cr_entry = ClientRequestEntry::from_entry(entry, tx_committed);
+        self.replicate_client_request(cr_entry).await;
+        self.report_metrics();
+
+        // Respond once the config which removes the learner has been committed.
+        tokio::spawn(async move {
+            let res = match rx_committed.await {
+                Ok(Ok(_)) => Ok(()),
+                Ok(Err(err)) => Err(ChangeConfigError::from(err)),
+                Err(_) => Err(ChangeConfigError::RaftError(RaftError::ShuttingDown)),
+            };
+            let _ = tx.send(res);
+        });
     }
 
     #[tracing::instrument(level="trace", skip(self, tx))]
     pub(super) async fn change_membership(&mut self, members: HashSet<NodeId>, tx: ChangeMembershipTx) {
-        // Ensure cluster will have at least one node.
-        if members.is_empty() {
+        // Ensure cluster will have at least one node which is able to become leader.
+        if members.iter().all(|id| self.core.membership.is_witness(id)) {
             let _ = tx.send(Err(ChangeConfigError::InoperableConfig));
             return;
         }
@@ -88,7 +185,8 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
         //
         // Here, all we do is check to see which nodes still need to be synced, which determines
         // we can proceed.
-        let diff = members.difference(&self.core.membership.members).cloned().collect::<Vec<_>>();
+        let mut diff = members.difference(&self.core.membership.members).cloned().collect::<Vec<_>>();
+        diff.sort_unstable();
         let awaiting = diff.into_iter()
             .filter(|new_node| match self.non_voters.get(&new_node) {
                 Some(node) if node.is_ready_to_join => false,
@@ -114,6 +212,15 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
             self.is_stepping_down = true;
         }
         self.consensus_state = ConsensusState::Joint{is_committed: false};
+
+        // Promote any learners which are members of the new config. Their replication streams
+        // are moved into the pool of voting nodes, so that they count towards the new config's quorum.
+        self.core.membership.learners.retain(|id| !members.contains(id));
+        for id in members.iter() {
+            if let Some(node) = self.non_voters.remove(id) {
+                self.nodes.insert(*id, node.state);
+            }
+        }
         self.core.membership.members_after_consensus = Some(members);
 
         // Propagate the command as any other client request.
@@ -128,7 +235,7 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
         };
         let cr_entry = ClientRequestEntry::from_entry(entry, tx_joint);
         self.replicate_client_request(cr_entry).await;
-        self.core.report_metrics();
+        self.report_metrics();
 
         // Setup channels for eventual response to the 2-phase config change.
         let (tx_cfg_change, rx_cfg_change) = oneshot::channel();
@@ -179,6 +286,9 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
         if let Some(new_members) = self.core.membership.members_after_consensus.take() {
             self.core.membership.members = new_members;
         }
+        // Witnesses which have been removed from the cluster are no longer witnesses.
+        let MembershipConfig{members, learners, witnesses, ..} = &mut self.core.membership;
+        witnesses.retain(|id| members.contains(id) || learners.contains(id));
         self.consensus_state = ConsensusState::Uniform;
 
         // NOTE WELL: this implementation uses replication streams (src/replication/**) to replicate
@@ -197,7 +307,7 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
         let entry = self.append_payload_to_log(payload.entry).await?;
         let cr_entry = ClientRequestEntry::from_entry(entry, tx_uniform);
         self.replicate_client_request(cr_entry).await;
-        self.core.report_metrics();
+        self.report_metrics();
 
         // Setup channel for eventual commitment of the uniform consensus config.
         self.uniform_consensus_cb.push(rx_uniform); // Receiver for when the uniform consensus is committed.
@@ -235,7 +345,82 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
                 let _ = node.replstream.repltx.send(RaftEvent::Terminate);
             }
         }
-        self.core.report_metrics();
+        self.report_metrics();
         Ok(())
     }
+
+    /// Begin transferring leadership of the cluster to the target node (§3.10 of the Raft thesis).
+    ///
+    /// Client write requests will be rejected until the transfer completes or is aborted.
+    #[tracing::instrument(level="trace", skip(self, tx))]
+    pub(super) fn transfer_leadership(&mut self, target: NodeId, tx: LeadershipTransferTx) {
+        if self.leadership_transfer.is_some() {
+            let _ = tx.send(Err(LeadershipTransferError::TransferInProgress));
+            return;
+        }
+
+        // The target must be a voting member of every config group, and must not be a witness.
+        let is_voter = self.core.membership.members.contains(&target)
+            && self.core.membership.members_after_consensus.as_ref().map(|new| new.contains(&target)).unwrap_or(true);
+        if target == self.core.id || !is_voter || self.core.membership.is_witness(&target) || !self.nodes.contains_key(&target) {
+            tracing::debug!({target}, "rejecting leadership transfer to node which is not a voting member of the cluster");
+            let _ = tx.send(Err(LeadershipTransferError::InvalidTarget));
+            return;
+        }
+
+        let deadline = Instant::now() + Duration::from_millis(self.core.config.election_timeout_max);
+        self.leadership_transfer = Some(LeadershipTransferState{target, deadline, tx: Some(tx)});
+        self.reset_last_acks();
+        self.try_send_timeout_now();
+    }
+
+    /// Send a TimeoutNow RPC to the target of the current leadership transfer, if its log is up-to-date.
+    #[tracing::instrument(level="trace", skip(self))]
+    pub(super) fn try_send_timeout_now(&mut self) {
+        let transfer = match self.leadership_transfer.as_mut() {
+            Some(transfer) if transfer.tx.is_some() => transfer,
+            _ => return,
+        };
+        let last_log_index = self.core.last_log_index;
+        let is_up_to_date = self.nodes.get(&transfer.target)
+            .map(|node| node.match_index == last_log_index)
+            .unwrap_or(false);
+        if !is_up_to_date {
+            return;
+        }
+
+        let target = transfer.target;
+        let rpc = TimeoutNowRequest{term: self.core.current_term, leader_id: self.core.id};
+        let network = self.core.network.clone();
+        tokio::spawn(async move {
+            if let Err(err) = network.timeout_now(target, rpc).await {
+                tracing::error!({error=%err, target}, "error sending TimeoutNow RPC to target");
+            }
+        }.instrument(tracing::debug_span!("sending TimeoutNow RPC", target)));
+        if let Some(tx) = transfer.tx.take() {
+            let _ = tx.send(Ok(()));
+        }
+    }
+
+    /// Abort the current leadership transfer, resuming normal operation as the cluster leader.
+    #[tracing::instrument(level="trace", skip(self))]
+    pub(super) fn abort_leadership_transfer(&mut self) {
+        if let Some(transfer) = self.leadership_transfer.take() {
+            tracing::debug!({target=transfer.target}, "aborting leadership transfer as target did not become leader in time");
+            if let Some(tx) = transfer.tx {
+                let _ = tx.send(Err(LeadershipTransferError::Timeout));
+            }
+            // Acknowledgements from before the abort do not count towards a leader lease, as
+            // the target may still campaign without waiting out its election timeout.
+            self.reset_last_acks();
+        }
+    }
+
+    /// Reset the last acknowledgement time of all replication targets.
+    fn reset_last_acks(&mut self) {
+        for node in self.nodes.values_mut() {
+            node.last_ack = None;
+        }
+        self.acks_reset_at = Instant::now();
+    }
 }
@@ -1,5 +1,10 @@
+use std::collections::BTreeMap;
+
+use tokio::time::Instant;
+
 use crate::{AppData, AppDataResponse, RaftNetwork, RaftStorage};
 use crate::error::RaftResult;
+use crate::event::Event;
 use crate::raft::{AppendEntriesRequest, AppendEntriesResponse, ConflictOpt, Entry, EntryPayload};
 use crate::core::{RaftCore, State, UpdateCurrentLeader};
 
@@ -18,10 +23,12 @@ impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> Ra
             return Ok(AppendEntriesResponse{term: self.current_term, success: false, conflict_opt: None});
         }
 
-        // Update election timeout.
+        // Update election timeout & record the heartbeat. Any AppendEntries RPC wakes this node,
+        // unless it is a heartbeat which quiesces it.
         self.update_next_election_timeout();
+        self.last_heartbeat = Some(Instant::now());
+        self.is_quiescent = false;
         let mut report_metrics = false;
-        self.commit_index = msg.leader_commit; // The value for `self.commit_index` is only updated here when not the leader.
 
         // Update current term if needed.
         if &self.current_term != &msg.term {
@@ -48,6 +55,11 @@ impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> Ra
         if msg_prev_index_is_min || msg_index_and_term_match {
             // If this is just a heartbeat, then respond.
             if msg.entries.len() == 0 {
+                if msg.quiesce {
+                    tracing::debug!("quiesced by leader");
+                    self.is_quiescent = true;
+                }
+                self.update_commit_index(&msg);
                 self.replicate_to_state_machine_if_needed(&mut report_metrics).await?;
                 if report_metrics {
                     self.report_metrics();
@@ -56,7 +68,8 @@ impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> Ra
             }
 
             // Else, append log entries.
-            self.append_log_entries(&msg.entries).await?;
+            self.append_new_log_entries(&msg.entries).await?;
+            self.update_commit_index(&msg);
             self.replicate_to_state_machine_if_needed(&mut report_metrics).await?;
             if report_metrics {
                 self.report_metrics();
@@ -69,7 +82,7 @@ impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> Ra
         tracing::trace!("begin log consistency check");
 
         // Previous log info doesn't immediately line up, so perform log consistency check and proceed based on its result.
-        let entries = self.storage.get_log_entries(msg.prev_log_index, msg.prev_log_index).await.map_err(|err| self.map_fatal_storage_error(err))?;
+        let entries = self.storage.get_log_entries(msg.prev_log_index, msg.prev_log_index + 1).await.map_err(|err| self.map_fatal_storage_error(err))?;
         let target_entry = match entries.first() {
             Some(target_entry) => target_entry,
             // The target entry was not found. This can only mean that we don't have the
@@ -86,23 +99,19 @@ impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> Ra
         };
 
         // The target entry was found. Compare its term with target term to ensure everything is consistent.
-        if &target_entry.term == &msg.prev_log_term {
-            // We've found a point of agreement with the leader. If we have any logs present
-            // with an index greater than this, then we must delete them per §5.3.
-            if &self.last_log_index > &target_entry.index {
-                self.storage.delete_logs_from(target_entry.index + 1, None).await.map_err(|err| self.map_fatal_storage_error(err))?;
-                let membership = self.storage.get_membership_config().await.map_err(|err| self.map_fatal_storage_error(err))?;
-                self.update_membership(membership)?;
-            }
-        }
-        // The target entry does not have the same term. Fetch the last 50 logs, and use the last
+        // If the target entry does not have the same term, then fetch the last 50 logs, and use the last
         // entry of that payload which is still in the target term for conflict optimization.
-        else {
+        if target_entry.term != msg.prev_log_term {
             let start = if &msg.prev_log_index >= &50 { &msg.prev_log_index - 50 } else { 0 };
             let old_entries = self.storage.get_log_entries(start, msg.prev_log_index).await.map_err(|err| self.map_fatal_storage_error(err))?;
             let opt = match old_entries.iter().find(|entry| entry.term == msg.prev_log_term) {
                 Some(entry) => Some(ConflictOpt{term: entry.term, index: entry.index}),
-                None => Some(ConflictOpt{term: self.last_log_term, index: self.last_log_index}),
+                // Else, skip back over the conflicting term, as any of its entries may conflict. The
+                // opt must always be behind the target entry, else the leader would retry it forever.
+                None => match old_entries.iter().rev().find(|entry| entry.term < target_entry.term).or_else(|| old_entries.first()) {
+                    Some(entry) => Some(ConflictOpt{term: entry.term, index: entry.index}),
+                    None => Some(ConflictOpt{term: self.last_log_term, index: self.last_log_index}),
+                },
             };
             if report_metrics {
                 self.report_metrics();
@@ -114,7 +123,9 @@ impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> Ra
         //// End Log Consistency Check ////
         tracing::trace!("end log consistency check");
 
-        self.append_log_entries(&msg.entries).await?;
+        // We've found a point of agreement with the leader, so append the new entries.
+        self.append_new_log_entries(&msg.entries).await?;
+        self.update_commit_index(&msg);
         self.replicate_to_state_machine_if_needed(&mut report_metrics).await?;
         if report_metrics {
             self.report_metrics();
@@ -122,6 +133,54 @@ impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> Ra
         Ok(AppendEntriesResponse{term: self.current_term, success: true, conflict_opt: None})
     }
 
+    /// Update the commit index from an AppendEntries RPC whose entries have been appended to the log.
+    ///
+    /// Only the entries of the RPC are known to match the leader's log, so the commit index is
+    /// advanced no further than the last of them (§5.3). Entries beyond it may be left over from
+    /// an earlier term, and must not be applied to the state machine until they are overwritten.
+    fn update_commit_index(&mut self, msg: &AppendEntriesRequest<D>) {
+        let last_new_index = msg.prev_log_index + msg.entries.len() as u64;
+        self.commit_index = std::cmp::max(self.commit_index, std::cmp::min(msg.leader_commit, last_new_index));
+    }
+
+    /// Append the given entries, which follow an entry matching the leader's log, to the log.
+    ///
+    /// Entries already present in the log are skipped. If an entry conflicts with an existing one
+    /// (same index but different term), then the existing entry and all that follow it are deleted
+    /// before appending (§5.3). Existing entries are never deleted otherwise, as this request may
+    /// have been overtaken by a later one which appended them, and they may since have been
+    /// counted towards a commit by the leader.
+    #[tracing::instrument(level="trace", skip(self, entries))]
+    async fn append_new_log_entries(&mut self, entries: &[Entry<D>]) -> RaftResult<()> {
+        let (first, last) = match (entries.first(), entries.last()) {
+            (Some(first), Some(last)) => (first.index, last.index),
+            _ => return Ok(()),
+        };
+        let existing = if first <= self.last_log_index {
+            let stop = std::cmp::min(last, self.last_log_index) + 1;
+            self.storage.get_log_entries(first, stop).await.map_err(|err| self.map_fatal_storage_error(err))?
+                .into_iter().map(|entry| (entry.index, entry.term)).collect::<BTreeMap<_, _>>()
+        } else {
+            BTreeMap::new()
+        };
+        let conflict = entries.iter()
+            .position(|entry| existing.get(&entry.index).map(|term| *term != entry.term).unwrap_or(false));
+        let start = match conflict {
+            Some(idx) => {
+                self.storage.delete_logs_from(entries[idx].index, None).await.map_err(|err| self.map_fatal_storage_error(err))?;
+                self.emit_event(Event::LogTruncated{index: entries[idx].index, term: self.current_term});
+                let membership = self.storage.get_membership_config().await.map_err(|err| self.map_fatal_storage_error(err))?;
+                self.update_membership(membership)?;
+                idx
+            }
+            None => entries.iter().position(|entry| entry.index > self.last_log_index).unwrap_or(entries.len()),
+        };
+        if start < entries.len() {
+            self.append_log_entries(&entries[start..]).await?;
+        }
+        Ok(())
+    }
+
     /// Append the given entries to the log.
     ///
     /// Configuration changes are also detected and applied here. See `configuration changes`
@@ -160,16 +219,11 @@ impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> Ra
                 self.last_applied = entry.index;
                 *report_metrics = true;
             }
-            let data_entries: Vec<_> = entries.iter()
-                .filter_map(|entry| match &entry.payload {
-                    EntryPayload::Normal(inner) => Some((&entry.index, &inner.data)),
-                    _ => None,
-                })
-                .collect();
-            if data_entries.is_empty() {
+            self.emit_committed_configs(&entries);
+            if entries.is_empty() {
                 return Ok(());
             }
-            self.storage.replicate_to_state_machine(&data_entries).await.map_err(|err| self.map_fatal_storage_error(err))?;
+            self.apply_entries_to_state_machine(&entries).await?;
 
             // Request async compaction, if needed.
             self.trigger_log_compaction_if_needed();
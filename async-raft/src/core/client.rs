@@ -1,18 +1,21 @@
+use std::collections::HashSet;
 use std::sync::Arc;
 
 use anyhow::anyhow;
 use futures::stream::FuturesUnordered;
-use futures::future::TryFutureExt;
+use futures::future::FutureExt;
 use tokio::stream::StreamExt;
 use tokio::sync::oneshot;
-use tokio::time::{Duration, timeout};
+use tokio::time::{Duration, Instant, timeout};
+use tracing_futures::Instrument;
 
-use crate::{AppData, AppDataResponse, RaftNetwork, RaftStorage};
-use crate::core::{LeaderState, State};
-use crate::error::{ClientReadError, ClientWriteError, RaftError, RaftResult};
-use crate::raft::{ClientWriteRequest, ClientWriteResponse, ClientReadResponseTx, ClientWriteResponseTx, Entry, EntryPayload};
-use crate::raft::{AppendEntriesRequest};
-use crate::replication::RaftEvent;
+use crate::{AppData, AppDataResponse, NodeId, RaftNetwork, RaftStorage};
+use crate::core::{LeaderState, RaftCore, State, UpdateCurrentLeader};
+use crate::error::{ClientReadError, ClientWriteError, RaftError, RaftResult, RegisterSessionError};
+use crate::raft::{ClientWriteRequest, ClientWriteResponse, ClientReadResponseTx, ClientWriteTx, Entry, EntryNormal, EntryPayload, RaftMsg};
+use crate::raft::RegisterSessionTx;
+use crate::raft::{AppendEntriesRequest, ReadIndexRequest, ReadIndexResponse, ReadIndexResponseTx};
+use crate::replication::{RaftEvent, entry_size};
 
 /// A wrapper around a ClientRequest which has been transformed into an Entry, along with its response channel.
 pub(super) struct ClientRequestEntry<D: AppData, R: AppDataResponse> {
@@ -35,8 +38,84 @@ impl<D: AppData, R: AppDataResponse> ClientRequestEntry<D, R> {
 /// An enum type wrapping either a client response channel or an internal Raft response channel.
 #[derive(derive_more::From)]
 pub enum ClientOrInternalResponseTx<D: AppData, R: AppDataResponse> {
-    Client(ClientWriteResponseTx<D, R>),
+    Client(ClientWriteTx<D, R>),
     Internal(oneshot::Sender<Result<u64, RaftError>>),
+    /// The response channel of a request to register a client session.
+    Session(RegisterSessionTx),
+}
+
+impl<D: AppData, R: AppDataResponse> ClientOrInternalResponseTx<D, R> {
+    /// Notify a client write that its entry at `index` has been committed.
+    pub(super) fn send_committed(&mut self, index: u64) {
+        if let Self::Client(tx) = self {
+            tx.send_committed(index);
+        }
+    }
+}
+
+/// An enum type wrapping either a client read response channel or a ReadIndex RPC response channel.
+#[derive(derive_more::From)]
+pub(super) enum ReadResponseTx {
+    /// A client read submitted to this node.
+    Client(ClientReadResponseTx),
+    /// A ReadIndex RPC submitted by a follower or non-voter on behalf of its own client read.
+    Peer(ReadIndexResponseTx),
+}
+
+impl ReadResponseTx {
+    /// Respond with the given read index, which has been confirmed by the leader `leader_id`.
+    fn send_read_index(self, read_index: u64, term: u64, leader_id: NodeId) {
+        match self {
+            Self::Client(tx) => {
+                let _ = tx.send(Ok(read_index));
+            }
+            Self::Peer(tx) => {
+                let _ = tx.send(Ok(ReadIndexResponse{term, read_index: Some(read_index), leader_id: Some(leader_id)}));
+            }
+        }
+    }
+
+    /// Respond with a hint to retry the read against the given leader, if known.
+    fn send_forward_to_leader(self, term: u64, leader_id: Option<NodeId>) {
+        match self {
+            Self::Client(tx) => {
+                let _ = tx.send(Err(ClientReadError::ForwardToLeader(leader_id)));
+            }
+            Self::Peer(tx) => {
+                let _ = tx.send(Ok(ReadIndexResponse{term, read_index: None, leader_id}));
+            }
+        }
+    }
+
+    /// Respond with the given error.
+    fn send_error(self, err: RaftError) {
+        match self {
+            Self::Client(tx) => {
+                let _ = tx.send(Err(ClientReadError::RaftError(err)));
+            }
+            Self::Peer(tx) => {
+                let _ = tx.send(Err(err));
+            }
+        }
+    }
+}
+
+/// A batch of reads which share a read index.
+pub(super) struct ReadBatch {
+    /// The index which must be applied to the state machine before the reads may be resolved.
+    pub read_index: u64,
+    /// The response channels of the reads in this batch.
+    pub txs: Vec<ReadResponseTx>,
+}
+
+/// The outcome of a round of leadership confirmation for a batch of client reads.
+pub(super) enum ReadConfirmation {
+    /// A majority of each config group has confirmed this node's leadership.
+    Confirmed,
+    /// A node responded with a greater term, so this node is no longer the leader.
+    NewerTerm(u64),
+    /// Too many requests failed for leadership to be confirmed.
+    Failed,
 }
 
 impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> LeaderState<'a, D, R, N, S> {
@@ -72,9 +151,10 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
         let (tx_payload_committed, rx_payload_committed) = oneshot::channel();
         let entry = self.append_payload_to_log(req.entry).await?;
         self.core.last_log_term = self.core.current_term; // This only ever needs to be updated once per term.
+        self.initial_entry_index = entry.index;
         let cr_entry = ClientRequestEntry::from_entry(entry, tx_payload_committed);
         self.replicate_client_request(cr_entry).await;
-        self.core.report_metrics();
+        self.report_metrics();
 
         // Setup any callbacks needed for responding to commitment of a pending config.
         if let Some(is_in_join_consensus) = pending_config {
@@ -87,110 +167,340 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
         Ok(())
     }
 
-    /// Handle client read requests.
+    /// Handle client read requests (§6.4 of the Raft thesis).
     ///
-    /// Spawn requests to all members of the cluster, include members being added in joint
-    /// consensus. Each request will have a timeout, and we respond once we have a majority
-    /// agreement from each config group. Most of the time, we will have a single uniform
-    /// config group.
+    /// Reads are batched. Each batch records a read index, and leadership is confirmed once for
+    /// the whole batch, either via this node's leader lease (if enabled) or via a round of
+    /// heartbeats to all members of the cluster. Once leadership has been confirmed and the read
+    /// index has been applied to the state machine, every read in the batch is resolved with the
+    /// read index. Only one round of leadership confirmation is in flight at a time, reads which
+    /// arrive in the meantime are batched for the next round.
     #[tracing::instrument(level="trace", skip(self, tx))]
-    pub(super) async fn handle_client_read_request(&mut self, tx: ClientReadResponseTx) {
-        // Setup sentinel values to track when we've received majority confirmation of leadership.
-        let len_members = self.core.membership.members.len();
-        let mut c0_confirmed = 0usize;
-        let c0_needed: usize = if (len_members % 2) == 0 { (len_members/2)-1 } else { len_members/2 };
-        let mut c1_confirmed = 0usize;
-        let mut c1_needed = 0usize;
-        if let Some(joint_members) = &self.core.membership.members_after_consensus {
-            let len = joint_members.len(); // Will never be zero, as we don't allow it when proposing config changes.
-            c1_needed = if (len % 2) == 0 { (len/2)-1 } else { len/2 };
+    pub(super) fn handle_client_read_request(&mut self, tx: ClientReadResponseTx) {
+        self.pending_reads.push(tx.into());
+        if self.confirming_reads.is_none() {
+            self.start_read_batch();
+        }
+    }
+
+    /// Handle a ReadIndex RPC from a follower or non-voter (§6.4 of the Raft thesis).
+    ///
+    /// The request joins the next batch of reads. Once leadership has been confirmed for the
+    /// batch, the requester is sent the batch's read index right away, as it is the requester
+    /// which must wait for the read index to be applied to its own state machine.
+    #[tracing::instrument(level="trace", skip(self, rpc, tx), fields(term=rpc.term, requester_id=rpc.requester_id))]
+    pub(super) async fn handle_read_index_request(&mut self, rpc: ReadIndexRequest, tx: ReadIndexResponseTx) -> RaftResult<()> {
+        // If the requester has a greater term, then this node is no longer the leader.
+        if rpc.term > self.core.current_term {
+            self.core.update_current_term(rpc.term, None);
+            self.core.save_hard_state().await?;
+            self.core.update_current_leader(UpdateCurrentLeader::Unknown);
+            self.core.set_target_state(State::Follower);
+            self.core.reject_read_index_not_leader(tx);
+            return Ok(());
+        }
+        self.pending_reads.push(tx.into());
+        if self.confirming_reads.is_none() {
+            self.start_read_batch();
+        }
+        Ok(())
+    }
+
+    /// Move all pending reads into a new batch and begin confirming leadership for the batch.
+    #[tracing::instrument(level="trace", skip(self))]
+    fn start_read_batch(&mut self) {
+        if self.pending_reads.is_empty() {
+            return;
+        }
+        // The read index must include the initial entry of this term, as the commit index will
+        // not be up-to-date until that entry has been committed (§6.4).
+        let read_index = std::cmp::max(self.core.commit_index, self.initial_entry_index);
+        let batch = ReadBatch{read_index, txs: std::mem::take(&mut self.pending_reads)};
+        if self.core.config.leader_lease && self.has_valid_lease() {
+            self.confirm_read_batch(batch);
+            return;
         }
+        self.confirming_reads = Some(batch);
+        self.spawn_leadership_confirmation();
+    }
+
+    /// Spawn a round of heartbeats to all members of the cluster, including members being added in
+    /// joint consensus, in order to confirm that this node is still the cluster leader.
+    ///
+    /// Each request will have a timeout, and leadership is confirmed once we have a majority
+    /// agreement from each config group. Most of the time, we will have a single uniform config
+    /// group. The outcome is sent to the leader over its read confirmation channel.
+    #[tracing::instrument(level="trace", skip(self))]
+    fn spawn_leadership_confirmation(&self) {
+        let members = self.core.membership.members.clone();
+        let members_after_consensus = self.core.membership.members_after_consensus.clone();
+        let is_confirmed = move |confirmed: &HashSet<NodeId>| {
+            is_majority(&members, confirmed) && members_after_consensus.as_ref().map(|members| is_majority(members, confirmed)).unwrap_or(true)
+        };
 
-        // As long as we are not about to step down, then increment for our vote.
+        // As long as we are not about to step down, then count our own vote.
+        let mut confirmed = HashSet::new();
         if !self.is_stepping_down {
-            if self.core.membership.members.contains(&self.core.id) {
-                c0_confirmed += 1;
-            }
-            if self.core.membership.members_after_consensus.as_ref().map(|members| members.contains(&self.core.id)).unwrap_or(false) {
-                c1_confirmed += 1;
-            }
+            confirmed.insert(self.core.id);
         }
 
-        // Spawn parallel requests, all with the standard timeout for heartbeats.
-        let mut pending = FuturesUnordered::new();
-        for (id, node) in self.nodes.iter() {
-            let rpc = AppendEntriesRequest{
-                term: self.core.current_term,
+        let term = self.core.current_term;
+        let requests = self.nodes.iter()
+            .map(|(id, node)| (*id, AppendEntriesRequest{
+                term,
                 leader_id: self.core.id,
                 prev_log_index: node.match_index,
                 prev_log_term: node.match_term,
                 entries: vec![],
                 leader_commit: self.core.commit_index,
-            };
-            let target = id.clone();
-            let network = self.core.network.clone();
-            let ttl = Duration::from_millis(self.core.config.heartbeat_interval);
-            let task = tokio::spawn(async move {
-                match timeout(ttl, network.append_entries(target, rpc)).await {
-                    Ok(Ok(data)) => Ok((target, data)),
-                    Ok(Err(err)) => Err((target, err)),
-                    Err(_timeout) => Err((target, anyhow!("timeout waiting for leadership confirmation"))),
+                quiesce: false,
+            }))
+            .collect::<Vec<_>>();
+        let network = self.core.network.clone();
+        let tx = self.tx_read_confirmation.clone();
+        let ttl = Duration::from_millis(self.core.config.heartbeat_interval);
+        tokio::spawn(async move {
+            if is_confirmed(&confirmed) {
+                let _ = tx.send(ReadConfirmation::Confirmed);
+                return;
+            }
+
+            // Send requests in parallel, all with the standard timeout for heartbeats.
+            let mut pending = requests.into_iter()
+                .map(|(target, rpc)| timeout(ttl, network.append_entries(target, rpc)).map(move |res| (target, res)))
+                .collect::<FuturesUnordered<_>>();
+
+            // Handle responses as they return.
+            while let Some((target, res)) = pending.next().await {
+                let data = match res {
+                    Ok(Ok(data)) => data,
+                    Ok(Err(err)) => {
+                        tracing::error!({target, error=%err}, "error while confirming leadership for read request");
+                        continue;
+                    }
+                    Err(_) => {
+                        tracing::error!({target}, "timeout while confirming leadership for read request");
+                        continue;
+                    }
+                };
+
+                // If we receive a response with a greater term, then this node is no longer the leader.
+                if data.term > term {
+                    let _ = tx.send(ReadConfirmation::NewerTerm(data.term));
+                    return;
                 }
-            }).map_err(move |err| (*id, err));
-            pending.push(task);
-        }
 
-        // Handle responses as they return.
-        while let Some(res) = pending.next().await {
-            let (target, data) = match res {
-                Ok(Ok(res)) => res,
-                Ok(Err((target, err))) => {
-                    tracing::error!({target, error=%err}, "timeout while confirming leadership for read request");
-                    continue;
+                // If the term is the same, then it means we are still the leader.
+                confirmed.insert(target);
+                if is_confirmed(&confirmed) {
+                    let _ = tx.send(ReadConfirmation::Confirmed);
+                    return;
+                }
+            }
+
+            // If we've hit this location, then we've failed to gather needed confirmations due to
+            // request failures.
+            let _ = tx.send(ReadConfirmation::Failed);
+        }.instrument(tracing::trace_span!("confirming leadership for read requests")));
+    }
+
+    /// Handle the outcome of a round of leadership confirmation for the in-flight batch of reads.
+    #[tracing::instrument(level="trace", skip(self, res))]
+    pub(super) async fn handle_read_confirmation(&mut self, res: ReadConfirmation) -> RaftResult<()> {
+        let batch = match self.confirming_reads.take() {
+            Some(batch) => batch,
+            None => return Ok(()),
+        };
+        match res {
+            ReadConfirmation::Confirmed => self.confirm_read_batch(batch),
+            ReadConfirmation::NewerTerm(term) => {
+                for tx in batch.txs {
+                    tx.send_forward_to_leader(term, None);
                 }
-                Err((target, err)) => {
-                    tracing::error!({target}, "{}", err);
-                    continue;
+                if term > self.core.current_term {
+                    self.core.update_current_term(term, None);
+                    self.core.save_hard_state().await?;
+                    self.core.update_current_leader(UpdateCurrentLeader::Unknown);
+                    self.core.set_target_state(State::Follower);
                 }
-            };
+                return Ok(());
+            }
+            ReadConfirmation::Failed => {
+                for tx in batch.txs {
+                    tx.send_error(RaftError::RaftNetwork(anyhow!("too many requests failed, could not confirm leadership")));
+                }
+            }
+        }
 
-            // If we receive a response with a greater term, then revert to follower and abort this request.
-            if &data.term != &self.core.current_term {
-                self.core.update_current_term(data.term, None);
-                self.core.set_target_state(State::Follower);
+        // Start the next batch with any reads which have queued up in the meantime.
+        self.start_read_batch();
+        Ok(())
+    }
+
+    /// Handle a batch of reads for which leadership has been confirmed.
+    ///
+    /// ReadIndex RPCs are responded to immediately, while client reads are held until the read
+    /// index has been applied to the state machine.
+    fn confirm_read_batch(&mut self, batch: ReadBatch) {
+        let (term, id) = (self.core.current_term, self.core.id);
+        let mut client_txs = Vec::new();
+        for tx in batch.txs {
+            match tx {
+                ReadResponseTx::Client(_) => client_txs.push(tx),
+                ReadResponseTx::Peer(_) => tx.send_read_index(batch.read_index, term, id),
             }
+        }
+        if !client_txs.is_empty() {
+            self.awaiting_applied_reads.push(ReadBatch{read_index: batch.read_index, txs: client_txs});
+        }
+        self.resolve_applied_reads();
+    }
 
-            // If the term is the same, then it means we are still the leader.
-            if self.core.membership.members.contains(&target) {
-                c0_confirmed += 1;
+    /// Resolve all batches of reads whose read index has been applied to the state machine.
+    #[tracing::instrument(level="trace", skip(self))]
+    pub(super) fn resolve_applied_reads(&mut self) {
+        let (last_applied, term, id) = (self.core.last_applied, self.core.current_term, self.core.id);
+        let ready = self.awaiting_applied_reads.iter()
+            .take_while(|batch| batch.read_index <= last_applied)
+            .count();
+        for batch in self.awaiting_applied_reads.drain(..ready) {
+            for tx in batch.txs {
+                tx.send_read_index(batch.read_index, term, id);
             }
-            if self.core.membership.members_after_consensus.as_ref().map(|members| members.contains(&target)).unwrap_or(false) {
-                c1_confirmed += 1;
+        }
+    }
+
+    /// Reject all outstanding reads as this node is no longer the cluster leader.
+    pub(super) fn reject_outstanding_reads(&mut self) {
+        let (term, leader) = (self.core.current_term, self.core.current_leader);
+        let txs = self.pending_reads.drain(..)
+            .chain(self.confirming_reads.take().into_iter().flat_map(|batch| batch.txs))
+            .chain(self.awaiting_applied_reads.drain(..).flat_map(|batch| batch.txs));
+        for tx in txs {
+            tx.send_forward_to_leader(term, leader);
+        }
+    }
+
+    /// Reject all client writes & config changes which are awaiting commitment, as this node is no longer leader.
+    pub(super) fn reject_outstanding_writes(&mut self) {
+        for req in self.awaiting_committed.drain(..) {
+            match req.tx {
+                ClientOrInternalResponseTx::Client(tx) => {
+                    tx.send(Err(ClientWriteError::RaftError(RaftError::LeadershipLost)));
+                }
+                ClientOrInternalResponseTx::Internal(tx) => {
+                    let _ = tx.send(Err(RaftError::LeadershipLost));
+                }
+                ClientOrInternalResponseTx::Session(tx) => {
+                    let _ = tx.send(Err(RegisterSessionError::RaftError(RaftError::LeadershipLost)));
+                }
             }
-            if &c0_confirmed >= &c0_needed && &c1_confirmed >= &c1_needed {
-                let _ = tx.send(Ok(()));
-                return;
+        }
+        if let Some(tx) = self.propose_config_change_cb.take() {
+            let _ = tx.send(Err(RaftError::LeadershipLost));
+        }
+        // Client writes which were held back from a drained batch were never appended, so they are forwarded.
+        match self.next_api_msg.take() {
+            Some(RaftMsg::ClientWriteRequest{rpc, tx}) => self.core.forward_client_write_request(rpc, tx),
+            Some(RaftMsg::ClientWriteBatch{requests}) => {
+                for (rpc, tx) in requests {
+                    self.core.forward_client_write_request(rpc, tx);
+                }
             }
+            msg => self.next_api_msg = msg,
         }
+    }
+
+    /// Check if this node currently holds a valid leader lease.
+    ///
+    /// The lease is held for `Config::leader_lease_duration` from the send time of the most recent
+    /// heartbeat acknowledged by a majority of each config group. It is never valid while a
+    /// leadership transfer is in progress, as the target will campaign without waiting out its
+    /// election timeout.
+    fn has_valid_lease(&self) -> bool {
+        if self.leadership_transfer.is_some() {
+            return false;
+        }
+        let lease = Duration::from_millis(self.core.config.leader_lease_duration());
+        self.quorum_ack().map(|ack| ack + lease > Instant::now()).unwrap_or(false)
+    }
 
-        // If we've hit this location, then we've failed to gather needed confirmations due to
-        // request failures.
-        let _ = tx.send(Err(ClientReadError::RaftError(
-            RaftError::RaftNetwork(anyhow!("too many requests failed, could not confirm leadership"))
-        )));
+    /// Drain any client write requests which are queued up behind the given requests, so that
+    /// they may all be handled as a single batch.
+    ///
+    /// Draining stops at the first API message which is not a client write, which is then held
+    /// to be handled next, or once the batch has reached `Config::max_payload_entries` requests.
+    /// Any requests beyond that limit, such as the tail of a large `ClientWriteBatch`, are held
+    /// to be handled next as a batch of their own.
+    pub(super) fn drain_client_write_requests(
+        &mut self, mut requests: Vec<(ClientWriteRequest<D>, ClientWriteTx<D, R>)>,
+    ) -> Vec<(ClientWriteRequest<D>, ClientWriteTx<D, R>)> {
+        let max_payload_entries = self.core.config.max_payload_entries as usize;
+        while requests.len() < max_payload_entries {
+            match self.core.rx_api.try_recv() {
+                Ok(RaftMsg::ClientWriteRequest{rpc, tx}) => requests.push((rpc, tx)),
+                Ok(RaftMsg::ClientWriteBatch{requests: batch}) => requests.extend(batch),
+                Ok(msg) => {
+                    self.next_api_msg = Some(msg);
+                    break;
+                }
+                Err(_) => break,
+            }
+        }
+        if requests.len() > max_payload_entries {
+            let excess = requests.split_off(max_payload_entries);
+            self.next_api_msg = Some(RaftMsg::ClientWriteBatch{requests: excess});
+        }
+        requests
     }
 
-    /// Handle client write requests.
-    #[tracing::instrument(level="trace", skip(self, rpc, tx))]
-    pub(super) async fn handle_client_write_request(&mut self, rpc: ClientWriteRequest<D>, tx: ClientWriteResponseTx<D, R>) {
-        let entry = match self.append_payload_to_log(rpc.entry).await {
-            Ok(entry) => ClientRequestEntry::from_entry(entry, tx),
+    /// Handle a batch of client write requests.
+    ///
+    /// All entries of the batch are appended to the log with a single call to storage, and are
+    /// then handed to the replication streams together.
+    #[tracing::instrument(level="trace", skip(self, requests), fields(count=requests.len()))]
+    pub(super) async fn handle_client_write_requests(&mut self, requests: Vec<(ClientWriteRequest<D>, ClientWriteTx<D, R>)>) {
+        // Client writes are not accepted while leadership is being transferred (§3.10 of the Raft thesis).
+        if self.leadership_transfer.is_some() {
+            for (rpc, tx) in requests {
+                tx.send(Err(ClientWriteError::ForwardToLeader(rpc, None)));
+            }
+            return;
+        }
+        self.touch_client_sessions(requests.iter().map(|(rpc, _)| rpc));
+        let (payloads, txs): (Vec<_>, Vec<_>) = requests.into_iter().map(|(rpc, tx)| (rpc.entry, tx)).unzip();
+        let entries = match self.append_payloads_to_log(payloads).await {
+            Ok(entries) => entries,
             Err(err) => {
-                let _ = tx.send(Err(ClientWriteError::RaftError(err)));
+                for tx in txs {
+                    tx.send(Err(ClientWriteError::RaftError(err.duplicate())));
+                }
                 return;
             }
         };
-        self.replicate_client_request(entry).await;
+        let entries = entries.into_iter().zip(txs)
+            .map(|(entry, mut tx)| {
+                tx.send_appended(entry.index, entry.term);
+                ClientRequestEntry::from_entry(entry, tx)
+            })
+            .collect();
+        self.replicate_client_requests(entries).await;
+    }
+
+    /// Transform the given payloads into entries, assign an index and term to each, and append
+    /// the entries to the log as a single batch.
+    #[tracing::instrument(level="trace", skip(self, payloads))]
+    pub(super) async fn append_payloads_to_log(&mut self, payloads: Vec<EntryPayload<D>>) -> RaftResult<Vec<Entry<D>>> {
+        let (start, term) = (self.core.last_log_index + 1, self.core.current_term);
+        let entries = payloads.into_iter().enumerate()
+            .map(|(offset, payload)| Entry{index: start + offset as u64, term, payload})
+            .collect::<Vec<_>>();
+        if entries.is_empty() {
+            return Ok(entries);
+        }
+        self.core.storage.replicate_to_log(&entries).await.map_err(|err| self.core.map_fatal_storage_error(err))?;
+        self.core.last_log_index = start + entries.len() as u64 - 1;
+        Ok(entries)
     }
 
     /// Transform the given payload into an entry, assign an index and term, and append the entry to the log.
@@ -209,29 +519,48 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
     /// be generated asynchronously.
     #[tracing::instrument(level="trace", skip(self, req))]
     pub(super) async fn replicate_client_request(&mut self, req: ClientRequestEntry<D, R>) {
-        // Replicate the request if there are other cluster members. The client response will be
-        // returned elsewhere after the entry has been committed to the cluster.
-        let entry_arc = req.entry.clone();
+        self.replicate_client_requests(vec![req]).await;
+    }
+
+    /// Begin the process of replicating the given batch of client requests.
+    ///
+    /// The requests must be in log order. Each replication stream is notified of the whole batch
+    /// at once. See `replicate_client_request` for more details.
+    #[tracing::instrument(level="trace", skip(self, reqs))]
+    pub(super) async fn replicate_client_requests(&mut self, mut reqs: Vec<ClientRequestEntry<D, R>>) {
+        let entry_arcs = reqs.iter().map(|req| (req.entry.clone(), entry_size(&req.entry))).collect::<Vec<_>>();
+        let last_index = match entry_arcs.last() {
+            Some((entry, _)) => entry.index,
+            None => return,
+        };
+
+        // Replicate the requests if there are other cluster members. The client responses will be
+        // returned elsewhere after the entries have been committed to the cluster.
         if !self.nodes.is_empty() {
-            self.awaiting_committed.push(req);
+            self.awaiting_committed.extend(reqs);
             for node in self.nodes.values() {
                 let _ = node.replstream.repltx.send(RaftEvent::Replicate{
-                    entry: entry_arc.clone(),
+                    entries: entry_arcs.clone(),
                     commit_index: self.core.commit_index,
                 });
             }
         } else {
-            // Else, there are no voting nodes for replication, so the payload is now committed.
-            self.core.commit_index = entry_arc.index;
-            self.core.report_metrics();
-            self.client_request_post_commit(req).await;
+            // Else, there are no voting nodes for replication, so the payloads are now committed.
+            self.core.commit_index = last_index;
+            self.report_metrics();
+            for req in reqs.iter_mut() {
+                req.tx.send_committed(req.entry.index);
+            }
+            for req in reqs {
+                self.client_request_post_commit(req).await;
+            }
         }
 
         // Replicate to non-voters.
         if !self.non_voters.is_empty() {
             for node in self.non_voters.values() {
                 let _ = node.state.replstream.repltx.send(RaftEvent::Replicate{
-                    entry: entry_arc.clone(),
+                    entries: entry_arcs.clone(),
                     commit_index: self.core.commit_index,
                 });
             }
@@ -240,17 +569,18 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
 
     /// Handle the post-commit logic for a client request.
     #[tracing::instrument(level="trace", skip(self, req))]
-    pub(super) async fn client_request_post_commit(&mut self, req: ClientRequestEntry<D, R>) {
+    pub(super) async fn client_request_post_commit(&mut self, mut req: ClientRequestEntry<D, R>) {
+        req.tx.send_committed(req.entry.index);
         match req.tx {
             // If this is a client response channel, then it means that we are dealing with
             ClientOrInternalResponseTx::Client(tx) => match &req.entry.payload {
                 EntryPayload::Normal(inner) => {
-                    match self.apply_entry_to_state_machine(&req.entry.index, &inner.data).await {
-                        Ok(data) => {
-                            let _ = tx.send(Ok(ClientWriteResponse{index: req.entry.index, data}));
+                    match self.apply_entry_to_state_machine(&req.entry.index, inner).await {
+                        Ok(res) => {
+                            tx.send(res);
                         }
                         Err(err) => {
-                            let _ = tx.send(Err(ClientWriteError::RaftError(RaftError::from(err))));
+                            tx.send(Err(ClientWriteError::RaftError(RaftError::from(err))));
                         }
                     }
                 }
@@ -264,45 +594,133 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
                 }
             }
             ClientOrInternalResponseTx::Internal(tx) => {
-                self.core.last_applied = req.entry.index;
-                self.core.report_metrics();
-                let _ = tx.send(Ok(req.entry.index));
+                let _ = tx.send(self.apply_internal_entry(&req.entry).await);
+            }
+            ClientOrInternalResponseTx::Session(tx) => {
+                let _ = tx.send(self.apply_internal_entry(&req.entry).await.map_err(RegisterSessionError::from));
             }
         }
 
+        // Resolve any reads which were awaiting this entry to be applied.
+        self.resolve_applied_reads();
+
         // Trigger log compaction if needed.
         self.core.trigger_log_compaction_if_needed();
     }
 
     /// Apply the given log entry to the state machine.
+    ///
+    /// If the entry is part of a client session, then the response may be the cached response of
+    /// an earlier attempt, or an error rejecting the entry.
     #[tracing::instrument(level="trace", skip(self, entry))]
-    pub(super) async fn apply_entry_to_state_machine(&mut self, index: &u64, entry: &D) -> RaftResult<R> {
+    pub(super) async fn apply_entry_to_state_machine(&mut self, index: &u64, entry: &EntryNormal<D>) -> RaftResult<Result<ClientWriteResponse<R>, ClientWriteError<D>>> {
         // First, we just ensure that we apply any outstanding up to, but not including, the index
         // of the given entry. We need to be able to return the data response from applying this
         // entry to the state machine.
-        //
-        // Note that this would only ever happen if a node had unapplied logs from before becoming leader.
+        self.apply_outstanding_entries(*index).await?;
+
+        // Apply this entry to the state machine and return its data response.
+        let res = self.core.apply_normal_entry(*index, entry).await?;
+        if entry.session.is_some() {
+            self.core.save_client_sessions().await?;
+        }
+        self.core.last_applied = *index;
+        self.report_metrics();
+        Ok(res)
+    }
+
+    /// Apply an entry which was not submitted by a client, such as a config change or the
+    /// registration of a client session, returning its index.
+    #[tracing::instrument(level="trace", skip(self, entry))]
+    async fn apply_internal_entry(&mut self, entry: &Entry<D>) -> RaftResult<u64> {
+        self.apply_outstanding_entries(entry.index).await?;
+        self.core.apply_entries_to_state_machine(std::slice::from_ref(entry)).await?;
+        self.core.last_applied = entry.index;
+        self.report_metrics();
+        self.core.emit_committed_configs(std::iter::once(entry));
+        Ok(entry.index)
+    }
+
+    /// Apply any outstanding entries to the state machine, up to, but not including, the given index.
+    ///
+    /// Note that this would only ever happen if a node had unapplied logs from before becoming leader.
+    #[tracing::instrument(level="trace", skip(self))]
+    async fn apply_outstanding_entries(&mut self, index: u64) -> RaftResult<()> {
         let expected_next_index = self.core.last_applied + 1;
-        if index != &expected_next_index {
-            let entries = self.core.storage.get_log_entries(expected_next_index, *index).await.map_err(|err| self.core.map_fatal_storage_error(err))?;
+        if index > expected_next_index {
+            let entries = self.core.storage.get_log_entries(expected_next_index, index).await.map_err(|err| self.core.map_fatal_storage_error(err))?;
             if let Some(entry) = entries.last() {
                 self.core.last_applied = entry.index;
             }
-            let data_entries: Vec<_> = entries.iter()
-                .filter_map(|entry| match &entry.payload {
-                    EntryPayload::Normal(inner) => Some((&entry.index, &inner.data)),
-                    _ => None,
-                })
-                .collect();
-            if !data_entries.is_empty() {
-                self.core.storage.replicate_to_state_machine(&data_entries).await.map_err(|err| self.core.map_fatal_storage_error(err))?;
-            }
+            self.core.emit_committed_configs(&entries);
+            self.core.apply_entries_to_state_machine(&entries).await?;
         }
+        Ok(())
+    }
+}
 
-        // Apply this entry to the state machine and return its data response.
-        let res = self.core.storage.apply_entry_to_state_machine(index, entry).await.map_err(|err| self.core.map_fatal_storage_error(err))?;
-        self.core.last_applied = *index;
-        self.core.report_metrics();
-        Ok(res)
+impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> RaftCore<D, R, N, S> {
+    /// Handle a client read request on a follower or non-voter (§6.4 of the Raft thesis).
+    ///
+    /// The read index is requested from the cluster leader via a ReadIndex RPC. Once this node's
+    /// state machine has applied the read index, the read is resolved with it. If the leader is
+    /// not known, or the read index can not be obtained or applied within an election timeout,
+    /// then the read is forwarded to the leader.
+    #[tracing::instrument(level="trace", skip(self, tx))]
+    pub(super) fn handle_follower_read_request(&self, tx: ClientReadResponseTx) {
+        let leader = match self.current_leader {
+            Some(leader) if leader != self.id => leader,
+            _ => return self.forward_client_read_request(tx),
+        };
+        let rpc = ReadIndexRequest{term: self.current_term, requester_id: self.id};
+        let network = self.network.clone();
+        let mut rx_metrics = self.rx_metrics.clone();
+        let ttl = Duration::from_millis(self.config.election_timeout_max);
+        tokio::spawn(async move {
+            let read_index = match timeout(ttl, network.read_index(leader, rpc)).await {
+                Ok(Ok(ReadIndexResponse{read_index: Some(read_index), ..})) => read_index,
+                Ok(Ok(res)) => {
+                    let _ = tx.send(Err(ClientReadError::ForwardToLeader(res.leader_id)));
+                    return;
+                }
+                Ok(Err(err)) => {
+                    tracing::error!({leader, error=%err}, "error while requesting read index from leader");
+                    let _ = tx.send(Err(ClientReadError::RaftError(RaftError::RaftNetwork(err))));
+                    return;
+                }
+                Err(_) => {
+                    tracing::error!({leader}, "timeout while requesting read index from leader");
+                    let _ = tx.send(Err(ClientReadError::ForwardToLeader(Some(leader))));
+                    return;
+                }
+            };
+
+            // Wait for the state machine to catch up to the read index. The current metrics must be
+            // checked first, as `recv` only yields metrics which have not yet been observed.
+            let applied = timeout(ttl, async move {
+                if rx_metrics.borrow().last_applied >= read_index {
+                    return true;
+                }
+                while let Some(metrics) = rx_metrics.recv().await {
+                    if metrics.last_applied >= read_index {
+                        return true;
+                    }
+                }
+                false
+            }).await;
+            match applied {
+                Ok(true) => {
+                    let _ = tx.send(Ok(read_index));
+                }
+                _ => {
+                    let _ = tx.send(Err(ClientReadError::ForwardToLeader(Some(leader))));
+                }
+            }
+        }.instrument(tracing::trace_span!("requesting read index from leader")));
     }
 }
+
+/// Check if the given set of nodes contains a majority of the given members.
+fn is_majority(members: &HashSet<NodeId>, confirmed: &HashSet<NodeId>) -> bool {
+    members.iter().filter(|id| confirmed.contains(id)).count() > members.len() / 2
+}
@@ -1,11 +1,11 @@
-use std::io::SeekFrom;
-
-use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use tokio::io::AsyncWriteExt;
+use tokio::time::Instant;
 
 use crate::{AppData, AppDataResponse, RaftNetwork, RaftStorage};
 use crate::core::{State, RaftCore, SnapshotState, UpdateCurrentLeader};
 use crate::error::RaftResult;
-use crate::raft::{InstallSnapshotRequest, InstallSnapshotResponse};
+use crate::event::Event;
+use crate::raft::{InstallSnapshotRequest, InstallSnapshotResponse, MembershipConfig};
 
 impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> RaftCore<D, R, N, S> {
     /// Invoked by leader to send chunks of a snapshot to a follower (§7).
@@ -17,11 +17,12 @@ impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> Ra
     pub(super) async fn handle_install_snapshot_request(&mut self, req: InstallSnapshotRequest) -> RaftResult<InstallSnapshotResponse> {
         // If message's term is less than most recent term, then we do not honor the request.
         if &req.term < &self.current_term {
-            return Ok(InstallSnapshotResponse{term: self.current_term});
+            return Ok(InstallSnapshotResponse{term: self.current_term, offset: 0});
         }
 
-        // Update election timeout.
+        // Update election timeout & record the heartbeat.
         self.update_next_election_timeout();
+        self.last_heartbeat = Some(Instant::now());
 
         // Update current term if needed.
         let mut report_metrics = false;
@@ -46,54 +47,83 @@ impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> Ra
             self.report_metrics();
         }
 
+        // Witnesses keep no state machine, so their snapshots only carry metadata.
+        if let Some(membership) = req.membership.clone() {
+            return self.install_witness_snapshot(req, membership).await;
+        }
+
         // Compare current snapshot state with received RPC and handle as needed.
         match self.snapshot_state.take() {
-            None => Ok(self.begin_installing_snapshot(req).await?),
+            None => self.begin_installing_snapshot(req).await,
             Some(SnapshotState::Snapshotting{handle, ..}) => {
                 handle.abort(); // Abort the current compaction in favor of installation from leader.
-                Ok(self.begin_installing_snapshot(req).await?)
+                self.begin_installing_snapshot(req).await
+            }
+            // If this chunk belongs to the snapshot being streamed, then continue the stream. This
+            // holds even if the leader has changed, so that interrupted streams may be resumed.
+            Some(SnapshotState::Streaming{snapshot, id, snapshot_id, offset}) if snapshot_id == req.snapshot_id => {
+                self.continue_installing_snapshot(req, offset, id, snapshot_id, snapshot).await
             }
-            Some(SnapshotState::Streaming{snapshot, id, offset}) => Ok(self.continue_installing_snapshot(req, offset, id, snapshot).await?),
+            // Else, the leader is streaming a different snapshot, which supersedes the current one.
+            Some(SnapshotState::Streaming{..}) => self.begin_installing_snapshot(req).await,
         }
     }
 
     #[tracing::instrument(level="trace", skip(self, req))]
     async fn begin_installing_snapshot(&mut self, req: InstallSnapshotRequest) -> RaftResult<InstallSnapshotResponse> {
+        // A new snapshot stream must begin with the first chunk of the snapshot.
+        if req.offset != 0 {
+            // If the snapshot has already been installed, then this is a retransmission of the
+            // final chunk, whose response was lost, so report the stream as being complete.
+            if req.done && self.snapshot_index >= req.last_included_index {
+                return Ok(InstallSnapshotResponse{term: self.current_term, offset: req.offset + req.data.len() as u64});
+            }
+            return Ok(InstallSnapshotResponse{term: self.current_term, offset: 0});
+        }
+        if InstallSnapshotRequest::checksum(&req.data) != req.checksum {
+            tracing::warn!({snapshot_id=%req.snapshot_id, req.offset}, "snapshot chunk failed checksum verification");
+            return Ok(InstallSnapshotResponse{term: self.current_term, offset: 0});
+        }
+
         // Create a new snapshot and begin writing its contents.
         let (id, mut snapshot) = self.storage.create_snapshot().await
             .map_err(|err| self.map_fatal_storage_error(err))?;
         snapshot.as_mut().write_all(&req.data).await?;
+        let offset = req.data.len() as u64;
 
         // If this was a small snapshot, and it is already done, then finish up.
         if req.done {
             self.finalize_snapshot_installation(req, id, snapshot).await?;
-            return Ok(InstallSnapshotResponse{term: self.current_term});
+            return Ok(InstallSnapshotResponse{term: self.current_term, offset});
         }
 
         // Else, retain snapshot components for later segments & respod.
         self.snapshot_state = Some(SnapshotState::Streaming{
-            offset: req.data.len() as u64,
-            id, snapshot,
+            offset, id, snapshot_id: req.snapshot_id, snapshot,
         });
-        return Ok(InstallSnapshotResponse{term: self.current_term});
+        Ok(InstallSnapshotResponse{term: self.current_term, offset})
     }
 
     #[tracing::instrument(level="trace", skip(self, req, offset, snapshot))]
     async fn continue_installing_snapshot(
-        &mut self, req: InstallSnapshotRequest, mut offset: u64, id: String, mut snapshot: Box<S::Snapshot>,
+        &mut self, req: InstallSnapshotRequest, mut offset: u64, id: String, snapshot_id: String, mut snapshot: Box<S::Snapshot>,
     ) -> RaftResult<InstallSnapshotResponse> {
-        // Always seek to the target offset if not an exact match.
-        if &req.offset != &offset {
-            if let Err(err) = snapshot.as_mut().seek(SeekFrom::Start(req.offset)).await {
-                self.snapshot_state = Some(SnapshotState::Streaming{offset, id, snapshot});
-                return Err(err.into());
-            }
-            offset = req.offset;
+        // Only write the chunk if it is the next expected chunk and it is intact. Else, report the
+        // expected offset, from which the leader will continue streaming.
+        if req.offset != offset {
+            tracing::debug!({%snapshot_id, req.offset, offset}, "snapshot chunk does not match expected offset");
+            self.snapshot_state = Some(SnapshotState::Streaming{offset, id, snapshot_id, snapshot});
+            return Ok(InstallSnapshotResponse{term: self.current_term, offset});
+        }
+        if InstallSnapshotRequest::checksum(&req.data) != req.checksum {
+            tracing::warn!({%snapshot_id, req.offset}, "snapshot chunk failed checksum verification");
+            self.snapshot_state = Some(SnapshotState::Streaming{offset, id, snapshot_id, snapshot});
+            return Ok(InstallSnapshotResponse{term: self.current_term, offset});
         }
 
         // Write the next segment & update offset.
         if let Err(err) = snapshot.as_mut().write_all(&req.data).await {
-            self.snapshot_state = Some(SnapshotState::Streaming{offset, id, snapshot});
+            self.snapshot_state = Some(SnapshotState::Streaming{offset, id, snapshot_id, snapshot});
             return Err(err.into());
         }
         offset += req.data.len() as u64;
@@ -102,9 +132,36 @@ impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> Ra
         if req.done {
             self.finalize_snapshot_installation(req, id, snapshot).await?;
         } else {
-            self.snapshot_state = Some(SnapshotState::Streaming{offset, id, snapshot});
+            self.snapshot_state = Some(SnapshotState::Streaming{offset, id, snapshot_id, snapshot});
         }
-        return Ok(InstallSnapshotResponse{term: self.current_term});
+        Ok(InstallSnapshotResponse{term: self.current_term, offset})
+    }
+
+    /// Install a metadata-only snapshot, as sent to witnesses.
+    ///
+    /// Any errors which come up from this routine will cause the Raft node to go into shutdown.
+    #[tracing::instrument(level="trace", skip(self, req, membership))]
+    async fn install_witness_snapshot(&mut self, req: InstallSnapshotRequest, membership: MembershipConfig) -> RaftResult<InstallSnapshotResponse> {
+        if let Some(SnapshotState::Snapshotting{handle, ..}) = self.snapshot_state.take() {
+            handle.abort(); // Abort the current compaction in favor of installation from leader.
+        }
+        let delete_through = if &self.last_log_index > &req.last_included_index {
+            Some(req.last_included_index)
+        } else {
+            None
+        };
+        self.storage.install_witness_snapshot(req.last_included_index, req.last_included_term, delete_through, membership).await
+            .map_err(|err| self.map_fatal_storage_error(err))?;
+        let membership = self.storage.get_membership_config().await.map_err(|err| self.map_fatal_storage_error(err))?;
+        self.update_membership(membership)?;
+        self.load_client_sessions().await?;
+        self.last_log_index = req.last_included_index;
+        self.last_log_term = req.last_included_term;
+        self.last_applied = req.last_included_index;
+        self.snapshot_index = req.last_included_index;
+        self.report_metrics();
+        self.emit_event(Event::SnapshotInstalled{index: req.last_included_index, term: req.last_included_term});
+        Ok(InstallSnapshotResponse{term: self.current_term, offset: 0})
     }
 
     /// Finalize the installation of a new snapshot.
@@ -122,10 +179,13 @@ impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> Ra
             .map_err(|err| self.map_fatal_storage_error(err))?;
         let membership = self.storage.get_membership_config().await.map_err(|err| self.map_fatal_storage_error(err))?;
         self.update_membership(membership)?;
+        self.load_client_sessions().await?;
         self.last_log_index = req.last_included_index;
         self.last_log_term = req.last_included_term;
         self.last_applied = req.last_included_index;
         self.snapshot_index = req.last_included_index;
+        self.report_metrics();
+        self.emit_event(Event::SnapshotInstalled{index: req.last_included_index, term: req.last_included_term});
         Ok(())
     }
 }
@@ -5,9 +5,10 @@ mod append_entries;
 mod client;
 mod install_snapshot;
 pub(crate) mod replication;
+mod session;
 mod vote;
 
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 
@@ -21,11 +22,14 @@ use tracing_futures::Instrument;
 
 use crate::{AppData, AppDataResponse, RaftNetwork, RaftStorage, NodeId};
 use crate::config::{Config, SnapshotPolicy};
-use crate::core::client::ClientRequestEntry;
-use crate::error::{ClientReadError, ClientWriteError, ChangeConfigError, InitializeError, RaftError, RaftResult};
-use crate::metrics::RaftMetrics;
-use crate::raft::{ChangeMembershipTx, ClientWriteRequest, ClientReadResponseTx, ClientWriteResponseTx, RaftMsg, MembershipConfig};
+use crate::core::client::{ClientRequestEntry, ReadBatch, ReadConfirmation, ReadResponseTx};
+use crate::error::{ClientReadError, ClientWriteError, ChangeConfigError, InitializeError, LeadershipTransferError, RaftError, RaftResult};
+use crate::event::Event;
+use crate::metrics::{RaftMetrics, ReplicationMetrics, ReplicationStreamState, SnapshotProgress};
+use crate::raft::{ChangeMembershipTx, ClientWriteRequest, ClientReadResponseTx, ClientWriteTx, LeadershipTransferTx, RaftMsg, MembershipConfig};
+use crate::raft::{Entry, EntryPayload, ReadIndexResponse, ReadIndexResponseTx};
 use crate::replication::{RaftEvent, ReplicationStream, ReplicaEvent};
+use crate::session::{ClientSessions, SessionId};
 use crate::storage::HardState;
 
 /// The core type implementing the Raft protocol.
@@ -89,6 +93,18 @@ pub struct RaftCore<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftSt
     last_heartbeat: Option<Instant>,
     /// The duration until the next election timeout.
     next_election_timeout: Option<Instant>,
+    /// A bool indicating if this node has been instructed to campaign as the target of a leadership transfer.
+    ///
+    /// This is set upon receiving a TimeoutNow RPC from the leader, and is reset by the candidate
+    /// when it starts its campaign.
+    is_leadership_transfer_target: bool,
+    /// A bool indicating if this node has been quiesced by the leader, in which case its election
+    /// timer is suspended until it is woken.
+    is_quiescent: bool,
+    /// The replication metrics of each target, which are only populated while this node is leader.
+    replication_metrics: BTreeMap<NodeId, ReplicationMetrics>,
+    /// The table of client sessions, as of the last entry applied to the state machine.
+    client_sessions: ClientSessions<R>,
 
     /// An atomic bool indicating if this node needs to shutdown.
     ///
@@ -98,27 +114,46 @@ pub struct RaftCore<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftSt
     tx_compaction: mpsc::Sender<SnapshotUpdate>,
     rx_compaction: mpsc::Receiver<SnapshotUpdate>,
 
-    rx_api: mpsc::UnboundedReceiver<RaftMsg<D, R>>,
+    rx_api: mpsc::Receiver<RaftMsg<D, R>>,
     tx_metrics: watch::Sender<RaftMetrics>,
+    /// A receiver of this node's own metrics, used by follower reads to await the state machine.
+    rx_metrics: watch::Receiver<RaftMetrics>,
+    tx_events: broadcast::Sender<Event>,
+}
+
+/// The channels over which a `RaftCore` communicates with its `Raft` handle.
+pub(crate) struct RaftCoreChannels<D: AppData, R: AppDataResponse> {
+    /// The receiver of messages from the Raft API.
+    pub rx_api: mpsc::Receiver<RaftMsg<D, R>>,
+    /// The sender of this node's metrics.
+    pub tx_metrics: watch::Sender<RaftMetrics>,
+    /// A receiver of this node's own metrics.
+    pub rx_metrics: watch::Receiver<RaftMetrics>,
+    /// The sender of this node's events.
+    pub tx_events: broadcast::Sender<Event>,
 }
 
 impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> RaftCore<D, R, N, S> {
     pub(crate) fn spawn(
         id: NodeId, config: Arc<Config>, network: Arc<N>, storage: Arc<S>,
-        rx_api: mpsc::UnboundedReceiver<RaftMsg<D, R>>,
-        tx_metrics: watch::Sender<RaftMetrics>,
+        channels: RaftCoreChannels<D, R>,
         needs_shutdown: Arc<AtomicBool>,
     ) -> JoinHandle<RaftResult<()>> {
+        let RaftCoreChannels{rx_api, tx_metrics, rx_metrics, tx_events} = channels;
         let membership = MembershipConfig::new_initial(id); // This is updated from storage in the main loop.
         let (tx_compaction, rx_compaction) = mpsc::channel(1);
+        // With leader leases, a node which has just started may have heard from a leader shortly
+        // before it stopped, so it refuses votes for the election timeout minimum, as if it had
+        // just received a heartbeat.
+        let last_heartbeat = if config.leader_lease { Some(Instant::now()) } else { None };
         let this = Self{
             id, config, membership, network, storage,
             target_state: State::Follower,
             commit_index: 0, last_applied: 0, current_term: 0, current_leader: None, voted_for: None,
             last_log_index: 0, last_log_term: 0,
             snapshot_state: None, snapshot_index: 0,
-            last_heartbeat: None, next_election_timeout: None,
-            tx_compaction, rx_compaction, rx_api, tx_metrics,
+            last_heartbeat, next_election_timeout: None, is_leadership_transfer_target: false,
+            is_quiescent: false, replication_metrics: BTreeMap::new(), client_sessions: ClientSessions::default(), tx_compaction, rx_compaction, rx_api, tx_metrics, rx_metrics, tx_events,
             needs_shutdown,
         };
         tokio::spawn(this.main())
@@ -135,6 +170,7 @@ impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> Ra
         self.voted_for = state.hard_state.voted_for;
         self.membership = state.membership;
         self.last_applied = state.last_applied_log;
+        self.load_client_sessions().await?;
         // NOTE: this is repeated here for clarity. It is unsafe to initialize the node's commit
         // index to any other value. The commit index must be determined by a leader after
         // successfully committing a new log to the cluster.
@@ -166,6 +202,11 @@ impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> Ra
         // controllers and simply awaits the delegated loop to return, which will only take place
         // if some error has been encountered, or if a state change is required.
         loop {
+            // Each state controller returns once shutdown is requested, so the node moves to shutdown here.
+            if self.needs_shutdown.load(Ordering::SeqCst) {
+                self.set_target_state(State::Shutdown);
+                self.report_metrics();
+            }
             match &self.target_state {
                 State::Leader => LeaderState::new(&mut self).run().await?,
                 State::Candidate => CandidateState::new(&mut self).run().await?,
@@ -185,14 +226,31 @@ impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> Ra
             current_term: self.current_term,
             last_log_index: self.last_log_index,
             last_applied: self.last_applied,
+            snapshot_index: self.snapshot_index,
             current_leader: self.current_leader,
             membership_config: self.membership.clone(),
+            replication: self.replication_metrics.clone(),
         });
         if let Err(err) = res {
             tracing::error!({error=%err, id=self.id}, "error reporting metrics");
         }
     }
 
+    /// Emit the given event to all subscribers.
+    fn emit_event(&self, event: Event) {
+        // Sending only fails when there are no subscribers, in which case the event is not needed.
+        let _ = self.tx_events.send(event);
+    }
+
+    /// Emit an event for each membership config carried by the given entries, which have just been committed.
+    fn emit_committed_configs<'a>(&self, entries: impl IntoIterator<Item=&'a Entry<D>>) {
+        for entry in entries {
+            if let EntryPayload::ConfigChange(cfg) = &entry.payload {
+                self.emit_event(Event::MembershipCommitted{index: entry.index, membership: cfg.membership.clone()});
+            }
+        }
+    }
+
     /// Save the Raft node's current hard state to disk.
     #[tracing::instrument(level="trace", skip(self))]
     async fn save_hard_state(&mut self) -> RaftResult<()> {
@@ -228,9 +286,20 @@ impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> Ra
         self.next_election_timeout = Some(Instant::now() + Duration::from_millis(self.config.new_rand_election_timeout()));
     }
 
+    /// Wake this node from quiescence, resuming its election timer.
+    #[tracing::instrument(level="trace", skip(self))]
+    fn wake(&mut self) {
+        if self.is_quiescent {
+            tracing::debug!("waking from quiescence");
+            self.is_quiescent = false;
+            self.update_next_election_timeout();
+        }
+    }
+
     /// Update the value of the `current_leader` property.
     #[tracing::instrument(level="trace", skip(self))]
     fn update_current_leader(&mut self, update: UpdateCurrentLeader) {
+        let previous = self.current_leader;
         match update {
             UpdateCurrentLeader::ThisNode => {
                 self.current_leader = Some(self.id);
@@ -242,6 +311,9 @@ impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> Ra
                 self.current_leader = None;
             },
         }
+        if self.current_leader != previous {
+            self.emit_event(Event::LeaderChanged{term: self.current_term, leader: self.current_leader});
+        }
     }
 
     /// Encapsulate the process of updating the current term, as updating the `voted_for` state must also be updated.
@@ -290,7 +362,9 @@ impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> Ra
     #[tracing::instrument(level="trace", skip(self))]
     fn update_snapshot_state(&mut self, update: SnapshotUpdate) {
         if let SnapshotUpdate::SnapshotComplete(index) = update {
-            self.snapshot_index = index
+            self.snapshot_index = index;
+            self.report_metrics();
+            self.emit_event(Event::SnapshotBuilt{index});
         }
         // If snapshot state is anything other than streaming, then drop it.
         match self.snapshot_state.take() {
@@ -356,10 +430,22 @@ impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> Ra
         let _ = tx.send(Err(ChangeConfigError::NodeNotLeader));
     }
 
+    /// Reject a leadership transfer request due to this node not being the cluster leader.
+    #[tracing::instrument(level="trace", skip(self, tx))]
+    fn reject_leadership_transfer_not_leader(&self, tx: LeadershipTransferTx) {
+        let _ = tx.send(Err(LeadershipTransferError::NodeNotLeader(self.current_leader)));
+    }
+
     /// Forward the given client write request to the leader.
     #[tracing::instrument(level="trace", skip(self, req, tx))]
-    fn forward_client_write_request(&self, req: ClientWriteRequest<D>, tx: ClientWriteResponseTx<D, R>) {
-        let _ = tx.send(Err(ClientWriteError::ForwardToLeader(req, self.current_leader.clone())));
+    fn forward_client_write_request(&self, req: ClientWriteRequest<D>, tx: ClientWriteTx<D, R>) {
+        tx.send(Err(ClientWriteError::ForwardToLeader(req, self.current_leader.clone())));
+    }
+
+    /// Reject a ReadIndex RPC due to this node not being the cluster leader.
+    #[tracing::instrument(level="trace", skip(self, tx))]
+    fn reject_read_index_not_leader(&self, tx: ReadIndexResponseTx) {
+        let _ = tx.send(Ok(ReadIndexResponse{term: self.current_term, read_index: None, leader_id: self.current_leader}));
     }
 
     /// Forward the given client read request to the leader.
@@ -394,6 +480,8 @@ pub(self) enum SnapshotState<S> {
         offset: u64,
         /// The ID of the snapshot being written.
         id: String,
+        /// The leader's ID for the snapshot being streamed, used to resume interrupted streams.
+        snapshot_id: String,
         /// A handle to the snapshot writer.
         snapshot: Box<S>,
     },
@@ -460,6 +548,19 @@ struct LeaderState<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: Raf
     pub(super) non_voters: BTreeMap<NodeId, NonVoterReplicationState<D>>,
     /// A bool indicating if this node will be stepping down after committing the current config change.
     pub(super) is_stepping_down: bool,
+    /// The time from which acknowledgements are counted towards a quorum, which is when this node
+    /// became leader or when the acknowledgements of its replication targets were last reset.
+    pub(super) acks_reset_at: Instant,
+    /// A bool indicating if this leader has quiesced its group, as it has been idle.
+    pub(super) is_quiescent: bool,
+    /// The time at which the group will be quiesced, if it has seen no activity by then.
+    pub(super) quiesce_deadline: Instant,
+    /// The time of the last write of each client session, as observed by this leader.
+    pub(super) session_activity: BTreeMap<SessionId, Instant>,
+    /// The client sessions whose expiry has been proposed by this leader, but not yet applied.
+    pub(super) expiring_sessions: BTreeSet<SessionId>,
+    /// The time at which idle client sessions will next be checked for expiry.
+    pub(super) session_expiry_deadline: Instant,
 
     /// The stream of events coming from replication streams.
     pub(super) replicationrx: mpsc::UnboundedReceiver<ReplicaEvent<S::Snapshot>>,
@@ -467,6 +568,22 @@ struct LeaderState<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: Raf
     pub(super) replicationtx: mpsc::UnboundedSender<ReplicaEvent<S::Snapshot>>,
     /// A buffer of client requests which have been appended locally and are awaiting to be committed to the cluster.
     pub(super) awaiting_committed: Vec<ClientRequestEntry<D, R>>,
+    /// The index of the initial entry committed by this node upon becoming leader of the current term.
+    pub(super) initial_entry_index: u64,
+    /// An API message which was received while draining a batch of client writes, and which must
+    /// be handled before any other API messages.
+    pub(super) next_api_msg: Option<RaftMsg<D, R>>,
+
+    /// Client reads which are awaiting the next round of leadership confirmation.
+    pub(super) pending_reads: Vec<ReadResponseTx>,
+    /// The batch of client reads for which a round of leadership confirmation is in flight.
+    pub(super) confirming_reads: Option<ReadBatch>,
+    /// Batches of client reads which have confirmed leadership, and are awaiting their read index to be applied.
+    pub(super) awaiting_applied_reads: Vec<ReadBatch>,
+    /// The stream of outcomes from rounds of leadership confirmation.
+    pub(super) rx_read_confirmation: mpsc::UnboundedReceiver<ReadConfirmation>,
+    /// The clonable sender channel for outcomes from rounds of leadership confirmation.
+    pub(super) tx_read_confirmation: mpsc::UnboundedSender<ReadConfirmation>,
     /// A field tracking the cluster's current consensus state, which is used for dynamic membership.
     pub(super) consensus_state: ConsensusState,
 
@@ -476,6 +593,9 @@ struct LeaderState<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: Raf
     pub(super) joint_consensus_cb: FuturesOrdered<oneshot::Receiver<Result<u64, RaftError>>>,
     /// An optional receiver for when a uniform consensus config is committed.
     pub(super) uniform_consensus_cb: FuturesOrdered<oneshot::Receiver<Result<u64, RaftError>>>,
+
+    /// The state of the leadership transfer currently in progress, if any.
+    pub(super) leadership_transfer: Option<LeadershipTransferState>,
 }
 
 impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> LeaderState<'a, D, R, N, S> {
@@ -487,31 +607,52 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
             ConsensusState::Uniform
         };
         let (replicationtx, replicationrx) = mpsc::unbounded_channel();
+        let (tx_read_confirmation, rx_read_confirmation) = mpsc::unbounded_channel();
         Self{
-            core, nodes: BTreeMap::new(), non_voters: BTreeMap::new(), is_stepping_down: false,
-            replicationtx, replicationrx, consensus_state, awaiting_committed: Vec::new(),
+            core, nodes: BTreeMap::new(), non_voters: BTreeMap::new(), is_stepping_down: false, acks_reset_at: Instant::now(),
+            is_quiescent: false, quiesce_deadline: Instant::now(),
+            session_activity: BTreeMap::new(), expiring_sessions: BTreeSet::new(), session_expiry_deadline: Instant::now(),
+            replicationtx, replicationrx, consensus_state, awaiting_committed: Vec::new(), initial_entry_index: 0,
+            next_api_msg: None,
+            pending_reads: Vec::new(), confirming_reads: None, awaiting_applied_reads: Vec::new(),
+            rx_read_confirmation, tx_read_confirmation,
             propose_config_change_cb: None, joint_consensus_cb: FuturesOrdered::new(),
-            uniform_consensus_cb: FuturesOrdered::new(),
+            uniform_consensus_cb: FuturesOrdered::new(), leadership_transfer: None,
         }
     }
 
     /// Transition to the Raft leader state.
     #[tracing::instrument(level="trace", skip(self), fields(id=self.core.id, raft_state="leader"))]
     pub(self) async fn run(mut self) -> RaftResult<()> {
-        // Spawn replication streams.
-        let targets = self.core.membership.all_nodes().into_iter()
+        // Spawn replication streams. Targets are sorted so that streams are spawned in a deterministic order.
+        let mut targets = self.core.membership.all_nodes().into_iter()
             .filter(|elem| elem != &self.core.id)
             .collect::<Vec<_>>();
+        targets.sort_unstable();
         for target in targets {
             let state = self.spawn_replication_stream(target);
             self.nodes.insert(target, state);
         }
+        // Spawn replication streams for learners, which are tracked as non-voters.
+        let mut learners = self.core.membership.learners.iter()
+            .filter(|elem| *elem != &self.core.id)
+            .copied()
+            .collect::<Vec<_>>();
+        learners.sort_unstable();
+        for target in learners {
+            let state = self.spawn_replication_stream(target);
+            self.non_voters.insert(target, NonVoterReplicationState{state, is_ready_to_join: false, tx: None});
+        }
 
         // Setup state as leader.
+        self.acks_reset_at = Instant::now();
+        self.wake();
         self.core.last_heartbeat = None;
         self.core.next_election_timeout = None;
         self.core.update_current_leader(UpdateCurrentLeader::ThisNode);
-        self.core.report_metrics();
+        self.report_metrics();
+        let term = self.core.current_term;
+        self.core.emit_event(Event::BecameLeader{term});
 
         // Per §8, commit an initial entry as part of becoming the cluster leader.
         self.commit_initial_leader_entry().await?;
@@ -524,35 +665,28 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
                 for node in self.non_voters.values() {
                     let _ = node.state.replstream.repltx.send(RaftEvent::Terminate);
                 }
+                if let Some(tx) = self.leadership_transfer.take().and_then(|transfer| transfer.tx) {
+                    let _ = tx.send(Err(LeadershipTransferError::NodeNotLeader(self.core.current_leader)));
+                }
+                self.reject_outstanding_reads();
+                // Replication metrics are only published by the leader, so the next state reports them as empty.
+                self.core.replication_metrics.clear();
+                self.core.emit_event(Event::SteppedDown{term});
+                // On shutdown, pending writes are simply dropped, which is reported to clients as such.
+                if !self.core.needs_shutdown.load(Ordering::SeqCst) {
+                    self.reject_outstanding_writes();
+                }
                 return Ok(());
             }
-            tokio::select!{
-                Some(msg) = self.core.rx_api.next() => match msg {
-                    RaftMsg::AppendEntries{rpc, tx} => {
-                        let _ = tx.send(self.core.handle_append_entries_request(rpc).await);
-                    }
-                    RaftMsg::RequestVote{rpc, tx} => {
-                        let _ = tx.send(self.core.handle_vote_request(rpc).await);
-                    }
-                    RaftMsg::InstallSnapshot{rpc, tx} => {
-                        let _ = tx.send(self.core.handle_install_snapshot_request(rpc).await);
-                    }
-                    RaftMsg::ClientReadRequest{tx} => {
-                        self.handle_client_read_request(tx).await;
-                    }
-                    RaftMsg::ClientWriteRequest{rpc, tx} => {
-                        self.handle_client_write_request(rpc, tx).await;
-                    }
-                    RaftMsg::Initialize{tx, ..} => {
-                        self.core.reject_init_with_config(tx);
-                    }
-                    RaftMsg::AddNonVoter{id, tx} => {
-                        self.add_member(id, tx);
-                    }
-                    RaftMsg::ChangeMembership{members, tx} => {
-                        self.change_membership(members, tx).await;
-                    }
-                },
+            // Handle any message which was received while draining a batch of client writes.
+            if let Some(msg) = self.next_api_msg.take() {
+                self.handle_api_msg(msg).await?;
+                continue;
+            }
+            let transfer_deadline = self.leadership_transfer.as_ref().map(|transfer| transfer.deadline).unwrap_or_else(Instant::now);
+            let check_quorum_deadline = self.check_quorum_deadline();
+            select!{
+                Some(msg) = self.core.rx_api.next() => self.handle_api_msg(msg).await?,
                 Some(update) = self.core.rx_compaction.next() => self.core.update_snapshot_state(update),
                 Some(Ok(res)) = self.joint_consensus_cb.next() => {
                     match res {
@@ -576,16 +710,90 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
                     }
                 }
                 Some(event) = self.replicationrx.next() => self.handle_replica_event(event).await,
+                Some(res) = self.rx_read_confirmation.next() => self.handle_read_confirmation(res).await?,
+                _ = delay_until(transfer_deadline), if self.leadership_transfer.is_some() => self.abort_leadership_transfer(),
+                _ = delay_until(check_quorum_deadline), if self.core.config.check_quorum && !self.is_quiescent => self.handle_check_quorum_timeout(),
+                _ = delay_until(self.quiesce_deadline), if self.core.config.quiesce && !self.is_quiescent => self.handle_quiesce_timeout(),
+                _ = delay_until(self.session_expiry_deadline), if !self.core.client_sessions.is_empty() && !self.is_quiescent => self.handle_session_expiry_timeout().await?,
             }
         }
     }
+
+    /// Handle an API message received while in leader state.
+    ///
+    /// Any message counts as activity on the group, and wakes it if it is quiescent, except for
+    /// queries of this node's replication state & `Raft::wake`, which only wakes followers.
+    async fn handle_api_msg(&mut self, msg: RaftMsg<D, R>) -> RaftResult<()> {
+        if !matches!(msg, RaftMsg::ReplicationAckAges{..} | RaftMsg::Wake) {
+            self.wake();
+        }
+        match msg {
+            RaftMsg::AppendEntries{rpc, tx} => {
+                let _ = tx.send(self.core.handle_append_entries_request(rpc).await);
+            }
+            RaftMsg::RequestVote{rpc, tx} => {
+                let _ = tx.send(self.core.handle_vote_request(rpc).await);
+            }
+            RaftMsg::PreVote{rpc, tx} => {
+                let _ = tx.send(self.core.handle_pre_vote_request(rpc).await);
+            }
+            RaftMsg::InstallSnapshot{rpc, tx} => {
+                let _ = tx.send(self.core.handle_install_snapshot_request(rpc).await);
+            }
+            RaftMsg::TimeoutNow{rpc, tx} => {
+                let _ = tx.send(self.core.handle_timeout_now_request(rpc).await);
+            }
+            RaftMsg::ClientReadRequest{tx} => {
+                self.handle_client_read_request(tx);
+            }
+            RaftMsg::RegisterSession{tx} => {
+                self.handle_register_session(tx).await;
+            }
+            RaftMsg::ReadIndex{rpc, tx} => {
+                self.handle_read_index_request(rpc, tx).await?;
+            }
+            RaftMsg::ClientWriteRequest{rpc, tx} => {
+                let requests = self.drain_client_write_requests(vec![(rpc, tx)]);
+                self.handle_client_write_requests(requests).await;
+            }
+            RaftMsg::ClientWriteBatch{requests} => {
+                let requests = self.drain_client_write_requests(requests);
+                self.handle_client_write_requests(requests).await;
+            }
+            RaftMsg::Initialize{tx, ..} => {
+                self.core.reject_init_with_config(tx);
+            }
+            RaftMsg::AddNonVoter{id, is_witness, tx} => {
+                self.add_member(id, is_witness, tx).await;
+            }
+            RaftMsg::RemoveNonVoter{id, tx} => {
+                self.remove_learner(id, tx).await;
+            }
+            RaftMsg::ChangeMembership{members, tx} => {
+                self.change_membership(members, tx).await;
+            }
+            RaftMsg::TransferLeadership{target, tx} => {
+                self.transfer_leadership(target, tx);
+            }
+            RaftMsg::ReplicationAckAges{tx} => {
+                let _ = tx.send(self.replication_ack_ages());
+            }
+            RaftMsg::Wake => (),
+        }
+        Ok(())
+    }
 }
 
 /// A struct tracking the state of a replication stream from the perspective of the Raft actor.
 struct ReplicationState<D: AppData> {
     pub match_index: u64,
     pub match_term: u64,
-    pub is_at_line_rate: bool,
+    /// The send time of the most recent RPC which the target acknowledged in this node's term.
+    pub last_ack: Option<Instant>,
+    /// The state of the replication stream, as last reported by the stream.
+    pub stream_state: ReplicationStreamState,
+    /// The progress of the snapshot being streamed to the target, if any.
+    pub snapshot_progress: Option<SnapshotProgress>,
     pub remove_after_commit: Option<u64>,
     pub replstream: ReplicationStream<D>,
 }
@@ -600,6 +808,18 @@ struct NonVoterReplicationState<D: AppData> {
     pub tx: Option<oneshot::Sender<Result<(), ChangeConfigError>>>,
 }
 
+/// The state of a leadership transfer being driven by the Raft leader.
+struct LeadershipTransferState {
+    /// The ID of the node which leadership is being transferred to.
+    pub target: NodeId,
+    /// The time at which the transfer will be aborted if it has not yet completed.
+    pub deadline: Instant,
+    /// The response channel to use once the TimeoutNow RPC has been sent to the target.
+    ///
+    /// This will be `None` once the TimeoutNow RPC has been sent.
+    pub tx: Option<LeadershipTransferTx>,
+}
+
 /// A state enum used by Raft leaders to navigate the joint consensus protocol.
 pub enum ConsensusState {
     /// The cluster is preparring to go into joint consensus, but the leader is still syncing
@@ -664,14 +884,17 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
     pub(self) async fn run(mut self) -> RaftResult<()> {
         // Each iteration of the outer loop represents a new term.
         loop {
-            // Setup initial state per term.
-            self.votes_granted_old = 1; // We must vote for ourselves per the Raft spec.
-            self.votes_needed_old = ((self.core.membership.members.len() / 2) + 1) as u64; // Just need a majority.
-            if let Some(nodes) = &self.core.membership.members_after_consensus {
-                self.votes_granted_new = 1; // We must vote for ourselves per the Raft spec.
-                self.votes_needed_new = ((nodes.len() / 2) + 1) as u64; // Just need a majority.
+            // The target of a leadership transfer campaigns immediately, as the leader has asked it to.
+            let is_leadership_transfer = std::mem::replace(&mut self.core.is_leadership_transfer_target, false);
+
+            // If enabled, ensure a majority of the cluster would grant us its vote before disrupting it.
+            if self.core.config.pre_vote && !is_leadership_transfer && !self.run_pre_vote().await? {
+                return Ok(());
             }
 
+            // Setup initial state per term.
+            self.reset_vote_counts();
+
             // Setup new term.
             self.core.update_next_election_timeout(); // Generates a new rand value within range.
             self.core.current_term += 1;
@@ -681,7 +904,7 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
             self.core.report_metrics();
 
             // Send RPCs to all members in parallel.
-            let mut pending_votes = self.spawn_parallel_vote_requests();
+            let mut pending_votes = self.spawn_parallel_vote_requests(is_leadership_transfer);
 
             // Inner processing loop for this Raft state.
             loop {
@@ -690,40 +913,123 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
                 }
 
                 let mut timeout_fut = delay_until(self.core.get_next_election_timeout());
-                tokio::select!{
+                select!{
                     _ = &mut timeout_fut => break, // This election has timed-out. Break to outer loop, which starts a new term.
                     Some((res, peer)) = pending_votes.recv() => self.handle_vote_response(res, peer).await?,
-                    Some(msg) = self.core.rx_api.next() => match msg {
-                        RaftMsg::AppendEntries{rpc, tx} => {
-                            let _ = tx.send(self.core.handle_append_entries_request(rpc).await);
-                        }
-                        RaftMsg::RequestVote{rpc, tx} => {
-                            let _ = tx.send(self.core.handle_vote_request(rpc).await);
-                        }
-                        RaftMsg::InstallSnapshot{rpc, tx} => {
-                            let _ = tx.send(self.core.handle_install_snapshot_request(rpc).await);
-                        }
-                        RaftMsg::ClientReadRequest{tx} => {
-                            self.core.forward_client_read_request(tx);
-                        }
-                        RaftMsg::ClientWriteRequest{rpc, tx} => {
-                            self.core.forward_client_write_request(rpc, tx);
-                        }
-                        RaftMsg::Initialize{tx, ..} => {
-                            self.core.reject_init_with_config(tx);
-                        }
-                        RaftMsg::AddNonVoter{tx, ..} => {
-                            self.core.reject_config_change_not_leader(tx);
-                        }
-                        RaftMsg::ChangeMembership{tx, ..} => {
-                            self.core.reject_config_change_not_leader(tx);
-                        }
+                    Some(msg) = self.core.rx_api.next() => self.handle_api_msg(msg).await,
+                    Some(update) = self.core.rx_compaction.next() => self.core.update_snapshot_state(update),
+                }
+            }
+        }
+    }
+
+    /// Run the Pre-Vote phase of an election (§9.6 of the Raft thesis).
+    ///
+    /// Returns `true` once a majority of the cluster has indicated that it would grant its vote,
+    /// or `false` if this node is no longer a candidate. The current term is not incremented.
+    #[tracing::instrument(level="trace", skip(self))]
+    async fn run_pre_vote(&mut self) -> RaftResult<bool> {
+        // Each iteration of the outer loop represents a new round of pre-votes.
+        loop {
+            self.reset_vote_counts();
+            if self.votes_granted_old >= self.votes_needed_old && self.votes_granted_new >= self.votes_needed_new {
+                return Ok(true); // Single node cluster.
+            }
+
+            self.core.update_next_election_timeout(); // Generates a new rand value within range.
+            self.core.update_current_leader(UpdateCurrentLeader::Unknown);
+            self.core.report_metrics();
+
+            // Send RPCs to all members in parallel.
+            let mut pending_votes = self.spawn_parallel_pre_vote_requests();
+
+            // Inner processing loop for this round of pre-votes.
+            loop {
+                if !self.core.target_state.is_candidate() || self.core.needs_shutdown.load(Ordering::SeqCst) {
+                    return Ok(false);
+                }
+
+                let mut timeout_fut = delay_until(self.core.get_next_election_timeout());
+                select!{
+                    _ = &mut timeout_fut => break, // This round has timed-out. Break to outer loop, which starts a new round.
+                    Some((res, peer)) = pending_votes.recv() => if self.handle_pre_vote_response(res, peer).await? {
+                        return Ok(true);
                     },
+                    Some(msg) = self.core.rx_api.next() => self.handle_api_msg(msg).await,
                     Some(update) = self.core.rx_compaction.next() => self.core.update_snapshot_state(update),
                 }
             }
         }
     }
+
+    /// Reset the vote counters for a new round of voting.
+    fn reset_vote_counts(&mut self) {
+        self.votes_granted_old = 1; // We must vote for ourselves per the Raft spec.
+        self.votes_needed_old = ((self.core.membership.members.len() / 2) + 1) as u64; // Just need a majority.
+        self.votes_granted_new = 0;
+        self.votes_needed_new = 0;
+        if let Some(nodes) = &self.core.membership.members_after_consensus {
+            self.votes_granted_new = 1; // We must vote for ourselves per the Raft spec.
+            self.votes_needed_new = ((nodes.len() / 2) + 1) as u64; // Just need a majority.
+        }
+    }
+
+    /// Handle an API message received while in candidate state.
+    async fn handle_api_msg(&mut self, msg: RaftMsg<D, R>) {
+        match msg {
+            RaftMsg::AppendEntries{rpc, tx} => {
+                let _ = tx.send(self.core.handle_append_entries_request(rpc).await);
+            }
+            RaftMsg::RequestVote{rpc, tx} => {
+                let _ = tx.send(self.core.handle_vote_request(rpc).await);
+            }
+            RaftMsg::PreVote{rpc, tx} => {
+                let _ = tx.send(self.core.handle_pre_vote_request(rpc).await);
+            }
+            RaftMsg::InstallSnapshot{rpc, tx} => {
+                let _ = tx.send(self.core.handle_install_snapshot_request(rpc).await);
+            }
+            RaftMsg::TimeoutNow{rpc, tx} => {
+                let _ = tx.send(self.core.handle_timeout_now_request(rpc).await);
+            }
+            RaftMsg::ClientReadRequest{tx} => {
+                self.core.forward_client_read_request(tx);
+            }
+            RaftMsg::RegisterSession{tx} => {
+                self.core.forward_register_session(tx);
+            }
+            RaftMsg::ReadIndex{tx, ..} => {
+                self.core.reject_read_index_not_leader(tx);
+            }
+            RaftMsg::ClientWriteRequest{rpc, tx} => {
+                self.core.forward_client_write_request(rpc, tx);
+            }
+            RaftMsg::ClientWriteBatch{requests} => {
+                for (rpc, tx) in requests {
+                    self.core.forward_client_write_request(rpc, tx);
+                }
+            }
+            RaftMsg::Initialize{tx, ..} => {
+                self.core.reject_init_with_config(tx);
+            }
+            RaftMsg::AddNonVoter{tx, ..} => {
+                self.core.reject_config_change_not_leader(tx);
+            }
+            RaftMsg::RemoveNonVoter{tx, ..} => {
+                self.core.reject_config_change_not_leader(tx);
+            }
+            RaftMsg::ChangeMembership{tx, ..} => {
+                self.core.reject_config_change_not_leader(tx);
+            }
+            RaftMsg::TransferLeadership{tx, ..} => {
+                self.core.reject_leadership_transfer_not_leader(tx);
+            }
+            RaftMsg::ReplicationAckAges{tx} => {
+                let _ = tx.send(BTreeMap::new());
+            }
+            RaftMsg::Wake => (),
+        }
+    }
 }
 
 ///////////////////////////////////////////////////////////////////////////////////////////////////
@@ -741,7 +1047,7 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
 
     /// Run the follower loop.
     #[tracing::instrument(level="trace", skip(self), fields(id=self.core.id, raft_state="follower"))]
-    pub(self) async fn run(self) -> RaftResult<()> {
+    pub(self) async fn run(mut self) -> RaftResult<()> {
         self.core.report_metrics();
         loop {
             if !self.core.target_state.is_follower() || self.core.needs_shutdown.load(Ordering::SeqCst) {
@@ -749,39 +1055,84 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
             }
 
             let mut election_timeout = delay_until(self.core.get_next_election_timeout()); // Value is updated as heartbeats are received.
-            tokio::select!{
-                // If an election timeout is hit, then we need to transition to candidate.
-                _ = &mut election_timeout => self.core.set_target_state(State::Candidate),
-                Some(msg) = self.core.rx_api.next() => match msg {
-                    RaftMsg::AppendEntries{rpc, tx} => {
-                        let _ = tx.send(self.core.handle_append_entries_request(rpc).await);
-                    }
-                    RaftMsg::RequestVote{rpc, tx} => {
-                        let _ = tx.send(self.core.handle_vote_request(rpc).await);
-                    }
-                    RaftMsg::InstallSnapshot{rpc, tx} => {
-                        let _ = tx.send(self.core.handle_install_snapshot_request(rpc).await);
-                    }
-                    RaftMsg::ClientReadRequest{tx} => {
-                        self.core.forward_client_read_request(tx);
-                    }
-                    RaftMsg::ClientWriteRequest{rpc, tx} => {
-                        self.core.forward_client_write_request(rpc, tx);
-                    }
-                    RaftMsg::Initialize{tx, ..} => {
-                        self.core.reject_init_with_config(tx);
-                    }
-                    RaftMsg::AddNonVoter{tx, ..} => {
-                        self.core.reject_config_change_not_leader(tx);
-                    }
-                    RaftMsg::ChangeMembership{tx, ..} => {
-                        self.core.reject_config_change_not_leader(tx);
-                    }
+            select!{
+                // If an election timeout is hit, then we need to transition to candidate. Witnesses
+                // never campaign, and simply wait for a new leader to make contact. The timer is
+                // suspended while this node is quiescent.
+                _ = &mut election_timeout, if !self.core.is_quiescent => if self.core.membership.is_witness(&self.core.id) {
+                    self.core.update_next_election_timeout();
+                } else {
+                    self.core.set_target_state(State::Candidate);
                 },
+                Some(msg) = self.core.rx_api.next() => {
+                    // Any message other than AppendEntries from the leader or a query of this
+                    // node's replication state wakes a quiescent follower.
+                    if !matches!(msg, RaftMsg::AppendEntries{..} | RaftMsg::ReplicationAckAges{..}) {
+                        self.core.wake();
+                    }
+                    self.handle_api_msg(msg).await;
+                }
                 Some(update) = self.core.rx_compaction.next() => self.core.update_snapshot_state(update),
             }
         }
     }
+
+    /// Handle an API message received while in follower state.
+    async fn handle_api_msg(&mut self, msg: RaftMsg<D, R>) {
+        match msg {
+            RaftMsg::AppendEntries{rpc, tx} => {
+                let _ = tx.send(self.core.handle_append_entries_request(rpc).await);
+            }
+            RaftMsg::RequestVote{rpc, tx} => {
+                let _ = tx.send(self.core.handle_vote_request(rpc).await);
+            }
+            RaftMsg::PreVote{rpc, tx} => {
+                let _ = tx.send(self.core.handle_pre_vote_request(rpc).await);
+            }
+            RaftMsg::InstallSnapshot{rpc, tx} => {
+                let _ = tx.send(self.core.handle_install_snapshot_request(rpc).await);
+            }
+            RaftMsg::TimeoutNow{rpc, tx} => {
+                let _ = tx.send(self.core.handle_timeout_now_request(rpc).await);
+            }
+            RaftMsg::ClientReadRequest{tx} => {
+                self.core.handle_follower_read_request(tx);
+            }
+            RaftMsg::RegisterSession{tx} => {
+                self.core.forward_register_session(tx);
+            }
+            RaftMsg::ReadIndex{tx, ..} => {
+                self.core.reject_read_index_not_leader(tx);
+            }
+            RaftMsg::ClientWriteRequest{rpc, tx} => {
+                self.core.forward_client_write_request(rpc, tx);
+            }
+            RaftMsg::ClientWriteBatch{requests} => {
+                for (rpc, tx) in requests {
+                    self.core.forward_client_write_request(rpc, tx);
+                }
+            }
+            RaftMsg::Initialize{tx, ..} => {
+                self.core.reject_init_with_config(tx);
+            }
+            RaftMsg::AddNonVoter{tx, ..} => {
+                self.core.reject_config_change_not_leader(tx);
+            }
+            RaftMsg::RemoveNonVoter{tx, ..} => {
+                self.core.reject_config_change_not_leader(tx);
+            }
+            RaftMsg::ChangeMembership{tx, ..} => {
+                self.core.reject_config_change_not_leader(tx);
+            }
+            RaftMsg::TransferLeadership{tx, ..} => {
+                self.core.reject_leadership_transfer_not_leader(tx);
+            }
+            RaftMsg::ReplicationAckAges{tx} => {
+                let _ = tx.send(BTreeMap::new());
+            }
+            RaftMsg::Wake => (),
+        }
+    }
 }
 
 ///////////////////////////////////////////////////////////////////////////////////////////////////
@@ -805,7 +1156,7 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
             if !self.core.target_state.is_non_voter() || self.core.needs_shutdown.load(Ordering::SeqCst) {
                 return Ok(());
             }
-            tokio::select!{
+            select!{
                 Some(msg) = self.core.rx_api.next() => match msg {
                     RaftMsg::AppendEntries{rpc, tx} => {
                         let _ = tx.send(self.core.handle_append_entries_request(rpc).await);
@@ -813,24 +1164,51 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
                     RaftMsg::RequestVote{rpc, tx} => {
                         let _ = tx.send(self.core.handle_vote_request(rpc).await);
                     }
+                    RaftMsg::PreVote{rpc, tx} => {
+                        let _ = tx.send(self.core.handle_pre_vote_request(rpc).await);
+                    }
                     RaftMsg::InstallSnapshot{rpc, tx} => {
                         let _ = tx.send(self.core.handle_install_snapshot_request(rpc).await);
                     }
+                    RaftMsg::TimeoutNow{rpc, tx} => {
+                        let _ = tx.send(self.core.handle_timeout_now_request(rpc).await);
+                    }
                     RaftMsg::ClientReadRequest{tx} => {
-                        self.core.forward_client_read_request(tx);
+                        self.core.handle_follower_read_request(tx);
+                    }
+                    RaftMsg::RegisterSession{tx} => {
+                        self.core.forward_register_session(tx);
+                    }
+                    RaftMsg::ReadIndex{tx, ..} => {
+                        self.core.reject_read_index_not_leader(tx);
                     }
                     RaftMsg::ClientWriteRequest{rpc, tx} => {
                         self.core.forward_client_write_request(rpc, tx);
                     }
+                    RaftMsg::ClientWriteBatch{requests} => {
+                        for (rpc, tx) in requests {
+                            self.core.forward_client_write_request(rpc, tx);
+                        }
+                    }
                     RaftMsg::Initialize{members, tx} => {
                         let _ = tx.send(self.handle_init_with_config(members).await);
                     }
                     RaftMsg::AddNonVoter{tx, ..} => {
                         self.core.reject_config_change_not_leader(tx);
                     }
+                    RaftMsg::RemoveNonVoter{tx, ..} => {
+                        self.core.reject_config_change_not_leader(tx);
+                    }
                     RaftMsg::ChangeMembership{tx, ..} => {
                         self.core.reject_config_change_not_leader(tx);
                     }
+                    RaftMsg::TransferLeadership{tx, ..} => {
+                        self.core.reject_leadership_transfer_not_leader(tx);
+                    }
+                    RaftMsg::ReplicationAckAges{tx} => {
+                        let _ = tx.send(BTreeMap::new());
+                    }
+                    RaftMsg::Wake => (),
                 },
                 Some(update) = self.core.rx_compaction.next() => self.core.update_snapshot_state(update),
             }
@@ -1,25 +1,33 @@
+use std::collections::{BTreeMap, HashSet};
+
 use tokio::sync::oneshot;
+use tokio::time::{Duration, Instant};
 
 use crate::{AppData, AppDataResponse, NodeId, RaftNetwork, RaftStorage};
 use crate::config::SnapshotPolicy;
 use crate::error::RaftResult;
+use crate::metrics::{ReplicationMetrics, ReplicationStreamState, SnapshotProgress};
 use crate::core::{ConsensusState, LeaderState, ReplicationState, SnapshotState, State, UpdateCurrentLeader};
-use crate::replication::{RaftEvent, ReplicaEvent, ReplicationStream};
+use crate::replication::{RaftEvent, ReplicaEvent, ReplicationStream, ReplicationStreamParams};
 use crate::storage::CurrentSnapshotData;
 
 impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> LeaderState<'a, D, R, N, S> {
     /// Spawn a new replication stream returning its replication state handle.
     #[tracing::instrument(level="trace", skip(self))]
     pub(super) fn spawn_replication_stream(&self, target: NodeId) -> ReplicationState<D> {
+        let params = ReplicationStreamParams{
+            id: self.core.id, target, is_witness: self.core.membership.is_witness(&target), term: self.core.current_term,
+            last_log_index: self.core.last_log_index, last_log_term: self.core.last_log_term, commit_index: self.core.commit_index,
+        };
         let replstream = ReplicationStream::new(
-            self.core.id, target, self.core.current_term, self.core.config.clone(),
-            self.core.last_log_index, self.core.last_log_term, self.core.commit_index,
-            self.core.network.clone(), self.core.storage.clone(), self.replicationtx.clone(),
+            params, self.core.config.clone(), self.core.network.clone(), self.core.storage.clone(), self.replicationtx.clone(),
         );
         ReplicationState{
             match_index: self.core.last_log_index,
             match_term: self.core.current_term,
-            is_at_line_rate: false,
+            last_ack: None,
+            stream_state: ReplicationStreamState::Lagging,
+            snapshot_progress: None,
             replstream,
             remove_after_commit: None,
         }
@@ -29,9 +37,11 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
     #[tracing::instrument(level="trace", skip(self, event))]
     pub(super) async fn handle_replica_event(&mut self, event: ReplicaEvent<S::Snapshot>) {
         let res = match event {
-            ReplicaEvent::RateUpdate{target, is_line_rate} => self.handle_rate_update(target, is_line_rate).await,
+            ReplicaEvent::RateUpdate{target, state} => self.handle_rate_update(target, state).await,
+            ReplicaEvent::SnapshotProgress{target, progress} => self.handle_snapshot_progress(target, progress),
             ReplicaEvent::RevertToFollower{target, term} => self.handle_revert_to_follower(target, term).await,
             ReplicaEvent::UpdateMatchIndex{target, match_index, match_term} => self.handle_update_match_index(target, match_index, match_term).await,
+            ReplicaEvent::UpdateLastAck{target, sent_at} => self.handle_update_last_ack(target, sent_at),
             ReplicaEvent::NeedsSnapshot{target, tx} => self.handle_needs_snapshot(target, tx).await,
             ReplicaEvent::Shutdown => {
                 self.core.set_target_state(State::Shutdown);
@@ -41,19 +51,24 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
         if let Err(err) = res {
             tracing::error!({error=%err}, "error while processing event from replication stream");
         }
+        self.report_replication_metrics();
     }
 
     /// Handle events from replication streams updating their replication rate tracker.
-    #[tracing::instrument(level="trace", skip(self, target, is_line_rate))]
-    async fn handle_rate_update(&mut self, target: NodeId, is_line_rate: bool) -> RaftResult<()> {
+    #[tracing::instrument(level="trace", skip(self, target, stream_state))]
+    async fn handle_rate_update(&mut self, target: NodeId, stream_state: ReplicationStreamState) -> RaftResult<()> {
+        // Any previous snapshot progress is stale, as a stream reports progress anew after each transition.
+        let is_line_rate = stream_state == ReplicationStreamState::LineRate;
         // Get a handle the target's replication stat & update it as needed.
         if let Some(state) = self.nodes.get_mut(&target) {
-            state.is_at_line_rate = is_line_rate;
+            state.stream_state = stream_state;
+            state.snapshot_progress = None;
             return Ok(());
         }
         // Else, if this is a non-voter, then update as needed.
         if let Some(state) = self.non_voters.get_mut(&target) {
-            state.state.is_at_line_rate = is_line_rate;
+            state.state.stream_state = stream_state;
+            state.state.snapshot_progress = None;
             state.is_ready_to_join = is_line_rate;
             // Issue a response on the non-voters response channel if needed.
             if state.is_ready_to_join {
@@ -80,6 +95,20 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
         Ok(())
     }
 
+    /// Handle events from replication streams reporting progress on streaming a snapshot to the target.
+    #[tracing::instrument(level="trace", skip(self, progress))]
+    fn handle_snapshot_progress(&mut self, target: NodeId, progress: SnapshotProgress) -> RaftResult<()> {
+        let state = match self.nodes.get_mut(&target) {
+            Some(state) => state,
+            None => match self.non_voters.get_mut(&target) {
+                Some(state) => &mut state.state,
+                None => return Ok(()), // Node not found.
+            }
+        };
+        state.snapshot_progress = Some(progress);
+        Ok(())
+    }
+
     /// Handle events from replication streams for when this node needs to revert to follower state.
     #[tracing::instrument(level="trace", skip(self, term))]
     async fn handle_revert_to_follower(&mut self, _: NodeId, term: u64) -> RaftResult<()> {
@@ -164,16 +193,176 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
                 .last()
                 .map(|(idx, _)| idx);
             if let Some(offset) = filter {
+                // Notify every client of its commitment before any of the requests are applied.
+                let mut requests = self.awaiting_committed.drain(..=offset).collect::<Vec<_>>();
+                for request in requests.iter_mut() {
+                    request.tx.send_committed(request.entry.index);
+                }
                 // Build a new ApplyLogsTask from each of the given client requests.
-                for request in self.awaiting_committed.drain(..=offset).collect::<Vec<_>>() {
+                for request in requests {
                     self.client_request_post_commit(request).await;
                 }
             }
-            self.core.report_metrics();
+            self.report_metrics();
+        }
+
+        // If leadership is being transferred to this node, it may now be up-to-date.
+        if self.leadership_transfer.as_ref().map(|transfer| transfer.target == target).unwrap_or(false) {
+            self.try_send_timeout_now();
+        }
+        Ok(())
+    }
+
+    /// Handle events from a replication stream indicating that the target has acknowledged this node as leader.
+    #[tracing::instrument(level="trace", skip(self, sent_at))]
+    fn handle_update_last_ack(&mut self, target: NodeId, sent_at: Instant) -> RaftResult<()> {
+        let state = match self.nodes.get_mut(&target) {
+            Some(state) => state,
+            None => match self.non_voters.get_mut(&target) {
+                Some(state) => &mut state.state,
+                None => return Ok(()), // Node not found.
+            }
+        };
+        if state.last_ack.map(|last_ack| last_ack < sent_at).unwrap_or(true) {
+            state.last_ack = Some(sent_at);
         }
         Ok(())
     }
 
+    /// Build the replication metrics of every target, voters & non-voters alike.
+    fn replication_metrics(&self) -> BTreeMap<NodeId, ReplicationMetrics> {
+        self.nodes.iter()
+            .chain(self.non_voters.iter().map(|(id, node)| (id, &node.state)))
+            .map(|(id, node)| (*id, ReplicationMetrics{
+                match_index: node.match_index,
+                match_term: node.match_term,
+                state: node.stream_state,
+                last_ack: node.last_ack,
+                snapshot: node.snapshot_progress,
+            }))
+            .collect()
+    }
+
+    /// Get the time elapsed since each target last acknowledged this node as leader.
+    pub(super) fn replication_ack_ages(&self) -> BTreeMap<NodeId, Duration> {
+        let now = Instant::now();
+        self.nodes.iter()
+            .chain(self.non_voters.iter().map(|(id, node)| (id, &node.state)))
+            .filter_map(|(id, node)| node.last_ack.map(|last_ack| (*id, now.saturating_duration_since(last_ack))))
+            .collect()
+    }
+
+    /// Report a metrics payload on the current state of this node, including replication metrics.
+    pub(super) fn report_metrics(&mut self) {
+        self.core.replication_metrics = self.replication_metrics();
+        self.core.report_metrics();
+    }
+
+    /// Report a metrics payload if the replication metrics of any target have changed since last reported.
+    ///
+    /// Acks arrive with every heartbeat, so a change of `last_ack` alone is not reported.
+    fn report_replication_metrics(&mut self) {
+        let metrics = self.replication_metrics();
+        let is_changed = metrics.len() != self.core.replication_metrics.len()
+            || metrics.iter().zip(self.core.replication_metrics.iter()).any(|((id, repl), (prev_id, prev))| {
+                id != prev_id || &ReplicationMetrics{last_ack: prev.last_ack, ..repl.clone()} != prev
+            });
+        if is_changed {
+            self.core.replication_metrics = metrics;
+            self.core.report_metrics();
+        }
+    }
+
+    /// Calculate the most recent time at which a majority of each config group acknowledged this node as leader.
+    ///
+    /// This node's own acknowledgement is counted as current, unless it is stepping down.
+    pub(super) fn quorum_ack(&self) -> Option<Instant> {
+        let now = Instant::now();
+        let acks_for = |members: &HashSet<NodeId>| {
+            members.iter()
+                .map(|id| if id == &self.core.id {
+                    if self.is_stepping_down { None } else { Some(now) }
+                } else {
+                    self.nodes.get(id).and_then(|node| node.last_ack)
+                })
+                .collect::<Vec<_>>()
+        };
+        let ack_c0 = calculate_quorum_ack(acks_for(&self.core.membership.members));
+        match &self.core.membership.members_after_consensus {
+            Some(members) => std::cmp::min(ack_c0, calculate_quorum_ack(acks_for(members))),
+            None => ack_c0,
+        }
+    }
+
+    /// The time at which this node must step down if it has not heard from a quorum of the cluster.
+    ///
+    /// This is `election_timeout_max` after the most recent acknowledgement from a majority of
+    /// each config group, counting from when acknowledgements were last reset.
+    pub(super) fn check_quorum_deadline(&self) -> Instant {
+        let since = match self.quorum_ack() {
+            Some(ack) if ack > self.acks_reset_at => ack,
+            _ => self.acks_reset_at,
+        };
+        since + Duration::from_millis(self.core.config.election_timeout_max)
+    }
+
+    /// Handle the CheckQuorum deadline, stepping down if a quorum has not been heard from since.
+    #[tracing::instrument(level="trace", skip(self))]
+    pub(super) fn handle_check_quorum_timeout(&mut self) {
+        if self.check_quorum_deadline() > Instant::now() {
+            return; // A quorum has been heard from in the meantime.
+        }
+        tracing::warn!("stepping down as leader, as a quorum of the cluster has not been heard from within the election timeout");
+        self.core.update_current_leader(UpdateCurrentLeader::Unknown);
+        self.core.set_target_state(State::Follower);
+    }
+
+    /// Handle the quiescence deadline, quiescing the group if it is idle & fully caught up.
+    ///
+    /// If the group is not yet ready to be quiesced, then this is retried after the next heartbeat.
+    #[tracing::instrument(level="trace", skip(self))]
+    pub(super) fn handle_quiesce_timeout(&mut self) {
+        if !self.is_ready_to_quiesce() {
+            self.quiesce_deadline = Instant::now() + Duration::from_millis(self.core.config.heartbeat_interval);
+            return;
+        }
+        tracing::debug!("quiescing idle group");
+        self.is_quiescent = true;
+        for node in self.nodes.values().chain(self.non_voters.values().map(|node| &node.state)) {
+            let _ = node.replstream.repltx.send(RaftEvent::Quiesce);
+        }
+    }
+
+    /// Check if the group is idle, with every entry committed & replicated to every target.
+    fn is_ready_to_quiesce(&self) -> bool {
+        let last_log_index = self.core.last_log_index;
+        let is_idle = self.awaiting_committed.is_empty()
+            && self.pending_reads.is_empty() && self.confirming_reads.is_none() && self.awaiting_applied_reads.is_empty()
+            && self.leadership_transfer.is_none()
+            && matches!(self.consensus_state, ConsensusState::Uniform);
+        is_idle && self.core.commit_index == last_log_index
+            && self.nodes.values().chain(self.non_voters.values().map(|node| &node.state))
+                .all(|node| node.stream_state == ReplicationStreamState::LineRate && node.match_index == last_log_index)
+    }
+
+    /// Record activity on the group, waking it from quiescence if needed.
+    ///
+    /// CheckQuorum only counts acknowledgements received after waking, as none are received
+    /// while the group is quiescent.
+    #[tracing::instrument(level="trace", skip(self))]
+    pub(super) fn wake(&mut self) {
+        self.quiesce_deadline = Instant::now() + Duration::from_millis(self.core.config.quiesce_after);
+        if !self.is_quiescent {
+            return;
+        }
+        tracing::debug!("waking group from quiescence");
+        self.is_quiescent = false;
+        self.acks_reset_at = Instant::now();
+        for node in self.nodes.values().chain(self.non_voters.values().map(|node| &node.state)) {
+            let _ = node.replstream.repltx.send(RaftEvent::Wake);
+        }
+    }
+
     /// Handle events from replication streams requesting for snapshot info.
     #[tracing::instrument(level="trace", skip(self, tx))]
     async fn handle_needs_snapshot(&mut self, _: NodeId, tx: oneshot::Sender<CurrentSnapshotData<S::Snapshot>>) -> RaftResult<()> {
@@ -252,6 +441,18 @@ fn calculate_new_commit_index(mut entries: Vec<u64>, current_commit: u64) -> u64
     }
 }
 
+/// Determine the most recent time at which a majority of the given acknowledgements had been received.
+///
+/// Values of `None` represent nodes which have not yet acknowledged this node as leader.
+fn calculate_quorum_ack(mut acks: Vec<Option<Instant>>) -> Option<Instant> {
+    let len = acks.len();
+    if len == 0 {
+        return None;
+    }
+    acks.sort();
+    acks[(len - 1) / 2] // The most recent value which a majority of the slice is at least as recent as.
+}
+
 /// Check if the given snapshot data is within half of the configured threshold.
 fn snapshot_is_within_half_of_threshold(snapshot_last_index: &u64, last_log_index: &u64, threshold: &u64) -> bool {
     // Calculate distance from actor's last log index.
@@ -299,6 +500,53 @@ mod tests {
         });
     }
 
+    //////////////////////////////////////////////////////////////////////////
+    // calculate_quorum_ack //////////////////////////////////////////////////
+
+    mod calculate_quorum_ack {
+        use super::*;
+        use tokio::time::Duration;
+
+        macro_rules! test_calculate_quorum_ack {
+            ($name:ident, $expected:expr, $acks:expr) => {
+                #[test]
+                fn $name() {
+                    let base = Instant::now();
+                    let at = |millis: u64| Some(base + Duration::from_millis(millis));
+                    let expected: Option<u64> = $expected;
+                    let acks: Vec<Option<u64>> = $acks;
+                    let output = calculate_quorum_ack(acks.into_iter().map(|ack| ack.and_then(at)).collect());
+                    assert_eq!(output, expected.and_then(at));
+                }
+            }
+        }
+
+        test_calculate_quorum_ack!(
+            len_zero_should_return_none,
+            None, vec![]
+        );
+
+        test_calculate_quorum_ack!(
+            basic_values,
+            Some(10), vec![Some(20), Some(5), Some(0), Some(15), Some(10)]
+        );
+
+        test_calculate_quorum_ack!(
+            minority_acked_should_return_none,
+            None, vec![Some(20), None, None]
+        );
+
+        test_calculate_quorum_ack!(
+            majority_acked_ignores_missing,
+            Some(10), vec![Some(20), None, Some(10)]
+        );
+
+        test_calculate_quorum_ack!(
+            even_number_of_nodes,
+            Some(0), vec![Some(0), Some(100), Some(0), Some(100), None, Some(100)]
+        );
+    }
+
     //////////////////////////////////////////////////////////////////////////
     // calculate_new_commit_index ////////////////////////////////////////////
 
@@ -0,0 +1,189 @@
+use tokio::sync::oneshot;
+use tokio::time::{Duration, Instant};
+
+use crate::{AppData, AppDataResponse, RaftNetwork, RaftStorage};
+use crate::core::{LeaderState, RaftCore};
+use crate::core::client::ClientRequestEntry;
+use crate::error::{ClientWriteError, RaftResult, RegisterSessionError};
+use crate::raft::{ClientWriteRequest, ClientWriteResponse, Entry, EntryNormal, EntryPayload, RegisterSessionTx};
+use crate::session::SessionCheck;
+
+impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> RaftCore<D, R, N, S> {
+    /// Apply the given committed entries to the state machine, in order.
+    ///
+    /// Entries without a client session are applied together via `RaftStorage::replicate_to_state_machine`.
+    /// The entries of client sessions are checked against the table of client sessions, and are
+    /// applied one at a time via `RaftStorage::apply_entry_to_state_machine`, so that their
+    /// responses may be cached. The table is saved once all entries have been applied, if changed.
+    ///
+    /// Witnesses hold no state machine data, so they keep no table of client sessions either, and
+    /// ignore the registration & expiry of sessions.
+    #[tracing::instrument(level="trace", skip(self, entries))]
+    pub(super) async fn apply_entries_to_state_machine(&mut self, entries: &[Entry<D>]) -> RaftResult<()> {
+        let is_witness = self.membership.is_witness(&self.id);
+        let mut data_entries = Vec::new();
+        let mut sessions_changed = false;
+        for entry in entries {
+            match &entry.payload {
+                EntryPayload::RegisterSession | EntryPayload::ExpireSessions(_) if is_witness => (),
+                EntryPayload::Normal(inner) if inner.session.is_none() => data_entries.push((&entry.index, &inner.data)),
+                EntryPayload::Normal(inner) => {
+                    // Entries must be applied in log order, so first apply all entries before this one.
+                    self.replicate_to_state_machine(&mut data_entries).await?;
+                    let _ = self.apply_normal_entry(entry.index, inner).await?;
+                    sessions_changed = true;
+                }
+                EntryPayload::RegisterSession => {
+                    self.client_sessions.register(entry.index);
+                    sessions_changed = true;
+                }
+                EntryPayload::ExpireSessions(expire) => {
+                    self.client_sessions.expire(&expire.sessions);
+                    sessions_changed = true;
+                }
+                _ => (),
+            }
+        }
+        self.replicate_to_state_machine(&mut data_entries).await?;
+        if sessions_changed {
+            self.save_client_sessions().await?;
+        }
+        Ok(())
+    }
+
+    /// Apply the given batch of entries to the state machine, leaving the batch empty.
+    async fn replicate_to_state_machine(&mut self, data_entries: &mut Vec<(&u64, &D)>) -> RaftResult<()> {
+        if data_entries.is_empty() {
+            return Ok(());
+        }
+        self.storage.replicate_to_state_machine(data_entries).await.map_err(|err| self.map_fatal_storage_error(err))?;
+        data_entries.clear();
+        Ok(())
+    }
+
+    /// Apply the given normal entry to the state machine, returning the response for its client.
+    ///
+    /// If the entry is part of a client session, then it is only applied if its sequence number
+    /// has not yet been applied, in which case its response is cached in the table of client
+    /// sessions. Otherwise, the cached response is returned, or the entry is rejected. The table
+    /// is not saved here.
+    #[tracing::instrument(level="trace", skip(self, entry))]
+    pub(super) async fn apply_normal_entry(&mut self, index: u64, entry: &EntryNormal<D>) -> RaftResult<Result<ClientWriteResponse<R>, ClientWriteError<D>>> {
+        let session = match &entry.session {
+            Some(session) => session,
+            None => {
+                let data = self.storage.apply_entry_to_state_machine(&index, &entry.data).await.map_err(|err| self.map_fatal_storage_error(err))?;
+                return Ok(Ok(ClientWriteResponse{index, data}));
+            }
+        };
+        match self.client_sessions.check(session) {
+            SessionCheck::Apply => {
+                let data = self.storage.apply_entry_to_state_machine(&index, &entry.data).await.map_err(|err| self.map_fatal_storage_error(err))?;
+                self.client_sessions.record(session, index, data.clone());
+                Ok(Ok(ClientWriteResponse{index, data}))
+            }
+            SessionCheck::Duplicate(res) => {
+                tracing::debug!({index, session_id=session.session_id, sequence=session.sequence}, "skipping duplicate client session entry");
+                Ok(Ok(res))
+            }
+            SessionCheck::UnknownSession => Ok(Err(ClientWriteError::UnknownSession(session.session_id))),
+            SessionCheck::ResponseDiscarded => Ok(Err(ClientWriteError::ResponseDiscarded(session.session_id, session.sequence))),
+        }
+    }
+
+    /// Load the table of client sessions from storage.
+    pub(super) async fn load_client_sessions(&mut self) -> RaftResult<()> {
+        self.client_sessions = self.storage.get_client_sessions().await.map_err(|err| self.map_fatal_storage_error(err))?;
+        Ok(())
+    }
+
+    /// Save the table of client sessions to storage.
+    pub(super) async fn save_client_sessions(&mut self) -> RaftResult<()> {
+        self.storage.save_client_sessions(&self.client_sessions).await.map_err(|err| self.map_fatal_storage_error(err))
+    }
+
+    /// Forward the given request to register a client session to the leader.
+    #[tracing::instrument(level="trace", skip(self, tx))]
+    pub(super) fn forward_register_session(&self, tx: RegisterSessionTx) {
+        let _ = tx.send(Err(RegisterSessionError::ForwardToLeader(self.current_leader)));
+    }
+}
+
+impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> LeaderState<'a, D, R, N, S> {
+    /// Handle a request to register a new client session.
+    ///
+    /// The registration is appended to the log, and the request is resolved with the ID of the
+    /// new session once the registration has been applied.
+    #[tracing::instrument(level="trace", skip(self, tx))]
+    pub(super) async fn handle_register_session(&mut self, tx: RegisterSessionTx) {
+        // Sessions are not registered while leadership is being transferred, just like client writes.
+        if self.leadership_transfer.is_some() {
+            let _ = tx.send(Err(RegisterSessionError::ForwardToLeader(None)));
+            return;
+        }
+        let entry = match self.append_payload_to_log(ClientWriteRequest::<D>::new_register_session().entry).await {
+            Ok(entry) => entry,
+            Err(err) => {
+                let _ = tx.send(Err(RegisterSessionError::RaftError(err)));
+                return;
+            }
+        };
+        self.replicate_client_request(ClientRequestEntry::from_entry(entry, tx)).await;
+    }
+
+    /// Record activity on the client sessions of the given requests, deferring their expiry.
+    pub(super) fn touch_client_sessions<'b>(&mut self, rpcs: impl IntoIterator<Item=&'b ClientWriteRequest<D>>) {
+        let now = Instant::now();
+        for session in rpcs.into_iter().filter_map(|rpc| rpc.session()) {
+            self.session_activity.insert(session.session_id, now);
+        }
+    }
+
+    /// Propose the expiry of all client sessions which have been idle for `Config::client_session_timeout`.
+    ///
+    /// Sessions are tracked from when this node first observes them as leader, so a new leader
+    /// always gives every session a full timeout before expiring it. A session is only forgotten
+    /// once its expiry has been applied, and is not proposed for expiry again in the meantime.
+    #[tracing::instrument(level="trace", skip(self))]
+    pub(super) async fn handle_session_expiry_timeout(&mut self) -> RaftResult<()> {
+        let now = Instant::now();
+        let timeout = Duration::from_millis(self.core.config.client_session_timeout);
+        let sessions = &self.core.client_sessions;
+        self.session_activity.retain(|session_id, _| sessions.contains(*session_id));
+        self.expiring_sessions.retain(|session_id| sessions.contains(*session_id));
+        for session_id in sessions.ids() {
+            self.session_activity.entry(session_id).or_insert(now);
+        }
+        let expiring = &self.expiring_sessions;
+        let expired: Vec<_> = self.session_activity.iter()
+            .filter(|(session_id, last_active)| !expiring.contains(*session_id) && **last_active + timeout <= now)
+            .map(|(session_id, _)| *session_id)
+            .collect();
+
+        // Nothing is proposed while leadership is being transferred, so check again shortly.
+        if !expired.is_empty() && self.leadership_transfer.is_some() {
+            self.session_expiry_deadline = now + Duration::from_millis(self.core.config.heartbeat_interval);
+            return Ok(());
+        }
+        if !expired.is_empty() {
+            tracing::debug!({sessions=?expired}, "expiring idle client sessions");
+            let entry = self.append_payload_to_log(ClientWriteRequest::<D>::new_expire_sessions(expired.clone()).entry).await?;
+            let (tx, rx) = oneshot::channel::<RaftResult<u64>>();
+            self.replicate_client_request(ClientRequestEntry::from_entry(entry, tx)).await;
+            self.expiring_sessions.extend(expired);
+            tokio::spawn(async move {
+                match rx.await {
+                    Ok(Ok(index)) => tracing::debug!({index}, "idle client sessions expired"),
+                    Ok(Err(err)) => tracing::error!({error=%err}, "error expiring idle client sessions"),
+                    Err(_) => tracing::warn!("idle client sessions were not expired, the proposal was dropped"),
+                }
+            });
+        }
+        let expiring = &self.expiring_sessions;
+        self.session_expiry_deadline = self.session_activity.iter()
+            .filter(|(session_id, _)| !expiring.contains(*session_id))
+            .map(|(_, last_active)| *last_active)
+            .min().unwrap_or(now) + timeout;
+        Ok(())
+    }
+}
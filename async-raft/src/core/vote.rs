@@ -1,3 +1,5 @@
+use std::collections::HashSet;
+
 use tokio::time::Instant;
 use tokio::sync::mpsc;
 use tracing_futures::Instrument;
@@ -5,7 +7,7 @@ use tracing_futures::Instrument;
 use crate::{AppData, AppDataResponse, NodeId, RaftNetwork, RaftStorage};
 use crate::error::RaftResult;
 use crate::core::{CandidateState, RaftCore, State, UpdateCurrentLeader};
-use crate::raft::{VoteRequest, VoteResponse};
+use crate::raft::{PreVoteRequest, PreVoteResponse, TimeoutNowRequest, TimeoutNowResponse, VoteRequest, VoteResponse};
 
 impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> RaftCore<D, R, N, S> {
     /// An RPC invoked by candidates to gather votes (§5.2).
@@ -19,8 +21,9 @@ impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> Ra
             return Ok(VoteResponse{term: self.current_term, vote_granted: false});
         }
 
-        // Do not respond to the request if we've received a heartbeat within the election timeout minimum.
-        if let Some(inst) = &self.last_heartbeat {
+        // Do not respond to the request if we've received a heartbeat within the election timeout minimum,
+        // unless the candidate is campaigning as the target of a leadership transfer (§3.10 of the Raft thesis).
+        if let (Some(inst), false) = (&self.last_heartbeat, msg.leadership_transfer) {
             let now = Instant::now();
             let delta = now.duration_since(*inst);
             if self.config.election_timeout_min >= (delta.as_millis() as u64) {
@@ -66,6 +69,83 @@ impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> Ra
             },
         }
     }
+
+    /// An RPC invoked by nodes which have hit their election timeout, to determine if they could win an election (§9.6).
+    ///
+    /// Handling this RPC never updates this node's term, vote or election timeout.
+    #[tracing::instrument(level="trace", skip(self, msg))]
+    pub(super) async fn handle_pre_vote_request(&mut self, msg: PreVoteRequest) -> RaftResult<PreVoteResponse> {
+        // If the candidate's prospective term is less than this node's current term, reject.
+        if msg.term < self.current_term {
+            tracing::trace!({candidate=msg.candidate_id, self.current_term, rpc_term=msg.term}, "PreVote RPC term is less than current term");
+            return Ok(PreVoteResponse{term: self.current_term, vote_granted: false});
+        }
+
+        // Reject if this node believes the current leader is still alive. This is what prevents
+        // a node which has been partitioned from disrupting a healthy cluster.
+        if self.target_state.is_leader() {
+            tracing::trace!({candidate=msg.candidate_id}, "rejecting pre-vote request as this node is the cluster leader");
+            return Ok(PreVoteResponse{term: self.current_term, vote_granted: false});
+        }
+        if let Some(inst) = &self.last_heartbeat {
+            let delta = Instant::now().duration_since(*inst);
+            if self.config.election_timeout_min >= (delta.as_millis() as u64) {
+                tracing::trace!({candidate=msg.candidate_id}, "rejecting pre-vote request received within election timeout minimum");
+                return Ok(PreVoteResponse{term: self.current_term, vote_granted: false});
+            }
+        }
+
+        // Grant the pre-vote only if the candidate's log is at least as up-to-date as this node's.
+        let client_is_uptodate = (msg.last_log_term >= self.last_log_term) && (msg.last_log_index >= self.last_log_index);
+        if !client_is_uptodate {
+            tracing::trace!({candidate=msg.candidate_id}, "rejecting pre-vote request as candidate's log is not up-to-date");
+        }
+        Ok(PreVoteResponse{term: self.current_term, vote_granted: client_is_uptodate})
+    }
+
+    /// An RPC invoked by the leader to have this node start an election immediately, as part of a
+    /// leadership transfer (§3.10 of the Raft thesis).
+    #[tracing::instrument(level="trace", skip(self, msg))]
+    pub(super) async fn handle_timeout_now_request(&mut self, msg: TimeoutNowRequest) -> RaftResult<TimeoutNowResponse> {
+        // If the leader's term is less than this node's current term, reject.
+        if msg.term < self.current_term {
+            tracing::trace!({leader=msg.leader_id, self.current_term, rpc_term=msg.term}, "TimeoutNow RPC term is less than current term");
+            return Ok(TimeoutNowResponse{term: self.current_term});
+        }
+        // Per spec, if we observe a term greater than our own, then we must update term & immediately
+        // become follower. The leader of the new term is not yet known to this node, so the RPC is rejected.
+        if msg.term > self.current_term {
+            self.update_current_term(msg.term, None);
+            self.update_next_election_timeout();
+            self.update_current_leader(UpdateCurrentLeader::Unknown);
+            if !self.target_state.is_non_voter() {
+                self.set_target_state(State::Follower);
+            }
+            self.save_hard_state().await?;
+            tracing::trace!({leader=msg.leader_id, rpc_term=msg.term}, "rejecting TimeoutNow RPC from a leader of a newer term");
+            return Ok(TimeoutNowResponse{term: self.current_term});
+        }
+        // Only the current leader may hand over its leadership.
+        if self.current_leader != Some(msg.leader_id) {
+            tracing::trace!({leader=msg.leader_id, current_leader=?self.current_leader}, "rejecting TimeoutNow RPC as the sender is not the current leader");
+            return Ok(TimeoutNowResponse{term: self.current_term});
+        }
+
+        // Only followers may campaign. Non-voters are not part of the cluster config, and any
+        // other state indicates that an election is already underway.
+        if !self.target_state.is_follower() {
+            tracing::trace!({leader=msg.leader_id}, "ignoring TimeoutNow RPC as this node is not a follower");
+            return Ok(TimeoutNowResponse{term: self.current_term});
+        }
+        // Witnesses never become leader.
+        if self.membership.is_witness(&self.id) {
+            tracing::trace!({leader=msg.leader_id}, "ignoring TimeoutNow RPC as this node is a witness");
+            return Ok(TimeoutNowResponse{term: self.current_term});
+        }
+        self.is_leadership_transfer_target = true;
+        self.set_target_state(State::Candidate);
+        Ok(TimeoutNowResponse{term: self.current_term})
+    }
 }
 
 impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> CandidateState<'a, D, R, N, S> {
@@ -104,13 +184,63 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
         Ok(())
     }
 
+    /// Handle response from a pre-vote request sent to a peer.
+    ///
+    /// Returns `true` once enough pre-votes have been granted from both config groups for this
+    /// node to start a real election.
+    #[tracing::instrument(level="trace", skip(self, res, target))]
+    pub(super) async fn handle_pre_vote_response(&mut self, res: PreVoteResponse, target: NodeId) -> RaftResult<bool> {
+        // A rejection carrying a greater term means that there is a newer term in the cluster, so
+        // revert to follower state in that term.
+        if !res.vote_granted && res.term > self.core.current_term {
+            self.core.update_current_term(res.term, None);
+            self.core.update_current_leader(UpdateCurrentLeader::Unknown);
+            self.core.set_target_state(State::Follower);
+            self.core.save_hard_state().await?;
+            tracing::trace!("reverting to follower state due to greater term observed in PreVote RPC response");
+            return Ok(false);
+        }
+        if !res.vote_granted {
+            return Ok(false);
+        }
+
+        if self.core.membership.members.contains(&target) {
+            self.votes_granted_old += 1;
+        }
+        if self.core.membership.members_after_consensus.as_ref().map(|members| members.contains(&target)).unwrap_or(false) {
+            self.votes_granted_new += 1;
+        }
+        Ok(self.votes_granted_old >= self.votes_needed_old && self.votes_granted_new >= self.votes_needed_new)
+    }
+
+    /// Spawn parallel pre-vote requests to all cluster members.
+    #[tracing::instrument(level="trace", skip(self))]
+    pub(super) fn spawn_parallel_pre_vote_requests(&self) -> mpsc::Receiver<(PreVoteResponse, NodeId)> {
+        let all_members = self.core.membership.all_nodes();
+        let (tx, rx) = mpsc::channel(all_members.len());
+        for member in sorted_peers(all_members, self.core.id) {
+            let rpc = PreVoteRequest::new(self.core.current_term + 1, self.core.id, self.core.last_log_index, self.core.last_log_term);
+            let (network, mut tx_inner) = (self.core.network.clone(), tx.clone());
+            tokio::spawn(async move {
+                match network.pre_vote(member, rpc).await {
+                    Ok(res) => {
+                        let _ = tx_inner.send((res, member)).await;
+                    }
+                    Err(err) => tracing::error!({error=%err, peer=member}, "error while requesting pre-vote from peer"),
+                }
+            }.instrument(tracing::trace_span!("requesting pre-vote from peer", target=member)));
+        }
+        rx
+    }
+
     /// Spawn parallel vote requests to all cluster members.
     #[tracing::instrument(level="trace", skip(self))]
-    pub(super) fn spawn_parallel_vote_requests(&self) -> mpsc::Receiver<(VoteResponse, NodeId)> {
+    pub(super) fn spawn_parallel_vote_requests(&self, leadership_transfer: bool) -> mpsc::Receiver<(VoteResponse, NodeId)> {
         let all_members = self.core.membership.all_nodes();
         let (tx, rx) = mpsc::channel(all_members.len());
-        for member in all_members.into_iter().filter(|member| member != &self.core.id) {
-            let rpc = VoteRequest::new(self.core.current_term, self.core.id, self.core.last_log_index, self.core.last_log_term);
+        for member in sorted_peers(all_members, self.core.id) {
+            let mut rpc = VoteRequest::new(self.core.current_term, self.core.id, self.core.last_log_index, self.core.last_log_term);
+            rpc.leadership_transfer = leadership_transfer;
             let (network, mut tx_inner) = (self.core.network.clone(), tx.clone());
             let _ = tokio::spawn(async move {
                 match network.vote(member, rpc).await {
@@ -124,3 +254,12 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
         rx
     }
 }
+
+/// Collect the given members, excluding `id`, in ascending order.
+///
+/// Requests are spawned in a deterministic order, so that a seeded simulation is reproducible.
+fn sorted_peers(members: HashSet<NodeId>, id: NodeId) -> Vec<NodeId> {
+    let mut peers = members.into_iter().filter(|member| member != &id).collect::<Vec<_>>();
+    peers.sort_unstable();
+    peers
+}
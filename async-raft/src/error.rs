@@ -1,9 +1,14 @@
 //! Error types exposed by this crate.
 
+use std::time::Duration;
+
 use thiserror::Error;
 
 use crate::{AppData, NodeId};
+use crate::metrics::RaftMetrics;
+use crate::multi::GroupId;
 use crate::raft::ClientWriteRequest;
+use crate::session::SessionId;
 
 /// A result type where the error variant is always a `RaftError`.
 pub type RaftResult<T> = std::result::Result<T, RaftError>;
@@ -21,6 +26,26 @@ pub enum RaftError {
     /// An internal Raft error indicating that Raft is shutting down.
     #[error("Raft is shutting down")]
     ShuttingDown,
+    /// This node lost leadership before the request was committed.
+    ///
+    /// The request has been appended to the log, so it may still be committed by a new leader.
+    #[error("leadership was lost before the request was committed")]
+    LeadershipLost,
+}
+
+impl RaftError {
+    /// Create a copy of this error with the same variant, for reporting to more than one waiter.
+    ///
+    /// `anyhow::Error` is not `Clone`, so the copy of a storage or network error carries the
+    /// message of the original, including its chain of causes.
+    pub(crate) fn duplicate(&self) -> Self {
+        match self {
+            Self::RaftStorage(err) => Self::RaftStorage(anyhow::anyhow!("{:#}", err)),
+            Self::RaftNetwork(err) => Self::RaftNetwork(anyhow::anyhow!("{:#}", err)),
+            Self::ShuttingDown => Self::ShuttingDown,
+            Self::LeadershipLost => Self::LeadershipLost,
+        }
+    }
 }
 
 impl From<tokio::io::Error> for RaftError {
@@ -49,6 +74,47 @@ pub enum ClientWriteError<D: AppData> {
     /// The client write request must be forwarded to the cluster leader.
     #[error("the client write request must be forwarded to the cluster leader")]
     ForwardToLeader(ClientWriteRequest<D>, Option<NodeId>),
+    /// The node already has `Config::max_inflight_client_writes` client writes in flight.
+    ///
+    /// The request was not submitted, and may be retried once the node has caught up.
+    #[error("too many client writes are in flight, the client write request was rejected")]
+    Overloaded(ClientWriteRequest<D>),
+    /// The client session of the request does not exist, as it has expired or was never registered.
+    ///
+    /// The request was not applied to the state machine. A new session must be registered.
+    #[error("client session {0} does not exist")]
+    UnknownSession(SessionId),
+    /// The request has already been applied to the state machine, but its response has been discarded.
+    ///
+    /// This is the case for retries of requests through the `responded_to` sequence number of their session.
+    #[error("request {1} of client session {0} was already applied, and its response has been discarded")]
+    ResponseDiscarded(SessionId, u64),
+}
+
+/// An error related to registering a client session.
+#[derive(Debug, Error)]
+pub enum RegisterSessionError {
+    /// A Raft error.
+    #[error("{0}")]
+    RaftError(#[from] RaftError),
+    /// The request must be forwarded to the cluster leader.
+    #[error("the request to register a client session must be forwarded to the cluster leader")]
+    ForwardToLeader(Option<NodeId>),
+}
+
+/// An error related to a group hosted on a `MultiRaft`.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum MultiRaftError {
+    /// A Raft error.
+    #[error("{0}")]
+    RaftError(#[from] RaftError),
+    /// The target group is not hosted on this node.
+    #[error("group {0} is not hosted on this node")]
+    GroupNotFound(GroupId),
+    /// The target group is already hosted on this node.
+    #[error("group {0} is already hosted on this node")]
+    GroupExists(GroupId),
 }
 
 /// Error variants related to configuration.
@@ -61,6 +127,27 @@ pub enum ConfigError {
     /// The given value for max_payload_entries is too small, must be > 0.
     #[error("the given value for max_payload_entries is too small, must be > 0")]
     MaxPayloadEntriesTooSmall,
+    /// The given value for max_inflight_append_entries is too small, must be > 0.
+    #[error("the given value for max_inflight_append_entries is too small, must be > 0")]
+    MaxInflightAppendEntriesTooSmall,
+    /// The given value for max_inflight_client_writes is too small, must be > 0.
+    #[error("the given value for max_inflight_client_writes is too small, must be > 0")]
+    MaxInflightClientWritesTooSmall,
+    /// The given value for max_inflight_client_writes is too large, must be <= api_channel_capacity.
+    #[error("the given value for max_inflight_client_writes is too large, must be <= api_channel_capacity")]
+    MaxInflightClientWritesTooLarge,
+    /// The given value for api_channel_capacity is too small, must be > 0.
+    #[error("the given value for api_channel_capacity is too small, must be > 0")]
+    ApiChannelCapacityTooSmall,
+    /// The given value for max_replication_buffer_bytes is too small, must be > 0.
+    #[error("the given value for max_replication_buffer_bytes is too small, must be > 0")]
+    MaxReplicationBufferBytesTooSmall,
+    /// The given value for clock_drift_bound is too large, must be < election_timeout_min when leader leases are enabled.
+    #[error("the given value for clock_drift_bound is too large, must be < election_timeout_min when leader leases are enabled")]
+    ClockDriftBoundTooLarge,
+    /// The given value for heartbeat_coalesce_window is too large, must be < heartbeat_interval.
+    #[error("the given value for heartbeat_coalesce_window is too large, must be < heartbeat_interval")]
+    HeartbeatCoalesceWindowTooLarge,
 }
 
 /// The set of errors which may take place when initializing a pristine Raft node.
@@ -102,6 +189,57 @@ pub enum ChangeConfigError {
     /// This takes into account a current joint consensus and the end result of the config.
     #[error("the proposed config change would have no effect, this is a no-op")]
     Noop,
+    /// The node already has `Config::max_inflight_client_writes` client writes in flight.
+    ///
+    /// The config change was not proposed, and may be retried once the node has caught up.
+    #[error("too many client writes are in flight, the config change was rejected")]
+    Overloaded,
+    /// The config change was rejected by the given client session, as the session has expired or
+    /// the request's response has been discarded.
+    ///
+    /// Config changes are not proposed as part of a client session, so this indicates a bug.
+    #[error("the config change was rejected by client session {0}")]
+    SessionRejected(SessionId),
+}
+
+/// The set of errors which may take place when requesting a leadership transfer.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum LeadershipTransferError {
+    /// An internal error has taken place.
+    #[error("{0}")]
+    RaftError(#[from] RaftError),
+    /// The node the leadership transfer request was sent to was not the leader of the cluster.
+    #[error("this node is not the Raft leader")]
+    NodeNotLeader(Option<NodeId>),
+    /// The given target is not a voting member of the cluster, or is this node.
+    #[error("the given target is not a valid leadership transfer target")]
+    InvalidTarget,
+    /// A leadership transfer is already in progress.
+    #[error("a leadership transfer is already in progress")]
+    TransferInProgress,
+    /// The target node did not become leader within an election timeout.
+    #[error("the target node did not become leader within an election timeout")]
+    Timeout,
+}
+
+/// An error related to waiting for a condition on the metrics of a Raft node.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum WaitError {
+    /// An internal error has taken place, such as the node shutting down.
+    #[error("{0}")]
+    RaftError(#[from] RaftError),
+    /// The condition did not hold within the timeout.
+    #[error("timeout after {timeout:?} waiting for {condition}, last seen metrics: {metrics:?}")]
+    Timeout {
+        /// The timeout which elapsed.
+        timeout: Duration,
+        /// A description of the condition which was waited for.
+        condition: String,
+        /// The last metrics seen before the timeout elapsed.
+        metrics: Box<RaftMetrics>,
+    },
 }
 
 impl<D: AppData> From<ClientWriteError<D>> for ChangeConfigError {
@@ -109,6 +247,8 @@ impl<D: AppData> From<ClientWriteError<D>> for ChangeConfigError {
         match src {
             ClientWriteError::RaftError(err) => Self::RaftError(err),
             ClientWriteError::ForwardToLeader(_, _) => Self::NodeNotLeader,
+            ClientWriteError::Overloaded(_) => Self::Overloaded,
+            ClientWriteError::UnknownSession(id) | ClientWriteError::ResponseDiscarded(id, _) => Self::SessionRejected(id),
         }
     }
 }
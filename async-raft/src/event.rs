@@ -0,0 +1,66 @@
+//! Events emitted by a Raft node.
+//!
+//! Unlike metrics, which only describe the latest state of a Raft node, events describe each of
+//! the transitions which the node goes through, so that none are missed between two observations.
+//! Events are observed on a running Raft node via the `Raft::subscribe_events()` method, which
+//! returns a broadcast receiver of events.
+
+use crate::NodeId;
+use crate::raft::MembershipConfig;
+
+/// The capacity of the channel over which events are broadcast.
+///
+/// A subscriber which falls behind by more than this many events misses the oldest events, and
+/// observes a `RecvError::Lagged` error reporting how many were missed.
+pub(crate) const EVENT_CHANNEL_CAPACITY: usize = 256;
+
+/// An event describing a transition of a Raft node, or of the cluster as observed by the node.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// This node became the cluster leader.
+    BecameLeader {
+        /// The term which this node leads.
+        term: u64,
+    },
+    /// This node stepped down from being the cluster leader.
+    SteppedDown {
+        /// The term which this node led.
+        term: u64,
+    },
+    /// The leader known to this node changed.
+    LeaderChanged {
+        /// The current term of this node.
+        term: u64,
+        /// The new leader, or `None` if the leader is now unknown, such as during an election.
+        leader: Option<NodeId>,
+    },
+    /// A membership config was committed.
+    ///
+    /// A config which is in joint consensus marks the cluster entering joint consensus, and the
+    /// uniform config committed after it marks the cluster leaving joint consensus.
+    MembershipCommitted {
+        /// The index of the log entry which carries the config.
+        index: u64,
+        /// The committed config.
+        membership: MembershipConfig,
+    },
+    /// This node built a snapshot of its state machine, compacting its log.
+    SnapshotBuilt {
+        /// The index of the last log entry covered by the snapshot.
+        index: u64,
+    },
+    /// This node installed a snapshot which was streamed to it by the leader.
+    SnapshotInstalled {
+        /// The index of the last log entry covered by the snapshot.
+        index: u64,
+        /// The term of the last log entry covered by the snapshot.
+        term: u64,
+    },
+    /// This node deleted entries from its log which conflicted with entries sent by the leader.
+    LogTruncated {
+        /// The index of the first entry deleted. All entries from this index onwards were deleted.
+        index: u64,
+        /// The term of the leader which sent the conflicting entries.
+        term: u64,
+    },
+}
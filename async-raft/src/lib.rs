@@ -1,14 +1,26 @@
 #![cfg_attr(feature="docinclude", feature(external_doc))]
 #![cfg_attr(feature="docinclude", doc(include="../README.md"))]
 
+#[macro_use]
+mod macros;
+
 pub mod config;
 mod core;
 pub mod error;
+pub mod event;
 pub mod metrics;
+pub mod multi;
 pub mod network;
 mod replication;
 pub mod raft;
+#[cfg(feature="simulation")]
+pub mod rng;
+#[cfg(not(feature="simulation"))]
+mod rng;
+pub mod session;
 pub mod storage;
+#[cfg(feature="testing")]
+pub mod testing;
 
 use std::fmt::Debug;
 
@@ -19,6 +31,7 @@ pub use crate::{
     core::State,
     error::{ClientWriteError, ConfigError, InitializeError, ChangeConfigError, RaftError},
     metrics::RaftMetrics,
+    multi::{GroupId, MultiRaft, MultiRaftNetwork},
     network::RaftNetwork,
     raft::Raft,
     storage::RaftStorage,
@@ -0,0 +1,101 @@
+//! Macros used internally by the Raft core.
+
+/// Wait on multiple concurrent branches, returning when the first branch completes.
+///
+/// Without the `simulation` feature, this is simply `tokio::select!`.
+#[cfg(not(feature="simulation"))]
+macro_rules! select {
+    ($($t:tt)*) => { tokio::select!($($t)*) };
+}
+
+/// Wait on multiple concurrent branches, returning when the first branch completes.
+///
+/// This mirrors `tokio::select!` for the syntax used in this crate: each branch takes the form
+/// `<pattern> = <future> (, if <precondition>)? => <handler>`. A branch whose precondition is false,
+/// or whose output does not match its pattern, is disabled for the remainder of the call.
+///
+/// Like `tokio::select!`, the branch which is polled first is picked at random on every poll, so
+/// that no branch starves the others. Unlike `tokio::select!`, the random pick is drawn from
+/// `crate::rng`, which allows for it to be seeded for deterministic simulation.
+#[cfg(feature="simulation")]
+macro_rules! select {
+    // Normalize each branch into a `(variant, pattern, future, precondition, handler)` tuple.
+    (@{ ($($b:tt)*) ($v:ident $($vs:ident)*) } $p:pat = $f:expr, if $c:expr => $h:block, $($r:tt)*) => {
+        select!(@{ ($($b)* ($v, $p, $f, $c, $h)) ($($vs)*) } $($r)*)
+    };
+    (@{ ($($b:tt)*) ($v:ident $($vs:ident)*) } $p:pat = $f:expr, if $c:expr => $h:block $($r:tt)*) => {
+        select!(@{ ($($b)* ($v, $p, $f, $c, $h)) ($($vs)*) } $($r)*)
+    };
+    (@{ ($($b:tt)*) ($v:ident $($vs:ident)*) } $p:pat = $f:expr, if $c:expr => $h:expr, $($r:tt)*) => {
+        select!(@{ ($($b)* ($v, $p, $f, $c, { $h })) ($($vs)*) } $($r)*)
+    };
+    (@{ ($($b:tt)*) ($v:ident $($vs:ident)*) } $p:pat = $f:expr, if $c:expr => $h:expr) => {
+        select!(@{ ($($b)* ($v, $p, $f, $c, { $h })) ($($vs)*) })
+    };
+    (@{ ($($b:tt)*) ($v:ident $($vs:ident)*) } $p:pat = $f:expr => $h:block, $($r:tt)*) => {
+        select!(@{ ($($b)* ($v, $p, $f, true, $h)) ($($vs)*) } $($r)*)
+    };
+    (@{ ($($b:tt)*) ($v:ident $($vs:ident)*) } $p:pat = $f:expr => $h:block $($r:tt)*) => {
+        select!(@{ ($($b)* ($v, $p, $f, true, $h)) ($($vs)*) } $($r)*)
+    };
+    (@{ ($($b:tt)*) ($v:ident $($vs:ident)*) } $p:pat = $f:expr => $h:expr, $($r:tt)*) => {
+        select!(@{ ($($b)* ($v, $p, $f, true, { $h })) ($($vs)*) } $($r)*)
+    };
+    (@{ ($($b:tt)*) ($v:ident $($vs:ident)*) } $p:pat = $f:expr => $h:expr) => {
+        select!(@{ ($($b)* ($v, $p, $f, true, { $h })) ($($vs)*) })
+    };
+    (@{ ($($b:tt)*) ($($vs:ident)*) }) => {
+        select!(@emit $($b)*)
+    };
+
+    // Emit the select over the normalized branches.
+    (@emit $(($v:ident, $p:pat, $f:expr, $c:expr, $h:block))+) => {{
+        #[allow(non_camel_case_types)]
+        enum __SelectOutput<$($v),+> {
+            $($v($v),)+
+        }
+
+        #[allow(non_snake_case, unused_assignments, unused_variables, unreachable_patterns)]
+        let __output = {
+            let mut __enabled = [$($c),+];
+            let ($($v,)+) = ($($f,)+);
+            $(::futures::pin_mut!($v);)+
+            ::futures::future::poll_fn(|cx| {
+                let __count = __enabled.len();
+                let __start = $crate::rng::gen_index(__count);
+                let mut __is_pending = false;
+                for __offset in 0..__count {
+                    let __branch = (__start + __offset) % __count;
+                    let mut __index = 0;
+                    $(
+                        if __branch == __index && __enabled[__index] {
+                            match ::std::future::Future::poll($v.as_mut(), cx) {
+                                ::std::task::Poll::Ready(__out) => match &__out {
+                                    $p => return ::std::task::Poll::Ready(__SelectOutput::$v(__out)),
+                                    _ => __enabled[__index] = false,
+                                },
+                                ::std::task::Poll::Pending => __is_pending = true,
+                            }
+                        }
+                        __index += 1;
+                    )+
+                }
+                if !__is_pending {
+                    panic!("all branches are disabled and there is no else branch");
+                }
+                ::std::task::Poll::Pending
+            }).await
+        };
+
+        #[allow(unreachable_patterns)]
+        let __result = match __output {
+            $(__SelectOutput::$v($p) => $h,)+
+            _ => unreachable!(),
+        };
+        __result
+    }};
+
+    ($($t:tt)*) => {
+        select!(@{ () (B0 B1 B2 B3 B4 B5 B6 B7 B8 B9 B10 B11 B12 B13 B14 B15) } $($t)*)
+    };
+}
@@ -5,12 +5,22 @@
 //! use this data to trigger events within higher levels of the parent application.
 //!
 //! Metrics are observed on a running Raft node via the `Raft::metrics()` method, which will
-//! return a stream of metrics.
+//! return a stream of metrics. With the `prometheus` feature enabled, the `prometheus` module
+//! exports this stream as Prometheus metrics.
+
+use std::collections::{BTreeMap, HashSet};
+
+use tokio::sync::watch;
+use tokio::time::{Duration, Instant, timeout_at};
 
 use crate::NodeId;
 use crate::core::State;
+use crate::error::{RaftError, WaitError};
 use crate::raft::MembershipConfig;
 
+#[cfg(feature="prometheus")]
+pub mod prometheus;
+
 /// A set of metrics describing the current state of a Raft node.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct RaftMetrics {
@@ -24,15 +34,137 @@ pub struct RaftMetrics {
     pub last_log_index: u64,
     /// The last log index to be applied to this Raft node's state machine.
     pub last_applied: u64,
+    /// The index of the last log entry covered by this Raft node's current snapshot.
+    pub snapshot_index: u64,
     /// The current cluster leader.
     pub current_leader: Option<NodeId>,
     /// The current membership config of the cluster.
     pub membership_config: MembershipConfig,
+    /// The replication metrics of each target which the node is replicating to, keyed by target ID.
+    ///
+    /// This is only populated while the node is the cluster leader, and covers both voting
+    /// members & non-voters. It is empty in all other states.
+    pub replication: BTreeMap<NodeId, ReplicationMetrics>,
 }
 
 impl RaftMetrics {
     pub(crate) fn new_initial(id: NodeId) -> Self {
         let membership_config = MembershipConfig::new_initial(id);
-        Self{id, state: State::Follower, current_term: 0, last_log_index: 0, last_applied: 0, current_leader: None, membership_config}
+        Self{id, state: State::Follower, current_term: 0, last_log_index: 0, last_applied: 0, snapshot_index: 0, current_leader: None, membership_config, replication: BTreeMap::new()}
+    }
+}
+
+/// Metrics describing the replication of the leader's log to a single target node.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReplicationMetrics {
+    /// The index of the most recent log entry known to be replicated on the target.
+    pub match_index: u64,
+    /// The term of the most recent log entry known to be replicated on the target.
+    pub match_term: u64,
+    /// The state of the replication stream to the target.
+    pub state: ReplicationStreamState,
+    /// The send time of the most recent RPC which the target acknowledged in the leader's term.
+    ///
+    /// Heartbeats are RPCs too, so this is the time of the last heartbeat ack for an idle target.
+    /// As acks arrive with every heartbeat, they do not cause metrics to be reported on their own,
+    /// so this is only as current as the last report. Use `Raft::replication_ack_ages` for the
+    /// current value.
+    pub last_ack: Option<Instant>,
+    /// The progress of the snapshot being streamed to the target, if the stream is snapshotting.
+    pub snapshot: Option<SnapshotProgress>,
+}
+
+/// The state of a replication stream from the leader to a target node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReplicationStreamState {
+    /// The target is up-to-date, and receives new entries as soon as they are appended.
+    LineRate,
+    /// The target is behind, and is being sent entries from storage in order to catch up.
+    Lagging,
+    /// The target is so far behind that a snapshot is being streamed over to it.
+    Snapshotting,
+}
+
+/// The progress of a snapshot being streamed to a target node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SnapshotProgress {
+    /// The index of the last log entry covered by the snapshot.
+    pub index: u64,
+    /// The number of bytes of the snapshot which the target has received.
+    pub offset: u64,
+    /// The total size of the snapshot in bytes.
+    pub size: u64,
+}
+
+/// A handle for waiting until the metrics of a Raft node satisfy a condition.
+///
+/// This is created via `Raft::wait`. Each method resolves with the metrics which satisfied its
+/// condition as soon as the condition holds, or fails with `WaitError::Timeout`, carrying the last
+/// metrics seen, if it does not hold within the timeout.
+///
+/// ```ignore
+/// raft.wait(Duration::from_secs(1)).state(State::Leader).await?;
+/// raft.wait(Duration::from_secs(1)).applied(10).await?;
+/// ```
+#[derive(Clone)]
+pub struct Wait {
+    /// The time to wait for each condition.
+    timeout: Duration,
+    /// The metrics channel of the Raft node.
+    rx: watch::Receiver<RaftMetrics>,
+}
+
+impl Wait {
+    pub(crate) fn new(timeout: Duration, rx: watch::Receiver<RaftMetrics>) -> Self {
+        Self{timeout, rx}
+    }
+
+    /// Wait for the metrics to satisfy the given predicate, described by `condition` in errors.
+    pub async fn metrics<F>(&self, predicate: F, condition: impl ToString) -> Result<RaftMetrics, WaitError>
+        where F: Fn(&RaftMetrics) -> bool,
+    {
+        let deadline = Instant::now() + self.timeout;
+        let mut rx = self.rx.clone();
+        loop {
+            let latest = rx.borrow().clone();
+            if predicate(&latest) {
+                return Ok(latest);
+            }
+            match timeout_at(deadline, rx.recv()).await {
+                Ok(Some(_)) => continue,
+                Ok(None) => return Err(WaitError::RaftError(RaftError::ShuttingDown)),
+                Err(_) => return Err(WaitError::Timeout{
+                    timeout: self.timeout,
+                    condition: condition.to_string(),
+                    metrics: Box::new(rx.borrow().clone()),
+                }),
+            }
+        }
+    }
+
+    /// Wait for the last log index of the node to reach at least `index`.
+    pub async fn log_index(&self, index: u64) -> Result<RaftMetrics, WaitError> {
+        self.metrics(|metrics| metrics.last_log_index >= index, format!("last_log_index >= {}", index)).await
+    }
+
+    /// Wait for the last applied index of the node to reach at least `index`.
+    pub async fn applied(&self, index: u64) -> Result<RaftMetrics, WaitError> {
+        self.metrics(|metrics| metrics.last_applied >= index, format!("last_applied >= {}", index)).await
+    }
+
+    /// Wait for the node to be in the given state.
+    pub async fn state(&self, state: State) -> Result<RaftMetrics, WaitError> {
+        self.metrics(|metrics| metrics.state == state, format!("state == {:?}", state)).await
+    }
+
+    /// Wait for the node to know the given node as the cluster leader.
+    pub async fn current_leader(&self, leader: NodeId) -> Result<RaftMetrics, WaitError> {
+        self.metrics(|metrics| metrics.current_leader == Some(leader), format!("current_leader == {}", leader)).await
+    }
+
+    /// Wait for the members of the node's membership config to be exactly the given set.
+    pub async fn members(&self, members: HashSet<NodeId>) -> Result<RaftMetrics, WaitError> {
+        let condition = format!("members == {:?}", members);
+        self.metrics(|metrics| metrics.membership_config.members == members, condition).await
     }
 }
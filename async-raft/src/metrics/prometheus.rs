@@ -0,0 +1,202 @@
+//! A Prometheus exporter for the metrics of a running Raft node.
+//!
+//! This module is only available with the `prometheus` feature enabled.
+
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Mutex;
+
+use ::prometheus::{Encoder, IntCounter, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder};
+use tokio::sync::watch;
+
+use crate::NodeId;
+use crate::core::State;
+use crate::metrics::RaftMetrics;
+
+/// The content type of the payload produced by `PrometheusExporter::encode`.
+///
+/// This is the content type of the Prometheus text format, which HTTP handlers serving the
+/// payload should set on their responses.
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// All states of a Raft node, along with the value of the `state` label of each.
+const STATES: &[(State, &str)] = &[
+    (State::NonVoter, "non_voter"),
+    (State::Follower, "follower"),
+    (State::Candidate, "candidate"),
+    (State::Leader, "leader"),
+    (State::Shutdown, "shutdown"),
+];
+
+/// A set of Prometheus collectors which are kept current from the metrics of a Raft node.
+///
+/// The exporter registers its collectors in the `Registry` given at construction, and updates
+/// them from each `RaftMetrics` payload it observes. The collectors are:
+///
+/// - `raft_current_term`, `raft_last_log_index`, `raft_last_applied` & `raft_snapshot_index`.
+/// - `raft_state`, which is 1 for the label of the node's current state, and 0 for all others.
+/// - `raft_current_leader`, which is the ID of the current leader, or -1 if it is unknown.
+/// - `raft_membership_size`, the number of voting members in the node's membership config.
+/// - `raft_replication_match_index` & `raft_replication_lag` by `target`, which are only
+///   reported by the leader. The lag of a target is the number of entries of the leader's log
+///   which are not yet known to be replicated on the target.
+/// - `raft_elections_total`, the number of elections this node has campaigned in.
+/// - `raft_leader_changes_total`, the number of leaders this node has observed, counting the
+///   leader of each term once.
+/// - `raft_snapshots_total`, the number of snapshots this node has built or installed.
+///
+/// Counters are derived from consecutive payloads. As the metrics channel only retains the
+/// latest payload, an event which is superseded before the exporter observes it is not counted.
+///
+/// The payload to serve to Prometheus is produced by `encode`, which may be mounted on any HTTP
+/// server:
+///
+/// ```ignore
+/// let registry = Registry::new();
+/// let exporter = Arc::new(PrometheusExporter::new(&registry)?);
+/// tokio::spawn({
+///     let exporter = exporter.clone();
+///     async move { exporter.track(raft.metrics()).await }
+/// });
+/// // Within the HTTP handler for `/metrics`, respond with `CONTENT_TYPE` & this body.
+/// let body = exporter.encode()?;
+/// ```
+pub struct PrometheusExporter {
+    /// The registry in which the collectors are registered.
+    registry: Registry,
+    current_term: IntGauge,
+    state: IntGaugeVec,
+    current_leader: IntGauge,
+    last_log_index: IntGauge,
+    last_applied: IntGauge,
+    snapshot_index: IntGauge,
+    membership_size: IntGauge,
+    replication_match_index: IntGaugeVec,
+    replication_lag: IntGaugeVec,
+    elections: IntCounter,
+    leader_changes: IntCounter,
+    snapshots: IntCounter,
+    /// The most recently observed metrics payload.
+    last: Mutex<Option<RaftMetrics>>,
+}
+
+impl PrometheusExporter {
+    /// Create a new instance, registering its collectors in the given registry.
+    pub fn new(registry: &Registry) -> ::prometheus::Result<Self> {
+        Self::with_labels(registry, HashMap::new())
+    }
+
+    /// Create a new instance whose collectors all carry the given constant labels.
+    ///
+    /// This allows the exporters of several Raft nodes to share a registry, for example the
+    /// exporters of the groups hosted on a `MultiRaft`, each labelled with its group ID.
+    pub fn with_labels(registry: &Registry, labels: HashMap<String, String>) -> ::prometheus::Result<Self> {
+        let opts = |name: &str, help: &str| Opts::new(name, help).const_labels(labels.clone());
+        let this = Self{
+            registry: registry.clone(),
+            current_term: IntGauge::with_opts(opts("raft_current_term", "The current term of the Raft node."))?,
+            state: IntGaugeVec::new(opts("raft_state", "The state of the Raft node, which is 1 for the current state."), &["state"])?,
+            current_leader: IntGauge::with_opts(opts("raft_current_leader", "The ID of the current cluster leader, or -1 if unknown."))?,
+            last_log_index: IntGauge::with_opts(opts("raft_last_log_index", "The last log index to be appended to the log."))?,
+            last_applied: IntGauge::with_opts(opts("raft_last_applied", "The last log index to be applied to the state machine."))?,
+            snapshot_index: IntGauge::with_opts(opts("raft_snapshot_index", "The last log index covered by the current snapshot."))?,
+            membership_size: IntGauge::with_opts(opts("raft_membership_size", "The number of voting members of the cluster."))?,
+            replication_match_index: IntGaugeVec::new(
+                opts("raft_replication_match_index", "The last log index known to be replicated on the target, reported by the leader."),
+                &["target"],
+            )?,
+            replication_lag: IntGaugeVec::new(
+                opts("raft_replication_lag", "The number of log entries not yet known to be replicated on the target, reported by the leader."),
+                &["target"],
+            )?,
+            elections: IntCounter::with_opts(opts("raft_elections_total", "The number of elections the Raft node has campaigned in."))?,
+            leader_changes: IntCounter::with_opts(opts("raft_leader_changes_total", "The number of leaders the Raft node has observed."))?,
+            snapshots: IntCounter::with_opts(opts("raft_snapshots_total", "The number of snapshots the Raft node has built or installed."))?,
+            last: Mutex::new(None),
+        };
+        registry.register(Box::new(this.current_term.clone()))?;
+        registry.register(Box::new(this.state.clone()))?;
+        registry.register(Box::new(this.current_leader.clone()))?;
+        registry.register(Box::new(this.last_log_index.clone()))?;
+        registry.register(Box::new(this.last_applied.clone()))?;
+        registry.register(Box::new(this.snapshot_index.clone()))?;
+        registry.register(Box::new(this.membership_size.clone()))?;
+        registry.register(Box::new(this.replication_match_index.clone()))?;
+        registry.register(Box::new(this.replication_lag.clone()))?;
+        registry.register(Box::new(this.elections.clone()))?;
+        registry.register(Box::new(this.leader_changes.clone()))?;
+        registry.register(Box::new(this.snapshots.clone()))?;
+        Ok(this)
+    }
+
+    /// Update the collectors from the given metrics payload.
+    pub fn observe(&self, metrics: &RaftMetrics) {
+        let mut last = self.last.lock().unwrap();
+
+        // Update counters from the transition between the last payload & this one.
+        let (last_term, last_leader, last_snapshot_index) = match last.as_ref() {
+            Some(last) => (last.current_term, last.current_leader, last.snapshot_index),
+            None => (0, None, 0),
+        };
+        // A node which is leader of a new term must have campaigned in it, even if its candidacy was not observed.
+        let has_campaigned = matches!(metrics.state, State::Candidate | State::Leader);
+        if has_campaigned && metrics.current_term > last_term {
+            self.elections.inc();
+        }
+        let is_new_leader = metrics.current_leader != last_leader || metrics.current_term != last_term;
+        if metrics.current_leader.is_some() && is_new_leader {
+            self.leader_changes.inc();
+        }
+        if metrics.snapshot_index > last_snapshot_index {
+            self.snapshots.inc();
+        }
+
+        // Update gauges.
+        self.current_term.set(metrics.current_term as i64);
+        for (state, label) in STATES {
+            self.state.with_label_values(&[label]).set((metrics.state == *state) as i64);
+        }
+        self.current_leader.set(metrics.current_leader.map(|id| id as i64).unwrap_or(-1));
+        self.last_log_index.set(metrics.last_log_index as i64);
+        self.last_applied.set(metrics.last_applied as i64);
+        self.snapshot_index.set(metrics.snapshot_index as i64);
+        self.membership_size.set(metrics.membership_config.members.len() as i64);
+
+        // Update replication gauges, removing those of targets which are no longer replicated to.
+        let targets = metrics.replication.keys().copied().collect::<BTreeSet<NodeId>>();
+        if let Some(last) = last.as_ref() {
+            for target in last.replication.keys().filter(|target| !targets.contains(target)) {
+                let label = target.to_string();
+                let _ = self.replication_match_index.remove_label_values(&[&label]);
+                let _ = self.replication_lag.remove_label_values(&[&label]);
+            }
+        }
+        for (target, repl) in metrics.replication.iter() {
+            let label = target.to_string();
+            self.replication_match_index.with_label_values(&[&label]).set(repl.match_index as i64);
+            let lag = metrics.last_log_index.saturating_sub(repl.match_index);
+            self.replication_lag.with_label_values(&[&label]).set(lag as i64);
+        }
+
+        *last = Some(metrics.clone());
+    }
+
+    /// Keep the collectors current from the given metrics channel, until the channel is closed.
+    ///
+    /// This is typically spawned onto the runtime with the channel returned by `Raft::metrics`.
+    pub async fn track(&self, mut rx: watch::Receiver<RaftMetrics>) {
+        let current = rx.borrow().clone();
+        self.observe(&current);
+        while let Some(metrics) = rx.recv().await {
+            self.observe(&metrics);
+        }
+    }
+
+    /// Encode all metrics of the registry in the Prometheus text format.
+    ///
+    /// The payload should be served with the `CONTENT_TYPE` content type.
+    pub fn encode(&self) -> ::prometheus::Result<String> {
+        let mut buf = vec![];
+        TextEncoder::new().encode(&self.registry.gather(), &mut buf)?;
+        Ok(String::from_utf8_lossy(&buf).into_owned())
+    }
+}
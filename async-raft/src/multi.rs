@@ -0,0 +1,486 @@
+//! Hosting many Raft groups in one process over a shared transport.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use futures::future::join_all;
+use serde::{Serialize, Deserialize};
+use tokio::stream::StreamExt;
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio::time::{delay_for, interval};
+
+use crate::{AppData, AppDataResponse, NodeId, Raft, RaftNetwork, RaftStorage};
+use crate::config::Config;
+use crate::error::MultiRaftError;
+use crate::network::HeartbeatTicker;
+use crate::raft::{AppendEntriesRequest, AppendEntriesResponse};
+use crate::raft::{InstallSnapshotRequest, InstallSnapshotResponse};
+use crate::raft::{PreVoteRequest, PreVoteResponse, VoteRequest, VoteResponse};
+use crate::raft::{ReadIndexRequest, ReadIndexResponse, TimeoutNowRequest, TimeoutNowResponse};
+
+/// A Raft group's ID.
+pub type GroupId = u64;
+
+/// A Raft group hosted on a `MultiRaft`.
+pub type GroupRaft<D, R, N, S> = Raft<D, R, GroupNetwork<D, N>, S>;
+
+/// The groups hosted on a `MultiRaft`, keyed by their IDs.
+type GroupMap<D, R, N, S> = HashMap<GroupId, Arc<GroupRaft<D, R, N, S>>>;
+
+/// A trait defining the interface for a network shared by all of the Raft groups on a node.
+///
+/// This is the same as `RaftNetwork`, except that each RPC is addressed to a specific group on
+/// the target node, and heartbeats for many groups are sent to the target node as a single RPC.
+/// The receiving node's network layer should call the method of the same name on its `MultiRaft`.
+#[async_trait]
+pub trait MultiRaftNetwork<D>: Send + Sync + 'static
+    where
+        D: AppData,
+{
+    /// Send an AppendEntries RPC to the given group on the target node (§5).
+    async fn append_entries(&self, target: NodeId, group: GroupId, rpc: AppendEntriesRequest<D>) -> Result<AppendEntriesResponse>;
+
+    /// Send a batch of heartbeats for any number of groups to the target node.
+    async fn heartbeat(&self, target: NodeId, rpc: HeartbeatRequest) -> Result<HeartbeatResponse>;
+
+    /// Send an InstallSnapshot RPC to the given group on the target node (§7).
+    async fn install_snapshot(&self, target: NodeId, group: GroupId, rpc: InstallSnapshotRequest) -> Result<InstallSnapshotResponse>;
+
+    /// Send a RequestVote RPC to the given group on the target node (§5).
+    async fn vote(&self, target: NodeId, group: GroupId, rpc: VoteRequest) -> Result<VoteResponse>;
+
+    /// Send a PreVote RPC to the given group on the target node (§9.6 of the Raft thesis).
+    async fn pre_vote(&self, target: NodeId, group: GroupId, rpc: PreVoteRequest) -> Result<PreVoteResponse>;
+
+    /// Send a TimeoutNow RPC to the given group on the target node (§3.10 of the Raft thesis).
+    async fn timeout_now(&self, target: NodeId, group: GroupId, rpc: TimeoutNowRequest) -> Result<TimeoutNowResponse>;
+
+    /// Send a ReadIndex RPC to the given group on the target node (§6.4 of the Raft thesis).
+    async fn read_index(&self, target: NodeId, group: GroupId, rpc: ReadIndexRequest) -> Result<ReadIndexResponse>;
+}
+
+/// A heartbeat from the leader of a group, which is an AppendEntries RPC without entries.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Heartbeat {
+    /// The group which this heartbeat is addressed to.
+    pub group: GroupId,
+    /// The leader's current term.
+    pub term: u64,
+    /// The leader's ID.
+    pub leader_id: NodeId,
+    /// The index of the log entry immediately preceding the next entries to be replicated.
+    pub prev_log_index: u64,
+    /// The term of the `prev_log_index` entry.
+    pub prev_log_term: u64,
+    /// The leader's commit index.
+    pub leader_commit: u64,
+    /// Will be `true` if the leader is quiescing the group.
+    #[serde(default)]
+    pub quiesce: bool,
+}
+
+/// A batch of heartbeats sent from one node to another, for any number of groups.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HeartbeatRequest {
+    /// The heartbeats in this batch.
+    pub heartbeats: Vec<Heartbeat>,
+}
+
+/// The response to a `HeartbeatRequest`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HeartbeatResponse {
+    /// The response to each heartbeat, in the order of the request.
+    ///
+    /// This is `None` for any heartbeat which could not be handled, such as those addressed to a
+    /// group which is not hosted on the responding node.
+    pub responses: Vec<Option<AppendEntriesResponse>>,
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////
+//////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// A host for many Raft groups, which share a single `MultiRaftNetwork`.
+///
+/// Each group is a full `Raft` node with its own `RaftStorage`, addressed by its `GroupId`. All of
+/// the groups hosted on a node share the same node ID & config. The heartbeats of all groups on this
+/// node to the same target node are driven by a single ticker, and are coalesced into a single
+/// `HeartbeatRequest` within `Config::heartbeat_coalesce_window`.
+pub struct MultiRaft<D: AppData, R: AppDataResponse, N: MultiRaftNetwork<D>, S: RaftStorage<D, R>> {
+    id: NodeId,
+    config: Arc<Config>,
+    network: Arc<N>,
+    heartbeats: Arc<HeartbeatCoalescer<D, N>>,
+    groups: RwLock<GroupMap<D, R, N, S>>,
+}
+
+impl<D: AppData, R: AppDataResponse, N: MultiRaftNetwork<D>, S: RaftStorage<D, R>> MultiRaft<D, R, N, S> {
+    /// Create a new host for Raft groups on the node with the given ID.
+    pub fn new(id: NodeId, config: Arc<Config>, network: Arc<N>) -> Self {
+        let period = Duration::from_millis(config.heartbeat_interval);
+        let window = Duration::from_millis(config.heartbeat_coalesce_window);
+        let heartbeats = Arc::new(HeartbeatCoalescer::new(network.clone(), period, window));
+        Self{id, config, network, heartbeats, groups: RwLock::new(HashMap::new())}
+    }
+
+    /// Create and spawn a new Raft task for the given group, using the given storage.
+    pub fn add_group(&self, group: GroupId, storage: Arc<S>) -> Result<Arc<GroupRaft<D, R, N, S>>, MultiRaftError> {
+        let mut groups = self.groups.write().unwrap();
+        if groups.contains_key(&group) {
+            return Err(MultiRaftError::GroupExists(group));
+        }
+        let network = Arc::new(GroupNetwork{group, network: self.network.clone(), heartbeats: self.heartbeats.clone()});
+        let raft = Arc::new(Raft::new(self.id, self.config.clone(), network, storage));
+        groups.insert(group, raft.clone());
+        Ok(raft)
+    }
+
+    /// Get a handle to the given group.
+    pub fn group(&self, group: GroupId) -> Option<Arc<GroupRaft<D, R, N, S>>> {
+        self.groups.read().unwrap().get(&group).cloned()
+    }
+
+    /// Get the IDs of all groups hosted on this node.
+    pub fn groups(&self) -> Vec<GroupId> {
+        self.groups.read().unwrap().keys().copied().collect()
+    }
+
+    /// Remove the given group from this host, returning its handle.
+    ///
+    /// RPCs for the group will no longer be routed to it. Once all other handles to the group have
+    /// been dropped, it may be shut down via `Raft::shutdown`.
+    pub fn remove_group(&self, group: GroupId) -> Option<Arc<GroupRaft<D, R, N, S>>> {
+        self.groups.write().unwrap().remove(&group)
+    }
+
+    /// Report that the given node has become unreachable, waking every group which it leads.
+    ///
+    /// A quiescent group does not notice the failure of its leader on its own, so applications
+    /// should call this when their transport detects that a peer is down. Each group on this node
+    /// whose current leader is the unreachable node is woken via `Raft::wake`, so its followers
+    /// resume their election timers & elect a new leader if the node does not come back in time.
+    pub async fn report_unreachable(&self, node: NodeId) {
+        let groups = self.groups.read().unwrap().values()
+            .filter(|raft| raft.metrics().borrow().current_leader == Some(node))
+            .cloned().collect::<Vec<_>>();
+        join_all(groups.iter().map(|raft| raft.wake())).await;
+    }
+
+    /// Get a handle to the given group, or an error if it is not hosted on this node.
+    fn group_or_err(&self, group: GroupId) -> Result<Arc<GroupRaft<D, R, N, S>>, MultiRaftError> {
+        self.group(group).ok_or(MultiRaftError::GroupNotFound(group))
+    }
+
+    /// Submit an AppendEntries RPC to the given group.
+    pub async fn append_entries(&self, group: GroupId, rpc: AppendEntriesRequest<D>) -> Result<AppendEntriesResponse, MultiRaftError> {
+        Ok(self.group_or_err(group)?.append_entries(rpc).await?)
+    }
+
+    /// Submit a batch of heartbeats to the groups which they are addressed to.
+    pub async fn heartbeat(&self, rpc: HeartbeatRequest) -> HeartbeatResponse {
+        let responses = join_all(rpc.heartbeats.into_iter().map(|hb| async move {
+            let raft = self.group(hb.group)?;
+            let rpc = AppendEntriesRequest{
+                term: hb.term, leader_id: hb.leader_id,
+                prev_log_index: hb.prev_log_index, prev_log_term: hb.prev_log_term,
+                entries: vec![], leader_commit: hb.leader_commit, quiesce: hb.quiesce,
+            };
+            raft.append_entries(rpc).await.ok()
+        })).await;
+        HeartbeatResponse{responses}
+    }
+
+    /// Submit an InstallSnapshot RPC to the given group.
+    pub async fn install_snapshot(&self, group: GroupId, rpc: InstallSnapshotRequest) -> Result<InstallSnapshotResponse, MultiRaftError> {
+        Ok(self.group_or_err(group)?.install_snapshot(rpc).await?)
+    }
+
+    /// Submit a VoteRequest (RequestVote in the spec) RPC to the given group.
+    pub async fn vote(&self, group: GroupId, rpc: VoteRequest) -> Result<VoteResponse, MultiRaftError> {
+        Ok(self.group_or_err(group)?.vote(rpc).await?)
+    }
+
+    /// Submit a PreVote RPC to the given group.
+    pub async fn pre_vote(&self, group: GroupId, rpc: PreVoteRequest) -> Result<PreVoteResponse, MultiRaftError> {
+        Ok(self.group_or_err(group)?.pre_vote(rpc).await?)
+    }
+
+    /// Submit a TimeoutNow RPC to the given group.
+    pub async fn timeout_now(&self, group: GroupId, rpc: TimeoutNowRequest) -> Result<TimeoutNowResponse, MultiRaftError> {
+        Ok(self.group_or_err(group)?.timeout_now(rpc).await?)
+    }
+
+    /// Submit a ReadIndex RPC to the given group.
+    pub async fn read_index(&self, group: GroupId, rpc: ReadIndexRequest) -> Result<ReadIndexResponse, MultiRaftError> {
+        Ok(self.group_or_err(group)?.read_index(rpc).await?)
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////
+//////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// The `RaftNetwork` of a single group hosted on a `MultiRaft`.
+///
+/// RPCs are addressed to the group on the target node, and heartbeats are driven by a ticker shared
+/// with the other groups on this node, and are handed off to be coalesced with theirs.
+pub struct GroupNetwork<D: AppData, N: MultiRaftNetwork<D>> {
+    group: GroupId,
+    network: Arc<N>,
+    heartbeats: Arc<HeartbeatCoalescer<D, N>>,
+}
+
+#[async_trait]
+impl<D: AppData, N: MultiRaftNetwork<D>> RaftNetwork<D> for GroupNetwork<D, N> {
+    async fn append_entries(&self, target: NodeId, rpc: AppendEntriesRequest<D>) -> Result<AppendEntriesResponse> {
+        if !rpc.entries.is_empty() {
+            return self.network.append_entries(target, self.group, rpc).await;
+        }
+        let hb = Heartbeat{
+            group: self.group, term: rpc.term, leader_id: rpc.leader_id,
+            prev_log_index: rpc.prev_log_index, prev_log_term: rpc.prev_log_term,
+            leader_commit: rpc.leader_commit, quiesce: rpc.quiesce,
+        };
+        self.heartbeats.send(target, hb).await
+    }
+
+    async fn install_snapshot(&self, target: NodeId, rpc: InstallSnapshotRequest) -> Result<InstallSnapshotResponse> {
+        self.network.install_snapshot(target, self.group, rpc).await
+    }
+
+    async fn vote(&self, target: NodeId, rpc: VoteRequest) -> Result<VoteResponse> {
+        self.network.vote(target, self.group, rpc).await
+    }
+
+    async fn pre_vote(&self, target: NodeId, rpc: PreVoteRequest) -> Result<PreVoteResponse> {
+        self.network.pre_vote(target, self.group, rpc).await
+    }
+
+    async fn timeout_now(&self, target: NodeId, rpc: TimeoutNowRequest) -> Result<TimeoutNowResponse> {
+        self.network.timeout_now(target, self.group, rpc).await
+    }
+
+    async fn read_index(&self, target: NodeId, rpc: ReadIndexRequest) -> Result<ReadIndexResponse> {
+        self.network.read_index(target, self.group, rpc).await
+    }
+
+    fn heartbeat_ticker(&self, target: NodeId) -> Option<HeartbeatTicker> {
+        Some(self.heartbeats.ticker(target))
+    }
+}
+
+/// A heartbeat awaiting its batch, along with its response channel.
+type PendingHeartbeat = (Heartbeat, oneshot::Sender<Result<AppendEntriesResponse>>);
+
+/// The heartbeat state of a `HeartbeatCoalescer` for a single target node.
+struct TargetHeartbeats {
+    /// The ticker shared by the replication streams of all groups to the target.
+    ticks: broadcast::Sender<()>,
+    /// The queue of heartbeats awaiting the next batch to the target.
+    queue: mpsc::UnboundedSender<PendingHeartbeat>,
+}
+
+/// The heartbeat state of a `HeartbeatCoalescer`, keyed by target node.
+type TargetMap = Arc<Mutex<HashMap<NodeId, TargetHeartbeats>>>;
+
+/// Drives & coalesces heartbeats from all of the groups on a node, with one task per target node.
+///
+/// Each target's task ticks every heartbeat interval for the replication streams of all groups to
+/// the target, and sends their heartbeats to it in batches. Once no replication stream holds the
+/// target's ticker, such as after the groups on this node have been removed or the target has
+/// been removed from their clusters, the task stops and the target is pruned.
+struct HeartbeatCoalescer<D: AppData, N: MultiRaftNetwork<D>> {
+    network: Arc<N>,
+    period: Duration,
+    window: Duration,
+    targets: TargetMap,
+    marker_d: std::marker::PhantomData<D>,
+}
+
+impl<D: AppData, N: MultiRaftNetwork<D>> HeartbeatCoalescer<D, N> {
+    fn new(network: Arc<N>, period: Duration, window: Duration) -> Self {
+        Self{network, period, window, targets: Arc::new(Mutex::new(HashMap::new())), marker_d: std::marker::PhantomData}
+    }
+
+    /// Get the state of the given target, spawning its task if it does not yet have one.
+    fn target<'a>(&self, targets: &'a mut HashMap<NodeId, TargetHeartbeats>, target: NodeId) -> &'a TargetHeartbeats {
+        targets.entry(target).or_insert_with(|| {
+            let (ticks, _) = broadcast::channel(1);
+            let (queue, rx) = mpsc::unbounded_channel();
+            tokio::spawn(Self::run_target(self.network.clone(), self.targets.clone(), target, self.period, self.window, rx));
+            TargetHeartbeats{ticks, queue}
+        })
+    }
+
+    /// Get a handle to the heartbeat ticker of the given target.
+    fn ticker(&self, target: NodeId) -> HeartbeatTicker {
+        let mut targets = self.targets.lock().unwrap();
+        let ticks = self.target(&mut targets, target).ticks.subscribe();
+        // A ticker which has lagged behind has missed ticks, so it ticks at once.
+        Box::pin(ticks.map(|_| ()))
+    }
+
+    /// Send the given heartbeat to the target as part of the next batch, and await its response.
+    async fn send(&self, target: NodeId, hb: Heartbeat) -> Result<AppendEntriesResponse> {
+        let (tx, rx) = oneshot::channel();
+        {
+            let mut targets = self.targets.lock().unwrap();
+            self.target(&mut targets, target).queue.send((hb, tx))
+                .map_err(|_| anyhow!("heartbeat task for node {} has stopped", target))?;
+        }
+        rx.await.map_err(|_| anyhow!("heartbeat batch for node {} was dropped", target))?
+    }
+
+    /// Tick for the target & send batches of heartbeats to it, until it no longer has any tickers.
+    ///
+    /// A batch is opened by the first heartbeat to arrive, and is sent once the window has elapsed.
+    async fn run_target(
+        network: Arc<N>, targets: TargetMap, target: NodeId, period: Duration, window: Duration,
+        mut rx: mpsc::UnboundedReceiver<PendingHeartbeat>,
+    ) {
+        let mut ticks = interval(period);
+        loop {
+            select!{
+                _ = ticks.tick() => {
+                    // The target is pruned under the same lock which guards new tickers & heartbeats,
+                    // so none can be handed out for this task once it has been removed.
+                    let is_pruned = {
+                        let mut targets = targets.lock().unwrap();
+                        match targets.get(&target) {
+                            Some(state) if state.ticks.receiver_count() > 0 => {
+                                let _ = state.ticks.send(());
+                                false
+                            }
+                            _ => {
+                                targets.remove(&target);
+                                true
+                            }
+                        }
+                    };
+                    if is_pruned {
+                        tracing::debug!({target}, "pruning heartbeat task of target which no longer has tickers");
+                        break;
+                    }
+                }
+                Some(first) = rx.recv() => {
+                    delay_for(window).await;
+                    let mut batch = vec![first];
+                    while let Ok(next) = rx.try_recv() {
+                        batch.push(next);
+                    }
+                    Self::send_batch(network.clone(), target, batch);
+                }
+            }
+        }
+        // Send any heartbeats which were queued before the target was pruned.
+        let mut batch = vec![];
+        while let Ok(next) = rx.try_recv() {
+            batch.push(next);
+        }
+        if !batch.is_empty() {
+            Self::send_batch(network, target, batch);
+        }
+    }
+
+    /// Send a batch of heartbeats to the target in the background, so that a slow target does not
+    /// hold up later batches.
+    fn send_batch(network: Arc<N>, target: NodeId, batch: Vec<PendingHeartbeat>) {
+        tokio::spawn(async move {
+            let (heartbeats, txs): (Vec<_>, Vec<_>) = batch.into_iter().unzip();
+            let groups = heartbeats.iter().map(|hb| hb.group).collect::<Vec<_>>();
+            match network.heartbeat(target, HeartbeatRequest{heartbeats}).await {
+                Ok(res) => {
+                    let mut responses = res.responses.into_iter();
+                    for (group, tx) in groups.into_iter().zip(txs) {
+                        let res = responses.next().flatten()
+                            .ok_or_else(|| anyhow!("no heartbeat response from group {} on node {}", group, target));
+                        let _ = tx.send(res);
+                    }
+                }
+                Err(err) => {
+                    tracing::error!({error=%err, target}, "error sending heartbeat batch");
+                    for tx in txs {
+                        let _ = tx.send(Err(anyhow!("error sending heartbeat batch: {}", err)));
+                    }
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::time::timeout;
+
+    use super::*;
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    struct Data;
+
+    impl AppData for Data {}
+
+    /// A network which answers every heartbeat of a batch.
+    struct Network;
+
+    #[async_trait]
+    impl MultiRaftNetwork<Data> for Network {
+        async fn append_entries(&self, _: NodeId, _: GroupId, _: AppendEntriesRequest<Data>) -> Result<AppendEntriesResponse> {
+            Err(anyhow!("append_entries RPCs are not supported by the test network"))
+        }
+
+        async fn heartbeat(&self, _: NodeId, rpc: HeartbeatRequest) -> Result<HeartbeatResponse> {
+            let responses = rpc.heartbeats.iter()
+                .map(|hb| Some(AppendEntriesResponse{term: hb.term, success: true, conflict_opt: None}))
+                .collect();
+            Ok(HeartbeatResponse{responses})
+        }
+
+        async fn install_snapshot(&self, _: NodeId, _: GroupId, _: InstallSnapshotRequest) -> Result<InstallSnapshotResponse> {
+            Err(anyhow!("install_snapshot RPCs are not supported by the test network"))
+        }
+
+        async fn vote(&self, _: NodeId, _: GroupId, _: VoteRequest) -> Result<VoteResponse> {
+            Err(anyhow!("vote RPCs are not supported by the test network"))
+        }
+
+        async fn pre_vote(&self, _: NodeId, _: GroupId, _: PreVoteRequest) -> Result<PreVoteResponse> {
+            Err(anyhow!("pre_vote RPCs are not supported by the test network"))
+        }
+
+        async fn timeout_now(&self, _: NodeId, _: GroupId, _: TimeoutNowRequest) -> Result<TimeoutNowResponse> {
+            Err(anyhow!("timeout_now RPCs are not supported by the test network"))
+        }
+
+        async fn read_index(&self, _: NodeId, _: GroupId, _: ReadIndexRequest) -> Result<ReadIndexResponse> {
+            Err(anyhow!("read_index RPCs are not supported by the test network"))
+        }
+    }
+
+    fn heartbeat(group: GroupId) -> Heartbeat {
+        Heartbeat{group, term: 1, leader_id: 0, prev_log_index: 0, prev_log_term: 0, leader_commit: 0, quiesce: false}
+    }
+
+    #[tokio::test]
+    async fn groups_share_one_ticker_per_target_which_is_pruned_once_unused() {
+        let coalescer = HeartbeatCoalescer::new(Arc::new(Network), Duration::from_millis(50), Duration::from_millis(10));
+        let (mut ticker0, mut ticker1) = (coalescer.ticker(1), coalescer.ticker(1));
+        let mut ticker2 = coalescer.ticker(2);
+        assert_eq!(coalescer.targets.lock().unwrap().len(), 2);
+
+        // Both groups' tickers to the same target tick together, and their heartbeats are batched.
+        timeout(Duration::from_secs(1), async { ticker0.next().await; ticker1.next().await; }).await.expect("expected tickers to tick");
+        let (res0, res1) = tokio::join!(coalescer.send(1, heartbeat(0)), coalescer.send(1, heartbeat(1)));
+        assert!(res0.expect("expected a heartbeat response").success);
+        assert!(res1.expect("expected a heartbeat response").success);
+
+        // Once the last ticker of a target is dropped, the target is pruned.
+        drop(ticker0);
+        drop(ticker1);
+        timeout(Duration::from_secs(1), async { ticker2.next().await; ticker2.next().await; }).await.expect("expected ticker to tick");
+        delay_for(Duration::from_millis(100)).await;
+        let targets = coalescer.targets.lock().unwrap();
+        assert!(!targets.contains_key(&1), "expected target 1 to be pruned");
+        assert!(targets.contains_key(&2), "expected target 2 to be kept");
+    }
+}
@@ -2,11 +2,16 @@
 
 use anyhow::Result;
 use async_trait::async_trait;
+use futures::stream::BoxStream;
 
 use crate::{AppData, NodeId};
 use crate::raft::{AppendEntriesRequest, AppendEntriesResponse};
 use crate::raft::{InstallSnapshotRequest, InstallSnapshotResponse};
-use crate::raft::{VoteRequest, VoteResponse};
+use crate::raft::{PreVoteRequest, PreVoteResponse, VoteRequest, VoteResponse};
+use crate::raft::{ReadIndexRequest, ReadIndexResponse, TimeoutNowRequest, TimeoutNowResponse};
+
+/// A stream of ticks which drives the heartbeats sent to a target Raft node.
+pub type HeartbeatTicker = BoxStream<'static, ()>;
 
 /// A trait defining the interface for a Raft network between cluster members.
 ///
@@ -25,4 +30,30 @@ pub trait RaftNetwork<D>: Send + Sync + 'static
 
     /// Send a RequestVote RPC to the target Raft node (§5).
     async fn vote(&self, target: NodeId, rpc: VoteRequest) -> Result<VoteResponse>;
+
+    /// Send a PreVote RPC to the target Raft node (§9.6 of the Raft thesis).
+    ///
+    /// This is only used when `Config::pre_vote` is enabled.
+    async fn pre_vote(&self, target: NodeId, rpc: PreVoteRequest) -> Result<PreVoteResponse>;
+
+    /// Send a TimeoutNow RPC to the target Raft node (§3.10 of the Raft thesis).
+    ///
+    /// This is only used by the leader when transferring leadership to the target node.
+    async fn timeout_now(&self, target: NodeId, rpc: TimeoutNowRequest) -> Result<TimeoutNowResponse>;
+
+    /// Send a ReadIndex RPC to the target Raft node (§6.4 of the Raft thesis).
+    ///
+    /// This is used by followers & non-voters to obtain a read index from the cluster leader, in
+    /// order to serve client reads locally.
+    async fn read_index(&self, target: NodeId, rpc: ReadIndexRequest) -> Result<ReadIndexResponse>;
+
+    /// Get a ticker which drives the heartbeats sent to the target Raft node.
+    ///
+    /// By default this is `None`, and the leader's replication stream to the target keeps its own
+    /// timer, ticking every `Config::heartbeat_interval`. A network which is shared by many Raft
+    /// nodes, such as that of a `MultiRaft`, may instead hand out a ticker which is shared by all
+    /// of their replication streams to the same target.
+    fn heartbeat_ticker(&self, _target: NodeId) -> Option<HeartbeatTicker> {
+        None
+    }
 }
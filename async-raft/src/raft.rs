@@ -1,18 +1,23 @@
 //! Public Raft interface and data types.
 
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
+use std::hash::Hasher;
 use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
 
+use fnv::FnvHasher;
 use serde::{Serialize, Deserialize};
-use tokio::sync::{mpsc, oneshot, watch};
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
 use tokio::task::JoinHandle;
 
 use crate::{AppData, AppDataResponse, NodeId, RaftNetwork, RaftStorage};
 use crate::config::Config;
-use crate::error::{ClientReadError, ClientWriteError, ChangeConfigError, InitializeError, RaftError, RaftResult};
-use crate::metrics::RaftMetrics;
-use crate::core::RaftCore;
+use crate::error::{ClientReadError, ClientWriteError, ChangeConfigError, InitializeError, LeadershipTransferError, RaftError, RaftResult, RegisterSessionError};
+use crate::event::{EVENT_CHANNEL_CAPACITY, Event};
+use crate::metrics::{RaftMetrics, Wait};
+use crate::core::{RaftCore, RaftCoreChannels};
+use crate::session::{SessionId, SessionRequest};
 
 /// The Raft API.
 ///
@@ -31,9 +36,22 @@ use crate::core::RaftCore;
 /// is shutting down (potentially for data safety reasons due to a storage error), and the `shutdown`
 /// method should be called on this type to await the shutdown of the node. If the parent
 /// application needs to shutdown the Raft node for any reason, calling `shutdown` will do the trick.
+///
+/// ### admission control
+/// At most `Config::max_inflight_client_writes` client writes may be in flight on a node at once.
+/// Writes beyond this are rejected with `ClientWriteError::Overloaded` without being queued, which
+/// bounds the memory used by a burst of writes, and allows applications to shed load.
+///
+/// Calls of every kind are queued for the Raft core on a channel of `Config::api_channel_capacity`
+/// messages. Once it is full, calls wait for the core to catch up rather than being rejected, so
+/// reads, RPCs from peers, membership changes & session registrations are bounded as well.
 pub struct Raft<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> {
-    tx_api: mpsc::UnboundedSender<RaftMsg<D, R>>,
+    tx_api: mpsc::Sender<RaftMsg<D, R>>,
+    config: Arc<Config>,
+    /// The number of client writes which are currently in flight.
+    inflight_client_writes: Arc<AtomicU64>,
     rx_metrics: watch::Receiver<RaftMetrics>,
+    tx_events: broadcast::Sender<Event>,
     raft_handle: JoinHandle<RaftResult<()>>,
     needs_shutdown: Arc<AtomicBool>,
     marker_n: std::marker::PhantomData<N>,
@@ -60,16 +78,18 @@ impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> Ra
     /// An implementation of the `RaftStorage` trait which will be used by Raft for data storage.
     /// See the docs on the `RaftStorage` trait for more details.
     pub fn new(id: NodeId, config: Arc<Config>, network: Arc<N>, storage: Arc<S>) -> Self {
-        let (tx_api, rx_api) = mpsc::unbounded_channel();
+        let (tx_api, rx_api) = mpsc::channel(config.api_channel_capacity as usize);
         let (tx_metrics, rx_metrics) = watch::channel(RaftMetrics::new_initial(id));
+        let (tx_events, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
         let needs_shutdown = Arc::new(AtomicBool::new(false));
+        let channels = RaftCoreChannels{rx_api, tx_metrics, rx_metrics: rx_metrics.clone(), tx_events: tx_events.clone()};
         let raft_handle = RaftCore::spawn(
-            id, config, network, storage.clone(),
-            rx_api, tx_metrics,
+            id, config.clone(), network, storage.clone(),
+            channels,
             needs_shutdown.clone(),
         );
         Self{
-            tx_api, rx_metrics, raft_handle, needs_shutdown,
+            tx_api, config, inflight_client_writes: Arc::new(AtomicU64::new(0)), rx_metrics, tx_events, raft_handle, needs_shutdown,
             marker_n: std::marker::PhantomData, marker_s: std::marker::PhantomData,
         }
     }
@@ -81,7 +101,7 @@ impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> Ra
     #[tracing::instrument(level="debug", skip(self, rpc))]
     pub async fn append_entries(&self, rpc: AppendEntriesRequest<D>) -> Result<AppendEntriesResponse, RaftError> {
         let (tx, rx) = oneshot::channel();
-        self.tx_api.send(RaftMsg::AppendEntries{rpc, tx}).map_err(|_| RaftError::ShuttingDown)?;
+        self.send_api_msg(RaftMsg::AppendEntries{rpc, tx}).await?;
         Ok(rx.await.map_err(|_| RaftError::ShuttingDown).and_then(|res| res)?)
     }
 
@@ -91,10 +111,21 @@ impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> Ra
     #[tracing::instrument(level="debug", skip(self, rpc))]
     pub async fn vote(&self, rpc: VoteRequest) -> Result<VoteResponse, RaftError> {
         let (tx, rx) = oneshot::channel();
-        self.tx_api.send(RaftMsg::RequestVote{rpc, tx}).map_err(|_| RaftError::ShuttingDown)?;
+        self.send_api_msg(RaftMsg::RequestVote{rpc, tx}).await?;
         Ok(rx.await.map_err(|_| RaftError::ShuttingDown).and_then(|res| res)?)
     }
 
+    /// Submit a PreVote RPC to this Raft node.
+    ///
+    /// These RPCs are sent by cluster peers which have hit their election timeout, in order to
+    /// determine if they could win an election before disrupting the cluster (§9.6 of the Raft thesis).
+    #[tracing::instrument(level="debug", skip(self, rpc))]
+    pub async fn pre_vote(&self, rpc: PreVoteRequest) -> Result<PreVoteResponse, RaftError> {
+        let (tx, rx) = oneshot::channel();
+        self.send_api_msg(RaftMsg::PreVote{rpc, tx}).await?;
+        rx.await.map_err(|_| RaftError::ShuttingDown).and_then(|res| res)
+    }
+
     /// Submit an InstallSnapshot RPC to this Raft node.
     ///
     /// These RPCs are sent by the cluster leader in order to bring a new node or a slow node up-to-speed
@@ -102,18 +133,50 @@ impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> Ra
     #[tracing::instrument(level="debug", skip(self, rpc))]
     pub async fn install_snapshot(&self, rpc: InstallSnapshotRequest) -> Result<InstallSnapshotResponse, RaftError> {
         let (tx, rx) = oneshot::channel();
-        self.tx_api.send(RaftMsg::InstallSnapshot{rpc, tx}).map_err(|_| RaftError::ShuttingDown)?;
+        self.send_api_msg(RaftMsg::InstallSnapshot{rpc, tx}).await?;
         Ok(rx.await.map_err(|_| RaftError::ShuttingDown).and_then(|res| res)?)
     }
 
+    /// Submit a TimeoutNow RPC to this Raft node.
+    ///
+    /// These RPCs are sent by the cluster leader during a leadership transfer, in order to have
+    /// the target node start an election immediately (§3.10 of the Raft thesis).
+    #[tracing::instrument(level="debug", skip(self, rpc))]
+    pub async fn timeout_now(&self, rpc: TimeoutNowRequest) -> Result<TimeoutNowResponse, RaftError> {
+        let (tx, rx) = oneshot::channel();
+        self.send_api_msg(RaftMsg::TimeoutNow{rpc, tx}).await?;
+        rx.await.map_err(|_| RaftError::ShuttingDown).and_then(|res| res)
+    }
+
+    /// Submit a ReadIndex RPC to this Raft node.
+    ///
+    /// These RPCs are sent by followers & non-voters to the cluster leader, in order to serve
+    /// linearizable client reads without forwarding them to the leader (§6.4 of the Raft thesis).
+    #[tracing::instrument(level="debug", skip(self, rpc))]
+    pub async fn read_index(&self, rpc: ReadIndexRequest) -> Result<ReadIndexResponse, RaftError> {
+        let (tx, rx) = oneshot::channel();
+        self.send_api_msg(RaftMsg::ReadIndex{rpc, tx}).await?;
+        rx.await.map_err(|_| RaftError::ShuttingDown).and_then(|res| res)
+    }
+
     /// Check to ensure this node is still the cluster leader, in order to guard against stale reads (§8).
     ///
     /// The actual read operation itself is up to the application, this method just ensures that
-    /// the read will not be stale.
+    /// the read will not be stale. This implements the ReadIndex protocol (§6.4 of the Raft
+    /// thesis): concurrent reads are batched, leadership is confirmed once per batch, and this
+    /// method returns once the batch's read index has been applied to the state machine.
+    ///
+    /// The returned value is the read index. The application's state machine on this node has
+    /// applied at least all entries up through this index, so it is safe to read from it.
+    ///
+    /// When called on a follower or non-voter, the node will request the read index from the
+    /// cluster leader via the `RaftNetwork::read_index` RPC, and will then wait for its own state
+    /// machine to apply the read index. If the node does not know of a leader, or does not catch
+    /// up within an election timeout, then `ClientReadError::ForwardToLeader` is returned.
     #[tracing::instrument(level="debug", skip(self))]
-    pub async fn client_read(&self) -> Result<(), ClientReadError> {
+    pub async fn client_read(&self) -> Result<u64, ClientReadError> {
         let (tx, rx) = oneshot::channel();
-        self.tx_api.send(RaftMsg::ClientReadRequest{tx}).map_err(|_| ClientReadError::RaftError(RaftError::ShuttingDown))?;
+        self.send_api_msg(RaftMsg::ClientReadRequest{tx}).await?;
         Ok(rx.await.map_err(|_| ClientReadError::RaftError(RaftError::ShuttingDown)).and_then(|res| res)?)
     }
 
@@ -133,14 +196,106 @@ impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> Ra
     /// to implement this.
     ///
     /// These are application specific requirements, and must be implemented by the application which is
-    /// being built on top of Raft.
+    /// being built on top of Raft, unless it uses client sessions. Requests created with
+    /// `ClientWriteRequest::new_in_session` are deduplicated by Raft itself, see
+    /// `register_client_session`.
+    ///
+    /// If `Config::max_inflight_client_writes` writes are already in flight on this node, then the
+    /// request is rejected at once with `ClientWriteError::Overloaded`.
     #[tracing::instrument(level="debug", skip(self, rpc))]
     pub async fn client_write(&self, rpc: ClientWriteRequest<D>) -> Result<ClientWriteResponse<R>, ClientWriteError<D>> {
+        let admitted = match self.admit_client_writes(1) {
+            Some(admitted) => admitted,
+            None => return Err(ClientWriteError::Overloaded(rpc)),
+        };
         let (tx, rx) = oneshot::channel();
-        self.tx_api.send(RaftMsg::ClientWriteRequest{rpc, tx}).map_err(|_| ClientWriteError::RaftError(RaftError::ShuttingDown))?;
+        self.send_api_msg(RaftMsg::ClientWriteRequest{rpc, tx: ClientWriteTx::new(tx, admitted)}).await?;
         Ok(rx.await.map_err(|_| ClientWriteError::RaftError(RaftError::ShuttingDown)).and_then(|res| res)?)
     }
 
+    /// Submit a mutating client request to Raft, returning a handle on each stage of the write.
+    ///
+    /// The request is handled exactly as with `client_write`, but rather than only resolving once
+    /// the write has been applied to the state machine, the returned `ClientWriteHandle` may be
+    /// used to wait for the write to be appended to the leader's log, to be committed to the
+    /// cluster, or to be applied, so that callers may choose which of these they wait for.
+    ///
+    /// If `Config::max_inflight_client_writes` writes are already in flight on this node, then the
+    /// request is rejected at once with `ClientWriteError::Overloaded`.
+    #[tracing::instrument(level="debug", skip(self, rpc))]
+    pub async fn client_write_staged(&self, rpc: ClientWriteRequest<D>) -> Result<ClientWriteHandle<D, R>, ClientWriteError<D>> {
+        let admitted = match self.admit_client_writes(1) {
+            Some(admitted) => admitted,
+            None => return Err(ClientWriteError::Overloaded(rpc)),
+        };
+        let (tx_appended, rx_appended) = oneshot::channel();
+        let (tx_committed, rx_committed) = oneshot::channel();
+        let (tx_applied, rx_applied) = oneshot::channel();
+        let tx = ClientWriteTx{tx_appended: Some(tx_appended), tx_committed: Some(tx_committed), tx_applied, admitted};
+        self.send_api_msg(RaftMsg::ClientWriteRequest{rpc, tx}).await?;
+        Ok(ClientWriteHandle{
+            appended: Stage::Pending(rx_appended),
+            committed: Stage::Pending(rx_committed),
+            rx_applied,
+        })
+    }
+
+    /// Submit a batch of mutating client requests to Raft to update the state of the system (§5.1).
+    ///
+    /// The requests are appended to the log together, in the given order, and are replicated to
+    /// the cluster together. A batch of more than `Config::max_payload_entries` requests is
+    /// appended in consecutive chunks of at most that many requests. Each request is otherwise
+    /// handled exactly as with `client_write`, and one response is returned for each request, in
+    /// the same order as the given requests.
+    ///
+    /// The batch is admitted as a whole. If admitting it would put more than
+    /// `Config::max_inflight_client_writes` writes in flight on this node, then every request of
+    /// the batch is rejected with `ClientWriteError::Overloaded`.
+    #[tracing::instrument(level="debug", skip(self, rpcs))]
+    pub async fn client_write_batch(&self, rpcs: Vec<ClientWriteRequest<D>>) -> Vec<Result<ClientWriteResponse<R>, ClientWriteError<D>>> {
+        let admitted = match self.admit_client_writes(rpcs.len() as u64) {
+            Some(admitted) => admitted,
+            None => return rpcs.into_iter().map(ClientWriteError::Overloaded).map(Err).collect(),
+        };
+        let (requests, rxs): (Vec<_>, Vec<_>) = rpcs.into_iter().zip(admitted.split())
+            .map(|(rpc, admitted)| {
+                let (tx, rx) = oneshot::channel();
+                ((rpc, ClientWriteTx::new(tx, admitted)), rx)
+            })
+            .unzip();
+        // If the message can not be sent, then the response channels are dropped along with it,
+        // and each response below will resolve as an error.
+        let _ = self.send_api_msg(RaftMsg::ClientWriteBatch{requests}).await;
+        let mut responses = Vec::with_capacity(rxs.len());
+        for res in futures::future::join_all(rxs).await {
+            responses.push(match res {
+                Ok(res) => res,
+                Err(_) => Err(ClientWriteError::RaftError(RaftError::ShuttingDown)),
+            });
+        }
+        responses
+    }
+
+    /// Register a new client session, returning the ID of the session (§6.3 of the Raft thesis).
+    ///
+    /// The session is registered through the log, and this call returns once the registration has
+    /// been committed & applied. Writes created with `ClientWriteRequest::new_in_session` are then
+    /// applied to the state machine at most once per sequence number of the session, even when
+    /// retried against a new leader: a retried write which has already been applied returns the
+    /// response of the original attempt.
+    ///
+    /// The leader expires sessions which have not submitted a write within
+    /// `Config::client_session_timeout`. Writes of an expired session are rejected with
+    /// `ClientWriteError::UnknownSession`, and the client must register a new session.
+    ///
+    /// If this Raft node is not the cluster leader, then this call will fail.
+    #[tracing::instrument(level="debug", skip(self))]
+    pub async fn register_client_session(&self) -> Result<SessionId, RegisterSessionError> {
+        let (tx, rx) = oneshot::channel();
+        self.send_api_msg(RaftMsg::RegisterSession{tx}).await?;
+        rx.await.map_err(|_| RegisterSessionError::RaftError(RaftError::ShuttingDown)).and_then(|res| res)
+    }
+
     /// Initialize a pristine Raft node with the given config.
     ///
     /// This command should be called on pristine nodes — where the log index is 0 and the node is
@@ -172,7 +327,7 @@ impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> Ra
     #[tracing::instrument(level="debug", skip(self))]
     pub async fn initialize(&self, members: HashSet<NodeId>) -> Result<(), InitializeError> {
         let (tx, rx) = oneshot::channel();
-        self.tx_api.send(RaftMsg::Initialize{members, tx}).map_err(|_| RaftError::ShuttingDown)?;
+        self.send_api_msg(RaftMsg::Initialize{members, tx}).await?;
         Ok(rx.await.map_err(|_| InitializeError::RaftError(RaftError::ShuttingDown)).and_then(|res| res)?)
     }
 
@@ -184,17 +339,55 @@ impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> Ra
     /// that it is up-to-date and ready to be added to the cluster.
     ///
     /// Calling this API will add the target node as a non-voter, starting the syncing process.
-    /// Once the node is up-to-speed, this function will return. It is the responsibility of the
-    /// application to then call `change_membership` once all of the new nodes are synced.
+    /// The node is recorded as a learner in the cluster's membership config, which is committed
+    /// through the log, so that it continues to receive replicated entries after a leader change.
+    /// Once the node is up-to-speed & the new config has been committed, this function will return.
+    /// It is the responsibility of the application to then call `change_membership` once all of
+    /// the new nodes are synced, if they are to become voting members.
     ///
     /// If this Raft node is not the cluster leader, then this call will fail.
     #[tracing::instrument(level="debug", skip(self))]
     pub async fn add_non_voter(&self, id: NodeId) -> Result<(), ChangeConfigError> {
         let (tx, rx) = oneshot::channel();
-        self.tx_api.send(RaftMsg::AddNonVoter{id, tx}).map_err(|_| RaftError::ShuttingDown)?;
+        self.send_api_msg(RaftMsg::AddNonVoter{id, is_witness: false, tx}).await?;
         Ok(rx.await.map_err(|_| ChangeConfigError::RaftError(RaftError::ShuttingDown)).and_then(|res| res)?)
     }
 
+    /// Synchronize a new Raft node as a witness, bringing it up-to-speed.
+    ///
+    /// This behaves the same as `add_non_voter`, except that the node is also recorded as a
+    /// witness in the cluster's membership config. Once added as a voting member via
+    /// `change_membership`, a witness takes part in elections & commit quorums, but it only keeps
+    /// the term & index of normal entries rather than their payloads, keeps no state machine, and
+    /// never becomes the cluster leader. This makes witnesses a cheap tiebreaker between replicas.
+    ///
+    /// A witness remains a witness until it is removed from the cluster. Its storage must support
+    /// `RaftStorage::install_witness_snapshot`.
+    ///
+    /// If this Raft node is not the cluster leader, then this call will fail.
+    #[tracing::instrument(level="debug", skip(self))]
+    pub async fn add_witness(&self, id: NodeId) -> Result<(), ChangeConfigError> {
+        let (tx, rx) = oneshot::channel();
+        self.send_api_msg(RaftMsg::AddNonVoter{id, is_witness: true, tx}).await?;
+        rx.await.map_err(|_| ChangeConfigError::RaftError(RaftError::ShuttingDown)).and_then(|res| res)
+    }
+
+    /// Remove a learner from the cluster, which was added via `add_non_voter` or `add_witness`.
+    ///
+    /// The node is removed from the learners (and witnesses) of the cluster's membership config,
+    /// which is committed through the log, and the leader stops replicating to it. This function
+    /// returns once the new config has been committed. If the learner was still being synced,
+    /// then the call which added it fails. Voting members must be removed via `change_membership`.
+    ///
+    /// If the node is not a learner, then `ChangeConfigError::Noop` is returned. If this Raft node
+    /// is not the cluster leader, then this call will fail.
+    #[tracing::instrument(level="debug", skip(self))]
+    pub async fn remove_non_voter(&self, id: NodeId) -> Result<(), ChangeConfigError> {
+        let (tx, rx) = oneshot::channel();
+        self.send_api_msg(RaftMsg::RemoveNonVoter{id, tx}).await?;
+        rx.await.map_err(|_| ChangeConfigError::RaftError(RaftError::ShuttingDown)).and_then(|res| res)
+    }
+
     /// Propose a cluster configuration change (§6).
     ///
     /// This will cause the leader to begin a cluster membership configuration change. If there
@@ -209,25 +402,201 @@ impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> Ra
     #[tracing::instrument(level="debug", skip(self))]
     pub async fn change_membership(&self, members: HashSet<NodeId>) -> Result<(), ChangeConfigError> {
         let (tx, rx) = oneshot::channel();
-        self.tx_api.send(RaftMsg::ChangeMembership{members, tx}).map_err(|_| RaftError::ShuttingDown)?;
+        self.send_api_msg(RaftMsg::ChangeMembership{members, tx}).await?;
         Ok(rx.await.map_err(|_| ChangeConfigError::RaftError(RaftError::ShuttingDown)).and_then(|res| res)?)
     }
 
+    /// Transfer leadership of the cluster to the target node (§3.10 of the Raft thesis).
+    ///
+    /// The leader will stop accepting client write requests, bring the target node's log
+    /// up-to-date, and then instruct the target to start an election immediately. This is useful
+    /// for moving leadership off of a node before taking it down for maintenance.
+    ///
+    /// This method will return once this node observes the target as the new cluster leader. If
+    /// the target does not become leader within an election timeout, the transfer is aborted, the
+    /// leader will resume accepting client write requests, and `LeadershipTransferError::Timeout`
+    /// will be returned.
+    ///
+    /// If this Raft node is not the cluster leader, then the request will be rejected.
+    #[tracing::instrument(level="debug", skip(self))]
+    pub async fn transfer_leadership(&self, target: NodeId) -> Result<(), LeadershipTransferError> {
+        let (tx, rx) = oneshot::channel();
+        self.send_api_msg(RaftMsg::TransferLeadership{target, tx}).await?;
+        let mut rx_metrics = self.rx_metrics.clone();
+        let transfer = async move {
+            rx.await.map_err(|_| LeadershipTransferError::RaftError(RaftError::ShuttingDown)).and_then(|res| res)?;
+            // Await for this node to observe the target as the cluster leader.
+            while let Some(metrics) = rx_metrics.recv().await {
+                if metrics.current_leader == Some(target) {
+                    return Ok(());
+                }
+            }
+            Err(LeadershipTransferError::RaftError(RaftError::ShuttingDown))
+        };
+        let timeout = Duration::from_millis(self.config.election_timeout_max);
+        tokio::time::timeout(timeout, transfer).await.map_err(|_| LeadershipTransferError::Timeout)?
+    }
+
+    /// Wake this Raft node from quiescence (see `Config::quiesce`).
+    ///
+    /// A quiescent follower suspends its election timer, so it does not notice the failure of its
+    /// leader on its own. Applications should call this once they suspect that the leader of a
+    /// quiescent group has failed, such as when the node hosting it becomes unreachable. This
+    /// resumes the follower's election timer, so it campaigns to replace the leader unless the
+    /// leader makes contact first. A leader is woken by client requests & RPCs instead, and is not
+    /// affected by this. `MultiRaft` offers `MultiRaft::report_unreachable` to wake every group
+    /// led by an unreachable node.
+    ///
+    /// This has no effect on a node which is not quiescent.
+    #[tracing::instrument(level="debug", skip(self))]
+    pub async fn wake(&self) -> Result<(), RaftError> {
+        self.send_api_msg(RaftMsg::Wake).await
+    }
+
+    /// Get the time elapsed since each replication target last acknowledged this node as leader.
+    ///
+    /// The age of a target's acknowledgement is the time elapsed since the send time of the most
+    /// recent RPC, heartbeats included, which the target acknowledged in the leader's current
+    /// term. Targets which have not acknowledged any RPC yet are omitted. This is only populated
+    /// while the node is the cluster leader; it is empty in all other states.
+    ///
+    /// `metrics::ReplicationMetrics::last_ack` only reports this as of the last time the metrics
+    /// were reported, as acknowledgements change with every heartbeat. Querying the ack ages does
+    /// not wake a quiescent node.
+    #[tracing::instrument(level="debug", skip(self))]
+    pub async fn replication_ack_ages(&self) -> Result<BTreeMap<NodeId, Duration>, RaftError> {
+        let (tx, rx) = oneshot::channel();
+        self.send_api_msg(RaftMsg::ReplicationAckAges{tx}).await?;
+        rx.await.map_err(|_| RaftError::ShuttingDown)
+    }
+
     /// Get a handle to the metrics channel.
     pub fn metrics(&self) -> watch::Receiver<RaftMetrics> {
         self.rx_metrics.clone()
     }
 
+    /// Get a handle for waiting until the metrics of this node satisfy a condition.
+    ///
+    /// Each condition waited for via the returned handle must hold within the given timeout.
+    /// See `Wait` for the conditions available.
+    pub fn wait(&self, timeout: Duration) -> Wait {
+        Wait::new(timeout, self.rx_metrics.clone())
+    }
+
+    /// Subscribe to the events emitted by this Raft node.
+    ///
+    /// The returned receiver observes every event emitted after this call, such as changes of
+    /// leadership, committed membership configs & snapshots. See the `event` module for details.
+    /// A receiver which falls too far behind misses the oldest events, which it observes as a
+    /// `RecvError::Lagged` error.
+    pub fn subscribe_events(&self) -> broadcast::Receiver<Event> {
+        self.tx_events.subscribe()
+    }
+
     /// Shutdown this Raft node, returning its join handle.
     pub fn shutdown(self) -> tokio::task::JoinHandle<RaftResult<()>> {
         self.needs_shutdown.store(true, Ordering::SeqCst);
+        // Wake the core, as a quiescent node may otherwise have nothing left to wait for. If the
+        // API channel is full, then the core is busy & will observe the shutdown soon enough.
+        let mut tx_api = self.tx_api;
+        let _ = tx_api.try_send(RaftMsg::Wake);
         self.raft_handle
     }
+
+    /// Queue the given message for the Raft core, waiting for room on the API channel if it is full.
+    async fn send_api_msg(&self, msg: RaftMsg<D, R>) -> Result<(), RaftError> {
+        self.tx_api.clone().send(msg).await.map_err(|_| RaftError::ShuttingDown)
+    }
+
+    /// Admit the given number of client writes, if doing so keeps the number of client writes in
+    /// flight within `Config::max_inflight_client_writes`.
+    ///
+    /// The writes remain in flight until the returned guard is dropped.
+    fn admit_client_writes(&self, count: u64) -> Option<AdmittedWrites> {
+        let max = self.config.max_inflight_client_writes;
+        self.inflight_client_writes
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |inflight| inflight.checked_add(count).filter(|total| *total <= max))
+            .ok()
+            .map(|_| AdmittedWrites{inflight: self.inflight_client_writes.clone(), count})
+    }
+}
+
+/// A guard over client writes which have been admitted, releasing them once dropped.
+///
+/// The guard travels with the response channels of its write to the Raft core, so that a write
+/// remains in flight until the core has answered or dropped it, even if the caller has stopped
+/// waiting for it.
+struct AdmittedWrites {
+    inflight: Arc<AtomicU64>,
+    count: u64,
+}
+
+impl AdmittedWrites {
+    /// Split the guard into one guard per admitted write, so that each write is released on its own.
+    fn split(mut self) -> Vec<AdmittedWrites> {
+        let count = std::mem::replace(&mut self.count, 0);
+        (0..count).map(|_| AdmittedWrites{inflight: self.inflight.clone(), count: 1}).collect()
+    }
+}
+
+impl Drop for AdmittedWrites {
+    fn drop(&mut self) {
+        self.inflight.fetch_sub(self.count, Ordering::SeqCst);
+    }
 }
 
 pub(crate) type ClientWriteResponseTx<D, R> = oneshot::Sender<Result<ClientWriteResponse<R>, ClientWriteError<D>>>;
-pub(crate) type ClientReadResponseTx = oneshot::Sender<Result<(), ClientReadError>>;
+
+/// The response channels of a client write.
+///
+/// The response is sent once the write has been applied to the state machine. Writes submitted
+/// via `Raft::client_write_staged` are also notified once appended to the leader's log, and once
+/// committed. A write which fails before reaching a stage drops the channel of that stage.
+///
+/// The write counts against `Config::max_inflight_client_writes` until its response has been
+/// sent, or until these channels are dropped.
+pub(crate) struct ClientWriteTx<D: AppData, R: AppDataResponse> {
+    tx_appended: Option<oneshot::Sender<ClientWriteAppended>>,
+    tx_committed: Option<oneshot::Sender<u64>>,
+    tx_applied: ClientWriteResponseTx<D, R>,
+    admitted: AdmittedWrites,
+}
+
+impl<D: AppData, R: AppDataResponse> ClientWriteTx<D, R> {
+    /// Create the response channel of an admitted write which is only notified once applied.
+    fn new(tx_applied: ClientWriteResponseTx<D, R>, admitted: AdmittedWrites) -> Self {
+        Self{tx_appended: None, tx_committed: None, tx_applied, admitted}
+    }
+
+    /// Notify the client that the write has been appended to the leader's log.
+    pub(crate) fn send_appended(&mut self, index: u64, term: u64) {
+        if let Some(tx) = self.tx_appended.take() {
+            let _ = tx.send(ClientWriteAppended{index, term});
+        }
+    }
+
+    /// Notify the client that the write has been committed.
+    pub(crate) fn send_committed(&mut self, index: u64) {
+        if let Some(tx) = self.tx_committed.take() {
+            let _ = tx.send(index);
+        }
+    }
+
+    /// Send the response of the write, once it has been applied or has failed.
+    ///
+    /// The response is dropped if the client is no longer waiting for it.
+    /// The write is released before the response is sent, so that the client may submit a new
+    /// write as soon as it has received the response.
+    pub(crate) fn send(self, res: Result<ClientWriteResponse<R>, ClientWriteError<D>>) {
+        drop(self.admitted);
+        let _ = self.tx_applied.send(res);
+    }
+}
+pub(crate) type ClientReadResponseTx = oneshot::Sender<Result<u64, ClientReadError>>;
+pub(crate) type ReadIndexResponseTx = oneshot::Sender<Result<ReadIndexResponse, RaftError>>;
 pub(crate) type ChangeMembershipTx = oneshot::Sender<Result<(), ChangeConfigError>>;
+pub(crate) type LeadershipTransferTx = oneshot::Sender<Result<(), LeadershipTransferError>>;
+pub(crate) type RegisterSessionTx = oneshot::Sender<Result<SessionId, RegisterSessionError>>;
 
 /// A message coming from the Raft API.
 pub(crate) enum RaftMsg<D: AppData, R: AppDataResponse> {
@@ -239,22 +608,45 @@ pub(crate) enum RaftMsg<D: AppData, R: AppDataResponse> {
         rpc: VoteRequest,
         tx: oneshot::Sender<Result<VoteResponse, RaftError>>,
     },
+    PreVote {
+        rpc: PreVoteRequest,
+        tx: oneshot::Sender<Result<PreVoteResponse, RaftError>>,
+    },
     InstallSnapshot {
         rpc: InstallSnapshotRequest,
         tx: oneshot::Sender<Result<InstallSnapshotResponse, RaftError>>,
     },
+    TimeoutNow {
+        rpc: TimeoutNowRequest,
+        tx: oneshot::Sender<Result<TimeoutNowResponse, RaftError>>,
+    },
     ClientWriteRequest {
         rpc: ClientWriteRequest<D>,
-        tx: ClientWriteResponseTx<D, R>,
+        tx: ClientWriteTx<D, R>,
+    },
+    ClientWriteBatch {
+        requests: Vec<(ClientWriteRequest<D>, ClientWriteTx<D, R>)>,
     },
     ClientReadRequest {
         tx: ClientReadResponseTx,
     },
+    RegisterSession {
+        tx: RegisterSessionTx,
+    },
+    ReadIndex {
+        rpc: ReadIndexRequest,
+        tx: ReadIndexResponseTx,
+    },
     Initialize {
         members: HashSet<NodeId>,
         tx: oneshot::Sender<Result<(), InitializeError>>,
     },
     AddNonVoter {
+        id: NodeId,
+        is_witness: bool,
+        tx: ChangeMembershipTx,
+    },
+    RemoveNonVoter {
         id: NodeId,
         tx: ChangeMembershipTx,
     },
@@ -262,13 +654,21 @@ pub(crate) enum RaftMsg<D: AppData, R: AppDataResponse> {
         members: HashSet<NodeId>,
         tx: ChangeMembershipTx,
     },
+    TransferLeadership {
+        target: NodeId,
+        tx: LeadershipTransferTx,
+    },
+    ReplicationAckAges {
+        tx: oneshot::Sender<BTreeMap<NodeId, Duration>>,
+    },
+    Wake,
 }
 
 //////////////////////////////////////////////////////////////////////////////////////////////////
 //////////////////////////////////////////////////////////////////////////////////////////////////
 
 /// An RPC sent by a cluster leader to replicate log entries (§5.3), and as a heartbeat (§5.2).
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct AppendEntriesRequest<D: AppData> {
     /// The leader's current term.
     pub term: u64,
@@ -286,6 +686,12 @@ pub struct AppendEntriesRequest<D: AppData> {
     pub entries: Vec<Entry<D>>,
     /// The leader's commit index.
     pub leader_commit: u64,
+    /// Will be `true` if the leader is quiescing the group, which is only ever set on heartbeats.
+    ///
+    /// A follower which accepts such a heartbeat suspends its election timer until it is woken
+    /// by a client request or by any other RPC. See `Config::quiesce`.
+    #[serde(default)]
+    pub quiesce: bool,
 }
 
 /// The response to an `AppendEntriesRequest`.
@@ -358,6 +764,10 @@ pub enum EntryPayload<D: AppData> {
     ConfigChange(EntryConfigChange),
     /// An entry which points to a snapshot.
     SnapshotPointer(EntrySnapshotPointer),
+    /// An entry registering a new client session, whose ID is the index of this entry.
+    RegisterSession,
+    /// An entry expiring client sessions, proposed by the leader.
+    ExpireSessions(EntryExpireSessions),
 }
 
 /// A normal log entry.
@@ -366,6 +776,11 @@ pub struct EntryNormal<D: AppData> {
     /// The contents of this entry.
     #[serde(bound="D: AppData")]
     pub data: D,
+    /// The client session which submitted this entry, if any.
+    ///
+    /// Entries of a client session are applied to the state machine at most once per sequence number.
+    #[serde(default)]
+    pub session: Option<SessionRequest>,
 }
 
 /// A log entry holding a config change.
@@ -375,6 +790,16 @@ pub struct EntryConfigChange {
     pub membership: MembershipConfig,
 }
 
+/// A log entry expiring client sessions.
+///
+/// The sessions to expire are chosen by the leader, so that every node expires the same sessions
+/// at the same point of the log.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EntryExpireSessions {
+    /// The IDs of the sessions to expire.
+    pub sessions: Vec<SessionId>,
+}
+
 /// A log entry pointing to a snapshot.
 ///
 /// This will only be present when read from storage. An entry of this type will never be
@@ -400,10 +825,24 @@ pub struct MembershipConfig {
     ///
     /// The presence of a value here indicates that the config is in joint consensus.
     pub members_after_consensus: Option<HashSet<NodeId>>,
+    /// All learners of the Raft cluster.
+    ///
+    /// Learners are non-voters which receive replicated entries from the leader, but which never
+    /// count towards a quorum. As learners are part of the replicated config, every new leader
+    /// will resume replicating to them.
+    #[serde(default)]
+    pub learners: HashSet<NodeId>,
+    /// All witnesses of the Raft cluster.
+    ///
+    /// Witnesses are members or learners which only keep the term & index of normal entries, and
+    /// which keep no state machine. Witnesses which are members take part in elections & commit
+    /// quorums, but they never become the cluster leader.
+    #[serde(default)]
+    pub witnesses: HashSet<NodeId>,
 }
 
 impl MembershipConfig {
-    /// Get an iterator over all nodes in the current config.
+    /// Get an iterator over all voting nodes in the current config.
     pub fn all_nodes(&self) -> HashSet<u64> {
         let mut all = self.members.clone();
         if let Some(members) = &self.members_after_consensus {
@@ -414,7 +853,8 @@ impl MembershipConfig {
 
     /// Check if the given NodeId exists in this membership config.
     ///
-    /// When in joint consensus, this will check both config groups.
+    /// When in joint consensus, this will check both config groups. Learners are not considered
+    /// to be members of the config.
     pub fn contains(&self, x: &NodeId) -> bool {
         self.members.contains(x) || if let Some(members) = &self.members_after_consensus {
             members.contains(x)
@@ -423,6 +863,16 @@ impl MembershipConfig {
         }
     }
 
+    /// Check if the given NodeId is a learner in this membership config.
+    pub fn is_learner(&self, x: &NodeId) -> bool {
+        self.learners.contains(x)
+    }
+
+    /// Check if the given NodeId is a witness in this membership config.
+    pub fn is_witness(&self, x: &NodeId) -> bool {
+        self.witnesses.contains(x)
+    }
+
     /// Check to see if the config is currently in joint consensus.
     pub fn is_in_joint_consensus(&self) -> bool {
         self.members_after_consensus.is_some()
@@ -432,7 +882,7 @@ impl MembershipConfig {
     pub fn new_initial(id: NodeId) -> Self {
         let mut members = HashSet::new();
         members.insert(id);
-        Self{members, members_after_consensus: None}
+        Self{members, members_after_consensus: None, learners: HashSet::new(), witnesses: HashSet::new()}
     }
 }
 
@@ -440,7 +890,7 @@ impl MembershipConfig {
 //////////////////////////////////////////////////////////////////////////////////////////////////
 
 /// An RPC sent by candidates to gather votes (§5.2).
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct VoteRequest {
     /// The candidate's current term.
     pub term: u64,
@@ -450,12 +900,18 @@ pub struct VoteRequest {
     pub last_log_index: u64,
     /// The term of the candidate’s last log entry (§5.4).
     pub last_log_term: u64,
+    /// Will be `true` if the candidate is campaigning as part of a leadership transfer.
+    ///
+    /// Nodes will grant their vote for such requests even if they have recently heard from the
+    /// current leader (§3.10 of the Raft thesis).
+    #[serde(default)]
+    pub leadership_transfer: bool,
 }
 
 impl VoteRequest {
     /// Create a new instance.
     pub fn new(term: u64, candidate_id: u64, last_log_index: u64, last_log_term: u64) -> Self {
-        Self{term, candidate_id, last_log_index, last_log_term}
+        Self{term, candidate_id, last_log_index, last_log_term, leadership_transfer: false}
     }
 }
 
@@ -468,6 +924,83 @@ pub struct VoteResponse {
     pub vote_granted: bool,
 }
 
+/// An RPC sent by a node which has hit its election timeout, before it becomes a candidate (§9.6 of the Raft thesis).
+///
+/// Receiving nodes do not update their term or record a vote when handling this RPC.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PreVoteRequest {
+    /// The term which the node would use if it were to start a real election (its current term + 1).
+    pub term: u64,
+    /// The ID of the node requesting the pre-vote.
+    pub candidate_id: u64,
+    /// The index of the node's last log entry (§5.4).
+    pub last_log_index: u64,
+    /// The term of the node's last log entry (§5.4).
+    pub last_log_term: u64,
+}
+
+impl PreVoteRequest {
+    /// Create a new instance.
+    pub fn new(term: u64, candidate_id: u64, last_log_index: u64, last_log_term: u64) -> Self {
+        Self{term, candidate_id, last_log_index, last_log_term}
+    }
+}
+
+/// The response to a `PreVoteRequest`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PreVoteResponse {
+    /// The current term of the responding node.
+    pub term: u64,
+    /// Will be true if the responder would grant its vote to the node in a real election.
+    pub vote_granted: bool,
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////
+//////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// An RPC sent by the Raft leader to the target of a leadership transfer, instructing it to start
+/// an election immediately (§3.10 of the Raft thesis).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TimeoutNowRequest {
+    /// The leader's current term.
+    pub term: u64,
+    /// The leader's ID.
+    pub leader_id: u64,
+}
+
+/// The response to a `TimeoutNowRequest`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TimeoutNowResponse {
+    /// The responding node's current term, for leader to update itself.
+    pub term: u64,
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////
+//////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// An RPC sent by followers & non-voters to the Raft leader in order to obtain a read index for
+/// serving linearizable client reads (§6.4 of the Raft thesis).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReadIndexRequest {
+    /// The requesting node's current term.
+    pub term: u64,
+    /// The requesting node's ID.
+    pub requester_id: u64,
+}
+
+/// The response to a `ReadIndexRequest`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReadIndexResponse {
+    /// The responding node's current term.
+    pub term: u64,
+    /// The read index, once the responding node has confirmed that it is the cluster leader.
+    ///
+    /// This will be `None` if the responding node is not the cluster leader.
+    pub read_index: Option<u64>,
+    /// The ID of the cluster leader as known to the responding node, if any.
+    pub leader_id: Option<u64>,
+}
+
 //////////////////////////////////////////////////////////////////////////////////////////////////
 //////////////////////////////////////////////////////////////////////////////////////////////////
 
@@ -482,12 +1015,35 @@ pub struct InstallSnapshotRequest {
     pub last_included_index: u64,
     /// The term of the `last_included_index`.
     pub last_included_term: u64,
+    /// The ID of the snapshot being streamed.
+    ///
+    /// This is derived from the snapshot's contents, so a leader which begins streaming the same
+    /// snapshot again, such as after a restart, will resume the receiving node's existing stream.
+    pub snapshot_id: String,
     /// The byte offset where this chunk of data is positioned in the snapshot file.
     pub offset: u64,
     /// The raw bytes of the snapshot chunk, starting at `offset`.
     pub data: Vec<u8>,
+    /// The checksum of `data`, as calculated by `InstallSnapshotRequest::checksum`.
+    pub checksum: u64,
     /// Will be `true` if this is the last chunk in the snapshot.
     pub done: bool,
+    /// The membership config covered by the snapshot, which is only sent to witnesses.
+    ///
+    /// Witnesses keep no state machine, so a snapshot sent to a witness carries no data. It
+    /// consists of a single chunk, and is installed via `RaftStorage::install_witness_snapshot`.
+    /// This defaults to `None` when absent, so requests from nodes which predate it are accepted.
+    #[serde(default)]
+    pub membership: Option<MembershipConfig>,
+}
+
+impl InstallSnapshotRequest {
+    /// Calculate the checksum of the given chunk of snapshot data.
+    pub fn checksum(data: &[u8]) -> u64 {
+        let mut hasher = FnvHasher::default();
+        hasher.write(data);
+        hasher.finish()
+    }
 }
 
 /// The response to an `InstallSnapshotRequest`.
@@ -495,6 +1051,11 @@ pub struct InstallSnapshotRequest {
 pub struct InstallSnapshotResponse {
     /// The receiving node's current term, for leader to update itself.
     pub term: u64,
+    /// The byte offset of the next chunk which the receiving node expects for the snapshot.
+    ///
+    /// If this is not the end of the chunk which was sent, then the chunk was not written, and
+    /// the leader should continue streaming from this offset instead.
+    pub offset: u64,
 }
 
 //////////////////////////////////////////////////////////////////////////////////////////////////
@@ -514,7 +1075,22 @@ pub struct ClientWriteRequest<D: AppData> {
 impl<D: AppData> ClientWriteRequest<D> {
     /// Create a new client payload instance with a normal entry type.
     pub fn new(entry: D) -> Self {
-        Self::new_base(EntryPayload::Normal(EntryNormal{data: entry}))
+        Self::new_base(EntryPayload::Normal(EntryNormal{data: entry, session: None}))
+    }
+
+    /// Create a new client payload instance with a normal entry type, submitted as part of a client session.
+    ///
+    /// See `Raft::register_client_session`.
+    pub fn new_in_session(entry: D, session: SessionRequest) -> Self {
+        Self::new_base(EntryPayload::Normal(EntryNormal{data: entry, session: Some(session)}))
+    }
+
+    /// The client session of this request, if any.
+    pub fn session(&self) -> Option<&SessionRequest> {
+        match &self.entry {
+            EntryPayload::Normal(inner) => inner.session.as_ref(),
+            _ => None,
+        }
     }
 
     /// Create a new instance.
@@ -533,6 +1109,16 @@ impl<D: AppData> ClientWriteRequest<D> {
     pub(crate) fn new_blank_payload() -> Self {
         Self::new_base(EntryPayload::Blank)
     }
+
+    /// Generate a new payload registering a client session.
+    pub(crate) fn new_register_session() -> Self {
+        Self::new_base(EntryPayload::RegisterSession)
+    }
+
+    /// Generate a new payload expiring the given client sessions.
+    pub(crate) fn new_expire_sessions(sessions: Vec<SessionId>) -> Self {
+        Self::new_base(EntryPayload::ExpireSessions(EntryExpireSessions{sessions}))
+    }
 }
 
 /// The response to a `ClientRequest`.
@@ -544,3 +1130,73 @@ pub struct ClientWriteResponse<R: AppDataResponse> {
     #[serde(bound="R: AppDataResponse")]
     pub data: R,
 }
+
+/// The position of a client write which has been appended to the leader's log.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientWriteAppended {
+    /// The log index of the write.
+    pub index: u64,
+    /// The term of the leader which appended the write.
+    pub term: u64,
+}
+
+/// A handle on each stage of a client write, as returned by `Raft::client_write_staged`.
+///
+/// The stages of a write are reached in order: the write is appended to the leader's log, then
+/// committed to the cluster, and then applied to the state machine. A write which has only been
+/// appended may still be lost if leadership changes, while a committed write is durable, but its
+/// effects may not yet be visible to reads.
+///
+/// Only `applied` reports the error of a failed write. If the write fails before reaching the
+/// stage which `appended` or `committed` waits for, then these return `None`, and `applied` will
+/// return the error.
+///
+/// The handle does not borrow the `Raft` node, so it may be moved into a spawned task, such as one
+/// which waits for the write to be committed in the background.
+pub struct ClientWriteHandle<D: AppData, R: AppDataResponse> {
+    appended: Stage<ClientWriteAppended>,
+    committed: Stage<u64>,
+    rx_applied: oneshot::Receiver<Result<ClientWriteResponse<R>, ClientWriteError<D>>>,
+}
+
+impl<D: AppData, R: AppDataResponse> ClientWriteHandle<D, R> {
+    /// Wait for the write to be appended to the leader's log, returning its index & term.
+    pub async fn appended(&mut self) -> Option<ClientWriteAppended> {
+        self.appended.wait().await
+    }
+
+    /// Wait for the write to be committed to the cluster, returning its index.
+    pub async fn committed(&mut self) -> Option<u64> {
+        self.committed.wait().await
+    }
+
+    /// Wait for the write to be applied to the state machine, returning its response.
+    ///
+    /// The response may be the cached response of an earlier attempt, if the write is part of a
+    /// client session. See `Raft::register_client_session`.
+    pub async fn applied(self) -> Result<ClientWriteResponse<R>, ClientWriteError<D>> {
+        match self.rx_applied.await {
+            Ok(res) => res,
+            Err(_) => Err(ClientWriteError::RaftError(RaftError::ShuttingDown)),
+        }
+    }
+}
+
+/// A stage of a client write, which is either still pending or has been resolved.
+enum Stage<T> {
+    Pending(oneshot::Receiver<T>),
+    Resolved(Option<T>),
+}
+
+impl<T: Copy> Stage<T> {
+    /// Wait for the stage to be reached, returning `None` if it never will be.
+    async fn wait(&mut self) -> Option<T> {
+        if let Stage::Pending(rx) = self {
+            *self = Stage::Resolved(rx.await.ok());
+        }
+        match self {
+            Stage::Resolved(res) => *res,
+            Stage::Pending(_) => None,
+        }
+    }
+}
@@ -1,5 +1,6 @@
 //! Replication stream.
 
+use std::hash::Hasher;
 use std::io::SeekFrom;
 use std::sync::Arc;
 
@@ -7,12 +8,17 @@ use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt};
 use tokio::stream::StreamExt;
 use tokio::sync::{mpsc, oneshot};
 use tokio::task::JoinHandle;
-use tokio::time::{Duration, Interval, interval, timeout};
+use futures::future::{BoxFuture, FutureExt};
+use futures::stream::FuturesUnordered;
+use tokio::time::{Duration, Elapsed, Instant, delay_for, interval, timeout};
+use fnv::FnvHasher;
 
 use crate::{AppData, AppDataResponse, NodeId, RaftNetwork, RaftStorage};
 use crate::config::{Config, SnapshotPolicy};
 use crate::error::RaftResult;
-use crate::raft::{AppendEntriesRequest, Entry, EntryPayload, InstallSnapshotRequest};
+use crate::metrics::{ReplicationStreamState, SnapshotProgress};
+use crate::network::HeartbeatTicker;
+use crate::raft::{AppendEntriesRequest, AppendEntriesResponse, Entry, EntryPayload, InstallSnapshotRequest};
 use crate::storage::CurrentSnapshotData;
 
 /// The public handle to a spawned replication stream.
@@ -26,22 +32,36 @@ pub(crate) struct ReplicationStream<D: AppData> {
 impl<D: AppData> ReplicationStream<D> {
     /// Create a new replication stream for the target peer.
     pub(crate) fn new<R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>>(
-        id: NodeId, target: NodeId, term: u64, config: Arc<Config>,
-        last_log_index: u64, last_log_term: u64, commit_index: u64,
+        params: ReplicationStreamParams, config: Arc<Config>,
         network: Arc<N>, storage: Arc<S>, replicationtx: mpsc::UnboundedSender<ReplicaEvent<S::Snapshot>>,
     ) -> Self {
-        ReplicationCore::spawn(
-            id, target, term, config, last_log_index, last_log_term, commit_index,
-            network, storage, replicationtx,
-        )
+        ReplicationCore::spawn(params, config, network, storage, replicationtx)
     }
 }
 
+/// The target of a new replication stream, and the state of the leader which it starts from.
+pub(crate) struct ReplicationStreamParams {
+    /// The ID of this Raft node.
+    pub id: NodeId,
+    /// The ID of the target Raft node which replication events are to be sent to.
+    pub target: NodeId,
+    /// Will be `true` if the target is a witness, which is only sent the metadata of entries.
+    pub is_witness: bool,
+    /// The current term of the leader.
+    pub term: u64,
+    /// The index of the last entry in the leader's log.
+    pub last_log_index: u64,
+    /// The term of the last entry in the leader's log.
+    pub last_log_term: u64,
+    /// The leader's commit index.
+    pub commit_index: u64,
+}
+
 /// A task responsible for sending replication events to a target follower in the Raft cluster.
 ///
-/// NOTE: we do not stack replication requests to targets because this could result in
-/// out-of-order delivery. We always buffer until we receive a success response, then send the
-/// next payload from the buffer.
+/// NOTE: replication requests are only stacked up to `Config::max_inflight_append_entries` while
+/// the target is replicating at line rate. In all other states, we always buffer until we receive
+/// a success response, then send the next payload from the buffer.
 struct ReplicationCore<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> {
     //////////////////////////////////////////////////////////////////////////
     // Static Fields /////////////////////////////////////////////////////////
@@ -50,6 +70,8 @@ struct ReplicationCore<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: Raf
     id: NodeId,
     /// The ID of the target Raft node which replication events are to be sent to.
     target: NodeId,
+    /// Whether the target is a witness, in which case only the metadata of normal entries is sent.
+    is_witness: bool,
     /// The current term, which will never change during the lifetime of this task.
     term: u64,
     /// A channel for sending events to the Raft node.
@@ -109,37 +131,53 @@ struct ReplicationCore<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: Raf
     /// A buffer of data to replicate to the target follower.
     ///
     /// The buffered payload here will be expanded as more replication commands come in from the
-    /// Raft node. Data from this buffer will flow into the `outbound_buffer` in chunks.
-    replication_buffer: Vec<Arc<Entry<D>>>,
+    /// Raft node. Data from this buffer will flow into the `outbound_buffer` in chunks. Each entry
+    /// is buffered along with its size in bytes.
+    replication_buffer: Vec<(Arc<Entry<D>>, u64)>,
+    /// The total size in bytes of the entries in `replication_buffer`.
+    ///
+    /// This is kept within `Config::max_replication_buffer_bytes` by falling back to lagging state.
+    replication_buffer_bytes: u64,
     /// A buffer of data which is being sent to the follower.
     ///
     /// Data in this buffer comes directly from the `replication_buffer` in chunks, and will
     /// remain here until it is confirmed that the payload has been successfully received by the
     /// target node. This allows for retransmission of payloads in the face of transient errors.
     outbound_buffer: Vec<OutboundEntry<D>>,
-    /// The heartbeat interval for ensuring that heartbeats are always delivered in a timely fashion.
-    heartbeat: Interval,
+    /// The heartbeat ticker for ensuring that heartbeats are always delivered in a timely fashion.
+    ///
+    /// This is the network's ticker for the target if it has one, else an interval of the
+    /// configured heartbeat interval.
+    heartbeat: HeartbeatTicker,
     /// The timeout duration for heartbeats.
     heartbeat_timeout: Duration,
+    /// A bool indicating if the Raft node has asked for the target to be quiesced.
+    quiesce: bool,
+    /// A bool indicating if the target has accepted a quiescing heartbeat, in which case no more
+    /// heartbeats are sent until the stream is woken.
+    is_quiescent: bool,
 }
 
 impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> ReplicationCore<D, R, N, S> {
     /// Spawn a new replication task for the target node.
     pub(self) fn spawn(
-        id: NodeId, target: NodeId, term: u64, config: Arc<Config>,
-        last_log_index: u64, last_log_term: u64, commit_index: u64,
+        params: ReplicationStreamParams, config: Arc<Config>,
         network: Arc<N>, storage: Arc<S>, rafttx: mpsc::UnboundedSender<ReplicaEvent<S::Snapshot>>,
     ) -> ReplicationStream<D> {
+        let ReplicationStreamParams{id, target, is_witness, term, last_log_index, last_log_term, commit_index} = params;
         let (raftrx_tx, raftrx) = mpsc::unbounded_channel();
         let heartbeat_timeout = Duration::from_millis(config.heartbeat_interval);
         let max_payload_entries = config.max_payload_entries as usize;
+        let heartbeat = network.heartbeat_ticker(target)
+            .unwrap_or_else(|| Box::pin(interval(heartbeat_timeout).map(|_| ())));
         let this = Self{
-            id, target, term, network, storage, config, max_payload_entries,
+            id, target, is_witness, term, network, storage, config, max_payload_entries,
             marker_r: std::marker::PhantomData,
             target_state: TargetReplState::Lagging, last_log_index, commit_index,
             next_index: last_log_index + 1, match_index: last_log_index, match_term: last_log_term,
-            rafttx, raftrx, heartbeat: interval(heartbeat_timeout), heartbeat_timeout,
-            replication_buffer: Vec::new(), outbound_buffer: Vec::new(),
+            rafttx, raftrx, heartbeat, heartbeat_timeout,
+            replication_buffer: Vec::new(), replication_buffer_bytes: 0, outbound_buffer: Vec::new(),
+            quiesce: false, is_quiescent: false,
         };
         let handle = tokio::spawn(this.main());
         ReplicationStream{handle, repltx: raftrx_tx}
@@ -172,20 +210,23 @@ impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> Re
             let repl_len = self.replication_buffer.len();
             if repl_len > 0 {
                 let chunk_size = if repl_len < self.max_payload_entries { repl_len } else { self.max_payload_entries };
-                self.outbound_buffer.extend(
-                    self.replication_buffer.drain(..chunk_size)
-                    .map(|entry| OutboundEntry::Arc(entry)));
+                let entries = self.drain_replication_buffer(chunk_size);
+                self.outbound_buffer.extend(entries.into_iter().map(|entry| OutboundEntry::Arc(entry)));
             }
         }
 
-        // Build the heartbeat frame to be sent to the follower.
+        // Build the heartbeat frame to be sent to the follower. Only a heartbeat to a target which
+        // is replicating at line rate may quiesce it.
+        let quiesce = self.quiesce && self.outbound_buffer.is_empty() && self.target_state == TargetReplState::LineRate;
         let payload = AppendEntriesRequest{
             term: self.term, leader_id: self.id,
             prev_log_index: self.match_index, prev_log_term: self.match_term,
-            leader_commit: self.commit_index, entries: self.outbound_buffer.iter().map(|entry| entry.as_ref().clone()).collect(),
+            leader_commit: self.commit_index, entries: self.outbound_buffer.iter().map(|entry| self.outbound_entry(entry.as_ref())).collect(),
+            quiesce,
         };
 
         // Send the payload.
+        let sent_at = Instant::now();
         let res = match timeout(self.heartbeat_timeout, self.network.append_entries(self.target, payload)).await {
             Ok(outer_res) => match outer_res {
                 Ok(res) => res,
@@ -205,9 +246,18 @@ impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> Re
         };
         self.outbound_buffer.clear(); // Once we've successfully sent a payload of entries, don't send them again.
 
+        // Any response in our term means the target has acknowledged this node as leader.
+        if res.term == self.term {
+            let _ = self.rafttx.send(ReplicaEvent::UpdateLastAck{target: self.target, sent_at});
+        }
+
         // Handle success conditions.
         if res.success {
             tracing::trace!("append entries succeeded");
+            if quiesce && self.quiesce {
+                tracing::debug!("target has been quiesced");
+                self.is_quiescent = true;
+            }
             // If this was a proper replication event (last index & term were provided), then update state.
             if let Some((index, term)) = last_index_and_term {
                 self.next_index = index + 1; // This should always be the next expected index.
@@ -256,7 +306,7 @@ impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> Re
             }
 
             // Fetch the entry at conflict index and use the term specified there.
-            match self.storage.get_log_entries(conflict.index, conflict.index).await.map(|entries| entries.iter().nth(0).map(|entry| entry.term)) {
+            match self.storage.get_log_entries(conflict.index, conflict.index + 1).await.map(|entries| entries.iter().nth(0).map(|entry| entry.term)) {
                 Ok(Some(term)) => {
                     self.match_term = term; // If we have the specified log, ensure we use its term.
                 }
@@ -297,6 +347,16 @@ impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> Re
         }
     }
 
+    /// Prepare the given entry for sending to the target.
+    ///
+    /// Witnesses only keep the term & index of normal entries, so their payloads are not sent.
+    fn outbound_entry(&self, entry: &Entry<D>) -> Entry<D> {
+        match &entry.payload {
+            EntryPayload::Normal(_) if self.is_witness => Entry{index: entry.index, term: entry.term, payload: EntryPayload::Blank},
+            _ => entry.clone(),
+        }
+    }
+
     /// Perform a check to see if this replication stream is lagging behind far enough that a
     /// snapshot is warranted.
     #[tracing::instrument(level="trace", skip(self))]
@@ -314,6 +374,46 @@ impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> Re
         }
     }
 
+    /// Buffer the given entries for replication to the target.
+    ///
+    /// If the buffer grows beyond `Config::max_replication_buffer_bytes`, then the target is not
+    /// able to replicate data fast enough, so the buffer is purged and the stream transitions to
+    /// a lagging state, in which entries are fetched from storage instead.
+    fn buffer_entries(&mut self, entries: Vec<(Arc<Entry<D>>, u64)>) {
+        self.replication_buffer_bytes = entries.iter().fold(self.replication_buffer_bytes, |bytes, (_, size)| bytes.saturating_add(*size));
+        self.replication_buffer.extend(entries);
+        if self.replication_buffer_bytes > self.config.max_replication_buffer_bytes {
+            tracing::debug!({bytes=self.replication_buffer_bytes}, "replication buffer is full, transitioning to lagging state");
+            self.clear_replication_buffer();
+            self.target_state = TargetReplState::Lagging;
+        }
+    }
+
+    /// Drain up to `count` entries from the front of the replication buffer.
+    fn drain_replication_buffer(&mut self, count: usize) -> Vec<Arc<Entry<D>>> {
+        let mut bytes = 0u64;
+        let entries: Vec<_> = self.replication_buffer.drain(..count)
+            .map(|(entry, size)| {
+                bytes = bytes.saturating_add(size);
+                entry
+            })
+            .collect();
+        self.replication_buffer_bytes = self.replication_buffer_bytes.saturating_sub(bytes);
+        entries
+    }
+
+    /// Clear the replication buffer.
+    fn clear_replication_buffer(&mut self) {
+        self.replication_buffer.clear();
+        self.replication_buffer_bytes = 0;
+    }
+
+    /// Wake this stream from quiescence, resuming heartbeats to the target.
+    fn wake(&mut self) {
+        self.quiesce = false;
+        self.is_quiescent = false;
+    }
+
     /// Fully drain the channel coming in from the Raft node.
     pub(self) fn drain_raftrx(&mut self, first: RaftEvent<D>) {
         let mut event_opt = Some(first);
@@ -333,13 +433,22 @@ impl<D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> Re
                 RaftEvent::UpdateCommitIndex{commit_index} => {
                     self.commit_index = commit_index;
                 }
-                RaftEvent::Replicate{entry, commit_index} => {
+                RaftEvent::Replicate{entries, commit_index} => {
+                    self.wake();
                     self.commit_index = commit_index;
-                    self.last_log_index = entry.index;
-                    if &self.target_state == &TargetReplState::LineRate {
-                        self.replication_buffer.push(entry);
+                    if let Some((entry, _)) = entries.last() {
+                        self.last_log_index = entry.index;
+                    }
+                    if self.target_state == TargetReplState::LineRate {
+                        self.buffer_entries(entries);
                     }
                 }
+                RaftEvent::Quiesce => {
+                    self.quiesce = true;
+                }
+                RaftEvent::Wake => {
+                    self.wake();
+                }
                 RaftEvent::Terminate => {
                     self.target_state = TargetReplState::Shutdown;
                     return;
@@ -359,7 +468,7 @@ enum OutboundEntry<D: AppData> {
     /// An entry owned by an Arc, hot off the replication stream from the Raft leader.
     Arc(Arc<Entry<D>>),
     /// An entry which was fetched directly from storage.
-    Raw(Entry<D>),
+    Raw(Box<Entry<D>>),
 }
 
 impl<D: AppData> AsRef<Entry<D>> for OutboundEntry<D> {
@@ -371,6 +480,21 @@ impl<D: AppData> AsRef<Entry<D>> for OutboundEntry<D> {
     }
 }
 
+/// The size of the given entry in bytes, as measured by the size of its bincode encoding.
+///
+/// This is computed once per entry by the Raft leader, and is passed to every replication stream
+/// along with the entry. An entry which can not be sized is counted as `u64::MAX` bytes, so that
+/// it overflows the replication buffer of every target, which then fetch it from storage.
+pub(crate) fn entry_size<D: AppData>(entry: &Entry<D>) -> u64 {
+    match bincode::serialized_size(entry) {
+        Ok(size) => size,
+        Err(err) => {
+            tracing::error!({error=%err, index=entry.index}, "error computing the size of entry for replication buffers, counting it as full");
+            u64::MAX
+        }
+    }
+}
+
 //////////////////////////////////////////////////////////////////////////////////////////////////
 //////////////////////////////////////////////////////////////////////////////////////////////////
 
@@ -390,11 +514,12 @@ enum TargetReplState {
 /// An event from the Raft node.
 pub(crate) enum RaftEvent<D: AppData> {
     Replicate {
-        /// The new entry which needs to be replicated.
+        /// The new entries which need to be replicated, in log order, each along with its size in
+        /// bytes as computed by `entry_size`.
         ///
-        /// This entry will always be the most recent entry to have been appended to the log, so its
-        /// index is the new last_log_index value.
-        entry: Arc<Entry<D>>,
+        /// The last of these entries will always be the most recent entry to have been appended to
+        /// the log, so its index is the new last_log_index value.
+        entries: Vec<(Arc<Entry<D>>, u64)>,
         /// The index of the highest log entry which is known to be committed in the cluster.
         commit_index: u64,
     },
@@ -403,6 +528,10 @@ pub(crate) enum RaftEvent<D: AppData> {
         /// The index of the highest log entry which is known to be committed in the cluster.
         commit_index: u64,
     },
+    /// A message from Raft indicating that the target is to be quiesced, as the group is idle.
+    Quiesce,
+    /// A message from Raft indicating that the group is no longer idle, and heartbeats must resume.
+    Wake,
     Terminate,
 }
 
@@ -414,12 +543,19 @@ pub(crate) enum ReplicaEvent<S>
     RateUpdate{
         /// The ID of the Raft node to which this event relates.
         target: NodeId,
-        /// A flag indicating if the corresponding target node is replicating at line rate.
+        /// The state which the replication stream has transitioned to.
         ///
         /// When replicating at line rate, the replication stream will receive log entires to
         /// replicate as soon as they are ready. When not running at line rate, the Raft node will
         /// only send over metadata without entries to replicate.
-        is_line_rate: bool,
+        state: ReplicationStreamState,
+    },
+    /// An event from a replication stream reporting progress on streaming a snapshot to the target.
+    SnapshotProgress{
+        /// The ID of the target node to which the snapshot is being streamed.
+        target: NodeId,
+        /// The progress of the snapshot stream.
+        progress: SnapshotProgress,
     },
     /// An event from a replication stream which updates the target node's match index.
     UpdateMatchIndex{
@@ -430,6 +566,13 @@ pub(crate) enum ReplicaEvent<S>
         /// The term of the most recent log known to have been successfully replicated on the target.
         match_term: u64,
     },
+    /// An event from a replication stream indicating that the target has acknowledged this node as leader.
+    UpdateLastAck{
+        /// The ID of the target node which acknowledged the RPC.
+        target: NodeId,
+        /// The time at which the acknowledged RPC was sent.
+        sent_at: Instant,
+    },
     /// An event indicating that the Raft node needs to revert to follower state.
     RevertToFollower{
         /// The ID of the target node from which the new term was observed.
@@ -455,43 +598,70 @@ pub(crate) enum ReplicaEvent<S>
 struct LineRateState<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> {
     /// An exclusive handle to the replication core.
     core: &'a mut ReplicationCore<D, R, N, S>,
+    /// The AppendEntries RPCs which have been sent to the target and are awaiting a response.
+    inflight: FuturesUnordered<BoxFuture<'static, (InflightAppendEntries, InflightResult)>>,
+    /// The index & term of the last entry to have been sent to the target.
+    ///
+    /// This is the `prev_log_index` & `prev_log_term` of the next payload to be sent.
+    last_sent: (u64, u64),
+}
+
+/// The metadata of an AppendEntries RPC which is in flight to the target.
+struct InflightAppendEntries {
+    /// The index & term of the last entry of the payload, if the payload was not empty.
+    last_index_and_term: Option<(u64, u64)>,
+    /// The time at which the RPC was sent.
+    sent_at: Instant,
 }
 
+/// The outcome of an in-flight AppendEntries RPC.
+type InflightResult = Result<anyhow::Result<AppendEntriesResponse>, Elapsed>;
+
 impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>> LineRateState<'a, D, R, N, S> {
     /// Create a new instance.
     pub fn new(core: &'a mut ReplicationCore<D, R, N, S>) -> Self {
-        Self{core}
+        let last_sent = (core.match_index, core.match_term);
+        Self{core, inflight: FuturesUnordered::new(), last_sent}
     }
 
     #[tracing::instrument(level="trace", skip(self), fields(state="line-rate"))]
     pub async fn run(mut self) {
-        let event = ReplicaEvent::RateUpdate{target: self.core.target, is_line_rate: true};
+        let event = ReplicaEvent::RateUpdate{target: self.core.target, state: ReplicationStreamState::LineRate};
         let _ = self.core.rafttx.send(event);
         loop {
             if &self.core.target_state != &TargetReplState::LineRate {
                 return;
             }
 
-            // We always prioritize draining our buffers first.
+            // Ensure that our buffered data matches up with `next_index`. When transitioning to
+            // line rate, it is always possible that new data has been sent for replication but has
+            // skipped this replication stream during transition, and the pipeline may have been
+            // reset after a failed request. In such cases, a single update from storage will put
+            // this stream back on track.
             let next_buf_index = self.core.outbound_buffer.first().map(|entry| entry.as_ref().index)
-                .or(self.core.replication_buffer.first().map(|entry| entry.index));
-            if let Some(index) = next_buf_index {
-                // Ensure that our buffered data matches up with `next_index`. When transitioning to
-                // line rate, it is always possible that new data has been sent for replication but has
-                // skipped this replication stream during transition. In such cases, a single update from
-                // storage will put this stream back on track.
-                if &self.core.next_index != &index {
-                    self.frontload_outbound_buffer(self.core.next_index, index).await;
-                    if &self.core.target_state != &TargetReplState::LineRate {
-                        return;
-                    }
+                .or(self.core.replication_buffer.first().map(|(entry, _)| entry.index))
+                .unwrap_or(self.core.last_log_index + 1);
+            if self.core.next_index < next_buf_index {
+                self.frontload_outbound_buffer(self.core.next_index, next_buf_index).await;
+                if &self.core.target_state != &TargetReplState::LineRate {
+                    return;
                 }
+            }
 
-                self.core.send_append_entries().await;
+            // We always prioritize draining our buffers first, up to the configured window.
+            let has_buffered = !self.core.outbound_buffer.is_empty() || !self.core.replication_buffer.is_empty();
+            if has_buffered && (self.inflight.len() as u64) < self.core.config.max_inflight_append_entries {
+                self.send_next_payload();
                 continue;
             }
-            tokio::select!{
-                _ = self.core.heartbeat.next() => self.core.send_append_entries().await,
+            select!{
+                Some((req, res)) = self.inflight.next() => self.handle_response(req, res),
+                _ = self.core.heartbeat.next(), if !self.core.is_quiescent => {
+                    // In-flight payloads serve as heartbeats, so only send one if the pipeline is empty.
+                    if self.inflight.is_empty() {
+                        self.core.send_append_entries().await;
+                    }
+                }
                 event = self.core.raftrx.next() => match event {
                     Some(event) => self.core.drain_raftrx(event),
                     None => self.core.target_state = TargetReplState::Shutdown,
@@ -500,6 +670,108 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
         }
     }
 
+    /// Send the next payload of buffered entries to the target without waiting for a response.
+    #[tracing::instrument(level="trace", skip(self))]
+    fn send_next_payload(&mut self) {
+        // Frontloaded entries always come before those in the replication buffer.
+        let entries: Vec<Entry<D>> = if !self.core.outbound_buffer.is_empty() {
+            let chunk_size = std::cmp::min(self.core.outbound_buffer.len(), self.core.max_payload_entries);
+            let entries: Vec<_> = self.core.outbound_buffer.drain(..chunk_size).collect();
+            entries.iter().map(|entry| self.core.outbound_entry(entry.as_ref())).collect()
+        } else {
+            let chunk_size = std::cmp::min(self.core.replication_buffer.len(), self.core.max_payload_entries);
+            let entries = self.core.drain_replication_buffer(chunk_size);
+            entries.iter().map(|entry| self.core.outbound_entry(entry)).collect()
+        };
+        let last_index_and_term = entries.last().map(|entry| (entry.index, entry.term));
+        let payload = AppendEntriesRequest{
+            term: self.core.term, leader_id: self.core.id,
+            prev_log_index: self.last_sent.0, prev_log_term: self.last_sent.1,
+            leader_commit: self.core.commit_index, entries, quiesce: false,
+        };
+        if let Some((index, term)) = last_index_and_term {
+            self.last_sent = (index, term);
+            self.core.next_index = index + 1;
+        }
+
+        let req = InflightAppendEntries{last_index_and_term, sent_at: Instant::now()};
+        let (network, target, ttl) = (self.core.network.clone(), self.core.target, self.core.heartbeat_timeout);
+        self.inflight.push(async move {
+            let res = timeout(ttl, network.append_entries(target, payload)).await;
+            (req, res)
+        }.boxed());
+    }
+
+    /// Handle the response to a pipelined AppendEntries RPC.
+    ///
+    /// Responses may arrive out-of-order, so the match index is only ever moved forward. Any
+    /// rejection causes this stream to fall back to lagging state, while any other failure
+    /// resets the pipeline to resume from the match index.
+    #[tracing::instrument(level="trace", skip(self, req, res))]
+    fn handle_response(&mut self, req: InflightAppendEntries, res: InflightResult) {
+        let res = match res {
+            Ok(Ok(res)) => res,
+            Ok(Err(err)) => {
+                tracing::error!({error=%err}, "error sending AppendEntries RPC to target");
+                self.reset_pipeline();
+                return;
+            }
+            Err(err) => {
+                tracing::error!({error=%err}, "timeout while sending AppendEntries RPC to target");
+                self.reset_pipeline();
+                return;
+            }
+        };
+
+        // Any response in our term means the target has acknowledged this node as leader.
+        if res.term == self.core.term {
+            let _ = self.core.rafttx.send(ReplicaEvent::UpdateLastAck{target: self.core.target, sent_at: req.sent_at});
+        }
+
+        // Handle success conditions.
+        if res.success {
+            if let Some((index, term)) = req.last_index_and_term {
+                if index > self.core.match_index {
+                    self.core.match_index = index;
+                    self.core.match_term = term;
+                    let _ = self.core.rafttx.send(ReplicaEvent::UpdateMatchIndex{target: self.core.target, match_index: index, match_term: term});
+                }
+
+                // If our buffered outbound requests have accumulated too much, we need to purge and
+                // transition to a lagging state. The target is not able to replicate data fast enough.
+                if self.core.last_log_index - self.core.match_index > self.core.config.replication_lag_threshold {
+                    self.core.next_index = self.core.match_index + 1;
+                    self.core.target_state = TargetReplState::Lagging;
+                }
+            }
+            return;
+        }
+
+        // Replication was not successful, if a newer term has been returned, revert to follower.
+        if res.term > self.core.term {
+            tracing::trace!({res.term}, "append entries failed, reverting to follower");
+            let _ = self.core.rafttx.send(ReplicaEvent::RevertToFollower{target: self.core.target, term: res.term});
+            self.core.target_state = TargetReplState::Shutdown;
+            return;
+        }
+
+        // The payload was rejected, so fall back to lagging state, which will resume replication
+        // from the most recent entry known to be replicated on the target.
+        tracing::trace!({res.term}, "pipelined append entries rejected, transitioning to lagging");
+        self.core.next_index = self.core.match_index + 1;
+        self.core.target_state = TargetReplState::Lagging;
+    }
+
+    /// Drop all in-flight requests, and resume replication from the match index.
+    ///
+    /// Entries which were in flight will be fetched again from storage.
+    fn reset_pipeline(&mut self) {
+        self.inflight = FuturesUnordered::new();
+        self.core.outbound_buffer.clear();
+        self.core.next_index = self.core.match_index + 1;
+        self.last_sent = (self.core.match_index, self.core.match_term);
+    }
+
     /// Ensure there are no gaps in the outbound buffer due to transition from lagging.
     #[tracing::instrument(level="trace", skip(self))]
     async fn frontload_outbound_buffer(&mut self, start: u64, stop: u64) {
@@ -508,6 +780,7 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
             Err(err) => {
                 tracing::error!({error=%err}, "error while frontloading outbound buffer");
                 let _ = self.core.rafttx.send(ReplicaEvent::Shutdown);
+                self.core.target_state = TargetReplState::Shutdown;
                 return;
             }
         };
@@ -519,7 +792,7 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
         }
         // Prepend.
         self.core.outbound_buffer.reverse();
-        self.core.outbound_buffer.extend(entries.into_iter().rev().map(|entry| OutboundEntry::Raw(entry)));
+        self.core.outbound_buffer.extend(entries.into_iter().rev().map(|entry| OutboundEntry::Raw(Box::new(entry))));
         self.core.outbound_buffer.reverse();
     }
 }
@@ -541,9 +814,9 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
 
     #[tracing::instrument(level="trace", skip(self), fields(state="lagging"))]
     pub async fn run(mut self) {
-        let event = ReplicaEvent::RateUpdate{target: self.core.target, is_line_rate: false};
+        let event = ReplicaEvent::RateUpdate{target: self.core.target, state: ReplicationStreamState::Lagging};
         let _ = self.core.rafttx.send(event);
-        self.core.replication_buffer.clear();
+        self.core.clear_replication_buffer();
         self.core.outbound_buffer.clear();
         loop {
             if &self.core.target_state != &TargetReplState::Lagging {
@@ -614,7 +887,7 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
                     return;
                 }
             }
-            self.core.outbound_buffer.extend(entries.into_iter().map(|entry| OutboundEntry::Raw(entry)));
+            self.core.outbound_buffer.extend(entries.into_iter().map(|entry| OutboundEntry::Raw(Box::new(entry))));
         }
     }
 }
@@ -638,9 +911,9 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
 
     #[tracing::instrument(level="trace", skip(self), fields(state="snapshotting"))]
     pub async fn run(mut self) {
-        let event = ReplicaEvent::RateUpdate{target: self.core.target, is_line_rate: false};
+        let event = ReplicaEvent::RateUpdate{target: self.core.target, state: ReplicationStreamState::Snapshotting};
         let _ = self.core.rafttx.send(event);
-        self.core.replication_buffer.clear();
+        self.core.clear_replication_buffer();
         self.core.outbound_buffer.clear();
 
         loop {
@@ -679,7 +952,7 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
     #[tracing::instrument(level="trace", skip(self, rx))]
     async fn wait_for_snapshot(&mut self, mut rx: oneshot::Receiver<CurrentSnapshotData<S::Snapshot>>) {
         loop {
-            tokio::select!{
+            select!{
                 _ = self.core.heartbeat.next() => self.core.send_append_entries().await,
                 event = self.core.raftrx.next() => match event {
                     Some(event) => self.core.drain_raftrx(event),
@@ -703,37 +976,63 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
 
     #[tracing::instrument(level="trace", skip(self, snapshot))]
     async fn stream_snapshot(&mut self, mut snapshot: CurrentSnapshotData<S::Snapshot>) -> RaftResult<()> {
-        let mut offset = 0;
-        self.core.last_log_index = snapshot.index;
+        // Entries may have been replicated while the snapshot was being fetched, so never move the
+        // last log index backwards, else the lag calculations of other states would underflow.
+        self.core.last_log_index = std::cmp::max(self.core.last_log_index, snapshot.index);
         self.core.next_index = snapshot.index + 1;
         self.core.match_index = snapshot.index;
         self.core.match_term = snapshot.term;
+        if self.core.is_witness {
+            self.send_witness_snapshot(snapshot).await;
+            return Ok(());
+        }
+        let snapshot_id = Self::snapshot_id(&mut snapshot, self.core.config.snapshot_max_chunk_size).await?;
+        let size = snapshot.snapshot.seek(SeekFrom::End(0)).await?;
         let mut buf = Vec::with_capacity(self.core.config.snapshot_max_chunk_size as usize);
+        let mut offset = 0;
+        let mut backoff: Option<Duration> = None;
+        // The first RPC carries no data, and is used to discover the offset from which the target
+        // expects the stream to continue, as it may have received part of this snapshot already.
+        let mut probe = true;
         loop {
+            // If the last RPC failed, then back off before retrying, staying up-to-date in the meantime.
+            if let Some(delay) = backoff {
+                self.backoff(delay).await;
+                if self.core.target_state != TargetReplState::Snapshotting {
+                    return Ok(());
+                }
+            }
+
             // Build the RPC.
             snapshot.snapshot.seek(SeekFrom::Start(offset)).await?;
-            let nread = snapshot.snapshot.read_buf(&mut buf).await?;
-            let done = nread == 0; // If bytes read == 0, then we're done.
+            let nread = if probe { 0 } else { snapshot.snapshot.read_buf(&mut buf).await? };
+            let done = !probe && nread == 0; // If bytes read == 0, then we're done.
             let req = InstallSnapshotRequest{
                 term: self.core.term, leader_id: self.core.id,
                 last_included_index: snapshot.index,
                 last_included_term: snapshot.term,
+                snapshot_id: snapshot_id.clone(),
                 offset, data: Vec::from(&buf[..nread]), done,
+                checksum: InstallSnapshotRequest::checksum(&buf[..nread]),
+                membership: None,
             };
             buf.clear();
 
             // Send the RPC over to the target.
             tracing::trace!({snapshot_size=req.data.len(), nread, req.done, req.offset}, "sending snapshot chunk");
+            let sent_at = Instant::now();
             let res = match timeout(self.core.heartbeat_timeout, self.core.network.install_snapshot(self.core.target, req)).await {
                 Ok(outer_res) => match outer_res {
                     Ok(res) => res,
                     Err(err) => {
                         tracing::error!({error=%err}, "error sending InstallSnapshot RPC to target");
+                        backoff = Some(self.next_backoff(backoff));
                         continue;
                     }
                 },
                 Err(err) => {
                     tracing::error!({error=%err}, "timeout while sending InstallSnapshot RPC to target");
+                    backoff = Some(self.next_backoff(backoff));
                     continue;
                 }
             };
@@ -745,6 +1044,27 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
                 return Ok(());
             }
 
+            // Any response in our term means the target has acknowledged this node as leader.
+            if res.term == self.core.term {
+                let _ = self.core.rafttx.send(ReplicaEvent::UpdateLastAck{target: self.core.target, sent_at});
+            }
+
+            // The offset reported by the target is the number of bytes of the snapshot it has received.
+            let progress = SnapshotProgress{index: snapshot.index, offset: res.offset, size};
+            let _ = self.core.rafttx.send(ReplicaEvent::SnapshotProgress{target: self.core.target, progress});
+
+            // If the target expects a different chunk, then continue from the offset it reported.
+            // This happens when resuming an interrupted stream, or when the chunk was rejected.
+            let next_offset = offset + nread as u64;
+            if res.offset != next_offset {
+                tracing::debug!({offset, res.offset}, "continuing snapshot stream from offset reported by target");
+                backoff = if !probe && res.offset == offset { Some(self.next_backoff(backoff)) } else { None };
+                offset = res.offset;
+                probe = false;
+                continue;
+            }
+            backoff = None;
+
             // If we just sent the final chunk of the snapshot, then transition to lagging state.
             if done {
                 self.core.target_state = TargetReplState::Lagging;
@@ -752,7 +1072,8 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
             }
 
             // Everything is good, so update offset for sending the next chunk.
-            offset += nread as u64;
+            offset = next_offset;
+            probe = false;
 
             // Check raft channel to ensure we are staying up-to-date, then loop.
             if let Ok(event) = self.core.raftrx.try_recv() {
@@ -760,4 +1081,101 @@ impl<'a, D: AppData, R: AppDataResponse, N: RaftNetwork<D>, S: RaftStorage<D, R>
             }
         }
     }
+
+    /// Send the metadata of the given snapshot to the target witness, which keeps no state machine.
+    #[tracing::instrument(level="trace", skip(self, snapshot))]
+    async fn send_witness_snapshot(&mut self, snapshot: CurrentSnapshotData<S::Snapshot>) {
+        let mut backoff: Option<Duration> = None;
+        loop {
+            // If the last RPC failed, then back off before retrying, staying up-to-date in the meantime.
+            if let Some(delay) = backoff {
+                self.backoff(delay).await;
+                if self.core.target_state != TargetReplState::Snapshotting {
+                    return;
+                }
+            }
+
+            let req = InstallSnapshotRequest{
+                term: self.core.term, leader_id: self.core.id,
+                last_included_index: snapshot.index,
+                last_included_term: snapshot.term,
+                snapshot_id: format!("{}-{}-witness", snapshot.term, snapshot.index),
+                offset: 0, data: Vec::new(), done: true,
+                checksum: InstallSnapshotRequest::checksum(&[]),
+                membership: Some(snapshot.membership.clone()),
+            };
+            let res = match timeout(self.core.heartbeat_timeout, self.core.network.install_snapshot(self.core.target, req)).await {
+                Ok(Ok(res)) => res,
+                Ok(Err(err)) => {
+                    tracing::error!({error=%err}, "error sending InstallSnapshot RPC to target");
+                    backoff = Some(self.next_backoff(backoff));
+                    continue;
+                }
+                Err(err) => {
+                    tracing::error!({error=%err}, "timeout while sending InstallSnapshot RPC to target");
+                    backoff = Some(self.next_backoff(backoff));
+                    continue;
+                }
+            };
+            if res.term > self.core.term {
+                let _ = self.core.rafttx.send(ReplicaEvent::RevertToFollower{target: self.core.target, term: res.term});
+                self.core.target_state = TargetReplState::Shutdown;
+                return;
+            }
+            self.core.target_state = TargetReplState::Lagging;
+            return;
+        }
+    }
+
+    /// Derive the ID of the given snapshot from its term, index & contents.
+    ///
+    /// As the ID depends only on the snapshot itself, a new leader streaming the same snapshot will
+    /// arrive at the same ID, which allows the target to resume an interrupted stream.
+    async fn snapshot_id(snapshot: &mut CurrentSnapshotData<S::Snapshot>, chunk_size: u64) -> RaftResult<String> {
+        let mut hasher = FnvHasher::default();
+        let mut buf = Vec::with_capacity(chunk_size as usize);
+        snapshot.snapshot.seek(SeekFrom::Start(0)).await?;
+        loop {
+            let nread = snapshot.snapshot.read_buf(&mut buf).await?;
+            if nread == 0 {
+                break;
+            }
+            hasher.write(&buf[..nread]);
+            buf.clear();
+        }
+        Ok(format!("{}-{}-{:016x}", snapshot.term, snapshot.index, hasher.finish()))
+    }
+
+    /// Get the delay to use before retrying a failed RPC, given the previous delay.
+    ///
+    /// Starts at the heartbeat interval & doubles on each failure, up to the max election timeout.
+    fn next_backoff(&self, backoff: Option<Duration>) -> Duration {
+        let max = Duration::from_millis(self.core.config.election_timeout_max);
+        match backoff {
+            Some(backoff) => std::cmp::min(backoff * 2, max),
+            None => self.core.heartbeat_timeout,
+        }
+    }
+
+    /// Wait for the given delay, while staying up-to-date with events from the Raft core.
+    #[tracing::instrument(level="trace", skip(self))]
+    async fn backoff(&mut self, delay: Duration) {
+        let delay = delay_for(delay);
+        tokio::pin!(delay);
+        loop {
+            select!{
+                _ = &mut delay => return,
+                event = self.core.raftrx.next() => match event {
+                    Some(event) => self.core.drain_raftrx(event),
+                    None => {
+                        self.core.target_state = TargetReplState::Shutdown;
+                        return;
+                    }
+                },
+            }
+            if self.core.target_state != TargetReplState::Snapshotting {
+                return;
+            }
+        }
+    }
 }
@@ -0,0 +1,47 @@
+//! The source of randomness used by Raft nodes.
+//!
+//! Each thread has its own random number generator, which drives randomized election timeouts and
+//! the order in which the branches of the Raft core's event loops are polled. It is seeded from
+//! the OS by default. Seeding it via `seed_thread_rng` makes a cluster whose nodes all run on a
+//! single-threaded runtime with paused time fully deterministic, which is what deterministic
+//! simulation tests are built upon.
+//!
+//! This module is only public with the `simulation` feature, which also makes the Raft core's
+//! event loops draw the order in which their branches are polled from it.
+
+use std::cell::RefCell;
+
+use rand::{Rng, SeedableRng, thread_rng};
+use rand::rngs::StdRng;
+
+thread_local! {
+    static THREAD_RNG: RefCell<StdRng> = RefCell::new(StdRng::from_rng(thread_rng()).expect("failed to seed thread RNG"));
+}
+
+/// Seed the random number generator used by the Raft nodes running on the current thread.
+///
+/// This is intended for deterministic simulation. Given the same seed, a cluster running on a
+/// single-threaded runtime with paused time (see `tokio::time::pause`) makes exactly the same
+/// decisions on every run. It should never be used in production, as nodes sharing a seed will
+/// roll the same election timeouts.
+#[cfg(feature="simulation")]
+pub fn seed_thread_rng(seed: u64) {
+    THREAD_RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+/// Generate a random value in the range `[low, high)`.
+pub(crate) fn gen_range(low: u64, high: u64) -> u64 {
+    THREAD_RNG.with(|rng| rng.borrow_mut().gen_range(low, high))
+}
+
+/// Generate a random index in the range `[0, n)`.
+#[cfg(feature="simulation")]
+pub(crate) fn gen_index(n: usize) -> usize {
+    THREAD_RNG.with(|rng| rng.borrow_mut().gen_range(0, n))
+}
+
+/// Generate a random boolean which is true with probability `p`.
+#[cfg_attr(not(feature="testing"), allow(dead_code))]
+pub(crate) fn gen_bool(p: f64) -> bool {
+    THREAD_RNG.with(|rng| rng.borrow_mut().gen_bool(p.clamp(0.0, 1.0)))
+}
@@ -0,0 +1,152 @@
+//! Client sessions, which give client writes exactly-once semantics (§6.3 of the Raft thesis).
+//!
+//! A client registers a session via `Raft::register_client_session`, which commits a
+//! registration entry to the log and returns the session's ID. The client then tags each of its
+//! writes with a `SessionRequest`, giving every new write a new sequence number, and reusing the
+//! sequence number of the original attempt whenever a write is retried.
+//!
+//! Every node tracks the sessions of the cluster in a `ClientSessions` table, which is updated as
+//! entries are applied to the state machine. A write whose sequence number has already been
+//! applied is never applied again. Instead, the response of the original attempt is returned from
+//! the table, including on a new leader after a failover.
+
+use std::collections::BTreeMap;
+
+use serde::{Serialize, Deserialize};
+
+use crate::AppDataResponse;
+use crate::raft::ClientWriteResponse;
+
+/// The ID of a client session, which is the log index of the entry which registered the session.
+pub type SessionId = u64;
+
+/// The client session info attached to a client write request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionRequest {
+    /// The ID of the session, as returned by `Raft::register_client_session`.
+    pub session_id: SessionId,
+    /// The sequence number of the write within its session.
+    ///
+    /// Each new write of a session must have a new sequence number, while a retried write must
+    /// keep the sequence number of its original attempt.
+    pub sequence: u64,
+    /// The sequence number through which the client has received the response of every write of
+    /// the session.
+    ///
+    /// The cached responses of these writes are discarded once this request is applied, and any
+    /// later retry of these writes is rejected with `ClientWriteError::ResponseDiscarded`.
+    pub responded_to: u64,
+}
+
+/// The table of client sessions of a Raft cluster, as of the last entry applied to the state machine.
+///
+/// This is maintained by Raft, and must be persisted by the storage layer as part of its state
+/// machine. See `RaftStorage::save_client_sessions`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ClientSessions<R: AppDataResponse> {
+    /// All live sessions, by ID.
+    #[serde(bound="R: AppDataResponse")]
+    sessions: BTreeMap<SessionId, ClientSession<R>>,
+}
+
+impl<R: AppDataResponse> Default for ClientSessions<R> {
+    fn default() -> Self {
+        Self{sessions: BTreeMap::new()}
+    }
+}
+
+/// The state of a single client session.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct ClientSession<R: AppDataResponse> {
+    /// The sequence number through which the client has received every response.
+    responded_to: u64,
+    /// The cached responses of applied writes, by sequence number.
+    #[serde(bound="R: AppDataResponse")]
+    responses: BTreeMap<u64, CachedResponse<R>>,
+}
+
+/// The cached response of an applied write.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct CachedResponse<R: AppDataResponse> {
+    /// The log index of the write.
+    index: u64,
+    /// The response returned from applying the write to the state machine.
+    #[serde(bound="R: AppDataResponse")]
+    data: R,
+}
+
+/// The outcome of checking a session write against the table of client sessions.
+pub(crate) enum SessionCheck<R: AppDataResponse> {
+    /// The write has not yet been applied, and must now be applied to the state machine.
+    Apply,
+    /// The write has already been applied, and this is its cached response.
+    Duplicate(ClientWriteResponse<R>),
+    /// The write's session does not exist.
+    UnknownSession,
+    /// The write has already been applied, but its response has been discarded.
+    ResponseDiscarded,
+}
+
+impl<R: AppDataResponse> ClientSessions<R> {
+    /// Check if the given session exists.
+    pub fn contains(&self, session_id: SessionId) -> bool {
+        self.sessions.contains_key(&session_id)
+    }
+
+    /// The number of live sessions.
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    /// Check if there are no live sessions.
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+
+    /// Get an iterator over the IDs of all live sessions.
+    pub fn ids(&self) -> impl Iterator<Item=SessionId> + '_ {
+        self.sessions.keys().copied()
+    }
+
+    /// Register a new session with the given ID.
+    pub(crate) fn register(&mut self, session_id: SessionId) {
+        self.sessions.entry(session_id).or_insert_with(|| ClientSession{responded_to: 0, responses: BTreeMap::new()});
+    }
+
+    /// Expire the given sessions, discarding all of their cached responses.
+    pub(crate) fn expire(&mut self, session_ids: &[SessionId]) {
+        for session_id in session_ids {
+            self.sessions.remove(session_id);
+        }
+    }
+
+    /// Check how the given session write must be handled.
+    pub(crate) fn check(&self, req: &SessionRequest) -> SessionCheck<R> {
+        let session = match self.sessions.get(&req.session_id) {
+            Some(session) => session,
+            None => return SessionCheck::UnknownSession,
+        };
+        if let Some(cached) = session.responses.get(&req.sequence) {
+            return SessionCheck::Duplicate(ClientWriteResponse{index: cached.index, data: cached.data.clone()});
+        }
+        if req.sequence <= session.responded_to {
+            return SessionCheck::ResponseDiscarded;
+        }
+        SessionCheck::Apply
+    }
+
+    /// Record the response of the given session write, which has just been applied at `index`.
+    ///
+    /// The responses of all writes which the client has reported as received are discarded.
+    pub(crate) fn record(&mut self, req: &SessionRequest, index: u64, data: R) {
+        let session = match self.sessions.get_mut(&req.session_id) {
+            Some(session) => session,
+            None => return,
+        };
+        session.responses.insert(req.sequence, CachedResponse{index, data});
+        if req.responded_to > session.responded_to {
+            session.responded_to = req.responded_to;
+            session.responses = session.responses.split_off(&(req.responded_to + 1));
+        }
+    }
+}
@@ -1,12 +1,13 @@
 //! The Raft storage interface and data types.
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use async_trait::async_trait;
 use serde::{Serialize, Deserialize};
 use tokio::io::{AsyncRead, AsyncSeek, AsyncWrite};
 
 use crate::{AppData, AppDataResponse, NodeId};
 use crate::raft::{Entry, MembershipConfig};
+use crate::session::ClientSessions;
 
 /// The data associated with the current snapshot.
 pub struct CurrentSnapshotData<S>
@@ -121,6 +122,9 @@ pub trait RaftStorage<D, R>: Send + Sync + 'static
     ///
     /// Though the entries will always be presented in order, each entry's index should be used to
     /// determine its location to be written in the log.
+    ///
+    /// This is used by followers to replicate entries from the leader, and by the leader to
+    /// append batches of client requests to its own log.
     async fn replicate_to_log(&self, entries: &[Entry<D>]) -> Result<()>;
 
     /// Apply the given log entry to the state machine.
@@ -139,6 +143,10 @@ pub trait RaftStorage<D, R>: Send + Sync + 'static
     /// If instead some application specific error needs to be returned to the client, those
     /// variants must be encapsulated in the type `R`, which may have application specific success
     /// and error variants encoded in the type, perhaps using an inner `Result` type.
+    ///
+    /// Writes which are part of a client session are always applied one at a time via this
+    /// method, on every node, so that their responses may be cached in the table of client
+    /// sessions. Retried session writes which have already been applied are never passed here.
     async fn apply_entry_to_state_machine(&self, index: &u64, data: &D) -> Result<R>;
 
     /// Apply the given payload of entries to the state machine, as part of replication.
@@ -147,6 +155,38 @@ pub trait RaftStorage<D, R>: Send + Sync + 'static
     /// have been replicated to a majority of the cluster, will be applied to the state machine.
     async fn replicate_to_state_machine(&self, entries: &[(&u64, &D)]) -> Result<()>;
 
+    /// Save the table of client sessions.
+    ///
+    /// Raft maintains the table of client sessions as entries are applied to the state machine, and
+    /// calls this method whenever the table changes. The table must be persisted as part of the
+    /// state machine: it must be included in snapshots, replaced when a snapshot is installed, and
+    /// it must reflect the same applied entries as the state machine when recovered on startup.
+    /// Only then can Raft guarantee that the writes of a client session are applied at most once.
+    ///
+    /// As such, the table must be made durable atomically with the state machine. A session write
+    /// is applied via `apply_entry_to_state_machine` and then the table is saved here, so a storage
+    /// engine which persists the two separately would re-apply the write after a crash between the
+    /// two calls. For example, the effects of entries applied since the last save may be buffered
+    /// and written together with the table.
+    ///
+    /// This is only called once a client session has been registered. The default implementation
+    /// returns an error, as storage engines are not required to support client sessions; a node
+    /// whose storage engine does not support them shuts down if a session is registered.
+    async fn save_client_sessions(&self, sessions: &ClientSessions<R>) -> Result<()> {
+        let _ = sessions;
+        Err(anyhow!("this storage engine does not support client sessions"))
+    }
+
+    /// Get the table of client sessions, as last saved via `save_client_sessions` or as covered by
+    /// the last installed snapshot.
+    ///
+    /// This is called when the Raft node starts, and after a snapshot has been installed. If the
+    /// system is pristine, then it should return `ClientSessions::default()`. The default
+    /// implementation always returns `ClientSessions::default()`.
+    async fn get_client_sessions(&self) -> Result<ClientSessions<R>> {
+        Ok(ClientSessions::default())
+    }
+
     /// Perform log compaction, returning a handle to the generated snapshot.
     ///
     /// ### `through`
@@ -188,6 +228,26 @@ pub trait RaftStorage<D, R>: Send + Sync + 'static
         id: String, snapshot: Box<Self::Snapshot>,
     ) -> Result<()>;
 
+    /// Install a metadata-only snapshot on a witness node.
+    ///
+    /// Witnesses keep no state machine, so rather than streaming a snapshot's data to a witness,
+    /// the leader only sends the index, term & membership config which the snapshot covers. This
+    /// is the light mode of the storage engine used by witnesses.
+    ///
+    /// Delete all entries in the log through `delete_through`, unless `None`, in which case
+    /// all entries of the log are to be deleted. Write a new snapshot pointer, holding the given
+    /// membership config, to the log at the given `index`. The state machine should treat all
+    /// entries through `index` as having been applied.
+    ///
+    /// This is only ever called on witness nodes. The default implementation returns an error, as
+    /// storage engines are not required to support running as a witness.
+    async fn install_witness_snapshot(
+        &self, index: u64, term: u64, delete_through: Option<u64>, membership: MembershipConfig,
+    ) -> Result<()> {
+        let _ = (index, term, delete_through, membership);
+        Err(anyhow!("this storage engine does not support running as a witness"))
+    }
+
     /// Get a readable handle to the current snapshot, along with its metadata.
     ///
     /// ### implementation algorithm
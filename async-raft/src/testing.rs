@@ -0,0 +1,604 @@
+//! Utilities for testing applications built on async-raft.
+//!
+//! This module is only available when the `testing` feature is enabled. It provides:
+//!
+//! - `StorageTestSuite`, a conformance test suite for `RaftStorage` implementations.
+//! - `FaultyNetwork`, a `RaftNetwork` wrapper which injects faults into the RPCs of a cluster.
+//!
+//! Storage implementations should run the conformance suite as part of their own tests, for example:
+//!
+//! ```ignore
+//! #[tokio::test]
+//! async fn test_storage_conformance() -> anyhow::Result<()> {
+//!     let root = tempfile::TempDir::new()?;
+//!     let path = root.path().to_path_buf();
+//!     StorageTestSuite::new(
+//!         move |id| {
+//!             let path = path.join(id.to_string());
+//!             async move { Ok(Arc::new(YourStorage::open(id, path).await?)) }
+//!         },
+//!         |index| YourRequest::new(format!("request-{}", index)),
+//!     ).run().await
+//! }
+//! ```
+
+use std::collections::HashSet;
+use std::future::Future;
+use std::io::SeekFrom;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::{Context, Result, ensure};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+use crate::{AppData, AppDataResponse, NodeId, RaftStorage};
+use crate::raft::{Entry, EntryConfigChange, EntryNormal, EntryPayload, MembershipConfig};
+use crate::session::{ClientSessions, SessionId};
+use crate::storage::HardState;
+
+mod network;
+
+pub use network::{FaultyNetwork, LinkFaults, NetworkFaults};
+
+/// A conformance test suite covering the contract of the `RaftStorage` trait.
+///
+/// The suite is built from a factory closure, which is called with a node ID and must return a
+/// storage instance for that node. The first call for any node ID must return a pristine instance.
+/// Calling the factory again with the same node ID, once the suite has dropped its previous
+/// instance, must return an instance backed by the same durable state, as if the node had
+/// restarted. In-memory implementations may simply return the same instance again.
+///
+/// The suite is also given a payload factory, which is called with a log index and must return
+/// the application data of a normal entry at that index. The suite applies these entries to the
+/// state machine, but only checks the index of the last applied entry, so it does not cover the
+/// application specific behavior of the state machine.
+///
+/// Support for client sessions & for running as a witness is optional for storage engines, so the
+/// tests which cover them only run once enabled via `with_client_sessions` & `with_witnesses`.
+/// `run` otherwise only covers the contract which every storage engine must meet.
+pub struct StorageTestSuite<D, R, S, F, P> {
+    /// The factory used for creating & restarting storage instances.
+    factory: F,
+    /// The factory used for creating the payloads of normal entries.
+    payload: P,
+    /// The next node ID to use, so that every test begins with pristine storage.
+    next_id: AtomicU64,
+    /// Whether to run the tests of `save_client_sessions` & `get_client_sessions`.
+    client_sessions: bool,
+    /// Whether to run the tests of `install_witness_snapshot`.
+    witnesses: bool,
+    marker: PhantomData<(D, R, S)>,
+}
+
+impl<D, R, S, F, Fut, P> StorageTestSuite<D, R, S, F, P>
+    where
+        D: AppData,
+        R: AppDataResponse,
+        S: RaftStorage<D, R>,
+        F: Fn(NodeId) -> Fut,
+        Fut: Future<Output=Result<Arc<S>>>,
+        P: Fn(u64) -> D,
+{
+    /// Create a new instance from the given storage & payload factories.
+    pub fn new(factory: F, payload: P) -> Self {
+        Self{factory, payload, next_id: AtomicU64::new(0), client_sessions: false, witnesses: false, marker: PhantomData}
+    }
+
+    /// Also run the tests of `save_client_sessions` & `get_client_sessions`, for storage engines
+    /// which support client sessions.
+    pub fn with_client_sessions(mut self) -> Self {
+        self.client_sessions = true;
+        self
+    }
+
+    /// Also run the tests of `install_witness_snapshot`, for storage engines which support running
+    /// as a witness.
+    pub fn with_witnesses(mut self) -> Self {
+        self.witnesses = true;
+        self
+    }
+
+    /// Run all enabled tests of the suite, returning an error describing the first failure.
+    pub async fn run(&self) -> Result<()> {
+        self.test_get_membership_config_default().await.context("test_get_membership_config_default")?;
+        self.test_get_membership_config_from_log().await.context("test_get_membership_config_from_log")?;
+        self.test_get_membership_config_from_snapshot_pointer().await.context("test_get_membership_config_from_snapshot_pointer")?;
+        self.test_get_membership_config_prefers_latest_config().await.context("test_get_membership_config_prefers_latest_config")?;
+        self.test_get_initial_state_default().await.context("test_get_initial_state_default")?;
+        self.test_get_initial_state_after_restart().await.context("test_get_initial_state_after_restart")?;
+        self.test_save_hard_state().await.context("test_save_hard_state")?;
+        self.test_get_log_entries().await.context("test_get_log_entries")?;
+        self.test_delete_logs_from().await.context("test_delete_logs_from")?;
+        self.test_append_entry_to_log().await.context("test_append_entry_to_log")?;
+        self.test_replicate_to_log().await.context("test_replicate_to_log")?;
+        self.test_do_log_compaction().await.context("test_do_log_compaction")?;
+        self.test_finalize_snapshot_installation_without_delete_through().await
+            .context("test_finalize_snapshot_installation_without_delete_through")?;
+        self.test_finalize_snapshot_installation_with_delete_through().await
+            .context("test_finalize_snapshot_installation_with_delete_through")?;
+        if self.witnesses {
+            self.test_install_witness_snapshot().await.context("test_install_witness_snapshot")?;
+        }
+        self.test_apply_entry_to_state_machine().await.context("test_apply_entry_to_state_machine")?;
+        self.test_replicate_to_state_machine().await.context("test_replicate_to_state_machine")?;
+        self.test_last_applied_log_after_restart().await.context("test_last_applied_log_after_restart")?;
+        if self.client_sessions {
+            self.test_save_client_sessions().await.context("test_save_client_sessions")?;
+            self.test_client_sessions_in_snapshot().await.context("test_client_sessions_in_snapshot")?;
+        }
+        Ok(())
+    }
+
+    //////////////////////////////////////////////////////////////////////////////////////////////
+
+    /// A pristine store must report the initial membership config of its node.
+    pub async fn test_get_membership_config_default(&self) -> Result<()> {
+        let (id, store) = self.new_store().await?;
+
+        let membership = store.get_membership_config().await?;
+
+        ensure!(membership == MembershipConfig::new_initial(id), "expected initial membership config, got {:?}", membership);
+        Ok(())
+    }
+
+    /// The membership config must be recovered from the most recent config change in the log.
+    pub async fn test_get_membership_config_from_log(&self) -> Result<()> {
+        let (id, store) = self.new_store().await?;
+        let membership = members(&[id, 1, 2]);
+        store.append_entry_to_log(&config(1, 1, membership.clone())).await?;
+        store.replicate_to_log(&[blank(2, 1), blank(3, 1)]).await?;
+
+        let found = store.get_membership_config().await?;
+        ensure!(found == membership, "expected membership config from log, got {:?}", found);
+
+        let store = self.restart(id, store).await?;
+        let found = store.get_membership_config().await?;
+        ensure!(found == membership, "expected membership config from log after restart, got {:?}", found);
+        Ok(())
+    }
+
+    /// Once compacted away, the membership config must be recovered from the snapshot pointer.
+    pub async fn test_get_membership_config_from_snapshot_pointer(&self) -> Result<()> {
+        let (id, store) = self.new_store().await?;
+        let membership = members(&[id, 1, 2]);
+        store.append_entry_to_log(&config(1, 1, membership.clone())).await?;
+        store.replicate_to_log(&blanks(2, 10, 1)).await?;
+
+        let snapshot = store.do_log_compaction(10).await?;
+        ensure!(snapshot.membership == membership, "unexpected snapshot membership config {:?}", snapshot.membership);
+        let found = store.get_membership_config().await?;
+        ensure!(found == membership, "expected membership config from snapshot pointer, got {:?}", found);
+
+        // A later compaction must carry the membership config over from the snapshot pointer.
+        store.replicate_to_log(&blanks(11, 12, 1)).await?;
+        let snapshot = store.do_log_compaction(12).await?;
+        ensure!(snapshot.membership == membership, "unexpected membership config for second snapshot {:?}", snapshot.membership);
+
+        let store = self.restart(id, store).await?;
+        let found = store.get_membership_config().await?;
+        ensure!(found == membership, "expected membership config from snapshot pointer after restart, got {:?}", found);
+        Ok(())
+    }
+
+    /// A config change which follows a snapshot pointer must take precedence over it.
+    pub async fn test_get_membership_config_prefers_latest_config(&self) -> Result<()> {
+        let (id, store) = self.new_store().await?;
+        store.append_entry_to_log(&config(1, 1, members(&[id, 1, 2]))).await?;
+        store.replicate_to_log(&blanks(2, 10, 1)).await?;
+        store.do_log_compaction(10).await?;
+        let membership = members(&[id, 1, 2, 3]);
+        store.append_entry_to_log(&config(11, 1, membership.clone())).await?;
+
+        let found = store.get_membership_config().await?;
+        ensure!(found == membership, "expected latest membership config, got {:?}", found);
+        Ok(())
+    }
+
+    //////////////////////////////////////////////////////////////////////////////////////////////
+
+    /// A pristine store must report the initial state of its node.
+    pub async fn test_get_initial_state_default(&self) -> Result<()> {
+        let (id, store) = self.new_store().await?;
+
+        let initial = store.get_initial_state().await?;
+
+        ensure!(initial.last_log_index == 0, "unexpected value for last log index: {}", initial.last_log_index);
+        ensure!(initial.last_log_term == 0, "unexpected value for last log term: {}", initial.last_log_term);
+        ensure!(initial.last_applied_log == 0, "unexpected value for last applied log: {}", initial.last_applied_log);
+        ensure!(initial.hard_state == HardState{current_term: 0, voted_for: None}, "unexpected value for hard state: {:?}", initial.hard_state);
+        ensure!(initial.membership == MembershipConfig::new_initial(id), "unexpected value for membership config: {:?}", initial.membership);
+        Ok(())
+    }
+
+    /// The initial state must reflect the log & hard state which were written before a restart.
+    pub async fn test_get_initial_state_after_restart(&self) -> Result<()> {
+        let (id, store) = self.new_store().await?;
+        let membership = members(&[id, 1, 2]);
+        store.append_entry_to_log(&config(1, 1, membership.clone())).await?;
+        store.replicate_to_log(&blanks(2, 10, 1)).await?;
+        store.append_entry_to_log(&blank(11, 2)).await?;
+        let hs = HardState{current_term: 2, voted_for: Some(id)};
+        store.save_hard_state(&hs).await?;
+
+        let store = self.restart(id, store).await?;
+        let initial = store.get_initial_state().await?;
+
+        ensure!(initial.last_log_index == 11, "unexpected value for last log index: {}", initial.last_log_index);
+        ensure!(initial.last_log_term == 2, "unexpected value for last log term: {}", initial.last_log_term);
+        ensure!(initial.hard_state == hs, "unexpected value for hard state: {:?}", initial.hard_state);
+        ensure!(initial.membership == membership, "unexpected value for membership config: {:?}", initial.membership);
+        Ok(())
+    }
+
+    /// The most recently saved hard state must be reported, including after a restart.
+    pub async fn test_save_hard_state(&self) -> Result<()> {
+        let (id, store) = self.new_store().await?;
+        store.save_hard_state(&HardState{current_term: 1, voted_for: Some(id)}).await?;
+        let hs = HardState{current_term: 100, voted_for: None};
+        store.save_hard_state(&hs).await?;
+
+        let initial = store.get_initial_state().await?;
+        ensure!(initial.hard_state == hs, "unexpected value for hard state: {:?}", initial.hard_state);
+
+        let store = self.restart(id, store).await?;
+        let initial = store.get_initial_state().await?;
+        ensure!(initial.hard_state == hs, "unexpected value for hard state after restart: {:?}", initial.hard_state);
+        Ok(())
+    }
+
+    //////////////////////////////////////////////////////////////////////////////////////////////
+
+    /// Log entries must be fetched over the range `[start, stop)`.
+    pub async fn test_get_log_entries(&self) -> Result<()> {
+        let (_, store) = self.new_store_with_logs().await?;
+
+        let logs = store.get_log_entries(10, 1).await?;
+        ensure!(logs.is_empty(), "expected no logs to be returned when start > stop");
+
+        let logs = store.get_log_entries(5, 7).await?;
+        ensure!(indices(&logs) == vec![5, 6], "unexpected logs returned for [5, 7): {:?}", indices(&logs));
+
+        let logs = store.get_log_entries(0, 100).await?;
+        ensure!(indices(&logs) == (1..=10).collect::<Vec<_>>(), "unexpected logs returned for [0, 100): {:?}", indices(&logs));
+        Ok(())
+    }
+
+    /// Logs must be deleted over the range `[start, stop)`, or through the end of the log.
+    pub async fn test_delete_logs_from(&self) -> Result<()> {
+        let cases: Vec<(u64, Option<u64>, Vec<u64>)> = vec![
+            (10, Some(1), (1..=10).collect()),
+            (1, Some(11), vec![]),
+            (1, None, vec![]),
+            (1, Some(10), vec![10]),
+            (4, Some(7), vec![1, 2, 3, 7, 8, 9, 10]),
+            (6, None, vec![1, 2, 3, 4, 5]),
+        ];
+        for (start, stop, expected) in cases {
+            let (id, store) = self.new_store_with_logs().await?;
+
+            store.delete_logs_from(start, stop).await?;
+            let logs = store.get_log_entries(0, 100).await?;
+            ensure!(indices(&logs) == expected, "unexpected logs after deleting from {} to {:?}: {:?}", start, stop, indices(&logs));
+
+            let store = self.restart(id, store).await?;
+            let logs = store.get_log_entries(0, 100).await?;
+            ensure!(indices(&logs) == expected, "unexpected logs after deleting from {} to {:?} & restarting: {:?}", start, stop, indices(&logs));
+        }
+        Ok(())
+    }
+
+    /// Appending an entry must place it at its index, replacing any entry already there.
+    pub async fn test_append_entry_to_log(&self) -> Result<()> {
+        let (_, store) = self.new_store_with_logs().await?;
+
+        store.append_entry_to_log(&blank(10, 2)).await?;
+        store.append_entry_to_log(&blank(11, 2)).await?;
+        let logs = store.get_log_entries(0, 100).await?;
+
+        ensure!(indices(&logs) == (1..=11).collect::<Vec<_>>(), "unexpected logs after append: {:?}", indices(&logs));
+        ensure!(logs[9].term == 2 && logs[10].term == 2, "expected appended entries to have term 2");
+        Ok(())
+    }
+
+    /// Replicating a payload of entries must append them to the log.
+    pub async fn test_replicate_to_log(&self) -> Result<()> {
+        let (id, store) = self.new_store_with_logs().await?;
+
+        store.replicate_to_log(&blanks(11, 13, 2)).await?;
+        let store = self.restart(id, store).await?;
+        let logs = store.get_log_entries(0, 100).await?;
+
+        ensure!(indices(&logs) == (1..=13).collect::<Vec<_>>(), "unexpected logs after replication: {:?}", indices(&logs));
+        ensure!(logs[12].term == 2, "expected replicated entries to have term 2");
+        Ok(())
+    }
+
+    //////////////////////////////////////////////////////////////////////////////////////////////
+
+    /// Compaction must replace the compacted entries with a snapshot pointer.
+    pub async fn test_do_log_compaction(&self) -> Result<()> {
+        let (id, store) = self.new_store().await?;
+        let membership = members(&[id, 1, 2]);
+        store.append_entry_to_log(&config(1, 1, membership.clone())).await?;
+        store.replicate_to_log(&blanks(2, 10, 1)).await?;
+
+        let snapshot = store.do_log_compaction(5).await?;
+        ensure!(snapshot.index == 5 && snapshot.term == 1, "unexpected snapshot index & term: {}, {}", snapshot.index, snapshot.term);
+        ensure!(snapshot.membership == membership, "unexpected snapshot membership config {:?}", snapshot.membership);
+        assert_snapshot_pointer(&*store, 5, &[6, 7, 8, 9, 10]).await?;
+        let current = store.get_current_snapshot().await?.context("expected a current snapshot")?;
+        ensure!(current.index == 5 && current.term == 1, "unexpected current snapshot index & term: {}, {}", current.index, current.term);
+        let initial = store.get_initial_state().await?;
+        ensure!(initial.last_log_index == 10, "unexpected value for last log index: {}", initial.last_log_index);
+
+        let store = self.restart(id, store).await?;
+        assert_snapshot_pointer(&*store, 5, &[6, 7, 8, 9, 10]).await?;
+        let current = store.get_current_snapshot().await?.context("expected a current snapshot after restart")?;
+        ensure!(current.index == 5 && current.term == 1, "unexpected current snapshot index & term after restart: {}, {}", current.index, current.term);
+        Ok(())
+    }
+
+    /// Installing a snapshot without `delete_through` must replace the entire log.
+    pub async fn test_finalize_snapshot_installation_without_delete_through(&self) -> Result<()> {
+        let (membership, data) = self.build_snapshot(10).await?;
+        let (id, store) = self.new_store().await?;
+        store.replicate_to_log(&blanks(1, 15, 1)).await?;
+
+        install_snapshot(&*store, 10, 1, None, data).await?;
+        assert_installed_snapshot(&*store, 10, &[], &membership).await?;
+
+        let store = self.restart(id, store).await?;
+        assert_installed_snapshot(&*store, 10, &[], &membership).await.context("after restart")?;
+        Ok(())
+    }
+
+    /// Installing a snapshot with `delete_through` must only delete the log through that index.
+    pub async fn test_finalize_snapshot_installation_with_delete_through(&self) -> Result<()> {
+        let (membership, data) = self.build_snapshot(10).await?;
+        let (id, store) = self.new_store().await?;
+        store.replicate_to_log(&blanks(1, 15, 1)).await?;
+
+        install_snapshot(&*store, 10, 1, Some(10), data).await?;
+        assert_installed_snapshot(&*store, 10, &[11, 12, 13, 14, 15], &membership).await?;
+
+        let store = self.restart(id, store).await?;
+        assert_installed_snapshot(&*store, 10, &[11, 12, 13, 14, 15], &membership).await.context("after restart")?;
+        Ok(())
+    }
+
+    /// Installing a witness snapshot must replace the log through `delete_through` with a snapshot
+    /// pointer, and treat every entry through its index as applied.
+    pub async fn test_install_witness_snapshot(&self) -> Result<()> {
+        let (id, store) = self.new_store().await?;
+        let membership = members(&[id, 1, 2]);
+        store.replicate_to_log(&blanks(1, 15, 1)).await?;
+
+        store.install_witness_snapshot(10, 1, Some(10), membership.clone()).await?;
+        assert_installed_snapshot(&*store, 10, &[11, 12, 13, 14, 15], &membership).await?;
+        let initial = store.get_initial_state().await?;
+        ensure!(initial.last_applied_log == 10, "unexpected value for last applied log: {}", initial.last_applied_log);
+
+        let store = self.restart(id, store).await?;
+        assert_installed_snapshot(&*store, 10, &[11, 12, 13, 14, 15], &membership).await.context("after restart")?;
+        let initial = store.get_initial_state().await?;
+        ensure!(initial.last_applied_log == 10, "unexpected value for last applied log after restart: {}", initial.last_applied_log);
+        Ok(())
+    }
+
+    //////////////////////////////////////////////////////////////////////////////////////////////
+
+    /// Applying entries one at a time must advance the last applied log index.
+    pub async fn test_apply_entry_to_state_machine(&self) -> Result<()> {
+        let (_, store) = self.new_store().await?;
+        let entries = self.normals(1, 5, 1);
+        store.replicate_to_log(&entries).await?;
+
+        for (index, data) in payloads(&entries) {
+            store.apply_entry_to_state_machine(index, data).await?;
+        }
+
+        let initial = store.get_initial_state().await?;
+        ensure!(initial.last_applied_log == 5, "unexpected value for last applied log: {}", initial.last_applied_log);
+        Ok(())
+    }
+
+    /// Applying a payload of entries must advance the last applied log index through the last of them.
+    pub async fn test_replicate_to_state_machine(&self) -> Result<()> {
+        let (_, store) = self.new_store().await?;
+        let entries = self.normals(1, 5, 1);
+        store.replicate_to_log(&entries).await?;
+
+        store.replicate_to_state_machine(&payloads(&entries[..3])).await?;
+        let initial = store.get_initial_state().await?;
+        ensure!(initial.last_applied_log == 3, "unexpected value for last applied log: {}", initial.last_applied_log);
+
+        store.replicate_to_state_machine(&payloads(&entries[3..])).await?;
+        let initial = store.get_initial_state().await?;
+        ensure!(initial.last_applied_log == 5, "unexpected value for last applied log: {}", initial.last_applied_log);
+        Ok(())
+    }
+
+    /// After a restart, the last applied log index must cover at least the current snapshot, and
+    /// no more than the entries which were applied.
+    ///
+    /// A store may recover its state machine from its current snapshot, leaving Raft to apply the
+    /// committed entries which follow the snapshot again.
+    pub async fn test_last_applied_log_after_restart(&self) -> Result<()> {
+        let (id, store) = self.new_store().await?;
+        let entries = self.normals(1, 8, 1);
+        store.replicate_to_log(&entries).await?;
+        store.replicate_to_state_machine(&payloads(&entries[..5])).await?;
+        store.do_log_compaction(5).await?;
+        store.replicate_to_state_machine(&payloads(&entries[5..])).await?;
+
+        let store = self.restart(id, store).await?;
+        let initial = store.get_initial_state().await?;
+
+        ensure!((5..=8).contains(&initial.last_applied_log), "unexpected value for last applied log after restart: {}", initial.last_applied_log);
+        Ok(())
+    }
+
+    //////////////////////////////////////////////////////////////////////////////////////////////
+
+    /// A pristine store must report an empty table of client sessions, and then the table last saved.
+    pub async fn test_save_client_sessions(&self) -> Result<()> {
+        let (_, store) = self.new_store().await?;
+        let sessions = store.get_client_sessions().await?;
+        ensure!(sessions.is_empty(), "expected no client sessions, got {:?}", sessions.ids().collect::<Vec<_>>());
+
+        store.save_client_sessions(&client_sessions(&[3, 7])).await?;
+        store.save_client_sessions(&client_sessions(&[7, 9])).await?;
+
+        let ids: Vec<_> = store.get_client_sessions().await?.ids().collect();
+        ensure!(ids == vec![7, 9], "unexpected client sessions: {:?}", ids);
+        Ok(())
+    }
+
+    /// The table of client sessions must be carried through a snapshot onto the store which installs it.
+    pub async fn test_client_sessions_in_snapshot(&self) -> Result<()> {
+        let (_, source) = self.new_store_with_logs().await?;
+        source.save_client_sessions(&client_sessions(&[3, 7])).await?;
+        let mut snapshot = source.do_log_compaction(10).await?;
+        let mut data = Vec::new();
+        snapshot.snapshot.seek(SeekFrom::Start(0)).await?;
+        snapshot.snapshot.read_to_end(&mut data).await?;
+        let (_, store) = self.new_store().await?;
+
+        install_snapshot(&*store, 10, 1, None, data).await?;
+
+        let ids: Vec<_> = store.get_client_sessions().await?.ids().collect();
+        ensure!(ids == vec![3, 7], "unexpected client sessions after snapshot installation: {:?}", ids);
+        Ok(())
+    }
+
+    //////////////////////////////////////////////////////////////////////////////////////////////
+
+    /// Create a new pristine store.
+    ///
+    /// As Raft does when it starts, this fetches the initial state of the store, which also
+    /// establishes its initial hard state.
+    async fn new_store(&self) -> Result<(NodeId, Arc<S>)> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let store = (self.factory)(id).await?;
+        store.get_initial_state().await?;
+        Ok((id, store))
+    }
+
+    /// Create a new store with blank entries at indices 1 through 10, and an initial hard state.
+    async fn new_store_with_logs(&self) -> Result<(NodeId, Arc<S>)> {
+        let (id, store) = self.new_store().await?;
+        store.replicate_to_log(&blanks(1, 10, 1)).await?;
+        store.save_hard_state(&HardState{current_term: 1, voted_for: Some(id)}).await?;
+        Ok((id, store))
+    }
+
+    /// Restart the given store.
+    async fn restart(&self, id: NodeId, store: Arc<S>) -> Result<Arc<S>> {
+        drop(store);
+        (self.factory)(id).await
+    }
+
+    /// Create normal entries at indices `start` through `stop`, inclusively.
+    fn normals(&self, start: u64, stop: u64, term: u64) -> Vec<Entry<D>> {
+        (start..=stop)
+            .map(|index| Entry{term, index, payload: EntryPayload::Normal(EntryNormal{data: (self.payload)(index), session: None})})
+            .collect()
+    }
+
+    /// Build a snapshot covering entries 1 through `index` on a separate store, returning its
+    /// membership config & contents.
+    async fn build_snapshot(&self, index: u64) -> Result<(MembershipConfig, Vec<u8>)> {
+        let (id, store) = self.new_store().await?;
+        let membership = members(&[id, 1, 2]);
+        store.append_entry_to_log(&config(1, 1, membership.clone())).await?;
+        store.replicate_to_log(&blanks(2, index, 1)).await?;
+        let mut snapshot = store.do_log_compaction(index).await?;
+        let mut data = Vec::new();
+        snapshot.snapshot.seek(SeekFrom::Start(0)).await?;
+        snapshot.snapshot.read_to_end(&mut data).await?;
+        Ok((membership, data))
+    }
+}
+
+/// Stream the given snapshot contents into the store & finalize its installation.
+async fn install_snapshot<D, R, S>(store: &S, index: u64, term: u64, delete_through: Option<u64>, data: Vec<u8>) -> Result<()>
+    where D: AppData, R: AppDataResponse, S: RaftStorage<D, R>,
+{
+    let (id, mut snapshot) = store.create_snapshot().await?;
+    snapshot.write_all(&data).await?;
+    snapshot.shutdown().await?;
+    store.finalize_snapshot_installation(index, term, delete_through, id, snapshot).await
+}
+
+/// Assert that the log holds a snapshot pointer at `index`, followed only by the given entries.
+async fn assert_snapshot_pointer<D, R, S>(store: &S, index: u64, following: &[u64]) -> Result<()>
+    where D: AppData, R: AppDataResponse, S: RaftStorage<D, R>,
+{
+    let logs = store.get_log_entries(0, 100).await?;
+    let mut expected = vec![index];
+    expected.extend_from_slice(following);
+    ensure!(indices(&logs) == expected, "unexpected logs after compaction: {:?}", indices(&logs));
+    ensure!(matches!(logs[0].payload, EntryPayload::SnapshotPointer(_)), "expected a snapshot pointer at index {}", index);
+    Ok(())
+}
+
+/// Assert that the snapshot at `index` has been installed, leaving the given entries in the log.
+async fn assert_installed_snapshot<D, R, S>(store: &S, index: u64, following: &[u64], membership: &MembershipConfig) -> Result<()>
+    where D: AppData, R: AppDataResponse, S: RaftStorage<D, R>,
+{
+    assert_snapshot_pointer(store, index, following).await?;
+    let found = store.get_membership_config().await?;
+    ensure!(&found == membership, "expected membership config from installed snapshot, got {:?}", found);
+    let current = store.get_current_snapshot().await?.context("expected a current snapshot")?;
+    ensure!(current.index == index && current.term == 1, "unexpected current snapshot index & term: {}, {}", current.index, current.term);
+    ensure!(&current.membership == membership, "unexpected current snapshot membership config {:?}", current.membership);
+    let initial = store.get_initial_state().await?;
+    let last_log_index = following.last().copied().unwrap_or(index);
+    ensure!(initial.last_log_index == last_log_index, "unexpected value for last log index: {}", initial.last_log_index);
+    Ok(())
+}
+
+/// Create a blank entry.
+fn blank<D: AppData>(index: u64, term: u64) -> Entry<D> {
+    Entry{term, index, payload: EntryPayload::Blank}
+}
+
+/// Create blank entries at indices `start` through `stop`, inclusively.
+fn blanks<D: AppData>(start: u64, stop: u64, term: u64) -> Vec<Entry<D>> {
+    (start..=stop).map(|index| blank(index, term)).collect()
+}
+
+/// Create a config change entry.
+fn config<D: AppData>(index: u64, term: u64, membership: MembershipConfig) -> Entry<D> {
+    Entry{term, index, payload: EntryPayload::ConfigChange(EntryConfigChange{membership})}
+}
+
+/// Get the indices & data of the given normal entries, as passed to the state machine.
+fn payloads<D: AppData>(entries: &[Entry<D>]) -> Vec<(&u64, &D)> {
+    entries.iter()
+        .filter_map(|entry| match &entry.payload {
+            EntryPayload::Normal(normal) => Some((&entry.index, &normal.data)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Create a table of client sessions with the given sessions.
+fn client_sessions<R: AppDataResponse>(ids: &[SessionId]) -> ClientSessions<R> {
+    let mut sessions = ClientSessions::default();
+    for id in ids {
+        sessions.register(*id);
+    }
+    sessions
+}
+
+/// Create a uniform membership config of the given members.
+fn members(ids: &[NodeId]) -> MembershipConfig {
+    MembershipConfig{members: ids.iter().copied().collect::<HashSet<_>>(), members_after_consensus: None, learners: HashSet::new(), witnesses: HashSet::new()}
+}
+
+/// Get the indices of the given entries.
+fn indices<D: AppData>(entries: &[Entry<D>]) -> Vec<u64> {
+    entries.iter().map(|entry| entry.index).collect()
+}
@@ -0,0 +1,227 @@
+//! A `RaftNetwork` wrapper which injects faults into the RPCs sent over an inner network.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use tokio::time::delay_for;
+
+use crate::{AppData, NodeId, RaftNetwork};
+use crate::raft::{AppendEntriesRequest, AppendEntriesResponse};
+use crate::raft::{InstallSnapshotRequest, InstallSnapshotResponse};
+use crate::raft::{PreVoteRequest, PreVoteResponse, VoteRequest, VoteResponse};
+use crate::raft::{ReadIndexRequest, ReadIndexResponse, TimeoutNowRequest, TimeoutNowResponse};
+use crate::rng;
+
+/// The faults injected into the RPCs sent over a single directed link between two nodes.
+///
+/// Probabilities are given in the range `[0, 1]`, and durations in milliseconds. The default value
+/// injects no faults at all.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LinkFaults {
+    /// The minimum latency added to each RPC.
+    pub latency_min: u64,
+    /// The maximum latency added to each RPC.
+    pub latency_max: u64,
+    /// The probability that an RPC is dropped.
+    ///
+    /// A dropped RPC is lost either on its way to the target, or on its way back after it has
+    /// been delivered. Either way, the sender observes an error.
+    pub drop_rate: f64,
+    /// The probability that an RPC is duplicated.
+    ///
+    /// The duplicate is delivered with its own latency, and its response is discarded.
+    pub duplicate_rate: f64,
+    /// The probability that an RPC is held back by an additional `reorder_delay`, so that RPCs
+    /// sent after it over the same link may overtake it.
+    pub reorder_rate: f64,
+    /// The additional latency of an RPC which is held back for reordering.
+    pub reorder_delay: u64,
+    /// If true, every RPC sent over this link fails without being delivered.
+    ///
+    /// Since links are directed, this is a one-way partition. The link in the other direction is
+    /// unaffected.
+    pub partitioned: bool,
+}
+
+/// A handle for controlling the faults injected by one or more `FaultyNetwork` instances.
+///
+/// Cloning this handle is cheap, and all clones control the same faults. Faults configured for a
+/// specific link take precedence over the default faults, which apply to all other links.
+#[derive(Clone, Debug, Default)]
+pub struct NetworkFaults {
+    inner: Arc<Mutex<FaultTable>>,
+}
+
+#[derive(Debug, Default)]
+struct FaultTable {
+    /// The faults of all links which have not been configured individually.
+    default: LinkFaults,
+    /// The faults of individually configured links, keyed by `(from, to)`.
+    links: HashMap<(NodeId, NodeId), LinkFaults>,
+}
+
+impl NetworkFaults {
+    /// Create a new instance, which injects no faults until configured.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the faults injected into all links which have not been configured individually.
+    pub fn set_default(&self, faults: LinkFaults) {
+        self.inner.lock().unwrap().default = faults;
+    }
+
+    /// Set the faults injected into the link from `from` to `to`.
+    pub fn set_link(&self, from: NodeId, to: NodeId, faults: LinkFaults) {
+        self.inner.lock().unwrap().links.insert((from, to), faults);
+    }
+
+    /// Partition the link from `from` to `to`, leaving the link from `to` to `from` intact.
+    pub fn partition_one_way(&self, from: NodeId, to: NodeId) {
+        let mut table = self.inner.lock().unwrap();
+        let faults = LinkFaults{partitioned: true, ..table.default.clone()};
+        table.links.entry((from, to)).or_insert(faults).partitioned = true;
+    }
+
+    /// Partition the links between `a` and `b` in both directions.
+    pub fn partition(&self, a: NodeId, b: NodeId) {
+        self.partition_one_way(a, b);
+        self.partition_one_way(b, a);
+    }
+
+    /// Remove the faults configured for the link from `from` to `to`, so that the default faults apply.
+    pub fn clear_link(&self, from: NodeId, to: NodeId) {
+        self.inner.lock().unwrap().links.remove(&(from, to));
+    }
+
+    /// Remove all configured faults, including the default faults.
+    pub fn clear(&self) {
+        let mut table = self.inner.lock().unwrap();
+        table.default = LinkFaults::default();
+        table.links.clear();
+    }
+
+    /// Get the faults currently injected into the link from `from` to `to`.
+    pub fn link(&self, from: NodeId, to: NodeId) -> LinkFaults {
+        let table = self.inner.lock().unwrap();
+        table.links.get(&(from, to)).unwrap_or(&table.default).clone()
+    }
+}
+
+/// A `RaftNetwork` wrapper which injects faults into the RPCs sent over an inner network.
+///
+/// Faults are injected according to the `NetworkFaults` handle given at construction, per
+/// directed link between the sending node & the target node. This supports latency, drops,
+/// duplication, reordering & one-way partitions, and applies to every RPC of the `RaftNetwork`
+/// trait. All random rolls are drawn from `crate::rng`, so a simulation seeded via
+/// `rng::seed_thread_rng` injects the same faults on every run.
+///
+/// A single `NetworkFaults` handle may be shared by the wrappers of all nodes in a cluster:
+///
+/// ```ignore
+/// let faults = NetworkFaults::new();
+/// let network = Arc::new(FaultyNetwork::new(Arc::new(YourNetwork::new(id)), faults.clone()));
+/// let raft = Raft::new(id, config, network, storage);
+/// faults.partition_one_way(0, 1);
+/// ```
+pub struct FaultyNetwork<N> {
+    /// The network over which RPCs are actually sent.
+    inner: Arc<N>,
+    /// The faults to inject.
+    faults: NetworkFaults,
+}
+
+impl<N> FaultyNetwork<N> {
+    /// Create a new instance wrapping the given network.
+    pub fn new(inner: Arc<N>, faults: NetworkFaults) -> Self {
+        Self{inner, faults}
+    }
+
+    /// Get a reference to the wrapped network.
+    pub fn inner(&self) -> &Arc<N> {
+        &self.inner
+    }
+
+    /// Get a reference to the handle controlling the faults of this network.
+    pub fn faults(&self) -> &NetworkFaults {
+        &self.faults
+    }
+}
+
+impl<N: Send + Sync + 'static> FaultyNetwork<N> {
+    /// Send the given RPC from `from` to `target` using `send`, injecting the faults of the link.
+    async fn send<Req, Res, F, Fut>(&self, from: NodeId, target: NodeId, rpc: Req, send: F) -> Result<Res>
+        where
+            Req: Clone + Send + 'static,
+            F: Fn(Arc<N>, Req) -> Fut + Copy + Send + 'static,
+            Fut: Future<Output=Result<Res>> + Send + 'static,
+    {
+        let faults = self.faults.link(from, target);
+        if faults.partitioned {
+            return Err(anyhow!("link from node {} to node {} is partitioned", from, target));
+        }
+
+        // Deliver a duplicate of the RPC independently of the original.
+        if rng::gen_bool(faults.duplicate_rate) {
+            let (inner, rpc, latency) = (self.inner.clone(), rpc.clone(), roll_latency(&faults));
+            tokio::spawn(async move {
+                delay_for(latency).await;
+                let _ = send(inner, rpc).await;
+            });
+        }
+
+        delay_for(roll_latency(&faults)).await;
+        if rng::gen_bool(faults.drop_rate) {
+            if rng::gen_bool(0.5) {
+                return Err(anyhow!("request from node {} to node {} was dropped", from, target));
+            }
+            let _ = send(self.inner.clone(), rpc).await;
+            return Err(anyhow!("response from node {} to node {} was dropped", target, from));
+        }
+        send(self.inner.clone(), rpc).await
+    }
+}
+
+/// Roll the latency of a single RPC sent over a link with the given faults.
+fn roll_latency(faults: &LinkFaults) -> Duration {
+    let mut latency = if faults.latency_max > faults.latency_min {
+        rng::gen_range(faults.latency_min, faults.latency_max + 1)
+    } else {
+        faults.latency_min
+    };
+    if rng::gen_bool(faults.reorder_rate) {
+        latency += faults.reorder_delay;
+    }
+    Duration::from_millis(latency)
+}
+
+#[async_trait]
+impl<D: AppData, N: RaftNetwork<D>> RaftNetwork<D> for FaultyNetwork<N> {
+    async fn append_entries(&self, target: NodeId, rpc: AppendEntriesRequest<D>) -> Result<AppendEntriesResponse> {
+        self.send(rpc.leader_id, target, rpc, move |net: Arc<N>, rpc| async move { net.append_entries(target, rpc).await }).await
+    }
+
+    async fn install_snapshot(&self, target: NodeId, rpc: InstallSnapshotRequest) -> Result<InstallSnapshotResponse> {
+        self.send(rpc.leader_id, target, rpc, move |net: Arc<N>, rpc| async move { net.install_snapshot(target, rpc).await }).await
+    }
+
+    async fn vote(&self, target: NodeId, rpc: VoteRequest) -> Result<VoteResponse> {
+        self.send(rpc.candidate_id, target, rpc, move |net: Arc<N>, rpc| async move { net.vote(target, rpc).await }).await
+    }
+
+    async fn pre_vote(&self, target: NodeId, rpc: PreVoteRequest) -> Result<PreVoteResponse> {
+        self.send(rpc.candidate_id, target, rpc, move |net: Arc<N>, rpc| async move { net.pre_vote(target, rpc).await }).await
+    }
+
+    async fn timeout_now(&self, target: NodeId, rpc: TimeoutNowRequest) -> Result<TimeoutNowResponse> {
+        self.send(rpc.leader_id, target, rpc, move |net: Arc<N>, rpc| async move { net.timeout_now(target, rpc).await }).await
+    }
+
+    async fn read_index(&self, target: NodeId, rpc: ReadIndexRequest) -> Result<ReadIndexResponse> {
+        self.send(rpc.requester_id, target, rpc, move |net: Arc<N>, rpc| async move { net.read_index(target, rpc).await }).await
+    }
+}
@@ -0,0 +1,95 @@
+mod fixtures;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_raft::{Config, State};
+use async_raft::error::ClientWriteError;
+use memstore::ClientRequest as MemClientRequest;
+use tokio::time::delay_for;
+
+use fixtures::RaftRouter;
+
+/// Admission control test.
+///
+/// What does this test do?
+///
+/// - creates a stable 3-node cluster which admits at most 4 in-flight client writes.
+/// - isolates both followers, so that writes sent to the leader can not be committed, and sends
+///   the leader 4 writes which stay in flight.
+/// - asserts that further writes & batches are rejected as overloaded, handing back the request.
+/// - restores the followers, and asserts that once the in-flight writes have resolved, new writes
+///   are admitted again.
+///
+/// RUST_LOG=async_raft,memstore,admission_control=trace cargo test -p async-raft --test admission_control
+#[tokio::test(core_threads=4)]
+async fn admission_control() -> Result<()> {
+    fixtures::init_tracing();
+
+    // Setup test dependencies.
+    let config = Arc::new(Config::build("test".into())
+        .max_inflight_client_writes(4)
+        .validate()
+        .expect("failed to build Raft config"));
+    let router = Arc::new(RaftRouter::new(config.clone()));
+    let timeout = Duration::from_secs(5);
+    for id in 0..3 {
+        router.new_raft_node(id).await;
+    }
+
+    // Initialize the cluster.
+    tracing::info!("--- initializing cluster");
+    router.initialize_from_single_node(0).await?;
+    let leader = router.wait(0, timeout).await
+        .metrics(|metrics| metrics.current_leader.is_some(), "a known leader").await?
+        .current_leader.expect("expected node 0 to know the leader");
+    router.wait(leader, timeout).await.applied(1).await?;
+
+    // Isolate both followers, and fill the leader with writes which can not be committed.
+    tracing::info!("--- filling the leader with writes");
+    for id in (0..3).filter(|id| *id != leader) {
+        router.isolate_node(id).await;
+    }
+    let mut inflight = vec![];
+    for serial in 0..4 {
+        let router = router.clone();
+        inflight.push(tokio::spawn(async move {
+            router.send_client_request(leader, request(serial)).await
+        }));
+    }
+    delay_for(Duration::from_millis(500)).await;
+
+    // Assert that further writes are rejected at once.
+    tracing::info!("--- asserting that further writes are rejected");
+    match router.send_client_request(leader, request(4)).await {
+        Err(ClientWriteError::Overloaded(rpc)) => assert!(format!("{:?}", rpc).contains("request-4"), "expected the request to be handed back, got {:?}", rpc),
+        res => panic!("expected the write to be rejected as overloaded, got {:?}", res),
+    }
+    let responses = router.client_request_batch(leader, "admission", 2).await;
+    assert_eq!(responses.len(), 2);
+    for res in responses {
+        assert!(matches!(res, Err(ClientWriteError::Overloaded(_))), "expected the batch to be rejected as overloaded, got {:?}", res);
+    }
+
+    // Restore the followers, and assert that new writes are admitted once the in-flight writes resolve.
+    tracing::info!("--- restoring followers");
+    for id in 0..3 {
+        router.restore_node(id).await;
+    }
+    for handle in inflight {
+        let _ = handle.await?;
+    }
+    delay_for(Duration::from_secs(1)).await;
+    let leader = router.leader().await.expect("expected the cluster to have a leader");
+    router.wait(leader, timeout).await.state(State::Leader).await?;
+    let res = router.send_client_request(leader, request(5)).await;
+    assert!(res.is_ok(), "expected the write to be admitted & committed, got {:?}", res);
+
+    Ok(())
+}
+
+/// Build a client request with the given serial.
+fn request(serial: u64) -> MemClientRequest {
+    MemClientRequest{client: "admission".into(), serial, status: format!("request-{}", serial)}
+}
@@ -0,0 +1,91 @@
+mod fixtures;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_raft::Config;
+use async_raft::error::ClientWriteError;
+use memstore::ClientRequest as MemClientRequest;
+use tokio::time::timeout;
+
+use fixtures::RaftRouter;
+
+/// Admission control with cancelled writes test.
+///
+/// What does this test do?
+///
+/// - creates a stable 3-node cluster which admits at most 4 in-flight client writes, with pre-vote
+///   enabled so that isolated followers do not disrupt the leader once restored.
+/// - isolates both followers, so that writes sent to the leader can not be committed, and sends
+///   the leader 4 writes whose callers stop waiting for them while they are still queued.
+/// - asserts that further writes & batches are still rejected as overloaded, as the cancelled
+///   writes remain in flight on the leader.
+/// - restores the followers, and asserts that once the cancelled writes have been applied, new
+///   writes are admitted again.
+///
+/// RUST_LOG=async_raft,memstore,admission_control_cancel=trace cargo test -p async-raft --test admission_control_cancel
+#[tokio::test(core_threads=4)]
+async fn admission_control_cancel() -> Result<()> {
+    fixtures::init_tracing();
+
+    // Setup test dependencies.
+    let config = Arc::new(Config::build("test".into())
+        .max_inflight_client_writes(4)
+        .pre_vote(true)
+        .validate()
+        .expect("failed to build Raft config"));
+    let router = Arc::new(RaftRouter::new(config.clone()));
+    let wait_timeout = Duration::from_secs(5);
+    for id in 0..3 {
+        router.new_raft_node(id).await;
+    }
+
+    // Initialize the cluster.
+    tracing::info!("--- initializing cluster");
+    router.initialize_from_single_node(0).await?;
+    let leader = router.wait(0, wait_timeout).await
+        .metrics(|metrics| metrics.current_leader.is_some(), "a known leader").await?
+        .current_leader.expect("expected node 0 to know the leader");
+    router.wait(leader, wait_timeout).await.applied(1).await?;
+
+    // Isolate both followers, and send the leader writes which are cancelled while still queued.
+    tracing::info!("--- sending writes which are cancelled");
+    for id in (0..3).filter(|id| *id != leader) {
+        router.isolate_node(id).await;
+    }
+    for serial in 0..4 {
+        let res = timeout(Duration::from_millis(100), router.send_client_request(leader, request(serial))).await;
+        assert!(res.is_err(), "expected the write to still be pending when cancelled, got {:?}", res);
+    }
+
+    // Assert that further writes are rejected at once, as the cancelled writes are still in flight.
+    tracing::info!("--- asserting that further writes are rejected");
+    match timeout(wait_timeout, router.send_client_request(leader, request(4))).await {
+        Ok(Err(ClientWriteError::Overloaded(_))) => (),
+        res => panic!("expected the write to be rejected as overloaded, got {:?}", res),
+    }
+    let responses = timeout(wait_timeout, router.client_request_batch(leader, "admission", 2)).await?;
+    assert_eq!(responses.len(), 2);
+    for res in responses {
+        assert!(matches!(res, Err(ClientWriteError::Overloaded(_))), "expected the batch to be rejected as overloaded, got {:?}", res);
+    }
+
+    // Restore the followers, and assert that new writes are admitted once the cancelled writes
+    // have been applied. The slot of the last cancelled write may be released just after it is
+    // reported as applied, so only a single write is sent.
+    tracing::info!("--- restoring followers");
+    for id in 0..3 {
+        router.restore_node(id).await;
+    }
+    router.wait(leader, wait_timeout).await.applied(5).await?;
+    let res = router.send_client_request(leader, request(5)).await;
+    assert!(res.is_ok(), "expected the write to be admitted & committed, got {:?}", res);
+
+    Ok(())
+}
+
+/// Build a client request with the given serial.
+fn request(serial: u64) -> MemClientRequest {
+    MemClientRequest{client: "admission".into(), serial, status: format!("request-{}", serial)}
+}
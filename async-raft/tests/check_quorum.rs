@@ -0,0 +1,84 @@
+mod fixtures;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_raft::{Config, State};
+use async_raft::error::{ClientWriteError, RaftError};
+use tokio::time::delay_for;
+
+use fixtures::RaftRouter;
+
+/// CheckQuorum test.
+///
+/// What does this test do?
+///
+/// - brings 3 nodes online & initializes the cluster.
+/// - isolates the leader, and sends it a client write which can never be committed.
+/// - asserts that the isolated leader steps down, and that the client write fails with `LeadershipLost`.
+/// - restores the old leader, and asserts that it rejoins the cluster under the new leader.
+///
+/// RUST_LOG=async_raft,memstore,check_quorum=trace cargo test -p async-raft --test check_quorum
+#[tokio::test(core_threads=4)]
+async fn check_quorum() -> Result<()> {
+    fixtures::init_tracing();
+
+    // Setup test dependencies.
+    let config = Arc::new(Config::build("test".into()).pre_vote(true).check_quorum(true).validate().expect("failed to build Raft config"));
+    let router = Arc::new(RaftRouter::new(config.clone()));
+    router.new_raft_node(0).await;
+    router.new_raft_node(1).await;
+    router.new_raft_node(2).await;
+
+    // Assert all nodes are in non-voter state & have no entries.
+    delay_for(Duration::from_secs(3)).await;
+    router.assert_pristine_cluster().await;
+
+    // Initialize the cluster, then assert that a stable cluster was formed & held.
+    tracing::info!("--- initializing cluster");
+    router.initialize_from_single_node(0).await?;
+    delay_for(Duration::from_secs(5)).await;
+    router.assert_stable_cluster(Some(1), Some(1)).await;
+    let orig_leader = router.leader().await.expect("expected the cluster to have a leader");
+
+    // Isolate the leader & send it a client write, which it can not commit.
+    tracing::info!("--- isolating leader node {}", orig_leader);
+    router.isolate_node(orig_leader).await;
+    let write = tokio::spawn({
+        let router = router.clone();
+        async move { router.client_request_batch(orig_leader, "0", 1).await }
+    });
+
+    // Assert that the isolated leader stepped down, and that the client write failed.
+    delay_for(Duration::from_secs(5)).await;
+    let isolated = router.latest_metrics().await.into_iter()
+        .find(|node| node.id == orig_leader)
+        .expect("expected to find metrics for the isolated node");
+    assert_ne!(isolated.state, State::Leader, "expected isolated leader to have stepped down");
+    assert_ne!(isolated.current_leader, Some(orig_leader), "expected isolated node to no longer consider itself leader");
+    let mut responses = write.await?;
+    assert_eq!(responses.len(), 1, "expected a single response");
+    let res = responses.remove(0);
+    assert!(
+        matches!(res, Err(ClientWriteError::RaftError(RaftError::LeadershipLost))),
+        "expected LeadershipLost error, got {:?}", res.map(|res| res.index)
+    );
+    let leader = router.leader().await.expect("expected a new leader");
+    assert_ne!(leader, orig_leader, "expected new leader to be different from the old leader");
+
+    // Restore the old leader & assert that it rejoins the cluster under the new leader.
+    tracing::info!("--- restoring node {}", orig_leader);
+    router.restore_node(orig_leader).await;
+    router.client_request_many(leader, "1", 10).await;
+    delay_for(Duration::from_secs(2)).await;
+    let metrics = router.latest_metrics().await;
+    let leader_metrics = metrics.iter().find(|node| node.id == leader).expect("expected to find metrics for new leader");
+    for node in metrics.iter() {
+        assert_eq!(node.current_leader, Some(leader), "node {} has leader {:?}, expected {}", node.id, node.current_leader, leader);
+        assert_eq!(node.current_term, leader_metrics.current_term, "node {} has term {}, expected {}", node.id, node.current_term, leader_metrics.current_term);
+        assert_eq!(node.last_applied, leader_metrics.last_log_index, "node {} has last_applied {}, expected {}", node.id, node.last_applied, leader_metrics.last_log_index);
+    }
+
+    Ok(())
+}
@@ -15,7 +15,10 @@ use fixtures::RaftRouter;
 ///
 /// - create a stable 3-node cluster.
 /// - call the client_read interface on the leader, and assert success.
-/// - call the client_read interface on the followers, and assert failure.
+/// - call the client_read interface on the followers, and assert success.
+/// - call the client_read interface on the leader many times concurrently, and assert that all
+///   reads resolve with the expected read index.
+/// - write some data, then assert that the read index covers the writes, on the leader & followers.
 ///
 /// RUST_LOG=async_raft,memstore,client_reads=trace cargo test -p async-raft --test client_reads
 #[tokio::test(core_threads=4)]
@@ -42,9 +45,28 @@ async fn client_reads() -> Result<()> {
     // Get the ID of the leader, and assert that client_read succeeds.
     let leader = router.leader().await.expect("leader not found");
     assert_eq!(leader, 0, "expected leader to be node 0, got {}", leader);
-    router.client_read(leader).await.expect(&format!("expected client_read to succeed for cluster leader {}", leader));
-    router.client_read(1).await.expect_err("expected client_read on follower node 1 to fail");
-    router.client_read(2).await.expect_err("expected client_read on follower node 2 to fail");
+    let read_index = router.client_read(leader).await.expect(&format!("expected client_read to succeed for cluster leader {}", leader));
+    assert_eq!(read_index, 1, "expected read index of 1, got {}", read_index);
+    for id in 1..3 {
+        let read_index = router.client_read(id).await.expect(&format!("expected client_read to succeed for follower {}", id));
+        assert_eq!(read_index, 1, "expected read index of 1 on follower {}, got {}", id, read_index);
+    }
+
+    // Issue a batch of concurrent reads, and assert that they all resolve.
+    let reads = futures::future::join_all((0..10).map(|_| router.client_read(leader))).await;
+    for res in reads {
+        let read_index = res.expect("expected concurrent client_read to succeed");
+        assert_eq!(read_index, 1, "expected read index of 1, got {}", read_index);
+    }
+
+    // Write some data, and assert that the read index covers the writes.
+    router.client_request_many(leader, "0", 10).await;
+    let read_index = router.client_read(leader).await.expect("expected client_read to succeed after writes");
+    assert_eq!(read_index, 11, "expected read index of 11, got {}", read_index);
+    for id in 1..3 {
+        let read_index = router.client_read(id).await.expect(&format!("expected client_read to succeed for follower {}", id));
+        assert_eq!(read_index, 11, "expected read index of 11 on follower {}, got {}", id, read_index);
+    }
 
     Ok(())
 }
@@ -0,0 +1,72 @@
+mod fixtures;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_raft::Config;
+use async_raft::error::ClientWriteError;
+use async_raft::session::SessionRequest;
+use memstore::ClientRequest;
+use tokio::time::delay_for;
+
+use fixtures::RaftRouter;
+
+/// Client session expiry test.
+///
+/// What does this test do?
+///
+/// - brings 3 nodes online & initializes the cluster, with a short client session timeout.
+/// - registers two client sessions, and keeps only one of them active.
+/// - asserts that the idle session is expired, and that its writes are rejected.
+/// - asserts that the active session is not expired.
+///
+/// RUST_LOG=async_raft,memstore,client_session_expiry=trace cargo test -p async-raft --test client_session_expiry
+#[tokio::test(core_threads=4)]
+async fn client_session_expiry() -> Result<()> {
+    fixtures::init_tracing();
+
+    // Setup test dependencies.
+    let config = Arc::new(Config::build("test".into()).client_session_timeout(2000).validate().expect("failed to build Raft config"));
+    let router = Arc::new(RaftRouter::new(config.clone()));
+    router.new_raft_node(0).await;
+    router.new_raft_node(1).await;
+    router.new_raft_node(2).await;
+
+    // Initialize the cluster, then assert that a stable cluster was formed & held.
+    tracing::info!("--- initializing cluster");
+    router.initialize_from_single_node(0).await?;
+    delay_for(Duration::from_secs(5)).await;
+    router.assert_stable_cluster(Some(1), Some(1)).await;
+    let leader = router.leader().await.expect("expected the cluster to have a leader");
+
+    // Register two sessions, and keep only one of them active.
+    tracing::info!("--- registering client sessions");
+    let active = router.register_client_session(leader).await?;
+    let idle = router.register_client_session(leader).await?;
+    for sequence in 1..=10 {
+        router.send_session_request(leader, request(sequence, "a"), SessionRequest{session_id: active, sequence, responded_to: sequence - 1}).await?;
+        delay_for(Duration::from_millis(500)).await;
+    }
+
+    // Assert that only the idle session was expired, on every node.
+    tracing::info!("--- asserting idle session was expired");
+    for node in 0..3 {
+        let storage = router.storage(node).await;
+        let sm = storage.get_state_machine().await;
+        assert!(!sm.client_sessions.contains(idle), "expected idle session to be expired on node {}", node);
+        assert!(sm.client_sessions.contains(active), "expected active session to be live on node {}", node);
+    }
+    let res = router.send_session_request(leader, request(11, "b"), SessionRequest{session_id: idle, sequence: 1, responded_to: 0}).await;
+    assert!(
+        matches!(res, Err(ClientWriteError::UnknownSession(id)) if id == idle),
+        "expected UnknownSession error, got {:?}", res.map(|res| res.index)
+    );
+
+    Ok(())
+}
+
+/// Build a client request for the client of the session with the given serial number.
+fn request(serial: u64, status: &str) -> ClientRequest {
+    ClientRequest{client: "session".into(), serial, status: status.into()}
+}
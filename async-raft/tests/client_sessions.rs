@@ -0,0 +1,105 @@
+mod fixtures;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_raft::Config;
+use async_raft::error::{ClientWriteError, RegisterSessionError};
+use async_raft::session::SessionRequest;
+use memstore::ClientRequest;
+use tokio::time::delay_for;
+
+use fixtures::RaftRouter;
+
+/// Client sessions test.
+///
+/// What does this test do?
+///
+/// - brings 3 nodes online & initializes the cluster.
+/// - registers a client session, and asserts that registration on a follower is forwarded.
+/// - sends a session write, then retries it, and asserts that the retry returns the original
+///   response without being applied again.
+/// - sends another session write, isolates the leader, and retries the write on the new leader,
+///   asserting that the write is still not applied twice.
+/// - asserts that retries of acknowledged writes, and writes of unknown sessions, are rejected.
+///
+/// RUST_LOG=async_raft,memstore,client_sessions=trace cargo test -p async-raft --test client_sessions
+#[tokio::test(core_threads=4)]
+async fn client_sessions() -> Result<()> {
+    fixtures::init_tracing();
+
+    // Setup test dependencies.
+    let config = Arc::new(Config::build("test".into()).validate().expect("failed to build Raft config"));
+    let router = Arc::new(RaftRouter::new(config.clone()));
+    router.new_raft_node(0).await;
+    router.new_raft_node(1).await;
+    router.new_raft_node(2).await;
+
+    // Initialize the cluster, then assert that a stable cluster was formed & held.
+    tracing::info!("--- initializing cluster");
+    router.initialize_from_single_node(0).await?;
+    delay_for(Duration::from_secs(5)).await;
+    router.assert_stable_cluster(Some(1), Some(1)).await;
+    let orig_leader = router.leader().await.expect("expected the cluster to have a leader");
+    let follower = (0..3).find(|id| *id != orig_leader).expect("expected the cluster to have a follower");
+
+    // Register a client session.
+    tracing::info!("--- registering client session");
+    let res = router.register_client_session(follower).await;
+    assert!(
+        matches!(res, Err(RegisterSessionError::ForwardToLeader(Some(leader))) if leader == orig_leader),
+        "expected session registration on follower to be forwarded to leader, got {:?}", res
+    );
+    let session_id = router.register_client_session(orig_leader).await?;
+    assert_eq!(session_id, 2, "expected session ID to be the index of its registration entry");
+
+    // Send a session write, then retry it. Each attempt is given a new serial number, so that
+    // only the client session can prevent the retry from being applied again.
+    tracing::info!("--- sending session write & its retry");
+    let session = SessionRequest{session_id, sequence: 1, responded_to: 0};
+    let orig = router.send_session_request(orig_leader, request(0, "a"), session).await?;
+    let retry = router.send_session_request(orig_leader, request(1, "a"), session).await?;
+    assert_eq!(retry.index, orig.index, "expected retry to return the index of the original write");
+    assert_eq!(retry.data.0.as_ref().ok(), orig.data.0.as_ref().ok(), "expected retry to return the response of the original write");
+    assert_eq!(orig.data.0.as_ref().ok(), Some(&None), "expected original write to be the first write of its client");
+    let serial = router.storage(orig_leader).await.get_state_machine().await.client_serial_responses.get("session").map(|(serial, _)| *serial);
+    assert_eq!(serial, Some(0), "expected retry to not be applied");
+
+    // Send another session write, then isolate the leader & retry the write on the new leader.
+    tracing::info!("--- sending session write & retrying it on a new leader");
+    let session = SessionRequest{session_id, sequence: 2, responded_to: 1};
+    let orig = router.send_session_request(orig_leader, request(2, "b"), session).await?;
+    for node in (0..3).filter(|id| *id != orig_leader) {
+        router.wait(node, Duration::from_secs(5)).await.applied(orig.index).await?;
+    }
+    router.isolate_node(orig_leader).await;
+    router.wait(follower, Duration::from_secs(10)).await
+        .metrics(|m| m.current_leader.is_some() && m.current_leader != Some(orig_leader), "a new leader").await?;
+    let leader = router.leader().await.expect("expected the cluster to have a new leader");
+    let retry = router.send_session_request(leader, request(3, "b"), session).await?;
+    assert_eq!(retry.index, orig.index, "expected retry on new leader to return the index of the original write");
+    assert_eq!(retry.data.0.as_ref().ok(), Some(&Some("a".into())), "expected retry on new leader to return the response of the original write");
+    let serial = router.storage(leader).await.get_state_machine().await.client_serial_responses.get("session").map(|(serial, _)| *serial);
+    assert_eq!(serial, Some(2), "expected retry to not be applied");
+
+    // Retries of acknowledged writes, and writes of unknown sessions, are rejected.
+    tracing::info!("--- sending rejected session writes");
+    let res = router.send_session_request(leader, request(4, "c"), SessionRequest{session_id, sequence: 1, responded_to: 1}).await;
+    assert!(
+        matches!(res, Err(ClientWriteError::ResponseDiscarded(id, 1)) if id == session_id),
+        "expected ResponseDiscarded error, got {:?}", res.map(|res| res.index)
+    );
+    let res = router.send_session_request(leader, request(5, "c"), SessionRequest{session_id: 1000, sequence: 1, responded_to: 0}).await;
+    assert!(
+        matches!(res, Err(ClientWriteError::UnknownSession(1000))),
+        "expected UnknownSession error, got {:?}", res.map(|res| res.index)
+    );
+
+    Ok(())
+}
+
+/// Build a client request for the client of the session with the given serial number.
+fn request(serial: u64, status: &str) -> ClientRequest {
+    ClientRequest{client: "session".into(), serial, status: status.into()}
+}
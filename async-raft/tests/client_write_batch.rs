@@ -0,0 +1,70 @@
+mod fixtures;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_raft::Config;
+use async_raft::error::ClientWriteError;
+use tokio::time::delay_for;
+
+use fixtures::RaftRouter;
+
+/// Client write batch tests.
+///
+/// What does this test do?
+///
+/// - create a stable 3-node cluster.
+/// - submit a batch of writes to a follower, and assert that each write is forwarded to the leader.
+/// - submit a batch of writes to the leader, and assert that each write receives its own response,
+///   in order, with consecutive log indices.
+/// - assert that the cluster stayed stable and has all of the expected data.
+///
+/// RUST_LOG=async_raft,memstore,client_write_batch=trace cargo test -p async-raft --test client_write_batch
+#[tokio::test(core_threads=4)]
+async fn client_write_batch() -> Result<()> {
+    fixtures::init_tracing();
+
+    // Setup test dependencies.
+    let config = Arc::new(Config::build("test".into()).validate().expect("failed to build Raft config"));
+    let router = Arc::new(RaftRouter::new(config.clone()));
+    router.new_raft_node(0).await;
+    router.new_raft_node(1).await;
+    router.new_raft_node(2).await;
+
+    // Assert all nodes are in non-voter state & have no entries.
+    delay_for(Duration::from_secs(10)).await;
+    router.assert_pristine_cluster().await;
+
+    // Initialize the cluster, then assert that a stable cluster was formed & held.
+    tracing::info!("--- initializing cluster");
+    router.initialize_from_single_node(0).await?;
+    delay_for(Duration::from_secs(10)).await;
+    router.assert_stable_cluster(Some(1), Some(1)).await;
+    let leader = router.leader().await.expect("leader not found");
+
+    // Submit a batch to a follower, and assert that every write is forwarded to the leader.
+    tracing::info!("--- writing batch to follower");
+    let follower = (0..3).find(|id| *id != leader).expect("follower not found");
+    let responses = router.client_request_batch(follower, "0", 10).await;
+    assert_eq!(responses.len(), 10, "expected 10 responses, got {}", responses.len());
+    for res in responses {
+        match res {
+            Err(ClientWriteError::ForwardToLeader(_, Some(id))) => assert_eq!(id, leader, "expected forward to leader {}, got {}", leader, id),
+            other => panic!("expected write to be forwarded to leader, got {:?}", other.map(|res| res.index)),
+        }
+    }
+
+    // Submit a batch to the leader, and assert that every write was committed in order.
+    tracing::info!("--- writing batch to leader");
+    let responses = router.client_request_batch(leader, "0", 100).await;
+    assert_eq!(responses.len(), 100, "expected 100 responses, got {}", responses.len());
+    for (offset, res) in responses.into_iter().enumerate() {
+        let res = res.expect("expected batched write to succeed");
+        assert_eq!(res.index, offset as u64 + 2, "expected response {} to have index {}, got {}", offset, offset + 2, res.index);
+    }
+    delay_for(Duration::from_secs(5)).await; // Ensure enough time is given for replication.
+    router.assert_stable_cluster(Some(1), Some(101)).await; // The extra 1 is from the leader's initial commit entry.
+
+    Ok(())
+}
@@ -0,0 +1,81 @@
+mod fixtures;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_raft::Config;
+use async_raft::error::{ClientWriteError, RaftError};
+use async_raft::raft::ClientWriteAppended;
+use memstore::ClientRequest;
+use tokio::time::delay_for;
+
+use fixtures::RaftRouter;
+
+/// Staged client writes test.
+///
+/// What does this test do?
+///
+/// - brings 3 nodes online & initializes the cluster.
+/// - sends a staged write to the leader, and asserts that it reports being appended, committed &
+///   applied at the same index.
+/// - sends a staged write to a follower, and asserts that it only reports being forwarded.
+/// - isolates the leader & sends it a staged write, and asserts that the write is appended but
+///   never committed, failing with `LeadershipLost` once the leader steps down.
+///
+/// RUST_LOG=async_raft,memstore,client_write_staged=trace cargo test -p async-raft --test client_write_staged
+#[tokio::test(core_threads=4)]
+async fn client_write_staged() -> Result<()> {
+    fixtures::init_tracing();
+
+    // Setup test dependencies.
+    let config = Arc::new(Config::build("test".into()).check_quorum(true).validate().expect("failed to build Raft config"));
+    let router = Arc::new(RaftRouter::new(config.clone()));
+    router.new_raft_node(0).await;
+    router.new_raft_node(1).await;
+    router.new_raft_node(2).await;
+
+    // Initialize the cluster, then assert that a stable cluster was formed & held.
+    tracing::info!("--- initializing cluster");
+    router.initialize_from_single_node(0).await?;
+    delay_for(Duration::from_secs(5)).await;
+    router.assert_stable_cluster(Some(1), Some(1)).await;
+    let leader = router.leader().await.expect("expected the cluster to have a leader");
+    let follower = (0..3).find(|id| *id != leader).expect("expected the cluster to have a follower");
+
+    // Send a staged write to the leader, and assert that it reaches every stage.
+    tracing::info!("--- sending staged write to leader");
+    let (appended, committed, res) = router.send_staged_client_request(leader, request(0, "a")).await;
+    assert_eq!(appended, Some(ClientWriteAppended{index: 2, term: 1}), "expected write to be appended at index 2 in term 1");
+    assert_eq!(committed, Some(2), "expected write to be committed at index 2");
+    let res = res?;
+    assert_eq!(res.index, 2, "expected write to be applied at index 2");
+
+    // Send a staged write to a follower, and assert that it is forwarded without reaching any stage.
+    tracing::info!("--- sending staged write to follower");
+    let (appended, committed, res) = router.send_staged_client_request(follower, request(1, "b")).await;
+    assert_eq!(appended, None, "expected write on follower to not be appended");
+    assert_eq!(committed, None, "expected write on follower to not be committed");
+    assert!(
+        matches!(res, Err(ClientWriteError::ForwardToLeader(_, Some(id))) if id == leader),
+        "expected ForwardToLeader error, got {:?}", res.map(|res| res.index)
+    );
+
+    // Isolate the leader & send it a staged write, which it can append but not commit.
+    tracing::info!("--- isolating leader node {} & sending it a staged write", leader);
+    router.isolate_node(leader).await;
+    let (appended, committed, res) = router.send_staged_client_request(leader, request(2, "c")).await;
+    assert_eq!(appended, Some(ClientWriteAppended{index: 3, term: 1}), "expected write to be appended at index 3 in term 1");
+    assert_eq!(committed, None, "expected write on isolated leader to not be committed");
+    assert!(
+        matches!(res, Err(ClientWriteError::RaftError(RaftError::LeadershipLost))),
+        "expected LeadershipLost error, got {:?}", res.map(|res| res.index)
+    );
+
+    Ok(())
+}
+
+/// Build a client request with the given serial number.
+fn request(serial: u64, status: &str) -> ClientRequest {
+    ClientRequest{client: "staged".into(), serial, status: status.into()}
+}
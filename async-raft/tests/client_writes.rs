@@ -54,7 +54,7 @@ async fn client_writes() -> Result<()> {
     while let Some(_) = clients.next().await { }
     delay_for(Duration::from_secs(5)).await; // Ensure enough time is given for replication (this is WAY more than enough).
     router.assert_stable_cluster(Some(1), Some(6001)).await; // The extra 1 is from the leader's initial commit entry.
-    router.assert_storage_state(1, 6001, Some(0), 6001, Some(((5000..5100).into(), 1, MembershipConfig{members: hashset![0, 1, 2], members_after_consensus: None}))).await;
+    router.assert_storage_state(1, 6001, Some(0), 6001, Some(((5000..5100).into(), 1, MembershipConfig{members: hashset![0, 1, 2], members_after_consensus: None, learners: hashset![], witnesses: hashset![]}))).await;
 
     Ok(())
 }
@@ -0,0 +1,73 @@
+mod fixtures;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_raft::Config;
+use tokio::time::delay_for;
+
+use fixtures::RaftRouter;
+
+/// Conflicting logs test.
+///
+/// What does this test do?
+///
+/// - brings 3 nodes online & initializes the cluster.
+/// - isolates the leader, and sends it client writes which are appended to its log but can never
+///   be committed.
+/// - sends client writes to the new leader, which conflict with the uncommitted tail of the old
+///   leader's log.
+/// - restores the old leader, and asserts that its log is brought back in line with the new leader.
+///
+/// RUST_LOG=async_raft,memstore,conflicting_logs=trace cargo test -p async-raft --test conflicting_logs
+#[tokio::test(core_threads=4)]
+async fn conflicting_logs() -> Result<()> {
+    fixtures::init_tracing();
+
+    // Setup test dependencies.
+    let config = Arc::new(Config::build("test".into()).validate().expect("failed to build Raft config"));
+    let router = Arc::new(RaftRouter::new(config.clone()));
+    router.new_raft_node(0).await;
+    router.new_raft_node(1).await;
+    router.new_raft_node(2).await;
+
+    // Assert all nodes are in non-voter state & have no entries.
+    delay_for(Duration::from_secs(3)).await;
+    router.assert_pristine_cluster().await;
+
+    // Initialize the cluster, then assert that a stable cluster was formed & held.
+    tracing::info!("--- initializing cluster");
+    router.initialize_from_single_node(0).await?;
+    delay_for(Duration::from_secs(5)).await;
+    router.assert_stable_cluster(Some(1), Some(1)).await;
+    let orig_leader = router.leader().await.expect("expected the cluster to have a leader");
+
+    // Isolate the leader & send it client writes, which it can append but not commit.
+    tracing::info!("--- isolating leader node {}", orig_leader);
+    router.isolate_node(orig_leader).await;
+    tokio::spawn({
+        let router = router.clone();
+        async move { router.client_request_many(orig_leader, "0", 5).await }
+    });
+
+    // Write to the new leader, conflicting with the uncommitted tail of the old leader's log.
+    delay_for(Duration::from_secs(5)).await;
+    let leader = router.leader().await.expect("expected a new leader");
+    assert_ne!(leader, orig_leader, "expected new leader to be different from the old leader");
+    router.client_request_many(leader, "1", 10).await;
+
+    // Restore the old leader & assert that its log is brought in line with the new leader's log.
+    tracing::info!("--- restoring node {}", orig_leader);
+    router.restore_node(orig_leader).await;
+    delay_for(Duration::from_secs(5)).await;
+    let metrics = router.latest_metrics().await;
+    let leader_metrics = metrics.iter().find(|node| node.id == leader).expect("expected to find metrics for new leader");
+    for node in metrics.iter() {
+        assert_eq!(node.current_leader, Some(leader), "node {} has leader {:?}, expected {}", node.id, node.current_leader, leader);
+        assert_eq!(node.last_log_index, leader_metrics.last_log_index, "node {} has last_log_index {}, expected {}", node.id, node.last_log_index, leader_metrics.last_log_index);
+        assert_eq!(node.last_applied, leader_metrics.last_log_index, "node {} has last_applied {}, expected {}", node.id, node.last_applied, leader_metrics.last_log_index);
+    }
+
+    Ok(())
+}
@@ -58,20 +58,20 @@ async fn dynamic_membership() -> Result<()> {
     tracing::info!("--- changing cluster config");
     router.change_membership(0, hashset![0, 1, 2, 3, 4]).await?;
     delay_for(Duration::from_secs(5)).await;
-    router.assert_stable_cluster(Some(1), Some(3)).await; // Still in term 1, so leader is still node 0.
+    router.assert_stable_cluster(Some(1), Some(7)).await; // Still in term 1, so leader is still node 0.
 
     // Isolate old leader and assert that a new leader takes over.
     tracing::info!("--- isolating master node 0");
     router.isolate_node(0).await;
     delay_for(Duration::from_secs(5)).await; // Wait for election and for everything to stabilize (this is way longer than needed).
-    router.assert_stable_cluster(Some(2), Some(4)).await;
+    router.assert_stable_cluster(Some(2), Some(8)).await;
     let leader = router.leader().await.expect("expected new leader");
     assert!(leader != 0, "expected new leader to be different from the old leader");
 
     // Restore isolated node.
     router.restore_node(0).await;
     delay_for(Duration::from_secs(5)).await; // Wait for election and for everything to stabilize (this is way longer than needed).
-    router.assert_stable_cluster(Some(2), Some(4)).await; // We should still be in term 2, as leaders should
+    router.assert_stable_cluster(Some(2), Some(8)).await; // We should still be in term 2, as leaders should
                                                           // not be deposed when they are not missing heartbeats.
     let current_leader = router.leader().await.expect("expected to find current leader");
     assert_eq!(leader, current_leader, "expected cluster leadership to stay the same");
@@ -0,0 +1,125 @@
+mod fixtures;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_raft::{Config, SnapshotPolicy};
+use async_raft::event::Event;
+use maplit::hashset;
+use memstore::ClientRequest as MemClientRequest;
+use tokio::sync::broadcast;
+use tokio::time::delay_for;
+
+use fixtures::RaftRouter;
+
+/// Events test.
+///
+/// What does this test do?
+///
+/// - brings 3 nodes online, subscribing to the events of each, & initializes the cluster.
+/// - asserts that the leader reports becoming leader, and that every node reports the new leader
+///   & the committed initial config.
+/// - writes enough data to trigger log compaction, and asserts that the leader reports the snapshot.
+/// - adds a new node, and asserts that it reports installing a snapshot, and that the leader
+///   reports the committed configs entering & leaving joint consensus.
+/// - isolates the leader & sends it a write which can never be committed, waits for a new leader,
+///   then restores the old leader, and asserts that it reports stepping down & truncating its log.
+///
+/// RUST_LOG=async_raft,memstore,events=trace cargo test -p async-raft --test events
+#[tokio::test(core_threads=4)]
+async fn events() -> Result<()> {
+    fixtures::init_tracing();
+
+    // Setup test dependencies.
+    let config = Arc::new(Config::build("test".into())
+        .snapshot_policy(SnapshotPolicy::LogsSinceLast(50))
+        .validate()
+        .expect("failed to build Raft config"));
+    let router = Arc::new(RaftRouter::new(config.clone()));
+    let mut subscribers = vec![];
+    for id in 0..3 {
+        router.new_raft_node(id).await;
+        subscribers.push(router.subscribe_events(id).await);
+    }
+
+    // Initialize the cluster, then assert that the election & initial config were reported.
+    tracing::info!("--- initializing cluster");
+    delay_for(Duration::from_secs(3)).await;
+    router.initialize_from_single_node(0).await?;
+    delay_for(Duration::from_secs(3)).await;
+    router.assert_stable_cluster(Some(1), Some(1)).await;
+    let leader = router.leader().await.expect("expected the cluster to have a leader");
+    for (id, rx) in subscribers.iter_mut().enumerate() {
+        let events = drain(rx);
+        assert!(events.contains(&Event::LeaderChanged{term: 1, leader: Some(leader)}), "expected node {} to report leader {}, got {:?}", id, leader, events);
+        let committed = events.iter().any(|event| matches!(event,
+            Event::MembershipCommitted{index: 1, membership} if membership.members == hashset![0, 1, 2]));
+        assert!(committed, "expected node {} to report the initial config as committed, got {:?}", id, events);
+        let became_leader = events.contains(&Event::BecameLeader{term: 1});
+        assert_eq!(became_leader, id as u64 == leader, "expected only the leader to report becoming leader, node {} got {:?}", id, events);
+    }
+
+    // Write enough data to trigger log compaction, and assert that the snapshot was reported.
+    tracing::info!("--- writing data to trigger compaction");
+    router.client_request_many(leader, "0", 59).await;
+    delay_for(Duration::from_secs(3)).await;
+    router.assert_stable_cluster(Some(1), Some(60)).await;
+    let events = drain(&mut subscribers[leader as usize]);
+    assert!(events.iter().any(|event| matches!(event, Event::SnapshotBuilt{index} if *index >= 50)), "expected the leader to report a snapshot, got {:?}", events);
+
+    // Add a new node, and assert that it installs a snapshot & that the config changes were reported.
+    tracing::info!("--- adding new node");
+    router.new_raft_node(3).await;
+    subscribers.push(router.subscribe_events(3).await);
+    router.add_non_voter(leader, 3).await.expect("failed to add new node as non-voter");
+    router.change_membership(leader, hashset![0, 1, 2, 3]).await.expect("failed to modify cluster membership");
+    delay_for(Duration::from_secs(3)).await;
+    router.assert_stable_cluster(Some(1), Some(63)).await;
+    let events = drain(&mut subscribers[3]);
+    assert!(events.iter().any(|event| matches!(event, Event::SnapshotInstalled{term: 1, ..})), "expected node 3 to report installing a snapshot, got {:?}", events);
+    let configs = drain(&mut subscribers[leader as usize]).into_iter()
+        .filter_map(|event| match event {
+            Event::MembershipCommitted{membership, ..} => Some(membership),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(configs.len(), 3, "expected the leader to report 3 committed configs, got {:?}", configs);
+    assert!(configs[0].learners.contains(&3), "expected the first config to add node 3 as a learner, got {:?}", configs[0]);
+    assert!(configs[1].is_in_joint_consensus(), "expected the second config to enter joint consensus, got {:?}", configs[1]);
+    assert!(!configs[2].is_in_joint_consensus(), "expected the third config to leave joint consensus, got {:?}", configs[2]);
+    assert_eq!(configs[2].members, hashset![0, 1, 2, 3], "expected the third config to have 4 members");
+
+    // Isolate the leader & send it a write which can never be committed, then wait for a new
+    // leader to be elected & restore the old leader, which must truncate the uncommitted entry.
+    tracing::info!("--- isolating leader {}", leader);
+    router.isolate_node(leader).await;
+    let req = MemClientRequest{client: "0".into(), serial: 60, status: "uncommitted".into()};
+    tokio::spawn({
+        let router = router.clone();
+        async move { router.send_client_request(leader, req).await }
+    });
+    delay_for(Duration::from_secs(3)).await;
+    let new_leader = router.leader().await.expect("expected a new leader to be elected");
+    assert_ne!(new_leader, leader, "expected a new leader to be elected");
+    tracing::info!("--- restoring old leader {}", leader);
+    router.restore_node(leader).await;
+    delay_for(Duration::from_secs(3)).await;
+    let events = drain(&mut subscribers[leader as usize]);
+    assert!(events.contains(&Event::SteppedDown{term: 1}), "expected the old leader to report stepping down, got {:?}", events);
+    let truncated = events.iter().any(|event| matches!(event, Event::LogTruncated{index: 64, term} if *term > 1));
+    assert!(truncated, "expected the old leader to report truncating its uncommitted entry, got {:?}", events);
+    let events = drain(&mut subscribers[new_leader as usize]);
+    assert!(events.iter().any(|event| matches!(event, Event::BecameLeader{term} if *term > 1)), "expected the new leader to report becoming leader, got {:?}", events);
+
+    Ok(())
+}
+
+/// Drain all events which the given subscriber has received so far.
+fn drain(rx: &mut broadcast::Receiver<Event>) -> Vec<Event> {
+    let mut events = vec![];
+    while let Ok(event) = rx.try_recv() {
+        events.push(event);
+    }
+    events
+}
@@ -0,0 +1,185 @@
+//! Recording of client histories, and a linearizability checker for the `MemStore` register model.
+//!
+//! The state machine of `MemStore` holds one register per client ID, and each write swaps the
+//! status held by its register, returning the previous status. A history of timestamped writes &
+//! reads against these registers is linearizable if there is a total order of its operations
+//! which respects real time, in which every operation observes the state left by the operations
+//! before it.
+//!
+//! Registers are independent, so the history of each register is checked on its own. Each check is
+//! a depth-first search for a linearization in the style of Wing & Gong, as used by Knossos &
+//! Porcupine, which memoizes the pairs of linearized operations & register state it has already
+//! explored.
+
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+/// An operation against a single register.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Op {
+    /// Write the given status, swapping it with the status previously held.
+    Write(String),
+    /// Read the status held.
+    Read,
+}
+
+/// The outcome of an operation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// A write which took effect, having swapped out the given status.
+    Written(Option<String>),
+    /// A read which observed the given status.
+    Read(Option<String>),
+    /// The operation failed & definitely did not take effect.
+    Failed,
+    /// The outcome of the operation is unknown, as it may or may not have taken effect.
+    Unknown,
+}
+
+/// A single operation of a history.
+#[derive(Clone, Debug)]
+pub struct Operation {
+    /// The register which the operation was performed against.
+    pub key: String,
+    /// The operation.
+    pub op: Op,
+    /// The time at which the operation was invoked, relative to the start of the history.
+    pub invoked_at: Duration,
+    /// The time at which the operation completed, relative to the start of the history.
+    ///
+    /// This is `None` for operations whose outcome is unknown.
+    pub completed_at: Option<Duration>,
+    /// The outcome of the operation.
+    pub outcome: Outcome,
+}
+
+/// A timestamped history of client operations.
+pub struct History {
+    /// The instant at which the history began.
+    started_at: Instant,
+    /// The recorded operations, in order of invocation.
+    ops: Mutex<Vec<Operation>>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self{started_at: Instant::now(), ops: Default::default()}
+    }
+}
+
+impl History {
+    /// Get the current time relative to the start of this history.
+    fn now(&self) -> Duration {
+        Instant::now().duration_since(self.started_at)
+    }
+
+    /// Record the invocation of an operation, returning its ID for recording its completion.
+    ///
+    /// Until its completion is recorded, the outcome of the operation is unknown. This is also
+    /// the case if the operation is abandoned, for example due to a timeout.
+    pub fn invoke(&self, key: &str, op: Op) -> usize {
+        let invoked_at = self.now();
+        let mut ops = self.ops.lock().unwrap();
+        ops.push(Operation{key: key.into(), op, invoked_at, completed_at: None, outcome: Outcome::Unknown});
+        ops.len() - 1
+    }
+
+    /// Record the completion of the operation bearing the given ID.
+    ///
+    /// An operation which completes with an unknown outcome may still take effect later on, so it
+    /// is treated as though it never completed.
+    pub fn complete(&self, id: usize, outcome: Outcome) {
+        let completed_at = self.now();
+        let mut ops = self.ops.lock().unwrap();
+        if outcome != Outcome::Unknown {
+            ops[id].completed_at = Some(completed_at);
+        }
+        ops[id].outcome = outcome;
+    }
+
+    /// Get a copy of all operations recorded so far.
+    pub fn operations(&self) -> Vec<Operation> {
+        self.ops.lock().unwrap().clone()
+    }
+
+    /// Check that the history recorded so far is linearizable.
+    ///
+    /// Returns an error describing the first register whose history is not linearizable.
+    pub fn check(&self) -> Result<(), String> {
+        let mut registers = BTreeMap::<String, Vec<Operation>>::new();
+        for op in self.operations() {
+            registers.entry(op.key.clone()).or_default().push(op);
+        }
+        for (key, ops) in registers {
+            if !is_linearizable(&ops) {
+                let ops = ops.iter().map(|op| format!("{:?}", op)).collect::<Vec<_>>().join("\n");
+                return Err(format!("history of register {:?} is not linearizable:\n{}", key, ops));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check whether the given history of a single register, initially empty, is linearizable.
+///
+/// Failed operations & reads of unknown outcome had no effect, and are ignored. Writes of unknown
+/// outcome may take effect at any time after their invocation, or never.
+pub fn is_linearizable(history: &[Operation]) -> bool {
+    let ops = history.iter()
+        .filter(|op| !matches!((&op.op, &op.outcome), (_, Outcome::Failed) | (Op::Read, Outcome::Unknown)))
+        .collect::<Vec<_>>();
+    let mut search = Search{ops, visited: HashSet::new()};
+    let linearized = vec![false; search.ops.len()];
+    search.explore(linearized, None)
+}
+
+/// The state of a search for a linearization of a single register's history.
+struct Search<'a> {
+    /// The operations to linearize.
+    ops: Vec<&'a Operation>,
+    /// The pairs of linearized operations & register state which have already been explored.
+    visited: HashSet<(Vec<bool>, Option<String>)>,
+}
+
+impl<'a> Search<'a> {
+    /// Search for a linearization of the remaining operations, given those already linearized &
+    /// the resulting state of the register.
+    fn explore(&mut self, linearized: Vec<bool>, state: Option<String>) -> bool {
+        // Writes of unknown outcome may never take effect, so only completed operations must be linearized.
+        let is_done = self.ops.iter().zip(linearized.iter())
+            .all(|(op, done)| *done || op.completed_at.is_none());
+        if is_done {
+            return true;
+        }
+        if !self.visited.insert((linearized.clone(), state.clone())) {
+            return false;
+        }
+
+        // An operation may only be linearized next if it was invoked before every remaining
+        // operation of known outcome completed.
+        let deadline = self.ops.iter().zip(linearized.iter())
+            .filter(|(_, done)| !**done)
+            .filter_map(|(op, _)| op.completed_at)
+            .min();
+        for (idx, op) in self.ops.clone().into_iter().enumerate() {
+            if linearized[idx] || matches!(deadline, Some(deadline) if op.invoked_at > deadline) {
+                continue;
+            }
+            let next_state = match (&op.op, &op.outcome) {
+                (Op::Write(status), Outcome::Written(previous)) if previous == &state => Some(status.clone()),
+                (Op::Write(status), Outcome::Unknown) => Some(status.clone()),
+                (Op::Read, Outcome::Read(value)) if value == &state => state.clone(),
+                _ => continue,
+            };
+            let mut next = linearized.clone();
+            next[idx] = true;
+            if self.explore(next, next_state) {
+                return true;
+            }
+        }
+        false
+    }
+}
@@ -3,24 +3,38 @@
 #![allow(dead_code)]
 
 use std::collections::{BTreeMap, HashSet};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
 
 use anyhow::{anyhow, Result};
 use async_raft::async_trait::async_trait;
 use async_raft::{Config, NodeId, Raft, RaftMetrics, RaftNetwork, State};
-use async_raft::error::{ChangeConfigError, ClientReadError, ClientWriteError};
+use async_raft::error::{ChangeConfigError, ClientReadError, ClientWriteError, LeadershipTransferError, RaftError, RegisterSessionError};
+use async_raft::event::Event;
+use async_raft::metrics::Wait;
 use async_raft::raft::{AppendEntriesRequest, AppendEntriesResponse};
 use async_raft::raft::{InstallSnapshotRequest, InstallSnapshotResponse};
-use async_raft::raft::{VoteRequest, VoteResponse};
-use async_raft::raft::ClientWriteRequest;
+use async_raft::raft::{PreVoteRequest, PreVoteResponse, VoteRequest, VoteResponse};
+use async_raft::raft::{ReadIndexRequest, ReadIndexResponse, TimeoutNowRequest, TimeoutNowResponse};
+use async_raft::raft::{ClientWriteAppended, ClientWriteRequest, ClientWriteResponse};
 use async_raft::raft::MembershipConfig;
+use async_raft::session::{SessionId, SessionRequest};
 use async_raft::storage::RaftStorage;
+use async_raft::testing::{FaultyNetwork, NetworkFaults};
 use memstore::{MemStore, ClientRequest as MemClientRequest, ClientResponse as MemClientResponse};
-use tokio::sync::RwLock;
+use rand::{Rng, SeedableRng, thread_rng};
+use rand::rngs::StdRng;
+use tokio::sync::{RwLock, broadcast, watch};
+use tokio::time::{Instant, delay_for};
 use tracing_subscriber::prelude::*;
 
+pub mod linearizability;
+
+use linearizability::{History, Op, Outcome};
+
 /// A concrete Raft type used during testing.
-pub type MemRaft = Raft<MemClientRequest, MemClientResponse, RaftRouter, MemStore>;
+pub type MemRaft = Raft<MemClientRequest, MemClientResponse, FaultyNetwork<RaftRouter>, MemStore>;
 
 /// Initialize the tracing system.
 pub fn init_tracing() {
@@ -33,6 +47,45 @@ pub fn init_tracing() {
     tracing::subscriber::set_global_default(subscriber).expect("error setting global tracing subscriber");
 }
 
+/// The environment variable used to set the seed of simulation tests.
+pub const SIMULATION_SEED_VAR: &str = "RAFT_SIM_SEED";
+
+/// Get the seed for a simulation test.
+///
+/// The seed is taken from the `RAFT_SIM_SEED` environment variable if set, else a random seed is
+/// used. The seed is printed, so that a failing run can be reproduced exactly.
+pub fn simulation_seed() -> u64 {
+    let seed = match std::env::var(SIMULATION_SEED_VAR) {
+        Ok(val) => val.parse().expect("expected RAFT_SIM_SEED to be a u64"),
+        Err(_) => thread_rng().gen(),
+    };
+    println!("simulation seed: {} (set {}={} to reproduce)", seed, SIMULATION_SEED_VAR, seed);
+    seed
+}
+
+/// Run the given simulation scenario to completion with the given seed.
+///
+/// The scenario runs on a fresh single-threaded runtime with paused time, so timers complete as
+/// soon as the runtime is idle, and all randomness used by the Raft nodes is seeded. Given the
+/// same seed, a scenario built on `RaftRouter::new_simulated` behaves identically on every run.
+///
+/// This is only available with the `simulation` feature.
+#[cfg(feature="simulation")]
+pub fn run_simulation<F, Fut, T>(seed: u64, scenario: F) -> T
+    where F: FnOnce(u64) -> Fut, Fut: std::future::Future<Output=T>,
+{
+    let mut runtime = tokio::runtime::Builder::new()
+        .basic_scheduler()
+        .enable_all()
+        .build()
+        .expect("failed to build simulation runtime");
+    runtime.block_on(async move {
+        tokio::time::pause();
+        async_raft::rng::seed_thread_rng(seed);
+        scenario(seed).await
+    })
+}
+
 //////////////////////////////////////////////////////////////////////////////////////////////////
 //////////////////////////////////////////////////////////////////////////////////////////////////
 
@@ -44,18 +97,72 @@ pub struct RaftRouter {
     routing_table: RwLock<BTreeMap<NodeId, (MemRaft, Arc<MemStore>)>>,
     /// Nodes which are isolated can neither send nor receive frames.
     isolated_nodes: RwLock<HashSet<NodeId>>,
+    /// The faults injected into the network of every node.
+    faults: NetworkFaults,
+    /// The history of all client writes & reads sent through this router.
+    history: History,
+    /// The number of upcoming InstallSnapshot RPCs whose responses will be lost in transit.
+    lost_snapshot_responses: RwLock<u64>,
+    /// The number of AppendEntries RPCs which have been delivered.
+    append_entries_delivered: AtomicU64,
+    /// The state of the simulated network, if this router is running a simulation.
+    simulation: Option<Simulation>,
+}
+
+/// The state of a simulated network, in which RPCs are delayed by a seeded random latency.
+struct Simulation {
+    /// The RNG used to roll the latency of each RPC.
+    rng: Mutex<StdRng>,
+    /// The instant at which the simulation started.
+    started_at: Instant,
+    /// A trace of every RPC sent over the network, in order.
+    trace: Mutex<Vec<String>>,
 }
 
 impl RaftRouter {
     /// Create a new instance.
     pub fn new(config: Arc<Config>) -> Self {
-        Self{config, routing_table: Default::default(), isolated_nodes: Default::default()}
+        Self{
+            config, routing_table: Default::default(), isolated_nodes: Default::default(), faults: Default::default(),
+            history: Default::default(),
+            lost_snapshot_responses: Default::default(), append_entries_delivered: Default::default(),
+            simulation: None,
+        }
+    }
+
+    /// Create a new instance which simulates network latency using the given seed.
+    ///
+    /// This must be called from within `run_simulation`.
+    pub fn new_simulated(config: Arc<Config>, seed: u64) -> Self {
+        let simulation = Simulation{rng: Mutex::new(StdRng::seed_from_u64(seed)), started_at: Instant::now(), trace: Default::default()};
+        Self{simulation: Some(simulation), ..Self::new(config)}
+    }
+
+    /// Get the trace of every RPC sent over the simulated network so far.
+    pub fn simulation_trace(&self) -> Vec<String> {
+        self.simulation.as_ref().map(|sim| sim.trace.lock().unwrap().clone()).unwrap_or_default()
+    }
+
+    /// Record the given RPC & delay it by a random latency, if this router is running a simulation.
+    ///
+    /// RPCs are delayed before isolation is checked, so that RPCs to isolated nodes still take time.
+    async fn simulate_rpc(&self, kind: &str, from: NodeId, target: NodeId) {
+        let latency = match &self.simulation {
+            Some(sim) => {
+                let elapsed = Instant::now().duration_since(sim.started_at).as_millis();
+                sim.trace.lock().unwrap().push(format!("{}ms {} {}->{}", elapsed, kind, from, target));
+                sim.rng.lock().unwrap().gen_range(1, 10)
+            }
+            None => return,
+        };
+        delay_for(Duration::from_millis(latency)).await;
     }
 
     /// Create and register a new Raft node bearing the given ID.
     pub async fn new_raft_node(self: &Arc<Self>, id: NodeId) {
         let memstore = Arc::new(MemStore::new(id));
-        let node = Raft::new(id, self.config.clone(), self.clone(), memstore.clone());
+        let network = Arc::new(FaultyNetwork::new(self.clone(), self.faults.clone()));
+        let node = Raft::new(id, self.config.clone(), network, memstore.clone());
         let mut rt = self.routing_table.write().await;
         rt.insert(id, (node, memstore));
     }
@@ -68,6 +175,14 @@ impl RaftRouter {
         isolated.remove(&id);
     }
 
+    /// Remove the target node from the routing table, and shut it down.
+    pub async fn shutdown_node(&self, id: NodeId) -> Result<()> {
+        let (node, _) = self.routing_table.write().await.remove(&id)
+            .unwrap_or_else(|| panic!("node '{}' does not exist in routing table", id));
+        node.shutdown().await??;
+        Ok(())
+    }
+
     /// Initialize all nodes based on the config in the routing table.
     pub async fn initialize_from_single_node(&self, node: NodeId) -> Result<()> {
         tracing::info!({node}, "initializing cluster from single node");
@@ -87,6 +202,26 @@ impl RaftRouter {
         self.isolated_nodes.write().await.insert(id);
     }
 
+    /// Get the handle controlling the faults injected into the network of every node.
+    pub fn faults(&self) -> &NetworkFaults {
+        &self.faults
+    }
+
+    /// Get the history of all client writes & reads sent through this router.
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    /// Lose the responses of the next `count` InstallSnapshot RPCs, after they have been delivered.
+    pub async fn lose_snapshot_responses(&self, count: u64) {
+        *self.lost_snapshot_responses.write().await = count;
+    }
+
+    /// Get the number of AppendEntries RPCs which have been delivered so far.
+    pub fn append_entries_delivered(&self) -> u64 {
+        self.append_entries_delivered.load(Ordering::SeqCst)
+    }
+
     /// Get a payload of the latest metrics from each node in the cluster.
     pub async fn latest_metrics(&self) -> Vec<RaftMetrics> {
         let rt = self.routing_table.read().await;
@@ -97,6 +232,24 @@ impl RaftRouter {
         metrics
     }
 
+    /// Get a handle to the metrics channel of the target node.
+    pub async fn metrics(&self, target: NodeId) -> watch::Receiver<RaftMetrics> {
+        let rt = self.routing_table.read().await;
+        rt.get(&target).unwrap_or_else(|| panic!("node '{}' does not exist in routing table", target)).0.metrics()
+    }
+
+    /// Get a handle for waiting until the metrics of the target node satisfy a condition.
+    pub async fn wait(&self, target: NodeId, timeout: Duration) -> Wait {
+        let rt = self.routing_table.read().await;
+        rt.get(&target).unwrap_or_else(|| panic!("node '{}' does not exist in routing table", target)).0.wait(timeout)
+    }
+
+    /// Subscribe to the events emitted by the target node.
+    pub async fn subscribe_events(&self, target: NodeId) -> broadcast::Receiver<Event> {
+        let rt = self.routing_table.read().await;
+        rt.get(&target).unwrap_or_else(|| panic!("node '{}' does not exist in routing table", target)).0.subscribe_events()
+    }
+
     /// Get the ID of the current leader.
     pub async fn leader(&self) -> Option<NodeId> {
         let isolated = self.isolated_nodes.read().await;
@@ -124,19 +277,73 @@ impl RaftRouter {
         node.0.add_non_voter(target).await
     }
 
+    pub async fn add_witness(&self, leader: NodeId, target: NodeId) -> Result<(), ChangeConfigError> {
+        let rt = self.routing_table.read().await;
+        let node = rt.get(&leader).expect(&format!("node with ID {} does not exist", leader));
+        node.0.add_witness(target).await
+    }
+
+    pub async fn remove_non_voter(&self, leader: NodeId, target: NodeId) -> Result<(), ChangeConfigError> {
+        let rt = self.routing_table.read().await;
+        let node = rt.get(&leader).expect(&format!("node with ID {} does not exist", leader));
+        node.0.remove_non_voter(target).await
+    }
+
+    /// Get the time elapsed since each replication target of the target node last acknowledged it.
+    pub async fn replication_ack_ages(&self, target: NodeId) -> Result<BTreeMap<NodeId, Duration>, RaftError> {
+        let rt = self.routing_table.read().await;
+        let node = rt.get(&target).expect(&format!("node with ID {} does not exist", target));
+        node.0.replication_ack_ages().await
+    }
+
+    /// Wake the target node from quiescence.
+    pub async fn wake(&self, target: NodeId) -> Result<(), RaftError> {
+        let rt = self.routing_table.read().await;
+        let node = rt.get(&target).expect(&format!("node with ID {} does not exist", target));
+        node.0.wake().await
+    }
+
+    /// Get a handle to the storage of the target node.
+    pub async fn storage(&self, id: NodeId) -> Arc<MemStore> {
+        let rt = self.routing_table.read().await;
+        rt.get(&id).expect(&format!("node with ID {} does not exist", id)).1.clone()
+    }
+
     pub async fn change_membership(&self, leader: NodeId, members: HashSet<NodeId>) -> Result<(), ChangeConfigError> {
         let rt = self.routing_table.read().await;
         let node = rt.get(&leader).expect(&format!("node with ID {} does not exist", leader));
         node.0.change_membership(members).await
     }
 
+    /// Request that the given leader transfer leadership of the cluster to the target node.
+    pub async fn transfer_leadership(&self, leader: NodeId, target: NodeId) -> Result<(), LeadershipTransferError> {
+        let rt = self.routing_table.read().await;
+        let node = rt.get(&leader).expect(&format!("node with ID {} does not exist", leader));
+        node.0.transfer_leadership(target).await
+    }
+
     /// Send a client read request to the target node.
-    pub async fn client_read(&self, target: NodeId) -> Result<(), ClientReadError> {
+    pub async fn client_read(&self, target: NodeId) -> Result<u64, ClientReadError> {
         let rt = self.routing_table.read().await;
         let node = rt.get(&target).expect(&format!("node with ID {} does not exist", target));
         node.0.client_read().await
     }
 
+    /// Send a client read request to the target node, then read the status of the given client
+    /// from the target node's state machine. The read is recorded in the history.
+    pub async fn client_read_status(&self, target: NodeId, client_id: &str) -> Result<Option<String>, ClientReadError> {
+        let op = self.history.invoke(client_id, Op::Read);
+        let res = match self.client_read(target).await {
+            Ok(_) => Ok(self.storage(target).await.get_state_machine().await.client_status.get(client_id).cloned()),
+            Err(err) => Err(err),
+        };
+        self.history.complete(op, match &res {
+            Ok(status) => Outcome::Read(status.clone()),
+            Err(_) => Outcome::Failed,
+        });
+        res
+    }
+
     /// Send a client request to the target node, causing test failure on error.
     pub async fn client_request(&self, target: NodeId, client_id: &str, serial: u64) {
         let req = MemClientRequest{client: client_id.into(), serial, status: format!("request-{}", serial)};
@@ -153,10 +360,74 @@ impl RaftRouter {
         }
     }
 
-    async fn send_client_request(&self, target: NodeId, req: MemClientRequest) -> std::result::Result<MemClientResponse, ClientWriteError<MemClientRequest>> {
+    /// Send a batch of client requests to the target node, returning the response to each request.
+    pub async fn client_request_batch(
+        &self, target: NodeId, client_id: &str, count: usize,
+    ) -> Vec<std::result::Result<ClientWriteResponse<MemClientResponse>, ClientWriteError<MemClientRequest>>> {
+        let ops = (0..count)
+            .map(|idx| self.history.invoke(client_id, Op::Write(format!("request-{}", idx))))
+            .collect::<Vec<_>>();
+        let rpcs = (0..count)
+            .map(|idx| ClientWriteRequest::new(MemClientRequest{client: client_id.into(), serial: idx as u64, status: format!("request-{}", idx)}))
+            .collect();
+        let rt = self.routing_table.read().await;
+        let node = rt.get(&target).expect(&format!("node '{}' does not exist in routing table", target));
+        let res = node.0.client_write_batch(rpcs).await;
+        for (op, res) in ops.into_iter().zip(res.iter()) {
+            self.history.complete(op, write_outcome(res.as_ref().map(|res| &res.data)));
+        }
+        res
+    }
+
+    /// Send a client write request to the target node. The write is recorded in the history.
+    pub async fn send_client_request(&self, target: NodeId, req: MemClientRequest) -> std::result::Result<MemClientResponse, ClientWriteError<MemClientRequest>> {
+        let op = self.history.invoke(&req.client, Op::Write(req.status.clone()));
+        let rt = self.routing_table.read().await;
+        let node = rt.get(&target).expect(&format!("node '{}' does not exist in routing table", target));
+        let res = node.0.client_write(ClientWriteRequest::new(req)).await.map(|res| res.data);
+        self.history.complete(op, write_outcome(res.as_ref()));
+        res
+    }
+
+    /// Send a client write request to the target node, waiting for each stage of the write in
+    /// turn. The write is recorded in the history.
+    pub async fn send_staged_client_request(&self, target: NodeId, req: MemClientRequest) -> (
+        Option<ClientWriteAppended>, Option<u64>, std::result::Result<ClientWriteResponse<MemClientResponse>, ClientWriteError<MemClientRequest>>,
+    ) {
+        let op = self.history.invoke(&req.client, Op::Write(req.status.clone()));
+        let rt = self.routing_table.read().await;
+        let node = rt.get(&target).expect(&format!("node '{}' does not exist in routing table", target));
+        let handle = node.0.client_write_staged(ClientWriteRequest::new(req)).await;
+        drop(rt);
+        let (appended, committed, res) = match handle {
+            // The handle does not borrow the node, so its stages may be awaited on another task.
+            Ok(mut handle) => tokio::spawn(async move {
+                (handle.appended().await, handle.committed().await, handle.applied().await)
+            }).await.expect("staged client write task panicked"),
+            Err(err) => (None, None, Err(err)),
+        };
+        self.history.complete(op, write_outcome(res.as_ref().map(|res| &res.data)));
+        (appended, committed, res)
+    }
+
+    /// Register a new client session on the target node.
+    pub async fn register_client_session(&self, target: NodeId) -> std::result::Result<SessionId, RegisterSessionError> {
+        let rt = self.routing_table.read().await;
+        let node = rt.get(&target).expect(&format!("node '{}' does not exist in routing table", target));
+        node.0.register_client_session().await
+    }
+
+    /// Send a client write request to the target node as part of the given client session. The
+    /// write is recorded in the history.
+    pub async fn send_session_request(
+        &self, target: NodeId, req: MemClientRequest, session: SessionRequest,
+    ) -> std::result::Result<ClientWriteResponse<MemClientResponse>, ClientWriteError<MemClientRequest>> {
+        let op = self.history.invoke(&req.client, Op::Write(req.status.clone()));
         let rt = self.routing_table.read().await;
         let node = rt.get(&target).expect(&format!("node '{}' does not exist in routing table", target));
-        node.0.client_write(ClientWriteRequest::new(req)).await.map(|res| res.data)
+        let res = node.0.client_write(ClientWriteRequest::new_in_session(req, session)).await;
+        self.history.complete(op, write_outcome(res.as_ref().map(|res| &res.data)));
+        res
     }
 
     //////////////////////////////////////////////////////////////////////////////////////////////
@@ -263,36 +534,95 @@ impl RaftRouter {
 impl RaftNetwork<MemClientRequest> for RaftRouter {
     /// Send an AppendEntries RPC to the target Raft node (§5).
     async fn append_entries(&self, target: u64, rpc: AppendEntriesRequest<MemClientRequest>) -> Result<AppendEntriesResponse> {
+        self.simulate_rpc("append_entries", rpc.leader_id, target).await;
         let rt = self.routing_table.read().await;
         let isolated = self.isolated_nodes.read().await;
-        let addr = rt.get(&target).expect("target node not found in routing table");
+        let addr = rt.get(&target).ok_or_else(|| anyhow!("target node not found in routing table"))?;
         if isolated.contains(&target) || isolated.contains(&rpc.leader_id) {
             return Err(anyhow!("target node is isolated"));
         }
+        self.append_entries_delivered.fetch_add(1, Ordering::SeqCst);
         Ok(addr.0.append_entries(rpc).await?)
     }
 
     /// Send an InstallSnapshot RPC to the target Raft node (§7).
     async fn install_snapshot(&self, target: u64, rpc: InstallSnapshotRequest) -> Result<InstallSnapshotResponse> {
+        self.simulate_rpc("install_snapshot", rpc.leader_id, target).await;
         let rt = self.routing_table.read().await;
         let isolated = self.isolated_nodes.read().await;
-        let addr = rt.get(&target).expect("target node not found in routing table");
+        let addr = rt.get(&target).ok_or_else(|| anyhow!("target node not found in routing table"))?;
         if isolated.contains(&target) || isolated.contains(&rpc.leader_id) {
             return Err(anyhow!("target node is isolated"));
         }
-        Ok(addr.0.install_snapshot(rpc).await?)
+        let res = addr.0.install_snapshot(rpc).await?;
+        let mut lost = self.lost_snapshot_responses.write().await;
+        if *lost > 0 {
+            *lost -= 1;
+            return Err(anyhow!("response lost in transit"));
+        }
+        Ok(res)
     }
 
     /// Send a RequestVote RPC to the target Raft node (§5).
     async fn vote(&self, target: u64, rpc: VoteRequest) -> Result<VoteResponse> {
+        self.simulate_rpc("vote", rpc.candidate_id, target).await;
         let rt = self.routing_table.read().await;
         let isolated = self.isolated_nodes.read().await;
-        let addr = rt.get(&target).expect("target node not found in routing table");
+        let addr = rt.get(&target).ok_or_else(|| anyhow!("target node not found in routing table"))?;
         if isolated.contains(&target) || isolated.contains(&rpc.candidate_id) {
             return Err(anyhow!("target node is isolated"));
         }
         Ok(addr.0.vote(rpc).await?)
     }
+
+    /// Send a PreVote RPC to the target Raft node (§9.6 of the Raft thesis).
+    async fn pre_vote(&self, target: u64, rpc: PreVoteRequest) -> Result<PreVoteResponse> {
+        self.simulate_rpc("pre_vote", rpc.candidate_id, target).await;
+        let rt = self.routing_table.read().await;
+        let isolated = self.isolated_nodes.read().await;
+        let addr = rt.get(&target).ok_or_else(|| anyhow!("target node not found in routing table"))?;
+        if isolated.contains(&target) || isolated.contains(&rpc.candidate_id) {
+            return Err(anyhow!("target node is isolated"));
+        }
+        Ok(addr.0.pre_vote(rpc).await?)
+    }
+
+    /// Send a TimeoutNow RPC to the target Raft node (§3.10 of the Raft thesis).
+    async fn timeout_now(&self, target: u64, rpc: TimeoutNowRequest) -> Result<TimeoutNowResponse> {
+        self.simulate_rpc("timeout_now", rpc.leader_id, target).await;
+        let rt = self.routing_table.read().await;
+        let isolated = self.isolated_nodes.read().await;
+        let addr = rt.get(&target).ok_or_else(|| anyhow!("target node not found in routing table"))?;
+        if isolated.contains(&target) || isolated.contains(&rpc.leader_id) {
+            return Err(anyhow!("target node is isolated"));
+        }
+        Ok(addr.0.timeout_now(rpc).await?)
+    }
+
+    /// Send a ReadIndex RPC to the target Raft node (§6.4 of the Raft thesis).
+    async fn read_index(&self, target: u64, rpc: ReadIndexRequest) -> Result<ReadIndexResponse> {
+        self.simulate_rpc("read_index", rpc.requester_id, target).await;
+        let rt = self.routing_table.read().await;
+        let isolated = self.isolated_nodes.read().await;
+        let addr = rt.get(&target).ok_or_else(|| anyhow!("target node not found in routing table"))?;
+        if isolated.contains(&target) || isolated.contains(&rpc.requester_id) {
+            return Err(anyhow!("target node is isolated"));
+        }
+        Ok(addr.0.read_index(rpc).await?)
+    }
+}
+
+/// Get the outcome of a client write from its response, for recording it in a history.
+fn write_outcome(res: std::result::Result<&MemClientResponse, &ClientWriteError<MemClientRequest>>) -> Outcome {
+    match res {
+        Ok(MemClientResponse(Ok(previous))) => Outcome::Written(previous.clone()),
+        // The write was rejected before being appended to the log, or was never applied.
+        Err(ClientWriteError::ForwardToLeader(..)) | Err(ClientWriteError::Overloaded(_)) | Err(ClientWriteError::UnknownSession(_)) => Outcome::Failed,
+        // The write may have been appended to the log, and may yet be committed.
+        Ok(MemClientResponse(Err(_))) | Err(ClientWriteError::RaftError(_)) => Outcome::Unknown,
+        // The write was applied by an earlier attempt, whose response is not known.
+        Err(ClientWriteError::ResponseDiscarded(..)) => Outcome::Unknown,
+    }
 }
 
 pub enum ValueTest<T> {
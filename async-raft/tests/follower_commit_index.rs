@@ -0,0 +1,82 @@
+mod fixtures;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_raft::{Config, RaftNetwork};
+use async_raft::raft::{AppendEntriesRequest, Entry, EntryPayload};
+use tokio::time::delay_for;
+
+use memstore::ClientRequest as MemClientRequest;
+
+use fixtures::RaftRouter;
+
+/// Follower commit index test.
+///
+/// What does this test do?
+///
+/// - brings 1 node online, which is replicated to directly using AppendEntries RPCs.
+/// - appends 3 entries from a leader of term 1, of which only the first is committed.
+/// - sends a heartbeat from a leader of term 2 which has not yet found a point of agreement with
+///   the node, carrying a commit index which covers all 3 entries.
+/// - asserts that only the first entry is applied, as the others may be overwritten by the new leader.
+/// - appends an entry from the leader of term 2, which replaces the second entry.
+/// - asserts that the commit index is advanced only through the new entry, the last one known to
+///   match the leader's log.
+///
+/// RUST_LOG=async_raft,memstore,follower_commit_index=trace cargo test -p async-raft --test follower_commit_index
+#[tokio::test(core_threads=4)]
+async fn follower_commit_index() -> Result<()> {
+    fixtures::init_tracing();
+
+    // Setup test dependencies.
+    let config = Arc::new(Config::build("test".into()).validate().expect("failed to build Raft config"));
+    let router = Arc::new(RaftRouter::new(config.clone()));
+    router.new_raft_node(0).await;
+
+    delay_for(Duration::from_secs(1)).await;
+    router.assert_pristine_cluster().await;
+
+    // Append 3 entries from the leader of term 1, of which only the first is committed.
+    tracing::info!("--- appending entries from leader of term 1");
+    let res = router.append_entries(0, AppendEntriesRequest{
+        term: 1, leader_id: 1, prev_log_index: 0, prev_log_term: 0,
+        entries: (1..=3).map(|index| blank(index, 1)).collect(), leader_commit: 1, quiesce: false,
+    }).await?;
+    assert!(res.success, "expected entries from leader of term 1 to be appended");
+
+    // Heartbeat from the leader of term 2, which does not yet know which entries match its log.
+    tracing::info!("--- sending heartbeat from leader of term 2");
+    let res = router.append_entries(0, AppendEntriesRequest{
+        term: 2, leader_id: 2, prev_log_index: 0, prev_log_term: 0,
+        entries: vec![], leader_commit: 3, quiesce: false,
+    }).await?;
+    assert!(res.success, "expected heartbeat from leader of term 2 to succeed");
+
+    delay_for(Duration::from_millis(500)).await;
+    let metrics = router.latest_metrics().await;
+    let node = metrics.iter().find(|node| node.id == 0).expect("expected to find metrics for node 0");
+    assert_eq!(node.last_log_index, 3, "expected node 0 to hold 3 entries");
+    assert_eq!(node.last_applied, 1, "expected node 0 to only apply the entry committed by the leader of term 1");
+
+    // Append an entry from the leader of term 2, which replaces the node's second entry.
+    tracing::info!("--- appending entry from leader of term 2");
+    let res = router.append_entries(0, AppendEntriesRequest{
+        term: 2, leader_id: 2, prev_log_index: 1, prev_log_term: 1,
+        entries: vec![blank(2, 2)], leader_commit: 3, quiesce: false,
+    }).await?;
+    assert!(res.success, "expected entry from leader of term 2 to be appended");
+
+    delay_for(Duration::from_millis(500)).await;
+    let metrics = router.latest_metrics().await;
+    let node = metrics.iter().find(|node| node.id == 0).expect("expected to find metrics for node 0");
+    assert_eq!(node.last_log_index, 2, "expected node 0 to hold 2 entries");
+    assert_eq!(node.last_applied, 2, "expected node 0 to apply the entries known to match the leader of term 2");
+
+    Ok(())
+}
+
+fn blank(index: u64, term: u64) -> Entry<MemClientRequest> {
+    Entry{index, term, payload: EntryPayload::Blank}
+}
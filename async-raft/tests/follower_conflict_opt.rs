@@ -0,0 +1,67 @@
+mod fixtures;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_raft::{Config, RaftNetwork};
+use async_raft::raft::{AppendEntriesRequest, Entry, EntryPayload};
+use memstore::ClientRequest as MemClientRequest;
+use tokio::time::delay_for;
+
+use fixtures::RaftRouter;
+
+/// Follower conflict opt test.
+///
+/// What does this test do?
+///
+/// - brings 1 node online, which is replicated to directly using AppendEntries RPCs.
+/// - appends 3 entries from a leader of term 1, then 3 more from a leader of term 2.
+/// - sends an AppendEntries RPC from a leader of term 3, whose `prev_log_index` points into the
+///   entries of term 2 but whose `prev_log_term` is 3.
+/// - asserts that the node looks up the entry at `prev_log_index` & rejects the RPC, reporting a
+///   conflict opt at the last entry before the conflicting term, rather than its last log entry
+///   which is ahead of the RPC's `prev_log_index`.
+///
+/// RUST_LOG=async_raft,memstore,follower_conflict_opt=trace cargo test -p async-raft --test follower_conflict_opt
+#[tokio::test(core_threads=4)]
+async fn follower_conflict_opt() -> Result<()> {
+    fixtures::init_tracing();
+
+    // Setup test dependencies.
+    let config = Arc::new(Config::build("test".into()).validate().expect("failed to build Raft config"));
+    let router = Arc::new(RaftRouter::new(config.clone()));
+    router.new_raft_node(0).await;
+
+    delay_for(Duration::from_secs(1)).await;
+    router.assert_pristine_cluster().await;
+
+    // Append 3 entries from the leader of term 1, then 3 entries from the leader of term 2.
+    tracing::info!("--- appending entries from leaders of terms 1 & 2");
+    let res = router.append_entries(0, AppendEntriesRequest{
+        term: 1, leader_id: 1, prev_log_index: 0, prev_log_term: 0,
+        entries: (1..=3).map(|index| blank(index, 1)).collect(), leader_commit: 0, quiesce: false,
+    }).await?;
+    assert!(res.success, "expected entries from leader of term 1 to be appended");
+    let res = router.append_entries(0, AppendEntriesRequest{
+        term: 2, leader_id: 2, prev_log_index: 3, prev_log_term: 1,
+        entries: (4..=6).map(|index| blank(index, 2)).collect(), leader_commit: 0, quiesce: false,
+    }).await?;
+    assert!(res.success, "expected entries from leader of term 2 to be appended");
+
+    // Send an RPC from the leader of term 3, whose log conflicts with the entries of term 2.
+    tracing::info!("--- sending conflicting RPC from leader of term 3");
+    let res = router.append_entries(0, AppendEntriesRequest{
+        term: 3, leader_id: 1, prev_log_index: 5, prev_log_term: 3,
+        entries: vec![], leader_commit: 0, quiesce: false,
+    }).await?;
+    assert!(!res.success, "expected conflicting RPC to be rejected");
+    let conflict = res.conflict_opt.expect("expected the rejection to carry a conflict opt");
+    assert_eq!((conflict.term, conflict.index), (1, 3), "expected conflict opt at the last entry of term 1");
+
+    Ok(())
+}
+
+fn blank(index: u64, term: u64) -> Entry<MemClientRequest> {
+    Entry{index, term, payload: EntryPayload::Blank}
+}
@@ -0,0 +1,76 @@
+mod fixtures;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_raft::{Config, RaftNetwork};
+use async_raft::raft::{AppendEntriesRequest, Entry, EntryPayload};
+use memstore::ClientRequest as MemClientRequest;
+use tokio::time::delay_for;
+
+use fixtures::RaftRouter;
+
+/// Follower log truncation test.
+///
+/// What does this test do?
+///
+/// - brings 1 node online, which is replicated to directly using AppendEntries RPCs.
+/// - appends 3 entries from the leader.
+/// - delivers a delayed AppendEntries RPC from the same leader, which only carries the second entry.
+/// - asserts that the third entry is kept, as it does not conflict with the RPC's entries.
+/// - delivers an AppendEntries RPC from a leader of term 2, whose entry conflicts with the second entry.
+/// - asserts that the conflicting entry & all that follow it are replaced by the RPC's entry.
+///
+/// RUST_LOG=async_raft,memstore,follower_log_truncation=trace cargo test -p async-raft --test follower_log_truncation
+#[tokio::test(core_threads=4)]
+async fn follower_log_truncation() -> Result<()> {
+    fixtures::init_tracing();
+
+    // Setup test dependencies.
+    let config = Arc::new(Config::build("test".into()).validate().expect("failed to build Raft config"));
+    let router = Arc::new(RaftRouter::new(config.clone()));
+    router.new_raft_node(0).await;
+
+    delay_for(Duration::from_secs(1)).await;
+    router.assert_pristine_cluster().await;
+
+    // Append 3 entries from the leader.
+    tracing::info!("--- appending entries from leader");
+    let res = router.append_entries(0, AppendEntriesRequest{
+        term: 1, leader_id: 1, prev_log_index: 0, prev_log_term: 0,
+        entries: (1..=3).map(|index| blank(index, 1)).collect(), leader_commit: 0, quiesce: false,
+    }).await?;
+    assert!(res.success, "expected entries from leader to be appended");
+
+    // Deliver a delayed RPC from the same leader, which was sent before the third entry was appended.
+    tracing::info!("--- delivering delayed RPC from leader");
+    let res = router.append_entries(0, AppendEntriesRequest{
+        term: 1, leader_id: 1, prev_log_index: 1, prev_log_term: 1,
+        entries: vec![blank(2, 1)], leader_commit: 0, quiesce: false,
+    }).await?;
+    assert!(res.success, "expected delayed RPC from leader to succeed");
+
+    delay_for(Duration::from_millis(500)).await;
+    let metrics = router.latest_metrics().await;
+    let node = metrics.iter().find(|node| node.id == 0).expect("expected to find metrics for node 0");
+    assert_eq!(node.last_log_index, 3, "expected node 0 to keep all 3 entries");
+
+    // Deliver an RPC from the leader of term 2, whose entry conflicts with the second entry.
+    tracing::info!("--- delivering conflicting RPC from leader of term 2");
+    let res = router.append_entries(0, AppendEntriesRequest{
+        term: 2, leader_id: 2, prev_log_index: 1, prev_log_term: 1,
+        entries: vec![blank(2, 2)], leader_commit: 0, quiesce: false,
+    }).await?;
+    assert!(res.success, "expected conflicting RPC from leader of term 2 to succeed");
+
+    delay_for(Duration::from_millis(500)).await;
+    let terms: Vec<_> = router.storage(0).await.get_log().await.values().map(|entry| (entry.index, entry.term)).collect();
+    assert_eq!(terms, vec![(1, 1), (2, 2)], "expected node 0 to replace its log from the conflicting entry onwards");
+
+    Ok(())
+}
+
+fn blank(index: u64, term: u64) -> Entry<MemClientRequest> {
+    Entry{index, term, payload: EntryPayload::Blank}
+}
@@ -0,0 +1,69 @@
+mod fixtures;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_raft::{Config, State};
+use async_raft::error::ClientReadError;
+use tokio::time::delay_for;
+
+use fixtures::RaftRouter;
+
+/// Follower read tests.
+///
+/// What does this test do?
+///
+/// - create a stable 3-node cluster.
+/// - write some data to the leader, then immediately call the client_read interface on the
+///   followers, and assert that the returned read index covers the writes & has been applied to
+///   the follower's state machine.
+/// - isolate the leader, and assert that client reads on the followers no longer succeed.
+///
+/// RUST_LOG=async_raft,memstore,follower_reads=trace cargo test -p async-raft --test follower_reads
+#[tokio::test(core_threads=4)]
+async fn follower_reads() -> Result<()> {
+    fixtures::init_tracing();
+
+    // Setup test dependencies.
+    let config = Arc::new(Config::build("test".into()).validate().expect("failed to build Raft config"));
+    let router = Arc::new(RaftRouter::new(config.clone()));
+    router.new_raft_node(0).await;
+    router.new_raft_node(1).await;
+    router.new_raft_node(2).await;
+
+    // Assert all nodes are in non-voter state & have no entries.
+    delay_for(Duration::from_secs(10)).await;
+    router.assert_pristine_cluster().await;
+
+    // Initialize the cluster, then assert that a stable cluster was formed & held.
+    tracing::info!("--- initializing cluster");
+    router.initialize_from_single_node(0).await?;
+    delay_for(Duration::from_secs(10)).await;
+    router.assert_stable_cluster(Some(1), Some(1)).await;
+    let leader = router.leader().await.expect("leader not found");
+
+    // Write some data, then read from the followers without waiting for replication.
+    tracing::info!("--- reading from followers after writes");
+    router.client_request_many(leader, "0", 50).await;
+    for id in (0..3).filter(|id| *id != leader) {
+        let read_index = router.client_read(id).await.expect(&format!("expected client_read to succeed for follower {}", id));
+        assert!(read_index >= 51, "expected read index of at least 51 on follower {}, got {}", id, read_index);
+        let metrics = router.latest_metrics().await.into_iter().find(|node| node.id == id).expect("node metrics not found");
+        assert_eq!(metrics.state, State::Follower, "expected node {} to be a follower", id);
+        assert!(metrics.last_applied >= read_index,
+            "expected node {} to have applied the read index {}, got {}", id, read_index, metrics.last_applied);
+    }
+
+    // Isolate the leader, and assert that the followers can no longer serve reads.
+    tracing::info!("--- isolating leader");
+    router.isolate_node(leader).await;
+    for id in (0..3).filter(|id| *id != leader) {
+        match router.client_read(id).await {
+            Ok(read_index) => panic!("expected client_read on follower {} to fail, got read index {}", id, read_index),
+            Err(ClientReadError::RaftError(_)) | Err(ClientReadError::ForwardToLeader(_)) => (),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,81 @@
+mod fixtures;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_raft::{Config, State};
+use async_raft::error::LeadershipTransferError;
+use tokio::time::delay_for;
+
+use fixtures::RaftRouter;
+
+/// Leadership transfer test.
+///
+/// What does this test do?
+///
+/// - brings 3 nodes online & initializes the cluster.
+/// - writes some data to the leader.
+/// - asserts that invalid leadership transfer targets are rejected.
+/// - transfers leadership to one of the followers.
+/// - asserts that the target became leader in the next term, that the old leader stepped down
+///   & rejects further transfer requests, and that the new leader is able to accept writes.
+///
+/// RUST_LOG=async_raft,memstore,leadership_transfer=trace cargo test -p async-raft --test leadership_transfer
+#[tokio::test(core_threads=4)]
+async fn leadership_transfer() -> Result<()> {
+    fixtures::init_tracing();
+
+    // Setup test dependencies.
+    let config = Arc::new(Config::build("test".into()).validate().expect("failed to build Raft config"));
+    let router = Arc::new(RaftRouter::new(config.clone()));
+    router.new_raft_node(0).await;
+    router.new_raft_node(1).await;
+    router.new_raft_node(2).await;
+
+    // Assert all nodes are in non-voter state & have no entries.
+    delay_for(Duration::from_secs(3)).await;
+    router.assert_pristine_cluster().await;
+
+    // Initialize the cluster, then assert that a stable cluster was formed & held.
+    tracing::info!("--- initializing cluster");
+    router.initialize_from_single_node(0).await?;
+    delay_for(Duration::from_secs(3)).await;
+    router.assert_stable_cluster(Some(1), Some(1)).await;
+
+    // Write some data to the cluster.
+    let orig_leader = router.leader().await.expect("expected the cluster to have a leader");
+    router.client_request_many(orig_leader, "0", 100).await;
+    delay_for(Duration::from_secs(1)).await; // Give time for followers to apply the new entries.
+    router.assert_stable_cluster(Some(1), Some(101)).await;
+
+    // Assert that invalid targets are rejected.
+    let res = router.transfer_leadership(orig_leader, orig_leader).await;
+    assert!(matches!(res, Err(LeadershipTransferError::InvalidTarget)), "expected InvalidTarget error, got {:?}", res);
+    let res = router.transfer_leadership(orig_leader, 10).await;
+    assert!(matches!(res, Err(LeadershipTransferError::InvalidTarget)), "expected InvalidTarget error, got {:?}", res);
+
+    // Transfer leadership to one of the followers.
+    let target = (0..3).find(|id| *id != orig_leader).expect("expected to find a follower");
+    tracing::info!("--- transferring leadership from {} to {}", orig_leader, target);
+    router.transfer_leadership(orig_leader, target).await?;
+    delay_for(Duration::from_secs(1)).await; // Give time for the new leader's initial entry to be replicated.
+
+    // Assert that the target is now the leader & that the old leader stepped down.
+    router.assert_stable_cluster(Some(2), Some(102)).await;
+    assert_eq!(router.leader().await, Some(target), "expected target node to be the new leader");
+    {
+        let metrics = router.latest_metrics().await.into_iter().find(|node| node.id == orig_leader)
+            .expect("expected to find metrics on original leader node");
+        assert_eq!(metrics.state, State::Follower, "expected old leader to have stepped down");
+    }
+    let res = router.transfer_leadership(orig_leader, target).await;
+    assert!(matches!(res, Err(LeadershipTransferError::NodeNotLeader(Some(id))) if id == target), "expected NodeNotLeader error, got {:?}", res);
+
+    // Assert that the new leader is able to accept writes.
+    router.client_request_many(target, "1", 100).await;
+    delay_for(Duration::from_secs(1)).await;
+    router.assert_stable_cluster(Some(2), Some(202)).await;
+
+    Ok(())
+}
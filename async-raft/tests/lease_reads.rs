@@ -0,0 +1,60 @@
+mod fixtures;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_raft::Config;
+use tokio::time::delay_for;
+
+use fixtures::RaftRouter;
+
+/// Leader lease client read tests.
+///
+/// What does this test do?
+///
+/// - create a stable 3-node cluster with leader leases enabled.
+/// - isolate both followers from the leader.
+/// - call the client_read interface on the leader, and assert success, as it still holds its lease.
+/// - wait for the lease to expire, call the client_read interface on the leader again, and assert
+///   failure, as leadership can no longer be confirmed.
+///
+/// RUST_LOG=async_raft,memstore,lease_reads=trace cargo test -p async-raft --test lease_reads
+#[tokio::test(core_threads=4)]
+async fn lease_reads() -> Result<()> {
+    fixtures::init_tracing();
+
+    // Setup test dependencies.
+    let config = Arc::new(Config::build("test".into())
+        .election_timeout_min(1000).election_timeout_max(1500)
+        .leader_lease(true).clock_drift_bound(100)
+        .validate().expect("failed to build Raft config"));
+    let router = Arc::new(RaftRouter::new(config.clone()));
+    router.new_raft_node(0).await;
+    router.new_raft_node(1).await;
+    router.new_raft_node(2).await;
+
+    // Assert all nodes are in non-voter state & have no entries.
+    delay_for(Duration::from_secs(3)).await;
+    router.assert_pristine_cluster().await;
+
+    // Initialize the cluster, then assert that a stable cluster was formed & held.
+    tracing::info!("--- initializing cluster");
+    router.initialize_from_single_node(0).await?;
+    delay_for(Duration::from_secs(5)).await;
+    router.assert_stable_cluster(Some(1), Some(1)).await;
+
+    // Isolate the followers, and assert that the leader can still serve reads under its lease.
+    let leader = router.leader().await.expect("leader not found");
+    router.client_read(leader).await.expect("expected client_read to succeed for cluster leader");
+    for id in (0..3).filter(|id| *id != leader) {
+        router.isolate_node(id).await;
+    }
+    router.client_read(leader).await.expect("expected client_read to succeed for leader holding a lease");
+
+    // Once the lease has expired, reads must fail as leadership can not be confirmed.
+    delay_for(Duration::from_secs(2)).await;
+    router.client_read(leader).await.expect_err("expected client_read to fail once the lease has expired");
+
+    Ok(())
+}
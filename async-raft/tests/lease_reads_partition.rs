@@ -0,0 +1,85 @@
+mod fixtures;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_raft::{Config, State};
+
+use fixtures::RaftRouter;
+
+/// Leader lease client reads with a campaigning follower test.
+///
+/// What does this test do?
+///
+/// - create a stable 3-node cluster with leader leases enabled.
+/// - partition the leader from one follower, so that the follower campaigns while the leader
+///   still holds its lease through the other follower.
+/// - assert that the other follower refuses to vote, so that the leader remains the only leader &
+///   may keep serving reads under its lease.
+/// - partition the leader from the other follower too, wait for a new leader to be elected & to
+///   commit a write, then assert that the old leader refuses to serve a read, as its lease must
+///   have expired before a new leader could be elected.
+///
+/// RUST_LOG=async_raft,memstore,lease_reads_partition=trace cargo test -p async-raft --test lease_reads_partition
+#[tokio::test(core_threads=4)]
+async fn lease_reads_partition() -> Result<()> {
+    fixtures::init_tracing();
+
+    // Setup test dependencies.
+    let config = Arc::new(Config::build("test".into())
+        .election_timeout_min(1000).election_timeout_max(1500)
+        .leader_lease(true).clock_drift_bound(100)
+        .validate().expect("failed to build Raft config"));
+    let router = Arc::new(RaftRouter::new(config.clone()));
+    let wait_timeout = Duration::from_secs(10);
+    for id in 0..3 {
+        router.new_raft_node(id).await;
+    }
+
+    // Initialize the cluster.
+    tracing::info!("--- initializing cluster");
+    router.initialize_from_single_node(0).await?;
+    let metrics = router.wait(0, wait_timeout).await
+        .metrics(|metrics| metrics.current_leader.is_some(), "a known leader").await?;
+    let (leader, term) = (metrics.current_leader.expect("expected node 0 to know the leader"), metrics.current_term);
+    // Every node must also hold the initial config entry, else a follower which has only heard a
+    // heartbeat would still campaign as the sole member of its initial config.
+    for id in 0..3 {
+        router.wait(id, wait_timeout).await.current_leader(leader).await?;
+        router.wait(id, wait_timeout).await.log_index(1).await?;
+    }
+    let mut followers = (0..3).filter(|id| *id != leader);
+    let (campaigner, voter) = (followers.next().unwrap(), followers.next().unwrap());
+
+    // Partition the leader from one follower, and wait for that follower to campaign.
+    tracing::info!("--- partitioning leader {} from follower {}", leader, campaigner);
+    router.faults().partition(leader, campaigner);
+    router.wait(campaigner, wait_timeout).await
+        .metrics(move |metrics| metrics.current_term > term, "a campaign for a new term").await?;
+
+    // The other follower has heard from the leader within its election timeout, so it must refuse
+    // to vote, leaving the leader free to serve reads under its lease.
+    let metrics = router.latest_metrics().await;
+    let voter_metrics = metrics.iter().find(|node| node.id == voter).expect("expected metrics of the voter");
+    assert_eq!(voter_metrics.current_term, term, "expected follower {} to refuse the campaign", voter);
+    assert_eq!(voter_metrics.current_leader, Some(leader), "expected follower {} to still follow the leader", voter);
+    for node in metrics.iter().filter(|node| node.id != leader) {
+        assert_ne!(node.state, State::Leader, "expected node {} not to have been elected", node.id);
+    }
+    router.client_read(leader).await.expect("expected client_read to succeed for the only leader");
+
+    // Partition the leader from the other follower too, and wait for a new leader to commit a write.
+    tracing::info!("--- partitioning leader {} from follower {}", leader, voter);
+    router.faults().partition(leader, voter);
+    let new_leader = router.wait(voter, wait_timeout).await
+        .metrics(|metrics| metrics.current_leader.map(|id| id != leader).unwrap_or(false), "a new leader").await?
+        .current_leader.expect("expected the voter to know the new leader");
+    router.wait(new_leader, wait_timeout).await.state(State::Leader).await?;
+    router.client_request(new_leader, "0", 0).await;
+
+    // The old leader's lease expired before the new leader was elected, so it must not serve reads.
+    router.client_read(leader).await.expect_err("expected client_read to fail on the deposed leader");
+
+    Ok(())
+}
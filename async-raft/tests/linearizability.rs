@@ -0,0 +1,191 @@
+mod fixtures;
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+use anyhow::Result;
+use async_raft::{Config, NodeId};
+use memstore::ClientRequest as MemClientRequest;
+use rand::{Rng, thread_rng};
+use rand::seq::SliceRandom;
+use tokio::time::{delay_for, timeout};
+
+use fixtures::RaftRouter;
+use fixtures::linearizability::{is_linearizable, Op, Operation, Outcome};
+
+const NODES: &[NodeId] = &[0, 1, 2];
+const KEYS: &[&str] = &["a", "b"];
+
+/// Linearizability test.
+///
+/// What does this test do?
+///
+/// - brings 3 nodes online & initializes the cluster.
+/// - runs concurrent clients which write to & read from a few registers on random nodes, while
+///   randomly partitioning links between nodes & isolating the leader.
+/// - heals the cluster, reads every register from the leader, and asserts that the recorded
+///   history of all client operations is linearizable.
+///
+/// RUST_LOG=async_raft,memstore,linearizability=trace cargo test -p async-raft --test linearizability
+#[tokio::test(core_threads=4)]
+async fn linearizability() -> Result<()> {
+    fixtures::init_tracing();
+
+    // Setup test dependencies.
+    let config = Arc::new(Config::build("test".into()).pre_vote(true).validate().expect("failed to build Raft config"));
+    let router = Arc::new(RaftRouter::new(config.clone()));
+    for id in NODES {
+        router.new_raft_node(*id).await;
+    }
+
+    // Initialize the cluster, then assert that a stable cluster was formed & held.
+    tracing::info!("--- initializing cluster");
+    delay_for(Duration::from_secs(3)).await;
+    router.initialize_from_single_node(0).await?;
+    delay_for(Duration::from_secs(5)).await;
+    router.assert_stable_cluster(Some(1), Some(1)).await;
+
+    // Run concurrent clients while injecting partitions & leader failures.
+    tracing::info!("--- running clients under partitions & leader failures");
+    let stop = Arc::new(AtomicBool::new(false));
+    let serial = Arc::new(AtomicU64::new(0));
+    let clients = (0..4)
+        .map(|_| tokio::spawn(run_client(router.clone(), stop.clone(), serial.clone())))
+        .collect::<Vec<_>>();
+    for _ in 0..10 {
+        inject_fault(&router).await;
+        delay_for(Duration::from_secs(1)).await;
+    }
+    stop.store(true, Ordering::SeqCst);
+    for client in clients {
+        client.await?;
+    }
+
+    // Heal the cluster, read every register from the leader & check the history.
+    tracing::info!("--- healing the cluster");
+    heal(&router).await;
+    delay_for(Duration::from_secs(3)).await;
+    let leader = router.leader().await.expect("expected the cluster to have a leader");
+    for key in KEYS {
+        router.client_read_status(leader, key).await?;
+    }
+    let history = router.history().operations();
+    let written = history.iter().filter(|op| matches!(op.outcome, Outcome::Written(_))).count();
+    let read = history.iter().filter(|op| matches!(op.outcome, Outcome::Read(_))).count();
+    tracing::info!({ops=history.len(), written, read}, "--- checking history");
+    assert!(written > 10, "expected more than 10 successful writes, got {}", written);
+    assert!(read > 10, "expected more than 10 successful reads, got {}", read);
+    if let Err(err) = router.history().check() {
+        panic!("{}", err);
+    }
+
+    Ok(())
+}
+
+/// Run a client which writes to & reads from random registers on random nodes until stopped.
+///
+/// Operations which take too long are abandoned, leaving their outcome unknown.
+async fn run_client(router: Arc<RaftRouter>, stop: Arc<AtomicBool>, serial: Arc<AtomicU64>) {
+    while !stop.load(Ordering::SeqCst) {
+        let (key, is_write, target, pause) = {
+            let mut rng = thread_rng();
+            let key = *KEYS.choose(&mut rng).unwrap();
+            (key, rng.gen_bool(0.6), *NODES.choose(&mut rng).unwrap(), rng.gen_range(10, 50))
+        };
+        if is_write {
+            let serial = serial.fetch_add(1, Ordering::SeqCst);
+            let target = router.leader().await.unwrap_or(target);
+            let req = MemClientRequest{client: key.into(), serial, status: format!("write-{}", serial)};
+            let _ = timeout(Duration::from_secs(2), router.send_client_request(target, req)).await;
+        } else {
+            let _ = timeout(Duration::from_secs(2), router.client_read_status(target, key)).await;
+        }
+        delay_for(Duration::from_millis(pause)).await;
+    }
+}
+
+/// Heal the cluster, then inject a random fault: either isolating the leader, or partitioning the
+/// links between two nodes in one or both directions. Sometimes the cluster is simply left healed.
+async fn inject_fault(router: &Arc<RaftRouter>) {
+    heal(router).await;
+    let (fault, pair) = {
+        let mut rng = thread_rng();
+        let pair = NODES.choose_multiple(&mut rng, 2).copied().collect::<Vec<_>>();
+        (rng.gen_range(0, 4), pair)
+    };
+    match fault {
+        0 => if let Some(leader) = router.leader().await {
+            tracing::info!("--- isolating leader {}", leader);
+            router.isolate_node(leader).await;
+        },
+        1 => {
+            tracing::info!("--- partitioning link from {} to {}", pair[0], pair[1]);
+            router.faults().partition_one_way(pair[0], pair[1]);
+        }
+        2 => {
+            tracing::info!("--- partitioning links between {} & {}", pair[0], pair[1]);
+            router.faults().partition(pair[0], pair[1]);
+        }
+        _ => tracing::info!("--- leaving the cluster healed"),
+    }
+}
+
+/// Restore all isolated nodes & clear all network faults.
+async fn heal(router: &Arc<RaftRouter>) {
+    for id in NODES {
+        router.restore_node(*id).await;
+    }
+    router.faults().clear();
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////
+//////////////////////////////////////////////////////////////////////////////////////////////////
+
+fn op(op: Op, invoked_at: u64, completed_at: Option<u64>, outcome: Outcome) -> Operation {
+    Operation{
+        key: "a".into(), op, outcome,
+        invoked_at: Duration::from_millis(invoked_at), completed_at: completed_at.map(Duration::from_millis),
+    }
+}
+
+fn write(status: &str, invoked_at: u64, completed_at: u64, previous: Option<&str>) -> Operation {
+    op(Op::Write(status.into()), invoked_at, Some(completed_at), Outcome::Written(previous.map(Into::into)))
+}
+
+fn read(invoked_at: u64, completed_at: u64, value: Option<&str>) -> Operation {
+    op(Op::Read, invoked_at, Some(completed_at), Outcome::Read(value.map(Into::into)))
+}
+
+#[test]
+fn checker_accepts_sequential_history() {
+    let history = vec![read(0, 1, None), write("x", 2, 3, None), read(4, 5, Some("x")), write("y", 6, 7, Some("x"))];
+    assert!(is_linearizable(&history));
+}
+
+#[test]
+fn checker_accepts_reordered_concurrent_operations() {
+    // All operations overlap, so they may be linearized in any order.
+    let history = vec![write("x", 0, 10, Some("y")), write("y", 1, 5, None), read(2, 20, Some("x"))];
+    assert!(is_linearizable(&history));
+}
+
+#[test]
+fn checker_rejects_stale_read() {
+    let history = vec![write("x", 0, 1, None), write("y", 2, 3, Some("x")), read(4, 5, Some("x"))];
+    assert!(!is_linearizable(&history));
+}
+
+#[test]
+fn checker_rejects_lost_write() {
+    let history = vec![write("x", 0, 1, None), write("y", 2, 3, None)];
+    assert!(!is_linearizable(&history));
+}
+
+#[test]
+fn checker_allows_unknown_writes_to_take_effect_or_not() {
+    let unknown = op(Op::Write("x".into()), 0, None, Outcome::Unknown);
+    assert!(is_linearizable(&[unknown.clone(), read(2, 3, Some("x"))]));
+    assert!(is_linearizable(&[unknown.clone(), read(2, 3, None)]));
+    assert!(!is_linearizable(&[unknown, read(2, 3, Some("y"))]));
+}
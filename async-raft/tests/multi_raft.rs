@@ -0,0 +1,145 @@
+mod fixtures;
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_raft::async_trait::async_trait;
+use async_raft::{Config, GroupId, MultiRaft, MultiRaftNetwork, NodeId, State};
+use async_raft::multi::{HeartbeatRequest, HeartbeatResponse};
+use async_raft::raft::{AppendEntriesRequest, AppendEntriesResponse, ClientWriteRequest};
+use async_raft::raft::{InstallSnapshotRequest, InstallSnapshotResponse};
+use async_raft::raft::{PreVoteRequest, PreVoteResponse, VoteRequest, VoteResponse};
+use async_raft::raft::{ReadIndexRequest, ReadIndexResponse, TimeoutNowRequest, TimeoutNowResponse};
+use memstore::{MemStore, ClientRequest as MemClientRequest, ClientResponse as MemClientResponse};
+use tokio::sync::RwLock;
+use tokio::time::delay_for;
+
+type MemMultiRaft = MultiRaft<MemClientRequest, MemClientResponse, MultiRouter, MemStore>;
+
+/// Multi-Raft test.
+///
+/// What does this test do?
+///
+/// - brings 3 multi-raft hosts online, each hosting the same 16 groups.
+/// - initializes every group, and asserts that each group elects a single leader.
+/// - writes some data to every group, and asserts that it is replicated to every host.
+/// - asserts that heartbeats were coalesced, with multiple groups sharing each heartbeat RPC.
+///
+/// RUST_LOG=async_raft,memstore,multi_raft=trace cargo test -p async-raft --test multi_raft
+#[tokio::test(core_threads=4)]
+async fn multi_raft() -> Result<()> {
+    fixtures::init_tracing();
+
+    // Setup test dependencies.
+    let config = Arc::new(Config::build("test".into()).heartbeat_coalesce_window(20).validate().expect("failed to build Raft config"));
+    let router = Arc::new(MultiRouter::default());
+    let groups: Vec<GroupId> = (0..16).collect();
+    for id in 0..3 {
+        let host = Arc::new(MemMultiRaft::new(id, config.clone(), router.clone()));
+        for group in groups.iter() {
+            host.add_group(*group, Arc::new(MemStore::new(id)))?;
+        }
+        router.hosts.write().await.insert(id, host);
+    }
+    let hosts = router.hosts.read().await.clone();
+
+    // Initialize every group from host 0, then assert that each group formed a stable cluster.
+    tracing::info!("--- initializing groups");
+    delay_for(Duration::from_secs(1)).await;
+    for group in groups.iter() {
+        let raft = hosts[&0].group(*group).expect("expected group to exist");
+        raft.initialize(vec![0, 1, 2].into_iter().collect()).await?;
+    }
+    delay_for(Duration::from_secs(5)).await;
+    let mut leaders = BTreeMap::new();
+    for group in groups.iter() {
+        let metrics = hosts.values()
+            .map(|host| host.group(*group).expect("expected group to exist").metrics().borrow().clone())
+            .collect::<Vec<_>>();
+        let group_leaders = metrics.iter().filter(|node| node.state == State::Leader).map(|node| node.id).collect::<Vec<_>>();
+        assert_eq!(group_leaders.len(), 1, "expected group {} to have a single leader, got {:?}", group, group_leaders);
+        for node in metrics.iter() {
+            assert_eq!(node.current_leader, Some(group_leaders[0]), "node {} of group {} has unexpected leader", node.id, group);
+            assert_eq!(node.last_log_index, 1, "node {} of group {} has unexpected last_log_index", node.id, group);
+        }
+        leaders.insert(*group, group_leaders[0]);
+    }
+
+    // Write some data to every group & assert that it was replicated to every host.
+    tracing::info!("--- writing data to every group");
+    for (group, leader) in leaders.iter() {
+        let raft = hosts[leader].group(*group).expect("expected group to exist");
+        for serial in 0..10 {
+            let req = MemClientRequest{client: format!("{}", group), serial, status: format!("request-{}", serial)};
+            raft.client_write(ClientWriteRequest::new(req)).await?;
+        }
+    }
+    delay_for(Duration::from_secs(1)).await;
+    for group in groups.iter() {
+        for host in hosts.values() {
+            let metrics = host.group(*group).expect("expected group to exist").metrics().borrow().clone();
+            assert_eq!(metrics.last_applied, 11, "node {} of group {} has unexpected last_applied", metrics.id, group);
+        }
+    }
+
+    // Assert that heartbeats were coalesced.
+    let rpcs = router.heartbeat_rpcs.load(Ordering::SeqCst);
+    let heartbeats = router.heartbeats.load(Ordering::SeqCst);
+    tracing::info!({rpcs, heartbeats}, "--- heartbeat stats");
+    assert!(rpcs > 0, "expected heartbeats to be sent");
+    assert!(heartbeats >= rpcs * 2, "expected at least 2 heartbeats per RPC on average, got {} heartbeats in {} RPCs", heartbeats, rpcs);
+
+    Ok(())
+}
+
+/// A type which emulates a network transport shared by the groups of each multi-raft host.
+#[derive(Default)]
+struct MultiRouter {
+    hosts: RwLock<BTreeMap<NodeId, Arc<MemMultiRaft>>>,
+    /// The number of heartbeat RPCs sent.
+    heartbeat_rpcs: AtomicU64,
+    /// The total number of heartbeats carried by heartbeat RPCs.
+    heartbeats: AtomicU64,
+}
+
+impl MultiRouter {
+    async fn host(&self, target: NodeId) -> Result<Arc<MemMultiRaft>> {
+        self.hosts.read().await.get(&target).cloned().ok_or_else(|| anyhow!("host {} not found", target))
+    }
+}
+
+#[async_trait]
+impl MultiRaftNetwork<MemClientRequest> for MultiRouter {
+    async fn append_entries(&self, target: NodeId, group: GroupId, rpc: AppendEntriesRequest<MemClientRequest>) -> Result<AppendEntriesResponse> {
+        Ok(self.host(target).await?.append_entries(group, rpc).await?)
+    }
+
+    async fn heartbeat(&self, target: NodeId, rpc: HeartbeatRequest) -> Result<HeartbeatResponse> {
+        self.heartbeat_rpcs.fetch_add(1, Ordering::SeqCst);
+        self.heartbeats.fetch_add(rpc.heartbeats.len() as u64, Ordering::SeqCst);
+        Ok(self.host(target).await?.heartbeat(rpc).await)
+    }
+
+    async fn install_snapshot(&self, target: NodeId, group: GroupId, rpc: InstallSnapshotRequest) -> Result<InstallSnapshotResponse> {
+        Ok(self.host(target).await?.install_snapshot(group, rpc).await?)
+    }
+
+    async fn vote(&self, target: NodeId, group: GroupId, rpc: VoteRequest) -> Result<VoteResponse> {
+        Ok(self.host(target).await?.vote(group, rpc).await?)
+    }
+
+    async fn pre_vote(&self, target: NodeId, group: GroupId, rpc: PreVoteRequest) -> Result<PreVoteResponse> {
+        Ok(self.host(target).await?.pre_vote(group, rpc).await?)
+    }
+
+    async fn timeout_now(&self, target: NodeId, group: GroupId, rpc: TimeoutNowRequest) -> Result<TimeoutNowResponse> {
+        Ok(self.host(target).await?.timeout_now(group, rpc).await?)
+    }
+
+    async fn read_index(&self, target: NodeId, group: GroupId, rpc: ReadIndexRequest) -> Result<ReadIndexResponse> {
+        Ok(self.host(target).await?.read_index(group, rpc).await?)
+    }
+}
@@ -0,0 +1,80 @@
+mod fixtures;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_raft::Config;
+use async_raft::testing::LinkFaults;
+use tokio::time::delay_for;
+
+use fixtures::RaftRouter;
+
+/// Network faults test.
+///
+/// What does this test do?
+///
+/// - brings 3 nodes online & initializes the cluster.
+/// - injects latency, drops, duplication & reordering into every link, writes some data, and
+///   asserts that it is replicated once the faults are cleared.
+/// - partitions the link from the leader to one follower only, writes some data, and asserts that
+///   the leader keeps its leadership while the follower falls behind.
+/// - heals the partition & asserts that the follower catches up.
+///
+/// RUST_LOG=async_raft,memstore,network_faults=trace cargo test -p async-raft --test network_faults
+#[tokio::test(core_threads=4)]
+async fn network_faults() -> Result<()> {
+    fixtures::init_tracing();
+
+    // Setup test dependencies.
+    let config = Arc::new(Config::build("test".into())
+        .election_timeout_min(500).election_timeout_max(1000).pre_vote(true)
+        .validate().expect("failed to build Raft config"));
+    let router = Arc::new(RaftRouter::new(config.clone()));
+    router.new_raft_node(0).await;
+    router.new_raft_node(1).await;
+    router.new_raft_node(2).await;
+
+    // Initialize the cluster, then assert that a stable cluster was formed & held.
+    tracing::info!("--- initializing cluster");
+    delay_for(Duration::from_secs(3)).await;
+    router.initialize_from_single_node(0).await?;
+    delay_for(Duration::from_secs(5)).await;
+    router.assert_stable_cluster(Some(1), Some(1)).await;
+    let leader = router.leader().await.expect("expected the cluster to have a leader");
+
+    // Write data over a lossy network, then clear the faults & assert that all data was replicated.
+    tracing::info!("--- writing data over a lossy network");
+    router.faults().set_default(LinkFaults{
+        latency_min: 1, latency_max: 10,
+        drop_rate: 0.05, duplicate_rate: 0.1,
+        reorder_rate: 0.1, reorder_delay: 30,
+        partitioned: false,
+    });
+    router.client_request_many(leader, "0", 50).await;
+    router.faults().clear();
+    delay_for(Duration::from_secs(2)).await;
+    router.assert_stable_cluster(Some(1), Some(51)).await;
+
+    // Partition the link from the leader to a follower, and assert that the follower falls behind
+    // while the leader keeps its leadership, as the follower can still reach the rest of the cluster.
+    let follower = if leader == 0 { 1 } else { 0 };
+    tracing::info!("--- partitioning the link from leader {} to follower {}", leader, follower);
+    router.faults().partition_one_way(leader, follower);
+    router.client_request_many(leader, "0", 10).await;
+    delay_for(Duration::from_secs(3)).await;
+    assert_eq!(router.leader().await, Some(leader), "expected leader to be unchanged");
+    let metrics = router.latest_metrics().await;
+    for node in metrics.iter() {
+        let expected = if node.id == follower { 51 } else { 61 };
+        assert_eq!(node.last_log_index, expected, "node {} has last_log_index {}, expected {}", node.id, node.last_log_index, expected);
+    }
+
+    // Heal the partition & assert that the follower catches up.
+    tracing::info!("--- healing the partition");
+    router.faults().clear_link(leader, follower);
+    delay_for(Duration::from_secs(2)).await;
+    router.assert_stable_cluster(Some(1), Some(61)).await;
+
+    Ok(())
+}
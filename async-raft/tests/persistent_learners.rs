@@ -0,0 +1,84 @@
+mod fixtures;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_raft::{Config, State};
+use maplit::hashset;
+use tokio::time::delay_for;
+
+use fixtures::RaftRouter;
+
+/// Persistent learners test.
+///
+/// What does this test do?
+///
+/// - brings 3 nodes online & initializes the cluster.
+/// - adds a 4th node as a learner, and asserts that every node's membership config shows it.
+/// - isolates the leader, and asserts that a new leader takes over.
+/// - writes some data to the new leader, and asserts that the learner is still being replicated to.
+///
+/// RUST_LOG=async_raft,memstore,persistent_learners=trace cargo test -p async-raft --test persistent_learners
+#[tokio::test(core_threads=4)]
+async fn persistent_learners() -> Result<()> {
+    fixtures::init_tracing();
+
+    // Setup test dependencies.
+    let config = Arc::new(Config::build("test".into()).pre_vote(true).validate().expect("failed to build Raft config"));
+    let router = Arc::new(RaftRouter::new(config.clone()));
+    router.new_raft_node(0).await;
+    router.new_raft_node(1).await;
+    router.new_raft_node(2).await;
+
+    // Assert all nodes are in non-voter state & have no entries.
+    delay_for(Duration::from_secs(3)).await;
+    router.assert_pristine_cluster().await;
+
+    // Initialize the cluster, then assert that a stable cluster was formed & held.
+    tracing::info!("--- initializing cluster");
+    router.initialize_from_single_node(0).await?;
+    delay_for(Duration::from_secs(5)).await;
+    router.assert_stable_cluster(Some(1), Some(1)).await;
+    let orig_leader = router.leader().await.expect("expected the cluster to have a leader");
+
+    // Add a learner & assert that its config was committed throughout the cluster.
+    tracing::info!("--- adding learner node 3");
+    router.new_raft_node(3).await;
+    router.add_non_voter(orig_leader, 3).await?;
+    delay_for(Duration::from_secs(2)).await;
+    for node in router.latest_metrics().await {
+        assert_eq!(node.membership_config.members, hashset![0, 1, 2], "node {} has unexpected members", node.id);
+        assert_eq!(node.membership_config.learners, hashset![3], "node {} has unexpected learners", node.id);
+        assert_eq!(node.last_log_index, 2, "node {} has last_log_index {}, expected 2", node.id, node.last_log_index);
+        if node.id == 3 {
+            assert_eq!(node.state, State::NonVoter, "expected learner to be in non-voter state");
+        }
+    }
+
+    // Isolate the leader & assert that a new leader takes over.
+    tracing::info!("--- isolating leader node {}", orig_leader);
+    router.isolate_node(orig_leader).await;
+    delay_for(Duration::from_secs(5)).await;
+    let leader = router.leader().await.expect("expected new leader");
+    assert!(leader != orig_leader, "expected new leader to be different from the old leader");
+
+    // Write some data to the new leader & assert that the learner received it.
+    tracing::info!("--- writing data to new leader {}", leader);
+    router.client_request_many(leader, "0", 10).await;
+    delay_for(Duration::from_secs(2)).await;
+    let leader_metrics = router.latest_metrics().await.into_iter()
+        .find(|node| node.id == leader)
+        .expect("expected to find metrics for new leader");
+    let learner = router.latest_metrics().await.into_iter()
+        .find(|node| node.id == 3)
+        .expect("expected to find metrics for learner");
+    assert_eq!(learner.state, State::NonVoter, "expected learner to still be in non-voter state");
+    assert_eq!(learner.current_leader, Some(leader), "expected learner to follow the new leader");
+    assert_eq!(learner.current_term, leader_metrics.current_term, "expected learner to be in the new leader's term");
+    assert_eq!(learner.last_log_index, leader_metrics.last_log_index, "expected learner to be up-to-date with the new leader");
+    assert_eq!(learner.last_applied, leader_metrics.last_log_index, "expected learner to have applied all entries");
+    assert_eq!(leader_metrics.membership_config.learners, hashset![3], "expected new leader to retain the learner");
+
+    Ok(())
+}
@@ -0,0 +1,72 @@
+mod fixtures;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_raft::Config;
+use futures::prelude::*;
+use tokio::time::delay_for;
+
+use fixtures::RaftRouter;
+
+/// Pipelined replication tests.
+///
+/// What does this test do?
+///
+/// - create a stable 3-node cluster with a window of in-flight AppendEntries RPCs & small payloads.
+/// - write a lot of data to it concurrently, so that many payloads are pipelined.
+/// - isolate a follower while writing more data, then restore it, forcing its replication stream
+///   to fall back from line rate & then catch up.
+/// - assert that the cluster stayed stable and that every node has all of the expected data.
+///
+/// RUST_LOG=async_raft,memstore,pipelined_replication=trace cargo test -p async-raft --test pipelined_replication
+#[tokio::test(core_threads=4)]
+async fn pipelined_replication() -> Result<()> {
+    fixtures::init_tracing();
+
+    // Setup test dependencies.
+    let config = Arc::new(Config::build("test".into())
+        .max_payload_entries(10)
+        .max_inflight_append_entries(8)
+        .pre_vote(true) // Ensure the isolated follower does not disrupt the cluster upon being restored.
+        .validate().expect("failed to build Raft config"));
+    let router = Arc::new(RaftRouter::new(config.clone()));
+    router.new_raft_node(0).await;
+    router.new_raft_node(1).await;
+    router.new_raft_node(2).await;
+
+    // Assert all nodes are in non-voter state & have no entries.
+    delay_for(Duration::from_secs(10)).await;
+    router.assert_pristine_cluster().await;
+
+    // Initialize the cluster, then assert that a stable cluster was formed & held.
+    tracing::info!("--- initializing cluster");
+    router.initialize_from_single_node(0).await?;
+    delay_for(Duration::from_secs(10)).await;
+    router.assert_stable_cluster(Some(1), Some(1)).await;
+
+    // Write a bunch of data concurrently, so that the replication streams are pipelined.
+    tracing::info!("--- writing data");
+    let leader = router.leader().await.expect("leader not found");
+    let mut clients = futures::stream::FuturesUnordered::new();
+    clients.push(router.client_request_many(leader, "0", 500));
+    clients.push(router.client_request_many(leader, "1", 500));
+    clients.push(router.client_request_many(leader, "2", 500));
+    clients.push(router.client_request_many(leader, "3", 500));
+    while clients.next().await.is_some() {}
+    delay_for(Duration::from_secs(5)).await;
+    router.assert_stable_cluster(Some(1), Some(2001)).await; // The extra 1 is from the leader's initial commit entry.
+
+    // Isolate a follower while writing more data, then restore it and let it catch up.
+    tracing::info!("--- isolating follower while writing data");
+    let follower = (0..3).find(|id| *id != leader).expect("follower not found");
+    router.isolate_node(follower).await;
+    router.client_request_many(leader, "4", 200).await;
+    router.restore_node(follower).await;
+    router.client_request_many(leader, "5", 200).await;
+    delay_for(Duration::from_secs(5)).await;
+    router.assert_stable_cluster(Some(1), Some(2401)).await;
+
+    Ok(())
+}
@@ -0,0 +1,64 @@
+mod fixtures;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_raft::Config;
+use tokio::time::delay_for;
+
+use fixtures::RaftRouter;
+
+/// Pre-Vote test.
+///
+/// What does this test do?
+///
+/// - brings 3 nodes online with Pre-Vote enabled & initializes the cluster.
+/// - asserts that the cluster is able to elect a leader through the Pre-Vote phase.
+/// - isolates a follower for long enough that it will repeatedly time out & campaign.
+/// - restores the follower & asserts that it rejoins the cluster without disrupting the
+///   leader, i.e., the cluster is still in its first term with the same leader.
+///
+/// RUST_LOG=async_raft,memstore,prevote=trace cargo test -p async-raft --test prevote
+#[tokio::test(core_threads=4)]
+async fn prevote() -> Result<()> {
+    fixtures::init_tracing();
+
+    // Setup test dependencies.
+    let config = Arc::new(Config::build("test".into()).pre_vote(true).validate().expect("failed to build Raft config"));
+    let router = Arc::new(RaftRouter::new(config.clone()));
+    router.new_raft_node(0).await;
+    router.new_raft_node(1).await;
+    router.new_raft_node(2).await;
+
+    // Assert all nodes are in non-voter state & have no entries.
+    delay_for(Duration::from_secs(3)).await;
+    router.assert_pristine_cluster().await;
+
+    // Initialize the cluster, then assert that a stable cluster was formed & held.
+    tracing::info!("--- initializing cluster");
+    router.initialize_from_single_node(0).await?;
+    delay_for(Duration::from_secs(3)).await;
+    router.assert_stable_cluster(Some(1), Some(1)).await;
+    let leader = router.leader().await.expect("expected the cluster to have a leader");
+
+    // Isolate a follower long enough for it to campaign a number of times.
+    let follower = (0..3).find(|id| *id != leader).expect("expected to find a follower");
+    tracing::info!("--- isolating node {}", follower);
+    router.isolate_node(follower).await;
+    delay_for(Duration::from_secs(3)).await;
+    {
+        let metrics = router.latest_metrics().await.into_iter().find(|node| node.id == follower)
+            .expect("expected to find metrics on isolated node");
+        assert_eq!(metrics.current_term, 1, "expected isolated node to still be in first term, got {}", metrics.current_term);
+    }
+
+    // Restore the follower & assert that the cluster was not disrupted.
+    tracing::info!("--- restoring node {}", follower);
+    router.restore_node(follower).await;
+    delay_for(Duration::from_secs(3)).await;
+    router.assert_stable_cluster(Some(1), Some(1)).await;
+    assert_eq!(router.leader().await, Some(leader), "expected leader to remain unchanged");
+
+    Ok(())
+}
@@ -0,0 +1,105 @@
+mod fixtures;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_raft::{Config, SnapshotPolicy};
+use async_raft::metrics::prometheus::{CONTENT_TYPE, PrometheusExporter};
+use maplit::hashmap;
+use prometheus::{Encoder, Registry, TextEncoder};
+use tokio::time::delay_for;
+
+use fixtures::RaftRouter;
+
+/// Prometheus exporter test.
+///
+/// What does this test do?
+///
+/// - brings 3 nodes online, tracking the metrics of each with an exporter labelled by node ID,
+///   all exporters sharing one registry.
+/// - initializes the cluster, and writes enough data that every node builds a snapshot.
+/// - isolates a follower & writes more data.
+/// - asserts that the encoded metrics describe the state of each node & the leader's lag to each
+///   follower, and count the election, the leader change & the snapshots of each node.
+///
+/// RUST_LOG=async_raft,memstore,prometheus=trace cargo test -p async-raft --test prometheus
+#[tokio::test(core_threads=4)]
+async fn prometheus() -> Result<()> {
+    fixtures::init_tracing();
+
+    // Setup test dependencies.
+    let config = Arc::new(Config::build("test".into())
+        .snapshot_policy(SnapshotPolicy::LogsSinceLast(10))
+        .validate()
+        .expect("failed to build Raft config"));
+    let router = Arc::new(RaftRouter::new(config.clone()));
+    let registry = Registry::new();
+    let mut exporters = vec![];
+    for id in 0..3 {
+        router.new_raft_node(id).await;
+        let exporter = Arc::new(PrometheusExporter::with_labels(&registry, hashmap!{"node".into() => id.to_string()})?);
+        let rx = router.metrics(id).await;
+        tokio::spawn({
+            let exporter = exporter.clone();
+            async move { exporter.track(rx).await }
+        });
+        exporters.push(exporter);
+    }
+
+    // Initialize the cluster, then write enough data that every node builds a snapshot.
+    tracing::info!("--- initializing cluster");
+    delay_for(Duration::from_secs(3)).await;
+    router.initialize_from_single_node(0).await?;
+    delay_for(Duration::from_secs(3)).await;
+    router.assert_stable_cluster(Some(1), Some(1)).await;
+    let leader = router.leader().await.expect("expected the cluster to have a leader");
+    router.client_request_many(leader, "0", 19).await;
+    delay_for(Duration::from_secs(2)).await;
+    router.assert_stable_cluster(Some(1), Some(20)).await;
+
+    // Isolate a follower, and write more data which it will not receive.
+    let follower = if leader == 0 { 1 } else { 0 };
+    let other = 3 - leader - follower;
+    tracing::info!("--- isolating follower {}", follower);
+    router.isolate_node(follower).await;
+    router.client_request_many(leader, "0", 5).await;
+    delay_for(Duration::from_secs(1)).await;
+
+    // Assert that the encoded metrics describe the cluster.
+    let text = exporters[0].encode()?;
+    assert_eq!(TextEncoder::new().format_type(), CONTENT_TYPE);
+    let value = |name: &str, labels: String| -> i64 {
+        let prefix = format!("{}{{{}}} ", name, labels);
+        text.lines()
+            .find_map(|line| line.strip_prefix(&prefix))
+            .unwrap_or_else(|| panic!("expected metric {}{{{}}} in:\n{}", name, labels, text))
+            .parse()
+            .expect("failed to parse metric value")
+    };
+    for id in 0..3u64 {
+        let node = format!("node=\"{}\"", id);
+        // The isolated follower campaigns in vain, so its state & term are not asserted.
+        if id != follower {
+            let state = if id == leader { "leader" } else { "follower" };
+            assert_eq!(value("raft_state", format!("{},state=\"{}\"", node, state)), 1, "expected node {} to be {}", id, state);
+            assert_eq!(value("raft_current_term", node.clone()), 1, "expected node {} to be in term 1", id);
+            assert_eq!(value("raft_current_leader", node.clone()), leader as i64, "expected node {} to know the leader", id);
+        }
+        assert_eq!(value("raft_membership_size", node.clone()), 3, "expected node {} to have 3 members", id);
+        assert_eq!(value("raft_leader_changes_total", node.clone()), 1, "expected node {} to observe one leader", id);
+        assert!(value("raft_snapshots_total", node.clone()) >= 1, "expected node {} to have built a snapshot", id);
+        assert!(value("raft_snapshot_index", node.clone()) >= 10, "expected node {} to have a snapshot through index 10", id);
+        let expected = if id == follower { 20 } else { 25 };
+        assert_eq!(value("raft_last_log_index", node.clone()), expected, "expected node {} to have last log index {}", id, expected);
+    }
+    let leader_node = format!("node=\"{}\"", leader);
+    assert_eq!(value("raft_elections_total", leader_node.clone()), 1, "expected the leader to have campaigned once");
+    assert_eq!(value("raft_replication_match_index", format!("{},target=\"{}\"", leader_node, other)), 25);
+    assert_eq!(value("raft_replication_lag", format!("{},target=\"{}\"", leader_node, other)), 0);
+    assert_eq!(value("raft_replication_match_index", format!("{},target=\"{}\"", leader_node, follower)), 20);
+    assert_eq!(value("raft_replication_lag", format!("{},target=\"{}\"", leader_node, follower)), 5);
+    assert!(!text.contains(&format!("raft_replication_lag{{node=\"{}\"", follower)), "expected no replication metrics from followers");
+
+    Ok(())
+}
@@ -0,0 +1,89 @@
+mod fixtures;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_raft::Config;
+use tokio::time::delay_for;
+
+use fixtures::RaftRouter;
+
+/// Quiescence test.
+///
+/// What does this test do?
+///
+/// - brings 3 nodes online & initializes the cluster.
+/// - asserts that once the cluster is idle, no more AppendEntries RPCs are sent & no elections take place.
+/// - asserts that querying the replication ack ages of each node does not wake the cluster.
+/// - writes some data & asserts that the cluster wakes up to replicate it, then quiesces again.
+/// - asserts that a client read on a follower wakes the cluster & is served.
+/// - isolates the leader & sends a client read to a follower, and asserts that a new leader is elected.
+///
+/// RUST_LOG=async_raft,memstore,quiescence=trace cargo test -p async-raft --test quiescence
+#[tokio::test(core_threads=4)]
+async fn quiescence() -> Result<()> {
+    fixtures::init_tracing();
+
+    // Setup test dependencies.
+    let config = Arc::new(Config::build("test".into()).pre_vote(true).quiesce(true).quiesce_after(500).validate().expect("failed to build Raft config"));
+    let router = Arc::new(RaftRouter::new(config.clone()));
+    router.new_raft_node(0).await;
+    router.new_raft_node(1).await;
+    router.new_raft_node(2).await;
+
+    // Assert all nodes are in non-voter state & have no entries.
+    delay_for(Duration::from_secs(3)).await;
+    router.assert_pristine_cluster().await;
+
+    // Initialize the cluster, then assert that a stable cluster was formed & held.
+    tracing::info!("--- initializing cluster");
+    router.initialize_from_single_node(0).await?;
+    delay_for(Duration::from_secs(5)).await;
+    router.assert_stable_cluster(Some(1), Some(1)).await;
+    let leader = router.leader().await.expect("expected the cluster to have a leader");
+
+    // Assert that the idle cluster has gone quiet, without any elections taking place.
+    tracing::info!("--- asserting that the idle cluster is quiescent");
+    assert_quiescent(&router).await;
+    router.assert_stable_cluster(Some(1), Some(1)).await;
+
+    // Assert that polling the ack ages of each node, as monitoring would, leaves the cluster quiet.
+    tracing::info!("--- polling ack ages of the quiescent cluster");
+    for id in 0..3 {
+        router.replication_ack_ages(id).await?;
+    }
+    assert_quiescent(&router).await;
+
+    // Write some data & assert that the cluster wakes up to replicate it, then goes quiet again.
+    tracing::info!("--- writing data to wake the cluster");
+    router.client_request_many(leader, "0", 10).await;
+    delay_for(Duration::from_secs(2)).await;
+    router.assert_stable_cluster(Some(1), Some(11)).await;
+    assert_quiescent(&router).await;
+
+    // Assert that a client read on a follower wakes the cluster & is served.
+    tracing::info!("--- reading from a follower");
+    let follower = if leader == 0 { 1 } else { 0 };
+    let read_index = router.client_read(follower).await?;
+    assert_eq!(read_index, 11, "expected follower read to be served at the last committed index");
+
+    // Isolate the leader, and assert that a client read on a follower wakes it to elect a new leader.
+    delay_for(Duration::from_secs(2)).await;
+    tracing::info!("--- isolating leader node {}", leader);
+    router.isolate_node(leader).await;
+    let _ = router.client_read(follower).await;
+    delay_for(Duration::from_secs(3)).await;
+    let new_leader = router.leader().await.expect("expected a new leader to be elected");
+    assert_ne!(new_leader, leader, "expected new leader to be different from the isolated leader");
+
+    Ok(())
+}
+
+/// Assert that no AppendEntries RPCs are delivered over a period well beyond the election timeout.
+async fn assert_quiescent(router: &Arc<RaftRouter>) {
+    let before = router.append_entries_delivered();
+    delay_for(Duration::from_secs(2)).await;
+    let after = router.append_entries_delivered();
+    assert_eq!(after, before, "expected no AppendEntries RPCs while quiescent, got {}", after - before);
+}
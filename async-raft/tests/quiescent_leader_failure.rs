@@ -0,0 +1,62 @@
+mod fixtures;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_raft::{Config, State};
+use tokio::time::delay_for;
+
+use fixtures::RaftRouter;
+
+/// Quiescent leader failure test.
+///
+/// What does this test do?
+///
+/// - brings 3 nodes online & initializes the cluster, then waits for the idle cluster to quiesce.
+/// - shuts down the leader, and asserts that the quiescent followers do not notice on their own.
+/// - wakes one of the followers, as an application would on detecting that the leader is down,
+///   and asserts that the followers elect a new leader.
+///
+/// RUST_LOG=async_raft,memstore,quiescent_leader_failure=trace cargo test -p async-raft --test quiescent_leader_failure
+#[tokio::test(core_threads=4)]
+async fn quiescent_leader_failure() -> Result<()> {
+    fixtures::init_tracing();
+
+    // Setup test dependencies.
+    let config = Arc::new(Config::build("test".into()).pre_vote(true).quiesce(true).quiesce_after(500).validate().expect("failed to build Raft config"));
+    let router = Arc::new(RaftRouter::new(config.clone()));
+    router.new_raft_node(0).await;
+    router.new_raft_node(1).await;
+    router.new_raft_node(2).await;
+
+    // Initialize the cluster, then assert that a stable cluster was formed & has gone quiet.
+    tracing::info!("--- initializing cluster");
+    router.initialize_from_single_node(0).await?;
+    delay_for(Duration::from_secs(5)).await;
+    router.assert_stable_cluster(Some(1), Some(1)).await;
+    let leader = router.leader().await.expect("expected the cluster to have a leader");
+    let before = router.append_entries_delivered();
+    delay_for(Duration::from_secs(2)).await;
+    assert_eq!(router.append_entries_delivered(), before, "expected no AppendEntries RPCs while quiescent");
+
+    // Shut down the leader, and assert that the quiescent followers do not notice.
+    tracing::info!("--- shutting down leader node {}", leader);
+    router.shutdown_node(leader).await?;
+    delay_for(Duration::from_secs(3)).await;
+    for node in router.latest_metrics().await {
+        assert_eq!(node.state, State::Follower, "expected node {} to remain a quiescent follower", node.id);
+        assert_eq!(node.current_leader, Some(leader), "expected node {} to still follow node {}", node.id, leader);
+    }
+
+    // Wake a follower, and assert that a new leader is elected.
+    let follower = (0..3).find(|id| *id != leader).expect("expected a follower");
+    tracing::info!("--- waking follower node {}", follower);
+    router.wake(follower).await?;
+    router.wait(follower, Duration::from_secs(5)).await
+        .metrics(|m| m.current_leader.is_some() && m.current_leader != Some(leader), "new leader elected").await?;
+    let new_leader = router.leader().await.expect("expected a new leader to be elected");
+    assert_ne!(new_leader, leader, "expected new leader to be different from the shut down leader");
+
+    Ok(())
+}
@@ -0,0 +1,95 @@
+mod fixtures;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_raft::Config;
+use async_raft::error::ChangeConfigError;
+use maplit::hashset;
+use tokio::time::delay_for;
+
+use fixtures::RaftRouter;
+
+/// Remove non-voter test.
+///
+/// What does this test do?
+///
+/// - brings 3 nodes online & initializes the cluster, then adds a 4th node as a learner.
+/// - asserts that learners can neither be added nor removed while config changes which add &
+///   remove a 5th node are in progress.
+/// - removes the learner, and asserts that it is removed from every node's config and is no
+///   longer replicated to.
+///
+/// RUST_LOG=async_raft,memstore,remove_non_voter=trace cargo test -p async-raft --test remove_non_voter
+#[tokio::test(core_threads=4)]
+async fn remove_non_voter() -> Result<()> {
+    fixtures::init_tracing();
+
+    // Setup test dependencies.
+    let config = Arc::new(Config::build("test".into()).validate().expect("failed to build Raft config"));
+    let router = Arc::new(RaftRouter::new(config.clone()));
+    router.new_raft_node(0).await;
+    router.new_raft_node(1).await;
+    router.new_raft_node(2).await;
+
+    // Assert all nodes are in non-voter state & have no entries.
+    delay_for(Duration::from_secs(3)).await;
+    router.assert_pristine_cluster().await;
+
+    // Initialize the cluster, then assert that a stable cluster was formed & held.
+    tracing::info!("--- initializing cluster");
+    router.initialize_from_single_node(0).await?;
+    delay_for(Duration::from_secs(5)).await;
+    router.assert_stable_cluster(Some(1), Some(1)).await;
+    let leader = router.leader().await.expect("expected the cluster to have a leader");
+
+    // Add a learner.
+    tracing::info!("--- adding learner node 3");
+    router.new_raft_node(3).await;
+    router.add_non_voter(leader, 3).await?;
+
+    // Assert that learners can neither be added nor removed while a config change is in
+    // progress. Each request is queued right behind a config change, so it is handled before the
+    // config change can complete.
+    tracing::info!("--- adding node 4 to the cluster while adding learner node 5");
+    router.new_raft_node(4).await;
+    let (change, res) = tokio::join!(router.change_membership(leader, hashset![0, 1, 2, 4]), router.add_non_voter(leader, 5));
+    change?;
+    assert!(matches!(res, Err(ChangeConfigError::ConfigChangeInProgress)), "expected adding a learner to be rejected, got {:?}", res);
+    tracing::info!("--- removing node 4 from the cluster while removing learner node 3");
+    let (change, res) = tokio::join!(router.change_membership(leader, hashset![0, 1, 2]), router.remove_non_voter(leader, 3));
+    change?;
+    assert!(matches!(res, Err(ChangeConfigError::ConfigChangeInProgress)), "expected removing a learner to be rejected, got {:?}", res);
+
+    // Remove the learner & assert that it is removed from every node's config.
+    tracing::info!("--- removing learner node 3");
+    router.remove_non_voter(leader, 3).await?;
+    delay_for(Duration::from_secs(2)).await;
+    let learner = router.latest_metrics().await.into_iter()
+        .find(|node| node.id == 3)
+        .expect("expected to find metrics for learner");
+    for node in router.latest_metrics().await.into_iter().filter(|node| node.id < 3) {
+        assert_eq!(node.membership_config.members, hashset![0, 1, 2], "node {} has unexpected members", node.id);
+        assert!(node.membership_config.learners.is_empty(), "node {} has unexpected learners {:?}", node.id, node.membership_config.learners);
+    }
+
+    // Write some data & assert that the removed learner no longer receives it.
+    tracing::info!("--- writing data to leader {}", leader);
+    router.client_request_many(leader, "0", 10).await;
+    delay_for(Duration::from_secs(2)).await;
+    let leader_metrics = router.latest_metrics().await.into_iter()
+        .find(|node| node.id == leader)
+        .expect("expected to find metrics for leader");
+    let removed = router.latest_metrics().await.into_iter()
+        .find(|node| node.id == 3)
+        .expect("expected to find metrics for learner");
+    assert_eq!(removed.last_log_index, learner.last_log_index, "expected removed learner to receive no more entries");
+    assert!(removed.last_log_index < leader_metrics.last_log_index, "expected removed learner to be behind the leader");
+
+    // Assert that removing a node which is not a learner is a no-op.
+    let res = router.remove_non_voter(leader, 3).await;
+    assert!(matches!(res, Err(ChangeConfigError::Noop)), "expected removing a non-learner to be a no-op, got {:?}", res);
+
+    Ok(())
+}
@@ -0,0 +1,110 @@
+mod fixtures;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_raft::{Config, SnapshotPolicy};
+use async_raft::metrics::{ReplicationMetrics, ReplicationStreamState};
+use maplit::hashset;
+use tokio::time::delay_for;
+
+use fixtures::RaftRouter;
+
+/// Replication metrics test.
+///
+/// What does this test do?
+///
+/// - build a stable single node cluster, with a small snapshot chunk size.
+/// - send enough requests to the node that log compaction will be triggered.
+/// - add a new node, recording the leader's replication metrics for it, and assert that they
+///   report the snapshot being streamed to it, with its progress.
+/// - assert that the leader's metrics report the new node as up-to-date at line rate, and that
+///   the metrics of the new node have no replication metrics.
+/// - assert that the leader reports a recent acknowledgement from the new node, and that the new
+///   node reports no acknowledgements.
+///
+/// RUST_LOG=async_raft,memstore,replication_metrics=trace cargo test -p async-raft --test replication_metrics
+#[tokio::test(core_threads=4)]
+async fn replication_metrics() -> Result<()> {
+    fixtures::init_tracing();
+
+    // Setup test dependencies.
+    let config = Arc::new(Config::build("test".into())
+        .snapshot_policy(SnapshotPolicy::LogsSinceLast(500))
+        .snapshot_max_chunk_size(16)
+        .validate()
+        .expect("failed to build Raft config"));
+    let router = Arc::new(RaftRouter::new(config.clone()));
+    router.new_raft_node(0).await;
+
+    // Initialize the cluster, then assert that a stable cluster was formed & held.
+    tracing::info!("--- initializing cluster");
+    delay_for(Duration::from_secs(3)).await;
+    router.initialize_from_single_node(0).await?;
+    delay_for(Duration::from_secs(3)).await;
+    router.assert_stable_cluster(Some(1), Some(1)).await;
+    assert!(router.metrics(0).await.borrow().replication.is_empty(), "expected no replication metrics for a single node cluster");
+
+    // Send enough requests to the cluster that compaction on the node should be triggered.
+    router.client_request_many(0, "0", 499).await; // Puts us exactly at the configured snapshot policy threshold.
+    delay_for(Duration::from_secs(5)).await; // Wait to ensure there is enough time for a snapshot to be built (this is way more than enough).
+    router.assert_stable_cluster(Some(1), Some(500)).await;
+
+    // Record the leader's replication metrics for the new node while it is added to the cluster.
+    tracing::info!("--- adding new node");
+    let mut rx = router.metrics(0).await;
+    let recorder = tokio::spawn(async move {
+        let mut observed: Vec<ReplicationMetrics> = vec![];
+        while let Some(metrics) = rx.recv().await {
+            if let Some(repl) = metrics.replication.get(&1) {
+                observed.push(repl.clone());
+                if repl.state == ReplicationStreamState::LineRate && repl.match_index >= 500 {
+                    break;
+                }
+            }
+        }
+        observed
+    });
+    router.new_raft_node(1).await;
+    router.add_non_voter(0, 1).await.expect("failed to add new node as non-voter");
+    router.change_membership(0, hashset![0, 1]).await.expect("failed to modify cluster membership");
+    delay_for(Duration::from_secs(5)).await; // Wait to ensure metrics are updated (this is way more than enough).
+    router.assert_stable_cluster(Some(1), Some(503)).await; // We expect index to be 500 + 3 (learner, joint & uniform config change entries).
+
+    // Assert that the snapshot stream & its progress were reported.
+    let observed = recorder.await?;
+    let progress = observed.iter()
+        .filter(|repl| repl.state == ReplicationStreamState::Snapshotting)
+        .filter_map(|repl| repl.snapshot)
+        .collect::<Vec<_>>();
+    assert!(!progress.is_empty(), "expected snapshot progress to be reported, observed {:?}", observed);
+    for (prev, next) in progress.iter().zip(progress.iter().skip(1)) {
+        assert!(prev.offset <= next.offset, "expected snapshot progress to never move backwards, observed {:?}", progress);
+    }
+    for progress in progress.iter() {
+        assert_eq!(progress.index, 500, "expected the snapshot covering index 500 to be streamed");
+        assert!(progress.offset <= progress.size, "expected offset {} to be within size {}", progress.offset, progress.size);
+    }
+    assert!(observed.iter().all(|repl| repl.state == ReplicationStreamState::Snapshotting || repl.snapshot.is_none()),
+        "expected snapshot progress to only be reported while snapshotting, observed {:?}", observed);
+
+    // Assert that the leader reports the new node as up-to-date, & that the new node reports nothing.
+    let leader_metrics = router.metrics(0).await.borrow().clone();
+    assert_eq!(leader_metrics.replication.keys().copied().collect::<Vec<_>>(), vec![1], "expected replication metrics for node 1 only");
+    let repl = &leader_metrics.replication[&1];
+    assert_eq!(repl.match_index, 503, "expected node 1 to have match index 503");
+    assert_eq!(repl.match_term, 1, "expected node 1 to have match term 1");
+    assert_eq!(repl.state, ReplicationStreamState::LineRate, "expected node 1 to be replicated at line rate");
+    assert!(repl.last_ack.is_some(), "expected node 1 to have acknowledged the leader");
+    assert!(repl.snapshot.is_none(), "expected no snapshot progress once node 1 is at line rate");
+    assert!(router.metrics(1).await.borrow().replication.is_empty(), "expected no replication metrics on a follower");
+
+    // Assert that the leader reports how long ago node 1 acknowledged it, & that the new node reports nothing.
+    let ack_ages = router.replication_ack_ages(0).await?;
+    assert_eq!(ack_ages.keys().copied().collect::<Vec<_>>(), vec![1], "expected an ack age for node 1 only");
+    assert!(ack_ages[&1] < Duration::from_millis(config.election_timeout_min), "expected node 1 to have acknowledged the leader recently, got {:?}", ack_ages[&1]);
+    assert!(router.replication_ack_ages(1).await?.is_empty(), "expected no ack ages on a follower");
+
+    Ok(())
+}
@@ -0,0 +1,60 @@
+mod fixtures;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_raft::{Config, State};
+use tokio::time::timeout;
+
+use fixtures::RaftRouter;
+
+/// Shutdown test.
+///
+/// What does this test do?
+///
+/// - brings 3 nodes online & initializes the cluster.
+/// - shuts down a follower, and asserts that its core task completes.
+/// - asserts that the follower reports the shutdown state in its metrics.
+///
+/// RUST_LOG=async_raft,memstore,shutdown=trace cargo test -p async-raft --test shutdown
+#[tokio::test(core_threads=4)]
+async fn shutdown() -> Result<()> {
+    fixtures::init_tracing();
+
+    // Setup test dependencies.
+    let config = Arc::new(Config::build("test".into()).validate().expect("failed to build Raft config"));
+    let router = Arc::new(RaftRouter::new(config.clone()));
+    let wait_timeout = Duration::from_secs(5);
+    for id in 0..3 {
+        router.new_raft_node(id).await;
+    }
+
+    // Wait for all nodes to be in non-voter state, & assert that they have no entries.
+    for id in 0..3 {
+        router.wait(id, wait_timeout).await.state(State::NonVoter).await?;
+    }
+    router.assert_pristine_cluster().await;
+
+    // Initialize the cluster, then wait for every node to know the leader & apply the initial config.
+    tracing::info!("--- initializing cluster");
+    router.initialize_from_single_node(0).await?;
+    let leader = router.wait(0, wait_timeout).await
+        .metrics(|metrics| metrics.current_leader.is_some(), "a known leader").await?
+        .current_leader.expect("expected the cluster to have a leader");
+    for id in 0..3 {
+        let wait = router.wait(id, wait_timeout).await;
+        wait.current_leader(leader).await?;
+        wait.applied(1).await?;
+    }
+    router.assert_stable_cluster(Some(1), Some(1)).await;
+
+    // Shut down a follower, and assert that its core task completes.
+    let follower = (0..3).find(|id| *id != leader).expect("expected the cluster to have a follower");
+    tracing::info!("--- shutting down node {}", follower);
+    let metrics = router.metrics(follower).await;
+    timeout(Duration::from_secs(5), router.shutdown_node(follower)).await.expect("timed out waiting for node to shut down")?;
+    assert_eq!(metrics.borrow().state, State::Shutdown, "expected node {} to report the shutdown state", follower);
+
+    Ok(())
+}
@@ -0,0 +1,79 @@
+mod fixtures;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_raft::{Config, RaftMetrics};
+use tokio::time::delay_for;
+
+use fixtures::RaftRouter;
+
+/// Deterministic simulation test.
+///
+/// What does this test do?
+///
+/// - runs a scenario twice on paused time with the same seed, the scenario being:
+///   - brings 3 nodes online & initializes the cluster.
+///   - writes some data to the leader.
+///   - isolates the leader & asserts that a new leader is elected.
+/// - asserts that both runs sent exactly the same RPCs at the same virtual times, & ended in the same state.
+///
+/// RUST_LOG=async_raft,memstore,simulation=trace cargo test -p async-raft --features simulation --test simulation
+///
+/// A failing run can be reproduced by setting `RAFT_SIM_SEED` to the seed printed by the test.
+#[test]
+fn simulation() -> Result<()> {
+    fixtures::init_tracing();
+
+    let seed = fixtures::simulation_seed();
+    let (first_trace, first_metrics) = fixtures::run_simulation(seed, scenario)?;
+    let (second_trace, second_metrics) = fixtures::run_simulation(seed, scenario)?;
+
+    assert!(!first_trace.is_empty(), "expected RPCs to be traced");
+    assert_eq!(first_trace, second_trace, "expected runs with seed {} to send identical RPCs", seed);
+    assert_eq!(first_metrics, second_metrics, "expected runs with seed {} to end in identical states", seed);
+
+    Ok(())
+}
+
+async fn scenario(seed: u64) -> Result<(Vec<String>, Vec<RaftMetrics>)> {
+    // Setup test dependencies.
+    let config = Arc::new(Config::build("test".into()).validate().expect("failed to build Raft config"));
+    let router = Arc::new(RaftRouter::new_simulated(config.clone(), seed));
+    router.new_raft_node(0).await;
+    router.new_raft_node(1).await;
+    router.new_raft_node(2).await;
+
+    // Assert all nodes are in non-voter state & have no entries.
+    delay_for(Duration::from_secs(3)).await;
+    router.assert_pristine_cluster().await;
+
+    // Initialize the cluster, then assert that a stable cluster was formed & held.
+    tracing::info!("--- initializing cluster");
+    router.initialize_from_single_node(0).await?;
+    delay_for(Duration::from_secs(5)).await;
+    router.assert_stable_cluster(Some(1), Some(1)).await;
+    let leader = router.leader().await.expect("expected the cluster to have a leader");
+
+    // Write some data & assert that it is replicated.
+    tracing::info!("--- writing data");
+    router.client_request_many(leader, "0", 10).await;
+    delay_for(Duration::from_secs(1)).await;
+    router.assert_stable_cluster(Some(1), Some(11)).await;
+
+    // Isolate the leader, and assert that a new leader is elected.
+    tracing::info!("--- isolating leader node {}", leader);
+    router.isolate_node(leader).await;
+    delay_for(Duration::from_secs(5)).await;
+    let new_leader = router.leader().await.expect("expected a new leader to be elected");
+    assert_ne!(new_leader, leader, "expected new leader to be different from the isolated leader");
+    router.assert_stable_cluster(None, None).await;
+
+    // Ack times are instants on the clock of each run's own runtime, so they differ between runs.
+    let mut metrics = router.latest_metrics().await;
+    for repl in metrics.iter_mut().flat_map(|node| node.replication.values_mut()) {
+        repl.last_ack = None;
+    }
+    Ok((router.simulation_trace(), metrics))
+}
@@ -0,0 +1,72 @@
+mod fixtures;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_raft::{Config, SnapshotPolicy};
+use tokio::time::delay_for;
+
+use fixtures::RaftRouter;
+
+/// Snapshot with concurrent writes test.
+///
+/// What does this test do?
+///
+/// - build a stable single node cluster, with a small snapshot chunk size.
+/// - send enough requests to the node that log compaction will be triggered.
+/// - send enough further requests that the snapshot is stale, so that the leader has to build a
+///   new one before it can be sent to a new node.
+/// - add a new node while writing to the leader, so that entries are replicated while the new
+///   node's snapshot is being fetched & streamed.
+/// - stop writing, and assert that the new node receives every entry of the leader's log, rather
+///   than its replication stream losing track of the entries written during the snapshot.
+///
+/// RUST_LOG=async_raft,memstore,snapshot_concurrent_writes=trace cargo test -p async-raft --test snapshot_concurrent_writes
+#[tokio::test(core_threads=4)]
+async fn snapshot_concurrent_writes() -> Result<()> {
+    fixtures::init_tracing();
+
+    // Setup test dependencies.
+    let config = Arc::new(Config::build("test".into())
+        .snapshot_policy(SnapshotPolicy::LogsSinceLast(500))
+        .snapshot_max_chunk_size(16)
+        .validate()
+        .expect("failed to build Raft config"));
+    let router = Arc::new(RaftRouter::new(config.clone()));
+    router.new_raft_node(0).await;
+
+    // Assert all nodes are in non-voter state & have no entries.
+    delay_for(Duration::from_secs(3)).await;
+    router.assert_pristine_cluster().await;
+
+    // Initialize the cluster, then assert that a stable cluster was formed & held.
+    tracing::info!("--- initializing cluster");
+    router.initialize_from_single_node(0).await?;
+    delay_for(Duration::from_secs(3)).await;
+    router.assert_stable_cluster(Some(1), Some(1)).await;
+
+    // Send enough requests to the cluster that compaction on the node should be triggered.
+    router.client_request_many(0, "0", 499).await; // Puts us exactly at the configured snapshot policy threshold.
+    delay_for(Duration::from_secs(5)).await; // Wait to ensure there is enough time for a snapshot to be built (this is way more than enough).
+    router.assert_stable_cluster(Some(1), Some(500)).await;
+
+    // Write enough that the snapshot is stale by the time the new node is added, so that the
+    // leader has to build a new one, and keep writing while it is built & streamed.
+    router.client_request_many(0, "0", 300).await;
+    tracing::info!("--- adding new node while writing");
+    router.new_raft_node(1).await;
+    let writer = {
+        let router = router.clone();
+        tokio::spawn(async move { router.client_request_many(0, "1", 500).await })
+    };
+    router.add_non_voter(0, 1).await.expect("failed to add new node as non-voter");
+    writer.await?;
+
+    // Without further writes, the new node must receive every entry of the leader's log.
+    let last_log_index = router.latest_metrics().await.into_iter().find(|node| node.id == 0)
+        .expect("expected to find metrics on leader").last_log_index;
+    router.wait(1, Duration::from_secs(5)).await.log_index(last_log_index).await?;
+
+    Ok(())
+}
@@ -0,0 +1,66 @@
+mod fixtures;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_raft::{Config, SnapshotPolicy};
+use async_raft::raft::MembershipConfig;
+use maplit::hashset;
+use tokio::time::delay_for;
+
+use fixtures::RaftRouter;
+
+/// Snapshot streaming test.
+///
+/// What does this test do?
+///
+/// - build a stable single node cluster, with a small snapshot chunk size.
+/// - send enough requests to the node that log compaction will be triggered.
+/// - lose the responses to a number of InstallSnapshot RPCs.
+/// - add a new node and assert that it receives the snapshot, the leader retrying chunks whose
+///   responses were lost, which the new node acknowledges without writing them twice.
+///
+/// RUST_LOG=async_raft,memstore,snapshot_streaming=trace cargo test -p async-raft --test snapshot_streaming
+#[tokio::test(core_threads=4)]
+async fn snapshot_streaming() -> Result<()> {
+    fixtures::init_tracing();
+
+    // Setup test dependencies.
+    let config = Arc::new(Config::build("test".into())
+        .snapshot_policy(SnapshotPolicy::LogsSinceLast(500))
+        .snapshot_max_chunk_size(16)
+        .validate()
+        .expect("failed to build Raft config"));
+    let router = Arc::new(RaftRouter::new(config.clone()));
+    router.new_raft_node(0).await;
+
+    // Assert all nodes are in non-voter state & have no entries.
+    delay_for(Duration::from_secs(3)).await;
+    router.assert_pristine_cluster().await;
+
+    // Initialize the cluster, then assert that a stable cluster was formed & held.
+    tracing::info!("--- initializing cluster");
+    router.initialize_from_single_node(0).await?;
+    delay_for(Duration::from_secs(3)).await;
+    router.assert_stable_cluster(Some(1), Some(1)).await;
+
+    // Send enough requests to the cluster that compaction on the node should be triggered.
+    router.client_request_many(0, "0", 499).await; // Puts us exactly at the configured snapshot policy threshold.
+    delay_for(Duration::from_secs(5)).await; // Wait to ensure there is enough time for a snapshot to be built (this is way more than enough).
+    router.assert_stable_cluster(Some(1), Some(500)).await;
+    router.assert_storage_state(1, 500, Some(0), 500, Some((500.into(), 1, MembershipConfig{members: hashset![0], members_after_consensus: None, learners: hashset![], witnesses: hashset![]}))).await;
+
+    // Lose the responses to the first few chunks, so that the leader has to retry chunks which
+    // the new node has already written, then add the new node.
+    tracing::info!("--- adding new node with lossy snapshot stream");
+    router.lose_snapshot_responses(3).await;
+    router.new_raft_node(1).await;
+    router.add_non_voter(0, 1).await.expect("failed to add new node as non-voter");
+    router.change_membership(0, hashset![0, 1]).await.expect("failed to modify cluster membership");
+    delay_for(Duration::from_secs(5)).await; // Wait to ensure metrics are updated (this is way more than enough).
+    router.assert_stable_cluster(Some(1), Some(503)).await; // We expect index to be 500 + 3 (learner, joint & uniform config change entries).
+    router.assert_storage_state(1, 503, None, 500, Some((500.into(), 1, MembershipConfig{members: hashset![0u64], members_after_consensus: None, learners: hashset![], witnesses: hashset![]}))).await;
+
+    Ok(())
+}
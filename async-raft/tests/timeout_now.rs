@@ -0,0 +1,60 @@
+mod fixtures;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_raft::{Config, RaftNetwork, State};
+use async_raft::raft::TimeoutNowRequest;
+use tokio::time::delay_for;
+
+use fixtures::RaftRouter;
+
+/// TimeoutNow validation test.
+///
+/// What does this test do?
+///
+/// - brings 3 nodes online & initializes the cluster.
+/// - sends a follower a TimeoutNow RPC from the other follower, and asserts that it does not
+///   campaign, i.e., the cluster is still in its first term with the same leader.
+/// - sends the leader a TimeoutNow RPC of a newer term, and asserts that it steps down rather
+///   than remaining leader in the newer term.
+///
+/// RUST_LOG=async_raft,memstore,timeout_now=trace cargo test -p async-raft --test timeout_now
+#[tokio::test(core_threads=4)]
+async fn timeout_now() -> Result<()> {
+    fixtures::init_tracing();
+
+    // Setup test dependencies.
+    let config = Arc::new(Config::build("test".into()).validate().expect("failed to build Raft config"));
+    let router = Arc::new(RaftRouter::new(config.clone()));
+    router.new_raft_node(0).await;
+    router.new_raft_node(1).await;
+    router.new_raft_node(2).await;
+
+    // Initialize the cluster, then assert that a stable cluster was formed & held.
+    tracing::info!("--- initializing cluster");
+    delay_for(Duration::from_secs(3)).await;
+    router.initialize_from_single_node(0).await?;
+    delay_for(Duration::from_secs(3)).await;
+    router.assert_stable_cluster(Some(1), Some(1)).await;
+    let leader = router.leader().await.expect("expected the cluster to have a leader");
+    let followers: Vec<_> = (0..3).filter(|id| *id != leader).collect();
+
+    // Assert that a TimeoutNow RPC from a node other than the leader is ignored.
+    tracing::info!("--- sending TimeoutNow from non-leader {} to {}", followers[1], followers[0]);
+    let res = router.timeout_now(followers[0], TimeoutNowRequest{term: 1, leader_id: followers[1]}).await?;
+    assert_eq!(res.term, 1, "expected follower to remain in first term");
+    delay_for(Duration::from_secs(1)).await;
+    router.assert_stable_cluster(Some(1), Some(1)).await;
+    assert_eq!(router.leader().await, Some(leader), "expected leader to remain unchanged");
+
+    // Assert that the leader steps down upon a TimeoutNow RPC of a newer term.
+    tracing::info!("--- sending TimeoutNow of a newer term from {} to leader {}", followers[0], leader);
+    let res = router.timeout_now(leader, TimeoutNowRequest{term: 5, leader_id: followers[0]}).await?;
+    assert_eq!(res.term, 5, "expected leader to adopt the newer term");
+    router.wait(leader, Duration::from_secs(1)).await
+        .metrics(|metrics| metrics.current_term >= 5 && metrics.state != State::Leader, "stepped down in the newer term").await?;
+
+    Ok(())
+}
@@ -0,0 +1,92 @@
+mod fixtures;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_raft::{Config, State};
+use async_raft::error::{RaftError, WaitError};
+use maplit::hashset;
+use tokio::time::Instant;
+
+use fixtures::RaftRouter;
+
+/// Wait helpers test.
+///
+/// What does this test do?
+///
+/// - brings 3 nodes online, and waits for each to be a follower with an empty log.
+/// - initializes the cluster, and waits for a leader to be elected & known to every node, and for
+///   every node to apply the initial config.
+/// - writes data, and waits for every node to append & apply it.
+/// - asserts that waiting for a condition which never holds times out with the last seen metrics,
+///   and that waiting on a node which shuts down fails.
+///
+/// RUST_LOG=async_raft,memstore,wait=trace cargo test -p async-raft --test wait
+#[tokio::test(core_threads=4)]
+async fn wait() -> Result<()> {
+    fixtures::init_tracing();
+
+    // Setup test dependencies.
+    let config = Arc::new(Config::build("test".into()).validate().expect("failed to build Raft config"));
+    let router = Arc::new(RaftRouter::new(config.clone()));
+    let timeout = Duration::from_secs(5);
+    for id in 0..3 {
+        router.new_raft_node(id).await;
+    }
+    for id in 0..3 {
+        let metrics = router.wait(id, timeout).await.state(State::NonVoter).await?;
+        assert_eq!(metrics.last_log_index, 0, "expected node {} to have an empty log", id);
+    }
+
+    // Initialize the cluster, then wait for a leader & the initial config on every node.
+    tracing::info!("--- initializing cluster");
+    router.initialize_from_single_node(0).await?;
+    let leader = router.wait(0, timeout).await
+        .metrics(|metrics| metrics.current_leader.is_some(), "a known leader").await?
+        .current_leader.expect("expected node 0 to know the leader");
+    router.wait(leader, timeout).await.state(State::Leader).await?;
+    for id in 0..3 {
+        let wait = router.wait(id, timeout).await;
+        wait.current_leader(leader).await?;
+        wait.members(hashset![0, 1, 2]).await?;
+        wait.applied(1).await?;
+    }
+
+    // Write data, then wait for every node to append & apply it.
+    tracing::info!("--- writing data");
+    router.client_request_many(leader, "0", 10).await;
+    for id in 0..3 {
+        let wait = router.wait(id, timeout).await;
+        wait.log_index(11).await?;
+        let metrics = wait.applied(11).await?;
+        assert_eq!(metrics.last_log_index, 11, "expected node {} to have last log index 11", id);
+    }
+
+    // Wait for a condition which never holds, and assert that it times out with the last metrics.
+    tracing::info!("--- waiting for an unreachable log index");
+    let start = Instant::now();
+    let res = router.wait(leader, Duration::from_millis(500)).await.log_index(100).await;
+    assert!(start.elapsed() >= Duration::from_millis(500), "expected the wait to last until its timeout");
+    match res {
+        Err(WaitError::Timeout{timeout, condition, metrics}) => {
+            assert_eq!(timeout, Duration::from_millis(500));
+            assert_eq!(condition, "last_log_index >= 100");
+            assert_eq!(metrics.id, leader);
+            assert_eq!(metrics.last_log_index, 11);
+        }
+        res => panic!("expected a timeout, got {:?}", res),
+    }
+
+    // Wait on a node which shuts down, and assert that the wait fails.
+    tracing::info!("--- waiting on a node which shuts down");
+    let follower = if leader == 0 { 1 } else { 0 };
+    let wait = router.wait(follower, timeout).await;
+    router.shutdown_node(follower).await?;
+    match wait.log_index(100).await {
+        Err(WaitError::RaftError(RaftError::ShuttingDown)) => (),
+        res => panic!("expected the wait to fail as the node shuts down, got {:?}", res),
+    }
+
+    Ok(())
+}
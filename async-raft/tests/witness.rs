@@ -0,0 +1,120 @@
+mod fixtures;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_raft::{Config, SnapshotPolicy, State};
+use async_raft::raft::EntryPayload;
+use maplit::hashset;
+use tokio::time::delay_for;
+
+use fixtures::RaftRouter;
+
+/// Witness members test.
+///
+/// What does this test do?
+///
+/// - brings 2 nodes online & initializes the cluster, then writes enough data to trigger compaction.
+/// - adds a 3rd node as a witness & promotes it to a voting member.
+/// - asserts that the witness holds only log metadata & has an empty state machine.
+/// - isolates the full follower, and asserts that the leader can still commit with the witness's vote.
+/// - restores the follower & isolates the leader, and asserts that the witness never becomes leader.
+///
+/// RUST_LOG=async_raft,memstore,witness=trace cargo test -p async-raft --test witness
+#[tokio::test(core_threads=4)]
+async fn witness() -> Result<()> {
+    fixtures::init_tracing();
+
+    // Setup test dependencies.
+    let config = Arc::new(
+        Config::build("test".into())
+            .pre_vote(true)
+            .snapshot_policy(SnapshotPolicy::LogsSinceLast(50))
+            .validate()
+            .expect("failed to build Raft config"),
+    );
+    let router = Arc::new(RaftRouter::new(config.clone()));
+    router.new_raft_node(0).await;
+    router.new_raft_node(1).await;
+
+    // Assert all nodes are in non-voter state & have no entries.
+    delay_for(Duration::from_secs(3)).await;
+    router.assert_pristine_cluster().await;
+
+    // Initialize the cluster, then assert that a stable cluster was formed & held.
+    tracing::info!("--- initializing cluster");
+    router.initialize_from_single_node(0).await?;
+    delay_for(Duration::from_secs(5)).await;
+    router.assert_stable_cluster(Some(1), Some(1)).await;
+    let leader = router.leader().await.expect("expected the cluster to have a leader");
+    let follower = if leader == 0 { 1 } else { 0 };
+
+    // Write enough data to trigger a snapshot, so that the witness is brought up to speed via the light path.
+    tracing::info!("--- writing data to trigger compaction");
+    router.client_request_many(leader, "0", 100).await;
+    delay_for(Duration::from_secs(2)).await;
+
+    // Add the witness & promote it to a voting member.
+    tracing::info!("--- adding witness node 2");
+    router.new_raft_node(2).await;
+    router.add_witness(leader, 2).await?;
+    router.change_membership(leader, hashset![0, 1, 2]).await?;
+    delay_for(Duration::from_secs(2)).await;
+    for node in router.latest_metrics().await {
+        assert_eq!(node.membership_config.members, hashset![0, 1, 2], "node {} has unexpected members", node.id);
+        assert_eq!(node.membership_config.witnesses, hashset![2], "node {} has unexpected witnesses", node.id);
+    }
+
+    // Assert that the witness holds no entry payloads & no state machine data.
+    let witness_store = router.storage(2).await;
+    for (index, entry) in witness_store.get_log().await.iter() {
+        assert!(
+            !matches!(entry.payload, EntryPayload::Normal(_)),
+            "witness holds a normal entry at index {}",
+            index
+        );
+    }
+    assert!(
+        witness_store.get_state_machine().await.client_serial_responses.is_empty(),
+        "expected witness to have an empty state machine"
+    );
+
+    // Isolate the full follower & assert that the leader can still commit with the witness.
+    tracing::info!("--- isolating follower node {}", follower);
+    router.isolate_node(follower).await;
+    let before = router
+        .latest_metrics()
+        .await
+        .into_iter()
+        .find(|node| node.id == leader)
+        .expect("expected to find metrics for leader");
+    router.client_request_many(leader, "1", 10).await;
+    delay_for(Duration::from_secs(2)).await;
+    let after = router
+        .latest_metrics()
+        .await
+        .into_iter()
+        .find(|node| node.id == leader)
+        .expect("expected to find metrics for leader");
+    assert_eq!(after.last_applied, before.last_log_index + 10, "expected leader to commit with the witness's vote");
+
+    // Restore the follower, isolate the leader & assert that the follower takes over, not the witness.
+    tracing::info!("--- restoring follower node {} & isolating leader node {}", follower, leader);
+    router.restore_node(follower).await;
+    delay_for(Duration::from_secs(2)).await;
+    router.isolate_node(leader).await;
+    delay_for(Duration::from_secs(5)).await;
+    let new_leader = router.leader().await.expect("expected new leader");
+    assert_eq!(new_leader, follower, "expected the full follower to become leader");
+    let witness = router
+        .latest_metrics()
+        .await
+        .into_iter()
+        .find(|node| node.id == 2)
+        .expect("expected to find metrics for witness");
+    assert_eq!(witness.state, State::Follower, "expected witness to remain a follower");
+    assert_eq!(witness.current_leader, Some(follower), "expected witness to follow the new leader");
+
+    Ok(())
+}
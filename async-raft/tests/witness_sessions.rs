@@ -0,0 +1,66 @@
+mod fixtures;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_raft::{Config, State};
+use maplit::hashset;
+
+use fixtures::RaftRouter;
+
+/// Witness client sessions test.
+///
+/// What does this test do?
+///
+/// - brings 2 nodes online & initializes the cluster, then adds a 3rd node as a witness & promotes
+///   it to a voting member.
+/// - registers a client session on the leader.
+/// - asserts that the witness applies the registration without recording the session, as it holds
+///   no state machine data, and that it remains a healthy follower.
+///
+/// RUST_LOG=async_raft,memstore,witness_sessions=trace cargo test -p async-raft --test witness_sessions
+#[tokio::test(core_threads=4)]
+async fn witness_sessions() -> Result<()> {
+    fixtures::init_tracing();
+
+    // Setup test dependencies.
+    let config = Arc::new(Config::build("test".into()).pre_vote(true).validate().expect("failed to build Raft config"));
+    let router = Arc::new(RaftRouter::new(config.clone()));
+    let wait_timeout = Duration::from_secs(5);
+    router.new_raft_node(0).await;
+    router.new_raft_node(1).await;
+
+    // Initialize the cluster.
+    tracing::info!("--- initializing cluster");
+    router.initialize_from_single_node(0).await?;
+    let leader = router.wait(0, wait_timeout).await
+        .metrics(|metrics| metrics.current_leader.is_some(), "a known leader").await?
+        .current_leader.expect("expected node 0 to know the leader");
+    router.wait(leader, wait_timeout).await.applied(1).await?;
+
+    // Add the witness & promote it to a voting member.
+    tracing::info!("--- adding witness node 2");
+    router.new_raft_node(2).await;
+    router.add_witness(leader, 2).await?;
+    router.change_membership(leader, hashset![0, 1, 2]).await?;
+
+    // Register a client session, and wait for the witness to apply its registration.
+    tracing::info!("--- registering client session");
+    let session = router.register_client_session(leader).await?;
+    let last_applied = router.wait(leader, wait_timeout).await
+        .metrics(|metrics| metrics.last_applied == metrics.last_log_index, "all entries applied").await?
+        .last_applied;
+    router.wait(2, wait_timeout).await.applied(last_applied).await?;
+
+    // Assert that only the full members recorded the session, and that the witness is still up.
+    assert!(router.storage(leader).await.get_state_machine().await.client_sessions.contains(session), "expected the leader to record the session");
+    assert!(
+        router.storage(2).await.get_state_machine().await.client_sessions.is_empty(),
+        "expected the witness to record no client sessions"
+    );
+    router.wait(2, wait_timeout).await.state(State::Follower).await?;
+    router.wait(2, wait_timeout).await.current_leader(leader).await?;
+
+    Ok(())
+}
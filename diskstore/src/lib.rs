@@ -0,0 +1,540 @@
+#![cfg_attr(feature="docinclude", feature(external_doc))]
+#![cfg_attr(feature="docinclude", doc(include="../README.md"))]
+
+mod log;
+#[cfg(test)]
+mod test;
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use async_raft::async_trait::async_trait;
+use async_raft::{AppData, AppDataResponse, NodeId, RaftStorage};
+use async_raft::raft::{Entry, MembershipConfig};
+use async_raft::session::ClientSessions;
+use async_raft::storage::{CurrentSnapshotData, HardState, InitialState};
+use serde::{Serialize, Deserialize};
+use tokio::io::AsyncReadExt;
+use tokio::sync::RwLock;
+use tokio::sync::{RwLockReadGuard, RwLockWriteGuard};
+
+use crate::log::{SegmentedLog, sync_dir};
+
+/// The default size in bytes past which a new log segment will be started.
+pub const DEFAULT_SEGMENT_SIZE: u64 = 1024 * 1024 * 64;
+
+const ERR_INCONSISTENT_LOG: &str = "a query was received which was expecting data to be in place which does not exist in the log";
+const ERR_LOG_LOCK_POISONED: &str = "the log lock was poisoned by a panicked storage task";
+const ERR_SNAPSHOT_LOCK_POISONED: &str = "the incoming snapshot lock was poisoned by a panicked storage task";
+
+/// The name of the file holding the ID of the node.
+const NODE_ID_FILE: &str = "node_id";
+/// The name of the file holding the hard state.
+const HARD_STATE_FILE: &str = "hard_state.json";
+/// The name of the directory holding the log segments.
+const LOG_DIR: &str = "log";
+/// The name of the directory holding snapshots.
+const SNAPSHOT_DIR: &str = "snapshots";
+/// The name of the file holding the current snapshot.
+const CURRENT_SNAPSHOT_FILE: &str = "current.snap";
+/// The file extension used for snapshots which are still being written.
+const PARTIAL_SNAPSHOT_EXT: &str = "partial";
+
+/// The application data request type which the `DiskStore` works with.
+///
+/// This sets the value of a key, returning the value which it previously held. Requests which
+/// must be applied at most once, even when retried, should be submitted in a client session.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ClientRequest {
+    /// The key to update.
+    pub key: String,
+    /// The new value of the key.
+    pub value: String,
+}
+
+impl AppData for ClientRequest {}
+
+/// The application data response type which the `DiskStore` works with.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ClientResponse {
+    /// The value which the key held before the request was applied.
+    pub previous: Option<String>,
+}
+
+impl AppDataResponse for ClientResponse {}
+
+/// The application snapshot type which the `DiskStore` works with.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DiskStoreSnapshot {
+    /// The last index covered by this snapshot.
+    pub index: u64,
+    /// The term of the last index covered by this snapshot.
+    pub term: u64,
+    /// The last memberhsip config included in this snapshot.
+    pub membership: MembershipConfig,
+    /// The data of the state machine at the time of this snapshot.
+    pub data: Vec<u8>,
+}
+
+/// The metadata of the current snapshot.
+#[derive(Debug, Clone)]
+struct SnapshotMeta {
+    /// The last index covered by the snapshot.
+    index: u64,
+    /// The term of the last index covered by the snapshot.
+    term: u64,
+    /// The last memberhsip config included in the snapshot.
+    membership: MembershipConfig,
+}
+
+/// The state machine of the `DiskStore`.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct DiskStoreStateMachine {
+    pub last_applied_log: u64,
+    /// The current value of each key.
+    pub data: HashMap<String, String>,
+    /// The table of client sessions, which is maintained by Raft.
+    #[serde(default)]
+    pub client_sessions: ClientSessions<ClientResponse>,
+}
+
+/// A durable, file-backed storage system implementing the `async_raft::RaftStorage` trait.
+///
+/// All data is kept within the directory which the store is opened on. The log is kept in a
+/// series of append-only segment files, and the hard state is replaced atomically. Snapshots are
+/// written to files, which are exposed directly as `RaftStorage::Snapshot`.
+///
+/// The state machine is held in memory, and is restored from the current snapshot when the
+/// store is opened. Raft will then re-apply any committed entries which follow the snapshot.
+pub struct DiskStore {
+    /// The ID of the Raft node for which this storage instance is configured.
+    id: NodeId,
+    /// The directory holding all of this store's data.
+    dir: PathBuf,
+    /// The Raft log.
+    ///
+    /// This lock is only ever taken within blocking tasks, as it is held for the duration of disk IO.
+    log: Arc<Mutex<SegmentedLog<ClientRequest>>>,
+    /// The Raft state machine.
+    sm: RwLock<DiskStoreStateMachine>,
+    /// The current hard state.
+    hs: RwLock<Option<HardState>>,
+    /// The metadata of the current snapshot.
+    current_snapshot: RwLock<Option<SnapshotMeta>>,
+    /// A counter used for generating unique snapshot IDs.
+    next_snapshot_id: AtomicU64,
+    /// The ID of the snapshot currently being streamed in from the leader, if any.
+    ///
+    /// Its file is removed once it is superseded by a new stream, or once another snapshot has
+    /// been installed.
+    incoming_snapshot: Mutex<Option<String>>,
+}
+
+impl DiskStore {
+    /// Open a `DiskStore` instance in the given directory, creating it if needed.
+    ///
+    /// If the directory holds data from a previous instance, then that data is recovered. It is an
+    /// error to open a directory which was created for a different node ID.
+    pub async fn open(id: NodeId, dir: impl AsRef<Path>) -> Result<Self> {
+        Self::open_with_segment_size(id, dir, DEFAULT_SEGMENT_SIZE).await
+    }
+
+    /// Open a `DiskStore` instance in the given directory, starting a new log segment whenever
+    /// the current segment grows past `segment_size` bytes.
+    pub async fn open_with_segment_size(id: NodeId, dir: impl AsRef<Path>, segment_size: u64) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        let (log, hs, sm, current_snapshot) = run_blocking(dir.clone(), move |dir| Self::recover(id, &dir, segment_size)).await?;
+        let next_snapshot_id = SystemTime::now().duration_since(UNIX_EPOCH).map(|dur| dur.as_nanos() as u64).unwrap_or(0);
+        Ok(Self{
+            id, dir,
+            log: Arc::new(Mutex::new(log)),
+            sm: RwLock::new(sm),
+            hs: RwLock::new(hs),
+            current_snapshot: RwLock::new(current_snapshot),
+            next_snapshot_id: AtomicU64::new(next_snapshot_id),
+            incoming_snapshot: Mutex::new(None),
+        })
+    }
+
+    /// Recover all state held in the given directory.
+    #[allow(clippy::type_complexity)]
+    fn recover(id: NodeId, dir: &Path, segment_size: u64) -> Result<(SegmentedLog<ClientRequest>, Option<HardState>, DiskStoreStateMachine, Option<SnapshotMeta>)> {
+        // Ensure this directory belongs to this node.
+        fs::create_dir_all(dir.join(SNAPSHOT_DIR))?;
+        let id_path = dir.join(NODE_ID_FILE);
+        match fs::read_to_string(&id_path) {
+            Ok(found) if found.trim() == id.to_string() => (),
+            Ok(found) => return Err(anyhow!("storage directory belongs to node {}, not node {}", found.trim(), id)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => write_atomic(dir, NODE_ID_FILE, id.to_string().as_bytes())?,
+            Err(err) => return Err(err.into()),
+        }
+
+        // Recover the hard state.
+        let hs = match fs::read(dir.join(HARD_STATE_FILE)) {
+            Ok(raw) => Some(serde_json::from_slice(&raw)?),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err.into()),
+        };
+
+        // Remove any snapshots which were never finished, then recover the current snapshot.
+        for dirent in fs::read_dir(dir.join(SNAPSHOT_DIR))? {
+            let path = dirent?.path();
+            if path.extension().and_then(|ext| ext.to_str()) == Some(PARTIAL_SNAPSHOT_EXT) {
+                fs::remove_file(path)?;
+            }
+        }
+        let mut log = SegmentedLog::open(dir.join(LOG_DIR), segment_size)?;
+        let snapshot: Option<DiskStoreSnapshot> = match fs::read(dir.join(SNAPSHOT_DIR).join(CURRENT_SNAPSHOT_FILE)) {
+            Ok(raw) => Some(serde_json::from_slice(&raw)?),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err.into()),
+        };
+        let (sm, current_snapshot) = match snapshot {
+            Some(snapshot) => {
+                // Entries covered by the snapshot may remain if a crash came before log compaction.
+                log.delete(0, Some(snapshot.index + 1))?;
+                let meta = SnapshotMeta{index: snapshot.index, term: snapshot.term, membership: snapshot.membership};
+                log.set_pointer(Entry::new_snapshot_pointer(meta.index, meta.term, CURRENT_SNAPSHOT_FILE.into(), meta.membership.clone()));
+                (serde_json::from_slice(&snapshot.data)?, Some(meta))
+            }
+            None => (DiskStoreStateMachine::default(), None),
+        };
+        Ok((log, hs, sm, current_snapshot))
+    }
+
+    /// Read a copy of the log for testing purposes.
+    pub async fn get_log(&self) -> Result<BTreeMap<u64, Entry<ClientRequest>>> {
+        self.with_log(|log| Ok(log.get(0, u64::MAX)?.into_iter().map(|entry| (entry.index, entry)).collect())).await
+    }
+
+    /// Get a handle to the state machine for testing purposes.
+    pub async fn get_state_machine<'a>(&'a self) -> RwLockWriteGuard<'a, DiskStoreStateMachine> {
+        self.sm.write().await
+    }
+
+    /// Get a handle to the current hard state for testing purposes.
+    pub async fn read_hard_state<'a>(&'a self) -> RwLockReadGuard<'a, Option<HardState>> {
+        self.hs.read().await
+    }
+
+    /// Run the given closure against the log within a blocking task.
+    async fn with_log<F, T>(&self, f: F) -> Result<T>
+        where
+            F: FnOnce(&mut SegmentedLog<ClientRequest>) -> Result<T> + Send + 'static,
+            T: Send + 'static,
+    {
+        let log = self.log.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut log = log.lock().map_err(|_| anyhow!(ERR_LOG_LOCK_POISONED))?;
+            f(&mut log)
+        }).await?
+    }
+
+    /// Generate a new snapshot ID, which is also used as the name of its file.
+    fn new_snapshot_id(&self) -> String {
+        format!("{:020}.{}", self.next_snapshot_id.fetch_add(1, Ordering::Relaxed), PARTIAL_SNAPSHOT_EXT)
+    }
+
+    /// Make the snapshot file with the given ID the current snapshot.
+    async fn install_snapshot_file(&self, id: String) -> Result<()> {
+        run_blocking(self.dir.join(SNAPSHOT_DIR), move |dir| {
+            fs::File::open(dir.join(&id))?.sync_all()?;
+            fs::rename(dir.join(&id), dir.join(CURRENT_SNAPSHOT_FILE))?;
+            sync_dir(&dir)
+        }).await
+    }
+
+    /// Replace the ID of the snapshot being streamed in from the leader, removing the file of the
+    /// previous one, which will never be finalized.
+    async fn replace_incoming_snapshot(&self, id: Option<String>) -> Result<()> {
+        let previous = {
+            let mut incoming = self.incoming_snapshot.lock().map_err(|_| anyhow!(ERR_SNAPSHOT_LOCK_POISONED))?;
+            std::mem::replace(&mut *incoming, id.clone())
+        };
+        match previous {
+            Some(previous) if Some(&previous) != id.as_ref() => run_blocking(self.dir.join(SNAPSHOT_DIR), move |dir| {
+                match fs::remove_file(dir.join(&previous)) {
+                    Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+                    _ => Ok(()),
+                }
+            }).await,
+            _ => Ok(()),
+        }
+    }
+
+    /// Open a read handle to the current snapshot.
+    async fn open_current_snapshot(&self) -> Result<tokio::fs::File> {
+        Ok(tokio::fs::File::open(self.dir.join(SNAPSHOT_DIR).join(CURRENT_SNAPSHOT_FILE)).await?)
+    }
+}
+
+#[async_trait]
+impl RaftStorage<ClientRequest, ClientResponse> for DiskStore {
+    type Snapshot = tokio::fs::File;
+
+    #[tracing::instrument(level="trace", skip(self))]
+    async fn get_membership_config(&self) -> Result<MembershipConfig> {
+        let id = self.id;
+        self.with_log(move |log| Ok(log.last_membership(u64::MAX)?.unwrap_or_else(|| MembershipConfig::new_initial(id)))).await
+    }
+
+    #[tracing::instrument(level="trace", skip(self))]
+    async fn get_initial_state(&self) -> Result<InitialState> {
+        let membership = self.get_membership_config().await?;
+        let hs = self.hs.read().await.clone();
+        match hs {
+            Some(hard_state) => {
+                let (last_log_index, last_log_term) = self.with_log(|log| Ok(log.last().unwrap_or((0, 0)))).await?;
+                let last_applied_log = self.sm.read().await.last_applied_log;
+                Ok(InitialState{
+                    last_log_index,
+                    last_log_term,
+                    last_applied_log,
+                    hard_state,
+                    membership,
+                })
+            }
+            None => {
+                let new = InitialState::new_initial(self.id);
+                self.save_hard_state(&new.hard_state).await?;
+                Ok(new)
+            }
+        }
+    }
+
+    #[tracing::instrument(level="trace", skip(self, hs))]
+    async fn save_hard_state(&self, hs: &HardState) -> Result<()> {
+        let raw = serde_json::to_vec(hs)?;
+        let mut current = self.hs.write().await;
+        run_blocking(self.dir.clone(), move |dir| write_atomic(&dir, HARD_STATE_FILE, &raw)).await?;
+        *current = Some(hs.clone());
+        Ok(())
+    }
+
+    #[tracing::instrument(level="trace", skip(self))]
+    async fn get_log_entries(&self, start: u64, stop: u64) -> Result<Vec<Entry<ClientRequest>>> {
+        // Invalid request, return empty vec.
+        if start > stop {
+            tracing::error!("invalid request, start > stop");
+            return Ok(vec![]);
+        }
+        self.with_log(move |log| log.get(start, stop)).await
+    }
+
+    #[tracing::instrument(level="trace", skip(self))]
+    async fn delete_logs_from(&self, start: u64, stop: Option<u64>) -> Result<()> {
+        if stop.map(|stop| start > stop).unwrap_or(false) {
+            tracing::error!("invalid request, start > stop");
+            return Ok(());
+        }
+        self.with_log(move |log| log.delete(start, stop)).await
+    }
+
+    #[tracing::instrument(level="trace", skip(self, entry))]
+    async fn append_entry_to_log(&self, entry: &Entry<ClientRequest>) -> Result<()> {
+        let entry = entry.clone();
+        self.with_log(move |log| log.append(&[entry])).await
+    }
+
+    #[tracing::instrument(level="trace", skip(self, entries))]
+    async fn replicate_to_log(&self, entries: &[Entry<ClientRequest>]) -> Result<()> {
+        let entries = entries.to_vec();
+        self.with_log(move |log| log.append(&entries)).await
+    }
+
+    #[tracing::instrument(level="trace", skip(self, data))]
+    async fn apply_entry_to_state_machine(&self, index: &u64, data: &ClientRequest) -> Result<ClientResponse> {
+        let mut sm = self.sm.write().await;
+        sm.last_applied_log = *index;
+        let previous = sm.data.insert(data.key.clone(), data.value.clone());
+        Ok(ClientResponse{previous})
+    }
+
+    #[tracing::instrument(level="trace", skip(self, entries))]
+    async fn replicate_to_state_machine(&self, entries: &[(&u64, &ClientRequest)]) -> Result<()> {
+        let mut sm = self.sm.write().await;
+        for (index, data) in entries {
+            sm.last_applied_log = **index;
+            sm.data.insert(data.key.clone(), data.value.clone());
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(level="trace", skip(self, sessions))]
+    async fn save_client_sessions(&self, sessions: &ClientSessions<ClientResponse>) -> Result<()> {
+        self.sm.write().await.client_sessions = sessions.clone();
+        Ok(())
+    }
+
+    #[tracing::instrument(level="trace", skip(self))]
+    async fn get_client_sessions(&self) -> Result<ClientSessions<ClientResponse>> {
+        Ok(self.sm.read().await.client_sessions.clone())
+    }
+
+    #[tracing::instrument(level="trace", skip(self))]
+    async fn do_log_compaction(&self, through: u64) -> Result<CurrentSnapshotData<Self::Snapshot>> {
+        let data;
+        {
+            // Serialize the data of the state machine.
+            let sm = self.sm.read().await;
+            data = serde_json::to_vec(&*sm)?;
+        } // Release state machine read lock.
+
+        // Go backwards through the log to find the most recent membership config <= the `through`
+        // index, along with the term of the `through` entry.
+        let id = self.id;
+        let (term, membership) = self.with_log(move |log| {
+            let term = log.term(through).ok_or_else(|| anyhow!(ERR_INCONSISTENT_LOG))?;
+            let membership = log.last_membership(through)?.unwrap_or_else(|| MembershipConfig::new_initial(id));
+            Ok((term, membership))
+        }).await?;
+
+        // Write the snapshot to disk & make it the current snapshot.
+        let snapshot = DiskStoreSnapshot{index: through, term, membership: membership.clone(), data};
+        let snapshot_bytes = serde_json::to_vec(&snapshot)?;
+        let snapshot_id = self.new_snapshot_id();
+        {
+            let mut current_snapshot = self.current_snapshot.write().await;
+            let path = self.dir.join(SNAPSHOT_DIR).join(&snapshot_id);
+            let raw = snapshot_bytes.clone();
+            run_blocking(path, move |path| {
+                let mut file = fs::File::create(&path)?;
+                file.write_all(&raw)?;
+                Ok(())
+            }).await?;
+            self.install_snapshot_file(snapshot_id).await?;
+
+            // Compact the log, replacing all entries covered by the snapshot with a pointer.
+            let pointer = Entry::new_snapshot_pointer(through, term, CURRENT_SNAPSHOT_FILE.into(), membership.clone());
+            self.with_log(move |log| {
+                log.delete(0, Some(through + 1))?;
+                log.set_pointer(pointer);
+                Ok(())
+            }).await?;
+            *current_snapshot = Some(SnapshotMeta{index: through, term, membership: membership.clone()});
+        } // Release snapshot write lock.
+
+        tracing::trace!({snapshot_size=snapshot_bytes.len()}, "log compaction complete");
+        Ok(CurrentSnapshotData{
+            term, index: through, membership,
+            snapshot: Box::new(self.open_current_snapshot().await?),
+        })
+    }
+
+    #[tracing::instrument(level="trace", skip(self))]
+    async fn create_snapshot(&self) -> Result<(String, Box<Self::Snapshot>)> {
+        // A new stream supersedes any stream which was still in progress.
+        let id = self.new_snapshot_id();
+        let file = tokio::fs::File::create(self.dir.join(SNAPSHOT_DIR).join(&id)).await?;
+        self.replace_incoming_snapshot(Some(id.clone())).await?;
+        Ok((id, Box::new(file)))
+    }
+
+    #[tracing::instrument(level="trace", skip(self, snapshot))]
+    async fn finalize_snapshot_installation(&self, index: u64, term: u64, delete_through: Option<u64>, id: String, snapshot: Box<Self::Snapshot>) -> Result<()> {
+        // Decode the snapshot which was streamed in from the leader.
+        drop(snapshot);
+        let mut raw = Vec::new();
+        tokio::fs::File::open(self.dir.join(SNAPSHOT_DIR).join(&id)).await?.read_to_end(&mut raw).await?;
+        tracing::trace!({snapshot_size=raw.len()}, "decoding snapshot for installation");
+        let new_snapshot: DiskStoreSnapshot = serde_json::from_slice(&raw)?;
+        let new_sm: DiskStoreStateMachine = serde_json::from_slice(&new_snapshot.data)?;
+
+        let mut current_snapshot = self.current_snapshot.write().await;
+        self.install_snapshot_file(id).await?;
+        self.replace_incoming_snapshot(None).await?;
+
+        // Update log.
+        let pointer = Entry::new_snapshot_pointer(index, term, CURRENT_SNAPSHOT_FILE.into(), new_snapshot.membership.clone());
+        self.with_log(move |log| {
+            match delete_through {
+                Some(through) => log.delete(0, Some(through + 1))?,
+                None => log.delete(0, None)?,
+            }
+            log.set_pointer(pointer);
+            Ok(())
+        }).await?;
+
+        // Update the state machine.
+        *self.sm.write().await = new_sm;
+
+        // Update current snapshot.
+        *current_snapshot = Some(SnapshotMeta{index, term, membership: new_snapshot.membership});
+        Ok(())
+    }
+
+    #[tracing::instrument(level="trace", skip(self, membership))]
+    async fn install_witness_snapshot(&self, index: u64, term: u64, delete_through: Option<u64>, membership: MembershipConfig) -> Result<()> {
+        // Witnesses keep no state machine data, so the snapshot only records the applied index.
+        let new_sm = DiskStoreStateMachine{last_applied_log: index, ..Default::default()};
+        let snapshot = DiskStoreSnapshot{index, term, membership: membership.clone(), data: serde_json::to_vec(&new_sm)?};
+        let snapshot_bytes = serde_json::to_vec(&snapshot)?;
+        let snapshot_id = self.new_snapshot_id();
+
+        let mut current_snapshot = self.current_snapshot.write().await;
+        let path = self.dir.join(SNAPSHOT_DIR).join(&snapshot_id);
+        run_blocking(path, move |path| {
+            let mut file = fs::File::create(&path)?;
+            file.write_all(&snapshot_bytes)?;
+            Ok(())
+        }).await?;
+        self.install_snapshot_file(snapshot_id).await?;
+        self.replace_incoming_snapshot(None).await?;
+
+        // Update log.
+        let pointer = Entry::new_snapshot_pointer(index, term, CURRENT_SNAPSHOT_FILE.into(), membership.clone());
+        self.with_log(move |log| {
+            match delete_through {
+                Some(through) => log.delete(0, Some(through + 1))?,
+                None => log.delete(0, None)?,
+            }
+            log.set_pointer(pointer);
+            Ok(())
+        }).await?;
+
+        // Update the state machine.
+        *self.sm.write().await = new_sm;
+
+        // Update current snapshot.
+        *current_snapshot = Some(SnapshotMeta{index, term, membership});
+        Ok(())
+    }
+
+    #[tracing::instrument(level="trace", skip(self))]
+    async fn get_current_snapshot(&self) -> Result<Option<CurrentSnapshotData<Self::Snapshot>>> {
+        match &*self.current_snapshot.read().await {
+            Some(snapshot) => Ok(Some(CurrentSnapshotData{
+                index: snapshot.index,
+                term: snapshot.term,
+                membership: snapshot.membership.clone(),
+                snapshot: Box::new(self.open_current_snapshot().await?),
+            })),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Run the given blocking closure against the given path within a blocking task.
+async fn run_blocking<F, T>(path: PathBuf, f: F) -> Result<T>
+    where
+        F: FnOnce(PathBuf) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+{
+    tokio::task::spawn_blocking(move || f(path)).await?
+}
+
+/// Atomically replace the contents of the named file within the given directory.
+fn write_atomic(dir: &Path, name: &str, contents: &[u8]) -> Result<()> {
+    let tmp = dir.join(format!("{}.tmp", name));
+    let mut file = fs::File::create(&tmp)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(&tmp, dir.join(name))?;
+    sync_dir(dir)
+}
@@ -0,0 +1,399 @@
+//! A segmented, append-only log of Raft entries.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use async_raft::AppData;
+use async_raft::raft::{Entry, EntryPayload, MembershipConfig};
+
+/// The file extension used for log segments.
+const SEGMENT_EXT: &str = "log";
+/// The file extension used for segments which are being rewritten.
+const TMP_EXT: &str = "tmp";
+/// The size of each record's header: a `u32` payload length followed by a `u32` CRC32 checksum.
+const HEADER_SIZE: usize = 8;
+
+const ERR_CORRUPT_RECORD: &str = "a record of the log could not be decoded, though it passed verification when the log was opened";
+
+/// The location & metadata of a record within the log's segments.
+#[derive(Clone, Copy, Debug)]
+struct RecordPos {
+    /// The sequence number of the segment holding the record.
+    segment: u64,
+    /// The byte offset of the record within its segment.
+    offset: u64,
+    /// The length of the record in bytes, including its header.
+    len: u64,
+    /// The term of the record's entry.
+    term: u64,
+    /// Whether the record's entry carries a membership config.
+    is_config: bool,
+}
+
+/// A segmented, append-only log of Raft entries.
+///
+/// Entries are written to the active segment, which is the segment with the highest sequence
+/// number, as records framed by their length & a CRC32 checksum. Once the active segment reaches
+/// the configured size, a new segment is started. Only the location, term & kind of each entry are
+/// held in memory, and entries are read back from their segments on demand.
+///
+/// Entries are always written in ascending order of their index. Appending an entry whose index is
+/// not greater than that of the last entry first truncates the log from that index. Deleting a
+/// range of entries which does not extend to the end of the log removes the segments which fall
+/// entirely within the range, and atomically rewrites any segment which only partially overlaps it.
+pub(crate) struct SegmentedLog<D: AppData> {
+    /// The directory holding the log's segments.
+    dir: PathBuf,
+    /// The size in bytes past which a new segment will be started.
+    max_segment_size: u64,
+    /// The sequence number & length in bytes of each segment.
+    segments: BTreeMap<u64, u64>,
+    /// A handle to the active segment, opened for appending.
+    active: Option<File>,
+    /// The snapshot pointer at the start of the log, which is recovered from the current snapshot
+    /// rather than being persisted.
+    pointer: Option<Entry<D>>,
+    /// The location of each persisted entry.
+    positions: BTreeMap<u64, RecordPos>,
+}
+
+impl<D: AppData> SegmentedLog<D> {
+    /// Open the log in the given directory, creating it if needed.
+    ///
+    /// If a torn or corrupt record is encountered, as may be left behind by a crash in the middle
+    /// of a write, then the log is truncated at that record.
+    pub fn open(dir: PathBuf, max_segment_size: u64) -> Result<Self> {
+        fs::create_dir_all(&dir)?;
+        let mut log = Self{
+            dir, max_segment_size,
+            segments: Default::default(), active: None,
+            pointer: None, positions: Default::default(),
+        };
+
+        // Gather all segments, removing any rewrites which were interrupted.
+        for dirent in fs::read_dir(&log.dir)? {
+            let path = dirent?.path();
+            match path.extension().and_then(|ext| ext.to_str()) {
+                Some(SEGMENT_EXT) => (),
+                Some(TMP_EXT) => {
+                    fs::remove_file(&path)?;
+                    continue;
+                }
+                _ => continue,
+            }
+            if let Some(seq) = path.file_stem().and_then(|stem| stem.to_str()).and_then(|stem| stem.parse::<u64>().ok()) {
+                log.segments.insert(seq, 0);
+            }
+        }
+
+        // Load the records of each segment in order, truncating the log at the first bad record.
+        let seqs: Vec<u64> = log.segments.keys().copied().collect();
+        for (idx, seq) in seqs.iter().enumerate() {
+            let path = log.segment_path(*seq);
+            let buf = fs::read(&path)?;
+            let (len, is_torn) = log.load_records(*seq, &buf);
+            log.segments.insert(*seq, len);
+            if !is_torn {
+                continue;
+            }
+            tracing::warn!({segment=%path.display(), offset=len}, "truncating torn record at tail of log");
+            let file = OpenOptions::new().write(true).open(&path)?;
+            file.set_len(len)?;
+            file.sync_all()?;
+            for seq in &seqs[idx + 1..] {
+                fs::remove_file(log.segment_path(*seq))?;
+                log.segments.remove(seq);
+            }
+            log.sync_dir()?;
+            break;
+        }
+        Ok(log)
+    }
+
+    /// Get the index & term of the last entry of the log.
+    pub fn last(&self) -> Option<(u64, u64)> {
+        match self.positions.iter().next_back() {
+            Some((index, pos)) => Some((*index, pos.term)),
+            None => self.pointer.as_ref().map(|entry| (entry.index, entry.term)),
+        }
+    }
+
+    /// Get the term of the entry at the given index.
+    pub fn term(&self, index: u64) -> Option<u64> {
+        match self.positions.get(&index) {
+            Some(pos) => Some(pos.term),
+            None => self.pointer.as_ref().filter(|entry| entry.index == index).map(|entry| entry.term),
+        }
+    }
+
+    /// Read the entries in the range `[start, stop)` from disk.
+    pub fn get(&self, start: u64, stop: u64) -> Result<Vec<Entry<D>>> {
+        let mut entries: Vec<Entry<D>> = self.pointer.iter()
+            .filter(|entry| entry.index >= start && entry.index < stop)
+            .cloned()
+            .collect();
+        let mut positions = self.positions.range(start..stop).map(|(_, pos)| *pos).peekable();
+        while let Some(first) = positions.next() {
+            // The records of a segment are contiguous, so read all of those in range at once.
+            let mut last = first;
+            let mut count = 1;
+            while let Some(pos) = positions.peek().filter(|pos| pos.segment == first.segment).copied() {
+                positions.next();
+                last = pos;
+                count += 1;
+            }
+            let buf = self.read_segment(first.segment, first.offset, last.offset + last.len - first.offset)?;
+            let mut offset = 0;
+            for _ in 0..count {
+                let (entry, len) = decode_record(&buf[offset..]).ok_or_else(|| anyhow!(ERR_CORRUPT_RECORD))?;
+                entries.push(entry);
+                offset += len;
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Get the last membership config of the log at or before the given index.
+    pub fn last_membership(&self, through: u64) -> Result<Option<MembershipConfig>> {
+        if let Some(pos) = self.positions.range(..=through).rev().map(|(_, pos)| *pos).find(|pos| pos.is_config) {
+            let buf = self.read_segment(pos.segment, pos.offset, pos.len)?;
+            let (entry, _) = decode_record::<D>(&buf).ok_or_else(|| anyhow!(ERR_CORRUPT_RECORD))?;
+            return Ok(membership(&entry));
+        }
+        Ok(self.pointer.as_ref().filter(|entry| entry.index <= through).and_then(membership))
+    }
+
+    /// Set the snapshot pointer at the start of the log, without persisting it.
+    ///
+    /// The pointer is recovered from the current snapshot when the log is opened.
+    pub fn set_pointer(&mut self, entry: Entry<D>) {
+        self.pointer = Some(entry);
+    }
+
+    /// Append the given entries to the log & sync them to disk.
+    pub fn append(&mut self, entries: &[Entry<D>]) -> Result<()> {
+        let first = match entries.first() {
+            Some(first) => first.index,
+            None => return Ok(()),
+        };
+        if self.has_entries_from(first) {
+            self.truncate_from(first)?;
+        }
+        let mut buf = Vec::new();
+        for entry in entries {
+            // Start a new segment once the active segment is full. Segment lengths include any
+            // records which are buffered for writing.
+            let (seq, offset) = match self.segments.iter().next_back() {
+                Some((seq, len)) if *len < self.max_segment_size => (*seq, *len),
+                last => {
+                    let seq = last.map(|(seq, _)| seq + 1).unwrap_or(0);
+                    self.flush(&mut buf)?;
+                    File::create(self.segment_path(seq))?;
+                    self.sync_dir()?;
+                    self.segments.insert(seq, 0);
+                    self.active = None;
+                    (seq, 0)
+                }
+            };
+            let start = buf.len();
+            encode_record(entry, &mut buf)?;
+            let len = (buf.len() - start) as u64;
+            self.segments.insert(seq, offset + len);
+            self.positions.insert(entry.index, RecordPos{segment: seq, offset, len, term: entry.term, is_config: is_config(entry)});
+        }
+        self.flush(&mut buf)
+    }
+
+    /// Delete all entries in the range `[start, stop)`, else from `start` through the end of the
+    /// log if `stop` is `None`.
+    pub fn delete(&mut self, start: u64, stop: Option<u64>) -> Result<()> {
+        let stop = match stop {
+            Some(stop) if self.has_entries_from(stop) => stop,
+            _ => return self.truncate_from(start),
+        };
+        if self.pointer.as_ref().map(|entry| entry.index >= start && entry.index < stop).unwrap_or(false) {
+            self.pointer = None;
+        }
+
+        // Find the segments which hold any of the target entries.
+        let mut targets: Vec<u64> = self.positions.range(start..stop).map(|(_, pos)| pos.segment).collect();
+        targets.dedup();
+        let removed: Vec<u64> = self.positions.range(start..stop).map(|(idx, _)| *idx).collect();
+        for idx in removed {
+            self.positions.remove(&idx);
+        }
+
+        // Remove or rewrite each of the target segments with its remaining records.
+        self.active = None;
+        for seq in targets {
+            let remaining: Vec<u64> = self.positions.iter()
+                .filter(|(_, pos)| pos.segment == seq)
+                .map(|(idx, _)| *idx)
+                .collect();
+            let is_active = self.segments.keys().next_back() == Some(&seq);
+            if remaining.is_empty() && !is_active {
+                fs::remove_file(self.segment_path(seq))?;
+                self.segments.remove(&seq);
+                continue;
+            }
+            let old = fs::read(self.segment_path(seq))?;
+            let mut buf = Vec::new();
+            for idx in remaining {
+                let pos = self.positions[&idx];
+                let record = old.get(pos.offset as usize..(pos.offset + pos.len) as usize).ok_or_else(|| anyhow!(ERR_CORRUPT_RECORD))?;
+                self.positions.insert(idx, RecordPos{offset: buf.len() as u64, ..pos});
+                buf.extend_from_slice(record);
+            }
+            let tmp = self.dir.join(format!("{:020}.{}", seq, TMP_EXT));
+            let mut file = File::create(&tmp)?;
+            file.write_all(&buf)?;
+            file.sync_all()?;
+            fs::rename(&tmp, self.segment_path(seq))?;
+            self.segments.insert(seq, buf.len() as u64);
+        }
+        self.sync_dir()
+    }
+
+    /// Delete all entries from `start` through the end of the log.
+    fn truncate_from(&mut self, start: u64) -> Result<()> {
+        if self.pointer.as_ref().map(|entry| entry.index >= start).unwrap_or(false) {
+            self.pointer = None;
+        }
+        let first = match self.positions.range(start..).next() {
+            Some((_, pos)) => *pos,
+            None => return Ok(()),
+        };
+        self.positions.split_off(&start);
+
+        // Truncate the segment holding the first target entry, and remove all later segments.
+        self.active = None;
+        let file = OpenOptions::new().write(true).open(self.segment_path(first.segment))?;
+        file.set_len(first.offset)?;
+        file.sync_all()?;
+        self.segments.insert(first.segment, first.offset);
+        for seq in self.segments.split_off(&(first.segment + 1)).keys() {
+            fs::remove_file(self.segment_path(*seq))?;
+        }
+        self.sync_dir()
+    }
+
+    /// Check if the log holds any entries at or after the given index.
+    fn has_entries_from(&self, index: u64) -> bool {
+        self.last().map(|(last, _)| last >= index).unwrap_or(false)
+    }
+
+    /// Read `len` bytes from the given segment, starting at `offset`.
+    fn read_segment(&self, seq: u64, offset: u64, len: u64) -> Result<Vec<u8>> {
+        let mut file = File::open(self.segment_path(seq))?;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut buf = vec![0; len as usize];
+        file.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Write the given buffer to the active segment & sync it to disk.
+    fn flush(&mut self, buf: &mut Vec<u8>) -> Result<()> {
+        if buf.is_empty() {
+            return Ok(());
+        }
+        let seq = match self.segments.keys().next_back() {
+            Some(seq) => *seq,
+            None => return Ok(()),
+        };
+        let mut file = match self.active.take() {
+            Some(file) => file,
+            None => OpenOptions::new().append(true).open(self.segment_path(seq))?,
+        };
+        file.write_all(buf)?;
+        file.sync_data()?;
+        self.active = Some(file);
+        buf.clear();
+        Ok(())
+    }
+
+    /// Load the location & metadata of the records of the given segment into memory.
+    ///
+    /// Returns the length of the valid portion of the segment, and whether a torn or corrupt
+    /// record was found after it.
+    fn load_records(&mut self, seq: u64, buf: &[u8]) -> (u64, bool) {
+        let mut offset = 0;
+        while offset < buf.len() {
+            let entry = match decode_record::<D>(&buf[offset..]) {
+                Some((entry, _)) if self.has_entries_from(entry.index) => None,
+                Some((entry, len)) => Some((entry, len)),
+                None => None,
+            };
+            match entry {
+                Some((entry, len)) => {
+                    let pos = RecordPos{segment: seq, offset: offset as u64, len: len as u64, term: entry.term, is_config: is_config(&entry)};
+                    self.positions.insert(entry.index, pos);
+                    offset += len;
+                }
+                None => return (offset as u64, true),
+            }
+        }
+        (offset as u64, false)
+    }
+
+    /// The path of the segment with the given sequence number.
+    fn segment_path(&self, seq: u64) -> PathBuf {
+        self.dir.join(format!("{:020}.{}", seq, SEGMENT_EXT))
+    }
+
+    /// Sync the log's directory, so that segment creation, removal & renaming is durable.
+    fn sync_dir(&self) -> Result<()> {
+        sync_dir(&self.dir)
+    }
+}
+
+/// Sync the given directory, so that changes to its entries are durable.
+pub(crate) fn sync_dir(dir: &Path) -> Result<()> {
+    File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+/// Check if the given entry carries a membership config.
+fn is_config<D: AppData>(entry: &Entry<D>) -> bool {
+    membership(entry).is_some()
+}
+
+/// Get the membership config carried by the given entry, if any.
+fn membership<D: AppData>(entry: &Entry<D>) -> Option<MembershipConfig> {
+    match &entry.payload {
+        EntryPayload::ConfigChange(cfg) => Some(cfg.membership.clone()),
+        EntryPayload::SnapshotPointer(snap) => Some(snap.membership.clone()),
+        _ => None,
+    }
+}
+
+/// Encode the given entry as a record, appending it to the given buffer.
+fn encode_record<D: AppData>(entry: &Entry<D>, buf: &mut Vec<u8>) -> Result<()> {
+    let payload = serde_json::to_vec(entry)?;
+    buf.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    buf.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+    buf.extend_from_slice(&payload);
+    Ok(())
+}
+
+/// Decode the record at the start of the given buffer, returning its entry & its length in bytes.
+///
+/// Returns `None` if the record is torn or corrupt.
+fn decode_record<D: AppData>(buf: &[u8]) -> Option<(Entry<D>, usize)> {
+    if buf.len() < HEADER_SIZE {
+        return None;
+    }
+    let mut len = [0u8; 4];
+    let mut checksum = [0u8; 4];
+    len.copy_from_slice(&buf[0..4]);
+    checksum.copy_from_slice(&buf[4..8]);
+    let len = u32::from_le_bytes(len) as usize;
+    let payload = buf.get(HEADER_SIZE..HEADER_SIZE + len)?;
+    if crc32fast::hash(payload) != u32::from_le_bytes(checksum) {
+        return None;
+    }
+    let entry = serde_json::from_slice(payload).ok()?;
+    Some((entry, HEADER_SIZE + len))
+}
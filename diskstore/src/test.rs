@@ -0,0 +1,480 @@
+use std::collections::HashSet;
+use std::fs::OpenOptions;
+use std::io::{Seek, SeekFrom};
+
+use super::*;
+use async_raft::raft::{EntryConfigChange, EntryPayload};
+use async_raft::testing::StorageTestSuite;
+use tempfile::TempDir;
+use tokio::io::AsyncWriteExt;
+
+const NODE_ID: u64 = 0;
+
+//////////////////////////////////////////////////////////////////////////////
+//////////////////////////////////////////////////////////////////////////////
+
+#[tokio::test]
+async fn test_get_membership_config_default() -> Result<()> {
+    let (_dir, store) = default_store().await?;
+    let membership = store.get_membership_config().await?;
+    assert_eq!(membership.members.len(), 1, "expected members len of 1");
+    assert!(membership.members_after_consensus.is_none(), "expected None for default members_after_consensus");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_get_membership_config_with_previous_state() -> Result<()> {
+    let (_dir, store) = default_store().await?;
+    let mut members: HashSet<NodeId> = Default::default();
+    members.insert(1);
+    members.insert(2);
+    members.insert(3);
+    store.append_entry_to_log(&Entry{term: 1, index: 1, payload: EntryPayload::ConfigChange(EntryConfigChange{
+        membership: MembershipConfig{members: members.clone(), members_after_consensus: None, learners: HashSet::new(), witnesses: HashSet::new()}
+    })}).await?;
+    store.save_hard_state(&HardState{current_term: 1, voted_for: Some(NODE_ID)}).await?;
+
+    let initial = store.get_membership_config().await?;
+
+    assert_eq!(&initial.members, &members, "unexpected len for members");
+    assert!(initial.members_after_consensus.is_none(), "unexpected value for members_after_consensus");
+    Ok(())
+}
+
+//////////////////////////////////////////////////////////////////////////////
+//////////////////////////////////////////////////////////////////////////////
+
+#[tokio::test]
+async fn test_get_initial_state_default() -> Result<()> {
+    let (_dir, store) = default_store().await?;
+    let expected_hs = HardState{current_term: 0, voted_for: None};
+    let expected_membership = MembershipConfig::new_initial(NODE_ID);
+
+    let initial = store.get_initial_state().await?;
+
+    assert_eq!(initial.last_log_index, 0, "unexpected default value for last log index");
+    assert_eq!(initial.last_log_term, 0, "unexpected default value for last log term");
+    assert_eq!(initial.last_applied_log, 0, "unexpected value for last applied log");
+    assert_eq!(initial.hard_state, expected_hs, "unexpected value for default hard state");
+    assert_eq!(initial.membership, expected_membership, "unexpected value for default membership config");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_get_initial_state_with_previous_state() -> Result<()> {
+    let (_dir, store) = default_store().await?;
+    store.append_entry_to_log(&Entry{term: 1, index: 1, payload: EntryPayload::Blank}).await?;
+    store.get_state_machine().await.last_applied_log = 1; // Just stubbed in for testing.
+    let hs = HardState{current_term: 1, voted_for: Some(NODE_ID)};
+    store.save_hard_state(&hs).await?;
+
+    let initial = store.get_initial_state().await?;
+
+    assert_eq!(initial.last_log_index, 1, "unexpected default value for last log index");
+    assert_eq!(initial.last_log_term, 1, "unexpected default value for last log term");
+    assert_eq!(initial.last_applied_log, 1, "unexpected value for last applied log");
+    assert_eq!(initial.hard_state, hs, "unexpected value for default hard state");
+    Ok(())
+}
+
+//////////////////////////////////////////////////////////////////////////////
+//////////////////////////////////////////////////////////////////////////////
+
+#[tokio::test]
+async fn test_save_hard_state() -> Result<()> {
+    let (_dir, store) = default_store().await?;
+    let new_hs = HardState{current_term: 100, voted_for: Some(NODE_ID)};
+
+    let initial = store.get_initial_state().await?;
+    store.save_hard_state(&new_hs).await?;
+    let post = store.get_initial_state().await?;
+
+    assert_ne!(initial.hard_state, post.hard_state, "hard state was expected to be different after update");
+    Ok(())
+}
+
+//////////////////////////////////////////////////////////////////////////////
+//////////////////////////////////////////////////////////////////////////////
+
+#[tokio::test]
+async fn test_get_log_entries_returns_emptry_vec_when_start_gt_stop() -> Result<()> {
+    let (_dir, store) = default_store_with_logs().await?;
+
+    let logs = store.get_log_entries(10, 1).await?;
+
+    assert_eq!(logs.len(), 0, "expected no logs to be returned");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_get_log_entries_returns_expected_entries() -> Result<()> {
+    let (_dir, store) = default_store_with_logs().await?;
+
+    let logs = store.get_log_entries(5, 7).await?;
+
+    assert_eq!(logs.len(), 2, "expected two logs to be returned");
+    assert_eq!(logs[0].index, 5, "unexpected value for log index");
+    assert_eq!(logs[0].term, 1, "unexpected value for log term");
+    assert_eq!(logs[1].index, 6, "unexpected value for log index");
+    assert_eq!(logs[1].term, 1, "unexpected value for log term");
+    Ok(())
+}
+
+//////////////////////////////////////////////////////////////////////////////
+//////////////////////////////////////////////////////////////////////////////
+
+#[tokio::test]
+async fn test_delete_logs_from_does_nothing_if_start_gt_stop() -> Result<()> {
+    let (_dir, store) = default_store_with_logs().await?;
+
+    store.delete_logs_from(10, Some(1)).await?;
+    let logs = store.get_log_entries(1, 11).await?;
+
+    assert_eq!(logs.len(), 10, "expected all (10) logs to be preserved");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_delete_logs_from_deletes_target_logs() -> Result<()> {
+    let (_dir, store) = default_store_with_logs().await?;
+
+    store.delete_logs_from(1, Some(11)).await?;
+    let logs = store.get_log_entries(0, 100).await?;
+
+    assert_eq!(logs.len(), 0, "expected all logs to be deleted");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_delete_logs_from_deletes_target_logs_no_stop() -> Result<()> {
+    let (_dir, store) = default_store_with_logs().await?;
+
+    store.delete_logs_from(1, None).await?;
+    let logs = store.get_log_entries(0, 100).await?;
+
+    assert_eq!(logs.len(), 0, "expected all logs to be deleted");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_delete_logs_from_deletes_only_target_logs() -> Result<()> {
+    let (_dir, store) = default_store_with_logs().await?;
+
+    store.delete_logs_from(1, Some(10)).await?;
+    let logs = store.get_log_entries(0, 100).await?;
+
+    assert_eq!(logs.len(), 1, "expected one log to be preserved");
+    assert_eq!(logs[0].index, 10, "unexpected log index");
+    Ok(())
+}
+
+//////////////////////////////////////////////////////////////////////////////
+//////////////////////////////////////////////////////////////////////////////
+
+#[tokio::test]
+async fn test_append_entry_to_log() -> Result<()> {
+    let (_dir, store) = default_store_with_logs().await?;
+
+    store.append_entry_to_log(&Entry{term: 2, index: 10, payload: EntryPayload::Blank}).await?;
+    let log = store.get_log().await?;
+
+    assert_eq!(log.len(), 10, "expected 10 entries to exist in the log");
+    assert_eq!(log[&10].index, 10, "unexpected log index");
+    assert_eq!(log[&10].term, 2, "unexpected log term");
+    Ok(())
+}
+
+//////////////////////////////////////////////////////////////////////////////
+//////////////////////////////////////////////////////////////////////////////
+
+#[tokio::test]
+async fn test_replicate_to_log() -> Result<()> {
+    let (_dir, store) = default_store_with_logs().await?;
+
+    store.replicate_to_log(&[Entry{term: 1, index: 11, payload: EntryPayload::Blank}]).await?;
+    let log = store.get_log().await?;
+
+    assert_eq!(log.len(), 11, "expected 11 entries to exist in the log");
+    assert_eq!(log[&11].index, 11, "unexpected log index");
+    assert_eq!(log[&11].term, 1, "unexpected log term");
+    Ok(())
+}
+
+//////////////////////////////////////////////////////////////////////////////
+//////////////////////////////////////////////////////////////////////////////
+
+#[tokio::test]
+async fn test_apply_entry_to_state_machine() -> Result<()> {
+    let (_dir, store) = default_store_with_logs().await?;
+
+    let res0 = store.apply_entry_to_state_machine(&1, &ClientRequest{key: "0".into(), value: "lit".into()}).await?;
+    let res1 = store.apply_entry_to_state_machine(&2, &ClientRequest{key: "0".into(), value: "unlit".into()}).await?;
+    let sm = store.get_state_machine().await;
+
+    assert_eq!(sm.last_applied_log, 2, "expected last_applied_log to be 2, got {}", sm.last_applied_log);
+    assert_eq!(res0, ClientResponse{previous: None}, "unexpected response for first request");
+    assert_eq!(res1, ClientResponse{previous: Some("lit".into())}, "unexpected response for second request");
+    let value = sm.data.get("0").expect("expected key to exist in data");
+    assert_eq!(value, "unlit", "expected value to be 'unlit', got '{}'", value);
+    Ok(())
+}
+
+//////////////////////////////////////////////////////////////////////////////
+//////////////////////////////////////////////////////////////////////////////
+
+#[tokio::test]
+async fn test_replicate_to_state_machine() -> Result<()> {
+    let (_dir, store) = default_store_with_logs().await?;
+
+    let req0 = ClientRequest{key: "1".into(), value: "old".into()};
+    let req1 = ClientRequest{key: "1".into(), value: "new".into()};
+    let req2 = ClientRequest{key: "2".into(), value: "other".into()};
+    let entries = vec![
+        (&1u64, &req0),
+        (&2u64, &req1),
+        (&3u64, &req2),
+    ];
+    store.replicate_to_state_machine(&entries).await?;
+    let sm = store.get_state_machine().await;
+
+    assert_eq!(sm.last_applied_log, 3, "expected last_applied_log to be 3, got {}", sm.last_applied_log);
+    let value1 = sm.data.get("1").expect("expected key 1 to exist in data");
+    let value2 = sm.data.get("2").expect("expected key 2 to exist in data");
+    assert_eq!(value1, "new", "expected value to be 'new', got '{}'", value1);
+    assert_eq!(value2, "other", "expected value to be 'other', got '{}'", value2);
+    Ok(())
+}
+
+//////////////////////////////////////////////////////////////////////////////
+//////////////////////////////////////////////////////////////////////////////
+
+#[tokio::test]
+async fn test_state_is_recovered_after_restart() -> Result<()> {
+    let (dir, store) = default_store_with_logs().await?;
+    store.delete_logs_from(9, None).await?;
+    store.append_entry_to_log(&Entry{term: 2, index: 9, payload: EntryPayload::Blank}).await?;
+    let hs = HardState{current_term: 2, voted_for: None};
+    store.save_hard_state(&hs).await?;
+    drop(store);
+
+    let store = DiskStore::open(NODE_ID, dir.path()).await?;
+    let initial = store.get_initial_state().await?;
+    let log = store.get_log().await?;
+
+    assert_eq!(log.len(), 9, "expected 9 entries to exist in the log");
+    assert_eq!(initial.last_log_index, 9, "unexpected value for last log index");
+    assert_eq!(initial.last_log_term, 2, "unexpected value for last log term");
+    assert_eq!(initial.hard_state, hs, "unexpected value for hard state");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_open_fails_for_different_node_id() -> Result<()> {
+    let (dir, store) = default_store().await?;
+    drop(store);
+
+    let res = DiskStore::open(NODE_ID + 1, dir.path()).await;
+
+    assert!(res.is_err(), "expected opening the store for a different node ID to fail");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_torn_tail_record_is_truncated_on_recovery() -> Result<()> {
+    let (dir, store) = default_store_with_logs().await?;
+    drop(store);
+
+    // Simulate a crash in the middle of writing a record.
+    let segment = dir.path().join(LOG_DIR).join(format!("{:020}.log", 0));
+    let mut file = OpenOptions::new().append(true).open(&segment)?;
+    std::io::Write::write_all(&mut file, &[42, 0, 0, 0, 1, 2, 3, 4, b'{'])?;
+    drop(file);
+
+    let store = DiskStore::open(NODE_ID, dir.path()).await?;
+    assert_eq!(store.get_log().await?.len(), 10, "expected all (10) complete entries to be recovered");
+
+    // The log must remain writable after recovery.
+    store.append_entry_to_log(&Entry{term: 1, index: 11, payload: EntryPayload::Blank}).await?;
+    drop(store);
+    let store = DiskStore::open(NODE_ID, dir.path()).await?;
+    let log = store.get_log().await?;
+
+    assert_eq!(log.len(), 11, "expected 11 entries to exist in the log");
+    assert_eq!(log[&11].index, 11, "unexpected log index");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_corrupt_tail_record_is_truncated_on_recovery() -> Result<()> {
+    let (dir, store) = default_store_with_logs().await?;
+    drop(store);
+
+    // Corrupt the final byte of the last record.
+    let segment = dir.path().join(LOG_DIR).join(format!("{:020}.log", 0));
+    let mut file = OpenOptions::new().read(true).write(true).open(&segment)?;
+    file.seek(SeekFrom::End(-1))?;
+    std::io::Write::write_all(&mut file, &[0])?;
+    drop(file);
+
+    let store = DiskStore::open(NODE_ID, dir.path()).await?;
+    let log = store.get_log().await?;
+
+    assert_eq!(log.len(), 9, "expected the corrupt entry to be truncated");
+    assert!(!log.contains_key(&10), "expected the corrupt entry to be truncated");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_segmented_log_is_recovered_after_deletes() -> Result<()> {
+    let dir = TempDir::new()?;
+    let store = DiskStore::open_with_segment_size(NODE_ID, dir.path(), 64).await?;
+    let entries: Vec<_> = (1..=20).map(|index| Entry{term: 1, index, payload: EntryPayload::Blank}).collect();
+    store.replicate_to_log(&entries).await?;
+    store.delete_logs_from(1, Some(6)).await?;
+    store.delete_logs_from(16, None).await?;
+    drop(store);
+
+    let segments = std::fs::read_dir(dir.path().join(LOG_DIR))?.count();
+    assert!(segments > 1, "expected the log to span multiple segments, got {}", segments);
+
+    let store = DiskStore::open_with_segment_size(NODE_ID, dir.path(), 64).await?;
+    let log = store.get_log().await?;
+
+    assert_eq!(log.keys().copied().collect::<Vec<_>>(), (6..=15).collect::<Vec<_>>(), "unexpected entries recovered");
+    Ok(())
+}
+
+//////////////////////////////////////////////////////////////////////////////
+//////////////////////////////////////////////////////////////////////////////
+
+#[tokio::test]
+async fn test_log_compaction_is_recovered_after_restart() -> Result<()> {
+    let (dir, store) = default_store_with_logs().await?;
+    let req = ClientRequest{key: "0".into(), value: "lit".into()};
+    store.replicate_to_state_machine(&[(&5u64, &req)]).await?;
+    store.do_log_compaction(5).await?;
+    drop(store);
+
+    let store = DiskStore::open(NODE_ID, dir.path()).await?;
+    let log = store.get_log().await?;
+    let snapshot = store.get_current_snapshot().await?.expect("expected a current snapshot");
+    let sm = store.get_state_machine().await;
+
+    assert_eq!(log.len(), 6, "expected a snapshot pointer & 5 entries to exist in the log");
+    assert!(matches!(log[&5].payload, EntryPayload::SnapshotPointer(_)), "expected a snapshot pointer at index 5");
+    assert_eq!(snapshot.index, 5, "unexpected snapshot index");
+    assert_eq!(snapshot.term, 1, "unexpected snapshot term");
+    assert_eq!(sm.last_applied_log, 5, "unexpected value for last applied log");
+    assert_eq!(sm.data.get("0"), Some(&String::from("lit")), "unexpected value");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_finalize_snapshot_installation() -> Result<()> {
+    let (dir, store) = default_store_with_logs().await?;
+    let mut sm = DiskStoreStateMachine{last_applied_log: 20, ..Default::default()};
+    sm.data.insert("0".into(), "lit".into());
+    let snapshot = DiskStoreSnapshot{
+        index: 20, term: 2, membership: MembershipConfig::new_initial(NODE_ID),
+        data: serde_json::to_vec(&sm)?,
+    };
+
+    let (id, mut handle) = store.create_snapshot().await?;
+    handle.write_all(&serde_json::to_vec(&snapshot)?).await?;
+    handle.shutdown().await?;
+    store.finalize_snapshot_installation(20, 2, None, id, handle).await?;
+    drop(store);
+
+    let store = DiskStore::open(NODE_ID, dir.path()).await?;
+    let initial = store.get_initial_state().await?;
+    let log = store.get_log().await?;
+
+    assert_eq!(log.len(), 1, "expected only a snapshot pointer to exist in the log");
+    assert_eq!(initial.last_log_index, 20, "unexpected value for last log index");
+    assert_eq!(initial.last_log_term, 2, "unexpected value for last log term");
+    assert_eq!(initial.last_applied_log, 20, "unexpected value for last applied log");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_superseded_snapshot_streams_are_removed() -> Result<()> {
+    let (dir, store) = default_store_with_logs().await?;
+    let sm = DiskStoreStateMachine{last_applied_log: 20, ..Default::default()};
+    let snapshot = DiskStoreSnapshot{
+        index: 20, term: 2, membership: MembershipConfig::new_initial(NODE_ID),
+        data: serde_json::to_vec(&sm)?,
+    };
+
+    // Begin a stream which is abandoned, then supersede it with a new one.
+    let (abandoned_id, mut abandoned) = store.create_snapshot().await?;
+    abandoned.write_all(b"partial").await?;
+    let (id, mut handle) = store.create_snapshot().await?;
+    assert!(!dir.path().join(SNAPSHOT_DIR).join(&abandoned_id).exists(), "expected the superseded stream to be removed");
+
+    handle.write_all(&serde_json::to_vec(&snapshot)?).await?;
+    handle.shutdown().await?;
+    store.finalize_snapshot_installation(20, 2, None, id, handle).await?;
+
+    let mut files: Vec<_> = std::fs::read_dir(dir.path().join(SNAPSHOT_DIR))?
+        .map(|dirent| dirent.map(|dirent| dirent.file_name()))
+        .collect::<std::io::Result<_>>()?;
+    files.sort();
+    assert_eq!(files, vec![std::ffi::OsString::from(CURRENT_SNAPSHOT_FILE)], "expected only the current snapshot to remain");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_install_witness_snapshot_is_recovered_after_restart() -> Result<()> {
+    let (dir, store) = default_store_with_logs().await?;
+    let mut membership = MembershipConfig::new_initial(NODE_ID);
+    membership.witnesses.insert(NODE_ID);
+    store.install_witness_snapshot(8, 1, Some(8), membership.clone()).await?;
+    drop(store);
+
+    let store = DiskStore::open(NODE_ID, dir.path()).await?;
+    let initial = store.get_initial_state().await?;
+    let log = store.get_log().await?;
+    let snapshot = store.get_current_snapshot().await?.expect("expected a current snapshot");
+    let sm = store.get_state_machine().await;
+
+    assert_eq!(log.len(), 3, "expected a snapshot pointer & 2 entries to exist in the log");
+    assert!(matches!(log[&8].payload, EntryPayload::SnapshotPointer(_)), "expected a snapshot pointer at index 8");
+    assert_eq!(initial.last_applied_log, 8, "unexpected value for last applied log");
+    assert_eq!(initial.membership, membership, "unexpected membership");
+    assert_eq!(snapshot.index, 8, "unexpected snapshot index");
+    assert!(sm.data.is_empty(), "expected witness state machine to be empty");
+    Ok(())
+}
+
+//////////////////////////////////////////////////////////////////////////////
+//////////////////////////////////////////////////////////////////////////////
+
+#[tokio::test]
+async fn test_storage_conformance() -> Result<()> {
+    let dir = TempDir::new()?;
+    let root = dir.path().to_path_buf();
+    StorageTestSuite::new(
+        move |id| {
+            let path = root.join(id.to_string());
+            async move { Ok(Arc::new(DiskStore::open(id, path).await?)) }
+        },
+        |index| ClientRequest{key: format!("key-{}", index % 3), value: format!("value-{}", index)},
+    ).with_client_sessions().with_witnesses().run().await
+}
+
+//////////////////////////////////////////////////////////////////////////////
+//////////////////////////////////////////////////////////////////////////////
+
+async fn default_store() -> Result<(TempDir, DiskStore)> {
+    let dir = TempDir::new()?;
+    let store = DiskStore::open(NODE_ID, dir.path()).await?;
+    Ok((dir, store))
+}
+
+async fn default_store_with_logs() -> Result<(TempDir, DiskStore)> {
+    let (dir, store) = default_store().await?;
+    let entries: Vec<_> = (1..=10).map(|index| Entry{term: 1, index, payload: EntryPayload::Blank}).collect();
+    store.replicate_to_log(&entries).await?;
+    store.save_hard_state(&HardState{current_term: 1, voted_for: Some(NODE_ID)}).await?;
+    Ok((dir, store))
+}
@@ -11,6 +11,7 @@ use anyhow::Result;
 use async_raft::async_trait::async_trait;
 use async_raft::{AppData, AppDataResponse, NodeId, RaftStorage};
 use async_raft::raft::{Entry, EntryPayload, MembershipConfig};
+use async_raft::session::ClientSessions;
 use async_raft::storage::{CurrentSnapshotData, HardState, InitialState};
 use serde::{Serialize, Deserialize};
 use tokio::sync::RwLock;
@@ -38,7 +39,7 @@ impl AppData for ClientRequest {}
 
 /// The application data response type which the `MemStore` works with.
 #[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct ClientResponse(std::result::Result<Option<String>, ClientError>);
+pub struct ClientResponse(pub std::result::Result<Option<String>, ClientError>);
 
 impl AppDataResponse for ClientResponse {}
 
@@ -71,6 +72,9 @@ pub struct MemStoreStateMachine {
     pub client_serial_responses: HashMap<String, (u64, Option<String>)>,
     /// The current status of a client by ID.
     pub client_status: HashMap<String, String>,
+    /// The table of client sessions, which is maintained by Raft.
+    #[serde(default)]
+    pub client_sessions: ClientSessions<ClientResponse>,
 }
 
 /// An in-memory storage system implementing the `async_raft::RaftStorage` trait.
@@ -258,6 +262,17 @@ impl RaftStorage<ClientRequest, ClientResponse> for MemStore {
         Ok(())
     }
 
+    #[tracing::instrument(level="trace", skip(self, sessions))]
+    async fn save_client_sessions(&self, sessions: &ClientSessions<ClientResponse>) -> Result<()> {
+        self.sm.write().await.client_sessions = sessions.clone();
+        Ok(())
+    }
+
+    #[tracing::instrument(level="trace", skip(self))]
+    async fn get_client_sessions(&self) -> Result<ClientSessions<ClientResponse>> {
+        Ok(self.sm.read().await.client_sessions.clone())
+    }
+
     #[tracing::instrument(level="trace", skip(self))]
     async fn do_log_compaction(&self, through: u64) -> Result<CurrentSnapshotData<Self::Snapshot>> {
         let data;
@@ -275,6 +290,7 @@ impl RaftStorage<ClientRequest, ClientResponse> for MemStore {
                 .skip_while(|entry| &entry.index > &through)
                 .find_map(|entry| match &entry.payload {
                     EntryPayload::ConfigChange(cfg) => Some(cfg.membership.clone()),
+                    EntryPayload::SnapshotPointer(snap) => Some(snap.membership.clone()),
                     _ => None,
                 })
                 .unwrap_or_else(|| MembershipConfig::new_initial(self.id));
@@ -314,15 +330,9 @@ impl RaftStorage<ClientRequest, ClientResponse> for MemStore {
         let new_snapshot: MemStoreSnapshot = serde_json::from_slice(snapshot.get_ref().as_slice())?;
         // Update log.
         {
-            // Go backwards through the log to find the most recent membership config <= the `through` index.
+            // The snapshot carries the membership config which it covers.
             let mut log = self.log.write().await;
-            let membership_config = log.values().rev()
-                .skip_while(|entry| &entry.index > &index)
-                .find_map(|entry| match &entry.payload {
-                    EntryPayload::ConfigChange(cfg) => Some(cfg.membership.clone()),
-                    _ => None,
-                })
-                .unwrap_or_else(|| MembershipConfig::new_initial(self.id));
+            let membership_config = new_snapshot.membership.clone();
 
             match &delete_through {
                 Some(through) => {
@@ -346,6 +356,32 @@ impl RaftStorage<ClientRequest, ClientResponse> for MemStore {
         Ok(())
     }
 
+    #[tracing::instrument(level="trace", skip(self, membership))]
+    async fn install_witness_snapshot(&self, index: u64, term: u64, delete_through: Option<u64>, membership: MembershipConfig) -> Result<()> {
+        // Update log.
+        {
+            let mut log = self.log.write().await;
+            match &delete_through {
+                Some(through) => {
+                    *log = log.split_off(&(through + 1));
+                }
+                None => log.clear(),
+            }
+            let id = format!("{}-{}-witness", term, index);
+            log.insert(index, Entry::new_snapshot_pointer(index, term, id, membership.clone()));
+        }
+
+        // Witnesses keep no state machine data, so just record the applied index.
+        let new_sm = MemStoreStateMachine{last_applied_log: index, ..Default::default()};
+        let data = serde_json::to_vec(&new_sm)?;
+        *self.sm.write().await = new_sm;
+
+        // Update current snapshot.
+        let mut current_snapshot = self.current_snapshot.write().await;
+        *current_snapshot = Some(MemStoreSnapshot{index, term, membership, data});
+        Ok(())
+    }
+
     #[tracing::instrument(level="trace", skip(self))]
     async fn get_current_snapshot(&self) -> Result<Option<CurrentSnapshotData<Self::Snapshot>>> {
         match &*self.current_snapshot.read().await {
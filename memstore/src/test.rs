@@ -1,7 +1,9 @@
 use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
 
 use super::*;
 use async_raft::raft::EntryConfigChange;
+use async_raft::testing::StorageTestSuite;
 
 const NODE_ID: u64 = 0;
 
@@ -25,7 +27,7 @@ async fn test_get_membership_config_with_previous_state() -> Result<()> {
     members.insert(2);
     members.insert(3);
     log.insert(1, Entry{term: 1, index: 1, payload: EntryPayload::ConfigChange(EntryConfigChange{
-        membership: MembershipConfig{members: members.clone(), members_after_consensus: None}
+        membership: MembershipConfig{members: members.clone(), members_after_consensus: None, learners: HashSet::new(), witnesses: HashSet::new()}
     })});
     let sm = MemStoreStateMachine::default();
     let hs = HardState{current_term: 1, voted_for: Some(NODE_ID)};
@@ -249,6 +251,47 @@ async fn test_replicate_to_state_machine() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_install_witness_snapshot() -> Result<()> {
+    let store = default_store_with_logs();
+    let membership = MembershipConfig{
+        members: vec![NODE_ID, 1, 2].into_iter().collect(),
+        members_after_consensus: None,
+        learners: HashSet::new(),
+        witnesses: vec![NODE_ID].into_iter().collect(),
+    };
+    store.install_witness_snapshot(8, 1, Some(8), membership.clone()).await?;
+
+    let log = store.get_log().await;
+    assert_eq!(log.keys().cloned().collect::<Vec<_>>(), vec![8, 9, 10], "expected logs through index 8 to be replaced by a pointer");
+    assert!(matches!(log[&8].payload, EntryPayload::SnapshotPointer(_)), "expected a snapshot pointer at index 8");
+    drop(log);
+    let sm = store.get_state_machine().await;
+    assert_eq!(sm.last_applied_log, 8, "expected last_applied_log to be 8, got {}", sm.last_applied_log);
+    assert!(sm.client_serial_responses.is_empty(), "expected witness state machine to be empty");
+    drop(sm);
+    let snapshot = store.get_current_snapshot().await?.expect("expected a current snapshot");
+    assert_eq!(snapshot.index, 8, "unexpected snapshot index");
+    assert_eq!(snapshot.membership, membership, "unexpected snapshot membership");
+    Ok(())
+}
+
+//////////////////////////////////////////////////////////////////////////////
+//////////////////////////////////////////////////////////////////////////////
+
+#[tokio::test]
+async fn test_storage_conformance() -> Result<()> {
+    // A restarted `MemStore` is simply the same instance, as its state only lives in memory.
+    let stores: Arc<Mutex<HashMap<NodeId, Arc<MemStore>>>> = Default::default();
+    StorageTestSuite::new(
+        move |id| {
+            let store = stores.lock().unwrap().entry(id).or_insert_with(|| Arc::new(MemStore::new(id))).clone();
+            async move { Ok(store) }
+        },
+        |index| ClientRequest{client: "conformance".into(), serial: index, status: format!("request-{}", index)},
+    ).with_client_sessions().with_witnesses().run().await
+}
+
 //////////////////////////////////////////////////////////////////////////////
 //////////////////////////////////////////////////////////////////////////////
 